@@ -49,11 +49,9 @@ loginButton.addEventListener("click", (e) => {
         },
         body: JSON.stringify({ email, password }),
     }).then(response => {
-        if (response.status === 206) {
+        if (response.headers.get("X-2FA-Required") === "true") {
             TwoFAForm.email.value = email;
-            response.json().then(data => {
-                TwoFAForm.login_attempt_id.value = data.loginAttemptId;
-            });
+            TwoFAForm.login_attempt_id.value = response.headers.get("X-Login-Attempt-Id");
 
             loginForm.email.value = "";
             loginForm.password.value = "";
@@ -152,6 +150,11 @@ TwoFAButton.addEventListener("click", (e) => {
         } else {
             response.json().then(data => {
                 let error_msg = data.error;
+                if (data.invalidated === true) {
+                    error_msg += ". No attempts left, please log in again.";
+                } else if (data.attempts_remaining !== undefined) {
+                    error_msg += `. ${data.attempts_remaining} attempt(s) remaining.`;
+                }
                 if (error_msg !== undefined && error_msg !== null && error_msg !== "") {
                     TwoFAErrAlter.innerHTML = `<span><strong>Error: </strong>${error_msg}</span>`;
                     TwoFAErrAlter.style.display = "block";
@@ -1,5 +1,34 @@
 // generated by `sqlx migrate build-script`
+use std::process::Command;
+
 fn main() {
     // trigger recompilation when a new migration is added
     println!("cargo:rerun-if-changed=migrations");
+
+    // Docker builds don't copy .git into the context, so the SHA can be passed in instead
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    // HEAD moves on checkout, the refs on commit. Only watched where they exist, since Cargo
+    // reruns the script on every build for a missing path.
+    for path in ["../.git/HEAD", "../.git/refs", "../.git/packed-refs"] {
+        if std::path::Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+    let git_sha = std::env::var("GIT_SHA")
+        .ok()
+        .or_else(git_head_sha)
+        .unwrap_or_else(|| "unknown".to_owned());
+    println!("cargo:rustc-env=GIT_SHA={}", git_sha);
+    println!(
+        "cargo:rustc-env=BUILD_TIME={}",
+        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+    );
+}
+
+fn git_head_sha() -> Option<String> {
+    let output = Command::new("git").args(["rev-parse", "HEAD"]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok().map(|sha| sha.trim().to_owned())
 }
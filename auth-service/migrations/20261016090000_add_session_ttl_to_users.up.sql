@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN IF NOT EXISTS session_ttl_secs INTEGER;
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS two_fa_deliveries;
\ No newline at end of file
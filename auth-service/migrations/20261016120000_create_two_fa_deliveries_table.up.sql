@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS two_fa_deliveries(
+    id BIGSERIAL PRIMARY KEY,
+    email_hash TEXT NOT NULL,
+    delivered BOOLEAN NOT NULL,
+    sent_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS audit_events(
+    id BIGSERIAL PRIMARY KEY,
+    email TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- `/admin/audit` filters by email or event type over a time range
+CREATE INDEX IF NOT EXISTS audit_events_email_created_at_idx ON audit_events (email, created_at);
+CREATE INDEX IF NOT EXISTS audit_events_event_type_created_at_idx ON audit_events (event_type, created_at);
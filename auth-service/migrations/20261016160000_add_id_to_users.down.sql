@@ -0,0 +1,7 @@
+DROP INDEX IF EXISTS audit_events_user_id_created_at_idx;
+ALTER TABLE audit_events DROP COLUMN IF EXISTS user_id;
+
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_email_key;
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_pkey;
+ALTER TABLE users ADD CONSTRAINT users_pkey PRIMARY KEY (email);
+ALTER TABLE users DROP COLUMN IF EXISTS id;
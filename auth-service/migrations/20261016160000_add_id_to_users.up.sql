@@ -0,0 +1,11 @@
+-- Emails can change, so rows that refer to a user need a key that doesn't. Existing users
+-- get a random id; the email stays unique.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS id UUID NOT NULL DEFAULT gen_random_uuid();
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_pkey;
+ALTER TABLE users ADD CONSTRAINT users_pkey PRIMARY KEY (id);
+ALTER TABLE users ADD CONSTRAINT users_email_key UNIQUE (email);
+
+-- Failed logins for unknown emails have no user, so the id is optional
+ALTER TABLE audit_events ADD COLUMN IF NOT EXISTS user_id UUID REFERENCES users (id) ON DELETE SET NULL;
+UPDATE audit_events SET user_id = users.id FROM users WHERE users.email = audit_events.email;
+CREATE INDEX IF NOT EXISTS audit_events_user_id_created_at_idx ON audit_events (user_id, created_at);
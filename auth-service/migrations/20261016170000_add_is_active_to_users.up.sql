@@ -0,0 +1,2 @@
+-- Disabled accounts keep their data but can't log in
+ALTER TABLE users ADD COLUMN IF NOT EXISTS is_active BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,2 @@
+-- Set by ops on compromised accounts; the user has to pick a new password before logging in
+ALTER TABLE users ADD COLUMN IF NOT EXISTS must_change_password BOOLEAN NOT NULL DEFAULT FALSE;
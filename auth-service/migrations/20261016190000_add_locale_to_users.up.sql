@@ -0,0 +1,3 @@
+-- BCP-47 tag of the language emails are sent in; NULL follows the client's Accept-Language,
+-- falling back to English when the header is missing or names no supported language
+ALTER TABLE users ADD COLUMN IF NOT EXISTS locale TEXT;
@@ -1,34 +1,257 @@
-use std::sync::Arc;
+use std::{sync::{Arc, Weak}, time::Duration};
+use reqwest::Url;
 use tokio::sync::RwLock;
-use crate::domain::data_stores::{BannedTokenStore, TwoFACodeStore, UserStore};
+use secrecy::{ExposeSecret, Secret};
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use crate::domain::data_stores::{
+    AdminKeyStore, AuditEventType, AuditLog, BannedTokenStore, BannedTokenStoreError, LoginFailureStore, RevocationNotifier, TrustedDeviceStore, TwoFACodeStore, TwoFaDeliveryLog, UserStore,
+    user_revocation_id,
+};
+use crate::domain::email::Email;
 use crate::domain::email_client::EmailClient;
+use crate::domain::health_check::HealthCheck;
+use crate::domain::password_hasher::PasswordHasher;
+use crate::services::data_stores::{
+    HashmapLoginFailureStore, HashmapTrustedDeviceStore, HashmapTwoFACodeStore, InMemoryAdminKeyStore,
+    InMemoryRevocationNotifier,
+};
+use crate::utils::{
+    auth::JwtSettings,
+    config::Config,
+    constants::TWO_FA_CODE_SWEEP_INTERVAL,
+    feature_flags::FeatureFlags,
+    load_shed::ConcurrencyLimit,
+    login_delay::LoginDelay,
+    maintenance::MaintenanceMode,
+    metrics::Metrics,
+    sliding_session::SlidingSessions,
+    token_cache::VerifiedTokenCache,
+};
 
 
-pub type UserStoreType = Arc<RwLock<dyn UserStore + Send + Sync>>;
+// User stores synchronize internally (e.g. through the Postgres pool), so no outer lock is needed
+pub type UserStoreType = Arc<dyn UserStore + Send + Sync>;
 pub type BannedTokenStoreType = Arc<RwLock<dyn BannedTokenStore + Send + Sync>>;
 pub type TwoFACodeStoreType = Arc<RwLock<dyn TwoFACodeStore + Send + Sync>>;
 pub type EmailClientType = Arc<dyn EmailClient + Send + Sync>;
+pub type TwoFaDeliveryLogType = Arc<dyn TwoFaDeliveryLog + Send + Sync>;
+pub type AuditLogType = Arc<dyn AuditLog + Send + Sync>;
+pub type AdminKeyStoreType = Arc<dyn AdminKeyStore + Send + Sync>;
+pub type TrustedDeviceStoreType = Arc<dyn TrustedDeviceStore + Send + Sync>;
+pub type LoginFailureStoreType = Arc<dyn LoginFailureStore + Send + Sync>;
+pub type PasswordHasherType = Arc<dyn PasswordHasher + Send + Sync>;
+pub type HealthCheckType = Arc<dyn HealthCheck + Send + Sync>;
+pub type RevocationNotifierType = Arc<dyn RevocationNotifier + Send + Sync>;
 
 #[derive(Clone)]
 pub struct AppState {
     pub user_store: UserStoreType,
     pub banned_token_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
+    // Wrong 2FA codes allowed per login attempt before the code is invalidated
+    pub two_fa_max_attempts: u32,
+    // Keys the hash 2FA codes are stored under. Changing it only invalidates codes already sent.
+    pub two_fa_code_pepper: Secret<String>,
+    // Devices that skip the 2FA challenge until their trust expires
+    pub trusted_device_store: TrustedDeviceStoreType,
+    // How long a device stays trusted after the user asks verify_2fa to remember it; 0 disables it
+    pub device_trust_ttl: Duration,
+    // Failed logins per email drive `login_delay`
+    pub login_failure_store: LoginFailureStoreType,
+    pub login_delay: LoginDelay,
     pub email_client: EmailClientType,
+    // Bootstrap admin key; admin endpoints are disabled when neither it nor a rotated key is set
+    pub admin_api_key: Option<Secret<String>>,
+    // Replaces `admin_api_key` once a key has been rotated in through `/admin/rotate_key`
+    pub admin_key_store: AdminKeyStoreType,
+    pub feature_flags: FeatureFlags,
+    pub jwt: JwtSettings,
+    pub verified_token_cache: VerifiedTokenCache,
+    // Auth cookies are reissued near expiry when set; otherwise tokens keep their fixed lifetime
+    pub sliding_sessions: Option<SlidingSessions>,
+    // How recently the user must have logged in for handlers that call `require_fresh_session`
+    pub fresh_session_max_age: Duration,
+    // Logouts are announced here for `/events` subscribers
+    pub revocation_notifier: RevocationNotifierType,
+    // 2FA email receipts are only recorded when a log is configured
+    pub two_fa_delivery_log: Option<TwoFaDeliveryLogType>,
+    // Account events are only recorded, and `/admin/audit` only served, when a log is configured
+    pub audit_log: Option<AuditLogType>,
+    pub maintenance_mode: MaintenanceMode,
+    pub metrics: Metrics,
+    // Where the UI is served from, for links in emails; emails carry no links when unset
+    pub public_app_url: Option<Url>,
+    // Requests over the limit are shed with a 503; unlimited when unset
+    pub concurrency_limit: Option<ConcurrencyLimit>,
+    // Dependencies `/readyz` checks; in-memory stores have nothing to check
+    pub health_checks: Vec<HealthCheckType>,
 }
 
 impl AppState {
+    // Settings come from `config`; stores without a `with_` override start out in memory. Must be
+    // called inside a Tokio runtime, which sweeps the in-memory 2FA code store.
     pub fn new(
+        config: &Config,
         user_store: UserStoreType,
         banned_token_store: BannedTokenStoreType,
-        two_fa_code_store: TwoFACodeStoreType,
         email_client: EmailClientType,
     ) -> Self {
         Self {
             user_store,
             banned_token_store,
-            two_fa_code_store,
+            two_fa_code_store: in_memory_two_fa_code_store(),
+            two_fa_max_attempts: config.two_fa_max_attempts,
+            two_fa_code_pepper: config.two_fa_code_pepper.clone(),
+            trusted_device_store: Arc::new(HashmapTrustedDeviceStore::default()),
+            device_trust_ttl: config.device_trust_ttl,
+            login_failure_store: Arc::new(HashmapLoginFailureStore::default()),
+            login_delay: config.login_delay,
             email_client,
+            admin_api_key: config.admin_api_key.clone(),
+            admin_key_store: Arc::new(InMemoryAdminKeyStore::default()),
+            feature_flags: config.feature_flags,
+            jwt: JwtSettings::from_config(config),
+            verified_token_cache: VerifiedTokenCache::new(config.verify_token_cache_ttl),
+            sliding_sessions: config.sliding_sessions,
+            fresh_session_max_age: config.fresh_session_max_age,
+            revocation_notifier: Arc::new(InMemoryRevocationNotifier::default()),
+            two_fa_delivery_log: None,
+            audit_log: None,
+            maintenance_mode: MaintenanceMode::new(config.maintenance_mode),
+            metrics: Metrics::default(),
+            public_app_url: config.public_app_url.clone(),
+            concurrency_limit: config.max_concurrent_requests.map(ConcurrencyLimit::new),
+            health_checks: Vec::new(),
         }
     }
-}
\ No newline at end of file
+
+    pub fn with_two_fa_delivery_log(mut self, two_fa_delivery_log: TwoFaDeliveryLogType) -> Self {
+        self.two_fa_delivery_log = Some(two_fa_delivery_log);
+        self
+    }
+
+    pub fn with_audit_log(mut self, audit_log: AuditLogType) -> Self {
+        self.audit_log = Some(audit_log);
+        self
+    }
+
+    pub fn with_two_fa_code_store(mut self, two_fa_code_store: TwoFACodeStoreType) -> Self {
+        self.two_fa_code_store = two_fa_code_store;
+        self
+    }
+
+    pub fn with_trusted_device_store(mut self, trusted_device_store: TrustedDeviceStoreType) -> Self {
+        self.trusted_device_store = trusted_device_store;
+        self
+    }
+
+    pub fn with_login_failure_store(mut self, login_failure_store: LoginFailureStoreType) -> Self {
+        self.login_failure_store = login_failure_store;
+        self
+    }
+
+    pub fn with_login_delay(mut self, login_delay: LoginDelay) -> Self {
+        self.login_delay = login_delay;
+        self
+    }
+
+    pub fn with_feature_flags(mut self, feature_flags: FeatureFlags) -> Self {
+        self.feature_flags = feature_flags;
+        self
+    }
+
+    pub fn with_two_fa_max_attempts(mut self, two_fa_max_attempts: u32) -> Self {
+        self.two_fa_max_attempts = two_fa_max_attempts;
+        self
+    }
+
+    pub fn with_sliding_sessions(mut self, sliding_sessions: SlidingSessions) -> Self {
+        self.sliding_sessions = Some(sliding_sessions);
+        self
+    }
+
+    pub fn with_revocation_notifier(mut self, revocation_notifier: RevocationNotifierType) -> Self {
+        self.revocation_notifier = revocation_notifier;
+        self
+    }
+
+    pub fn with_fresh_session_max_age(mut self, fresh_session_max_age: Duration) -> Self {
+        self.fresh_session_max_age = fresh_session_max_age;
+        self
+    }
+
+    pub fn with_max_concurrent_requests(mut self, max_in_flight: usize) -> Self {
+        self.concurrency_limit = Some(ConcurrencyLimit::new(max_in_flight));
+        self
+    }
+
+    pub fn with_admin_key_store(mut self, admin_key_store: AdminKeyStoreType) -> Self {
+        self.admin_key_store = admin_key_store;
+        self
+    }
+
+    pub fn with_health_check(mut self, health_check: HealthCheckType) -> Self {
+        self.health_checks.push(health_check);
+        self
+    }
+
+    // Like 2FA delivery receipts, a missing audit entry shouldn't fail the request, so errors are
+    // only logged
+    pub async fn record_audit_event(&self, email: &Email, event_type: AuditEventType) {
+        let Some(audit_log) = &self.audit_log else {
+            return;
+        };
+        if let Err(e) = audit_log.record(email, event_type).await {
+            tracing::error!("Failed to record {} audit event: {:?}", event_type.as_str(), e);
+        }
+    }
+
+    // Rejects every token already issued to `email` through a per-user token cutoff. The cutoff is
+    // in milliseconds, so a session started right after the revocation is still accepted.
+    pub async fn revoke_user_sessions(&self, email: &Email) -> Result<(), BannedTokenStoreError> {
+        let cutoff = usize::try_from(Utc::now().timestamp_millis())
+            .map_err(|e| BannedTokenStoreError::UnexpectedError(eyre!(e)))?;
+        // Under the write lock, like logout, so no in-flight verification caches a token after
+        // the cache is cleared
+        let banned_token_store = self.banned_token_store.write().await;
+        banned_token_store
+            .set_user_not_valid_before(email.as_ref().expose_secret(), cutoff)
+            .await?;
+        self.verified_token_cache.clear();
+        drop(banned_token_store);
+        // The cutoff already rejects the sessions, so a failed notification only delays their
+        // `/events` subscribers finding out
+        let revocation_id = user_revocation_id(email.as_ref().expose_secret());
+        if let Err(e) = self.revocation_notifier.publish(&revocation_id).await {
+            tracing::warn!("Failed to announce user session revocation: {:?}", e);
+        }
+        Ok(())
+    }
+}
+
+// Nothing expires the in-memory store's codes on its own, so a sweeper drops them periodically
+fn in_memory_two_fa_code_store() -> TwoFACodeStoreType {
+    let two_fa_code_store: TwoFACodeStoreType = Arc::new(RwLock::new(HashmapTwoFACodeStore::default()));
+    spawn_two_fa_code_sweeper(Arc::downgrade(&two_fa_code_store), TWO_FA_CODE_SWEEP_INTERVAL);
+    two_fa_code_store
+}
+
+// Holds the store weakly, so the task ends once the store is replaced or the app shuts down
+fn spawn_two_fa_code_sweeper(
+    two_fa_code_store: Weak<RwLock<dyn TwoFACodeStore + Send + Sync>>,
+    interval: Duration,
+) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let Some(two_fa_code_store) = two_fa_code_store.upgrade() else {
+                break;
+            };
+            if let Err(e) = two_fa_code_store.write().await.remove_expired_codes().await {
+                tracing::error!("Failed to remove expired 2FA codes: {:?}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,261 @@
+use reqwest::{Client, Response, StatusCode};
+use secrecy::{ExposeSecret, Secret};
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+use crate::{
+    routes::UserProfile,
+    utils::constants::JWT_COOKIE_NAME,
+    ApiResponse, ErrorResponse, TwoFactorAuthResponse,
+};
+
+/// Typed client for services that call the auth service over HTTP.
+///
+/// Responses are parsed into the same DTOs the handlers serialize, so a change to the API shows
+/// up as a compile error in consumers instead of a runtime parsing failure.
+#[derive(Clone)]
+pub struct AuthServiceClient {
+    http_client: Client,
+    base_url: String,
+}
+
+/// Result of a login with correct credentials.
+#[derive(Debug)]
+pub enum LoginOutcome {
+    /// The session token, as set in the auth cookie.
+    LoggedIn(Secret<String>),
+    /// The user must finish login through `/verify_2fa`.
+    TwoFactorRequired(TwoFactorAuthResponse),
+}
+
+#[derive(Debug, Error)]
+pub enum AuthServiceClientError {
+    /// The auth service answered with an error body; branch on `response.code`.
+    #[error("Auth service returned {status}: {}", .response.code)]
+    Api { status: StatusCode, response: ErrorResponse },
+    #[error("Unexpected response from auth service: {0}")]
+    UnexpectedResponse(StatusCode),
+    #[error("Request to auth service failed")]
+    Request(#[from] reqwest::Error),
+}
+
+impl AuthServiceClient {
+    /// `base_url` is the service root, e.g. `http://auth-service:3000`.
+    pub fn new(base_url: impl Into<String>, http_client: Client) -> Self {
+        let base_url = base_url.into().trim_end_matches('/').to_owned();
+        Self { http_client, base_url }
+    }
+
+    #[tracing::instrument(name = "Auth client signup", skip_all)]
+    pub async fn signup(
+        &self,
+        email: &str,
+        password: &Secret<String>,
+        requires_2fa: bool,
+    ) -> Result<UserProfile, AuthServiceClientError> {
+        let body = SignupBody { email, password: password.expose_secret(), requires_2fa };
+        let response = self.post("/signup", &body).await?;
+        match response.status() {
+            StatusCode::CREATED => parse_data::<UserProfile>(response).await,
+            status => Err(error_from(status, response).await),
+        }
+    }
+
+    #[tracing::instrument(name = "Auth client login", skip_all)]
+    pub async fn login(&self, email: &str, password: &Secret<String>) -> Result<LoginOutcome, AuthServiceClientError> {
+        let body = LoginBody { email, password: password.expose_secret() };
+        let response = self.post("/login", &body).await?;
+        match response.status() {
+            StatusCode::OK => response
+                .cookies()
+                .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+                .map(|cookie| LoginOutcome::LoggedIn(Secret::new(cookie.value().to_owned())))
+                .ok_or(AuthServiceClientError::UnexpectedResponse(StatusCode::OK)),
+            StatusCode::PARTIAL_CONTENT => parse_data::<TwoFactorAuthResponse>(response)
+                .await
+                .map(LoginOutcome::TwoFactorRequired),
+            status => Err(error_from(status, response).await),
+        }
+    }
+
+    /// Succeeds when the token is valid and not banned; a rejected token is an
+    /// [`AuthServiceClientError::Api`] with code `invalid_token`.
+    #[tracing::instrument(name = "Auth client verify token", skip_all)]
+    pub async fn verify_token(&self, token: &Secret<String>) -> Result<(), AuthServiceClientError> {
+        let body = VerifyTokenBody { token: token.expose_secret() };
+        let response = self.post("/verify_token", &body).await?;
+        match response.status() {
+            StatusCode::OK => Ok(()),
+            status => Err(error_from(status, response).await),
+        }
+    }
+
+    async fn post<B: Serialize>(&self, path: &str, body: &B) -> Result<Response, AuthServiceClientError> {
+        Ok(self
+            .http_client
+            .post(format!("{}{}", self.base_url, path))
+            .json(body)
+            .send()
+            .await?)
+    }
+}
+
+// Unwraps the `data` of an `ApiResponse` envelope
+async fn parse_data<T: DeserializeOwned>(response: Response) -> Result<T, AuthServiceClientError> {
+    let status = response.status();
+    let envelope: ApiResponse<T> = response.json().await?;
+    envelope.data.ok_or(AuthServiceClientError::UnexpectedResponse(status))
+}
+
+// Error bodies that aren't the service's own, e.g. from a proxy in between, keep only the status
+async fn error_from(status: StatusCode, response: Response) -> AuthServiceClientError {
+    match response.json::<ErrorResponse>().await {
+        Ok(response) => AuthServiceClientError::Api { status, response },
+        Err(_) => AuthServiceClientError::UnexpectedResponse(status),
+    }
+}
+
+#[derive(Serialize)]
+struct SignupBody<'a> {
+    email: &'a str,
+    password: &'a str,
+    #[serde(rename = "requires2FA")]
+    requires_2fa: bool,
+}
+
+#[derive(Serialize)]
+struct LoginBody<'a> {
+    email: &'a str,
+    password: &'a str,
+}
+
+#[derive(Serialize)]
+struct VerifyTokenBody<'a> {
+    token: &'a str,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{body_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn client() -> (AuthServiceClient, MockServer) {
+        let mock_server = MockServer::start().await;
+        (AuthServiceClient::new(mock_server.uri(), Client::new()), mock_server)
+    }
+
+    fn password() -> Secret<String> {
+        Secret::new("password123".to_owned())
+    }
+
+    #[tokio::test]
+    async fn signup_parses_created_profile() {
+        let (client, mock_server) = client().await;
+        Mock::given(method("POST"))
+            .and(path("/signup"))
+            .and(body_json(json!({ "email": "ada@example.com", "password": "password123", "requires2FA": true })))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+                "data": { "email": "ada@example.com", "requires2FA": true, "displayName": null },
+                "message": "User created successfully!"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let profile = client.signup("ada@example.com", &password(), true).await.unwrap();
+        assert_eq!(profile.email, "ada@example.com");
+        assert!(profile.requires_2fa);
+    }
+
+    #[tokio::test]
+    async fn signup_parses_error_body() {
+        let (client, mock_server) = client().await;
+        Mock::given(path("/signup"))
+            .respond_with(ResponseTemplate::new(409).set_body_json(json!({
+                "error": "User already exists",
+                "code": "user_already_exists"
+            })))
+            .mount(&mock_server)
+            .await;
+
+        match client.signup("ada@example.com", &password(), false).await {
+            Err(AuthServiceClientError::Api { status, response }) => {
+                assert_eq!(status, StatusCode::CONFLICT);
+                assert_eq!(response.code, "user_already_exists");
+            }
+            other => panic!("expected an API error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn login_returns_token_from_auth_cookie() {
+        let (client, mock_server) = client().await;
+        Mock::given(path("/login"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("set-cookie", format!("{}=session-token; HttpOnly; Path=/", JWT_COOKIE_NAME))
+                    .set_body_json(json!({ "data": null, "message": "Login successful" })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        match client.login("ada@example.com", &password()).await.unwrap() {
+            LoginOutcome::LoggedIn(token) => assert_eq!(token.expose_secret(), "session-token"),
+            other => panic!("expected a session, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn login_returns_2fa_challenge() {
+        let (client, mock_server) = client().await;
+        Mock::given(path("/login"))
+            .respond_with(ResponseTemplate::new(206).set_body_json(json!({
+                "data": { "loginAttemptId": "attempt-id" },
+                "message": "2FA required"
+            })))
+            .mount(&mock_server)
+            .await;
+
+        match client.login("ada@example.com", &password()).await.unwrap() {
+            LoginOutcome::TwoFactorRequired(challenge) => assert_eq!(challenge.login_attempt_id, "attempt-id"),
+            other => panic!("expected a 2FA challenge, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn verify_token_reports_invalid_token() {
+        let (client, mock_server) = client().await;
+        Mock::given(path("/verify_token"))
+            .and(body_json(json!({ "token": "good" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "data": null, "message": "Token is valid" })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/verify_token"))
+            .and(body_json(json!({ "token": "bad" })))
+            .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+                "error": "Invalid token",
+                "code": "invalid_token"
+            })))
+            .mount(&mock_server)
+            .await;
+
+        client.verify_token(&Secret::new("good".to_owned())).await.unwrap();
+        match client.verify_token(&Secret::new("bad".to_owned())).await {
+            Err(AuthServiceClientError::Api { response, .. }) => assert_eq!(response.code, "invalid_token"),
+            other => panic!("expected an API error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn non_json_error_keeps_status() {
+        let (client, mock_server) = client().await;
+        Mock::given(path("/verify_token"))
+            .respond_with(ResponseTemplate::new(502).set_body_string("Bad Gateway"))
+            .mount(&mock_server)
+            .await;
+
+        let result = client.verify_token(&Secret::new("token".to_owned())).await;
+        assert!(matches!(result, Err(AuthServiceClientError::UnexpectedResponse(StatusCode::BAD_GATEWAY))));
+    }
+}
@@ -2,18 +2,59 @@ use async_trait::async_trait;
 use crate::domain::user::User;
 use crate::domain::email::Email;
 use crate::domain::password::Password;
+use crate::domain::session_ttl::SessionTtl;
+use crate::domain::display_name::DisplayName;
+use crate::domain::locale::Locale;
+use std::any::Any;
+use std::time::Duration;
 use uuid::Uuid;  
 use rand::Rng; 
-use std::fmt;
 use thiserror::Error;
 use color_eyre::eyre::Report;
 use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use tokio::sync::broadcast;
 
 #[async_trait]
 pub trait UserStore {
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError>;
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError>;
+    // Adds the user in a transaction the caller commits, so writes that must land with the new
+    // user can be bundled with it and roll it back by failing
+    async fn add_user_tx(&self, user: User) -> Result<Box<dyn UserStoreTransaction>, UserStoreError>;
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError>;
+    async fn get_user_by_id(&self, id: &Uuid) -> Result<User, UserStoreError>;
     async fn validate_user(&self, email: &Email, password: &Password) -> Result<(), UserStoreError>;
+    async fn count_users(&self) -> Result<u64, UserStoreError>;
+    async fn set_session_ttl(
+        &self,
+        email: &Email,
+        session_ttl: Option<SessionTtl>,
+    ) -> Result<(), UserStoreError>;
+    async fn set_display_name(
+        &self,
+        email: &Email,
+        display_name: Option<DisplayName>,
+    ) -> Result<(), UserStoreError>;
+    async fn set_locale(&self, email: &Email, locale: Option<Locale>) -> Result<(), UserStoreError>;
+    async fn set_admin(&self, email: &Email, is_admin: bool) -> Result<(), UserStoreError>;
+    async fn set_active(&self, email: &Email, is_active: bool) -> Result<(), UserStoreError>;
+    async fn set_must_change_password(&self, email: &Email, must_change_password: bool) -> Result<(), UserStoreError>;
+    // Hashes and stores the new password. This also clears `must_change_password`, which the
+    // new password satisfies.
+    async fn update_password(&self, email: &Email, password: Password) -> Result<(), UserStoreError>;
+}
+
+// Writes made through the transaction only land on `commit`; dropping it rolls them back
+#[async_trait]
+pub trait UserStoreTransaction: Send {
+    // Lets a store backed by the same database recognise the transaction and write through it.
+    // Kept opaque so the domain doesn't depend on any one database.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    async fn commit(self: Box<Self>) -> Result<(), UserStoreError>;
 }
 
 #[derive(Debug, Error)]
@@ -44,6 +85,47 @@ impl PartialEq for UserStoreError {
 pub trait BannedTokenStore: Send + Sync {
     async fn store_token(&self, token: Secret<String>) -> Result<(), BannedTokenStoreError>;
     async fn contains_token(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError>;
+    // One result per token, in order. Stores that can check many tokens in a single round trip
+    // should override this.
+    async fn contains_tokens(&self, tokens: &[Secret<String>]) -> Result<Vec<bool>, BannedTokenStoreError> {
+        let mut banned = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            banned.push(self.contains_token(token).await?);
+        }
+        Ok(banned)
+    }
+    async fn list_tokens(&self) -> Result<Vec<BannedTokenEntry>, BannedTokenStoreError>;
+    // Returns false when no banned token has the given id
+    async fn remove_token(&self, id: &str) -> Result<bool, BannedTokenStoreError>;
+    // Tokens issued before this Unix timestamp are rejected, revoking every earlier session at
+    // once without rotating the signing key
+    async fn not_valid_before(&self) -> Result<Option<usize>, BannedTokenStoreError>;
+    async fn set_not_valid_before(&self, cutoff: usize) -> Result<(), BannedTokenStoreError>;
+    // The same, for the tokens of a single user (by `sub` claim), e.g. when their account is
+    // disabled. This one is in milliseconds, so a login right after the revocation isn't caught by it.
+    async fn user_not_valid_before(&self, subject: &str) -> Result<Option<usize>, BannedTokenStoreError>;
+    async fn set_user_not_valid_before(&self, subject: &str, cutoff: usize) -> Result<(), BannedTokenStoreError>;
+}
+
+// Banned tokens are identified by the SHA-256 of the JWT so they can be listed without exposing the token
+pub fn banned_token_id(token: &Secret<String>) -> String {
+    format!("{:x}", Sha256::digest(token.expose_secret().as_bytes()))
+}
+
+// Announced when every session of one user is revoked. Hashed like token ids, so the email
+// doesn't go out over the notifier.
+pub fn user_revocation_id(subject: &str) -> String {
+    format!("user:{:x}", Sha256::digest(subject.as_bytes()))
+}
+
+// Announced when the global token cutoff moves
+pub const ALL_SESSIONS_REVOKED: &str = "all";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BannedTokenEntry {
+    pub id: String,
+    // None when the store doesn't expire banned tokens
+    pub ttl_seconds: Option<u64>,
 }
 
 #[derive(Debug, Error)]
@@ -52,21 +134,204 @@ pub enum BannedTokenStoreError {
     UnexpectedError(#[source] Report),
 }
 
+// Tells `/events` subscribers, on this instance or others, that a token was revoked. Tokens are
+// identified by their `banned_token_id`, a user's sessions by `user_revocation_id` and all
+// sessions by `ALL_SESSIONS_REVOKED`. Delivery is best effort: a notification can be missed
+// while a subscriber lags or the notifier reconnects, so it complements the banned-token store
+// rather than replacing it.
+#[async_trait]
+pub trait RevocationNotifier {
+    async fn publish(&self, token_id: &str) -> Result<(), RevocationNotifierError>;
+    // Receives every revocation id published after the call
+    fn subscribe(&self) -> broadcast::Receiver<String>;
+}
+
+#[derive(Debug, Error)]
+pub enum RevocationNotifierError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+// Consecutive failed logins per email; a count is forgotten once `window` passes without a new failure
+#[async_trait]
+pub trait LoginFailureStore {
+    // Returns the count including this failure
+    async fn record_failure(&self, email: &Email, window: Duration) -> Result<u32, LoginFailureStoreError>;
+    async fn failure_count(&self, email: &Email) -> Result<u32, LoginFailureStoreError>;
+    async fn reset(&self, email: &Email) -> Result<(), LoginFailureStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum LoginFailureStoreError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+// Devices on which a user completed 2FA and asked not to be challenged again for a while
+#[async_trait]
+pub trait TrustedDeviceStore {
+    async fn add_device(
+        &self,
+        email: &Email,
+        device_id: &str,
+        ttl: Duration,
+    ) -> Result<(), TrustedDeviceStoreError>;
+    // False once the device's trust has expired
+    async fn contains_device(&self, email: &Email, device_id: &str) -> Result<bool, TrustedDeviceStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum TrustedDeviceStoreError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+// Holds the hash of an admin key rotated in at runtime; until then the configured key applies
+#[async_trait]
+pub trait AdminKeyStore {
+    async fn get_key_hash(&self) -> Result<Option<String>, AdminKeyStoreError>;
+    async fn set_key_hash(&self, key_hash: String) -> Result<(), AdminKeyStoreError>;
+}
+
+// Admin keys are long random strings rather than passwords, so a plain SHA-256 is enough to keep
+// the key itself out of storage
+pub fn admin_key_hash(key: &Secret<String>) -> String {
+    format!("{:x}", Sha256::digest(key.expose_secret().as_bytes()))
+}
+
+#[derive(Debug, Error)]
+pub enum AdminKeyStoreError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+// Compliance receipts for 2FA emails; the code itself is never recorded
+#[async_trait]
+pub trait TwoFaDeliveryLog {
+    async fn record_delivery(&self, email: &Email, delivered: bool) -> Result<(), TwoFaDeliveryLogError>;
+}
+
+// Receipts identify the recipient by the SHA-256 of the address, like banned token ids
+pub fn delivery_email_hash(email: &Email) -> String {
+    format!("{:x}", Sha256::digest(email.as_ref().expose_secret().as_bytes()))
+}
+
+#[derive(Debug, Error)]
+pub enum TwoFaDeliveryLogError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+// Account events ops can look back through with `/admin/audit`
+#[async_trait]
+pub trait AuditLog {
+    async fn record(&self, email: &Email, event_type: AuditEventType) -> Result<(), AuditLogError>;
+    // Records the event as part of `tx` when the log can share it. Otherwise it's recorded on
+    // its own straight away, and stays even if the transaction is rolled back.
+    async fn record_tx(
+        &self,
+        tx: &mut dyn UserStoreTransaction,
+        email: &Email,
+        event_type: AuditEventType,
+    ) -> Result<(), AuditLogError> {
+        let _ = tx;
+        self.record(email, event_type).await
+    }
+    // Newest first, at most `query.limit` events
+    async fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEvent>, AuditLogError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventType {
+    Signup,
+    LoginSucceeded,
+    LoginFailed,
+    Logout,
+    PasswordChanged,
+}
+
+impl AuditEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Signup => "signup",
+            Self::LoginSucceeded => "login_succeeded",
+            Self::LoginFailed => "login_failed",
+            Self::Logout => "logout",
+            Self::PasswordChanged => "password_changed",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        [Self::Signup, Self::LoginSucceeded, Self::LoginFailed, Self::Logout, Self::PasswordChanged]
+            .into_iter()
+            .find(|event_type| event_type.as_str() == s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    // Increases with every event, so it doubles as the pagination cursor
+    pub id: i64,
+    // The email the event was recorded under, which may since have changed
+    pub email: String,
+    // Absent for failed logins with an unknown email
+    pub user_id: Option<Uuid>,
+    pub event_type: AuditEventType,
+    // Unix seconds
+    pub timestamp: i64,
+}
+
+// Filters are combined; `before_id` continues from the last event of a previous page. `email`
+// also matches the user's events from before an email change.
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    pub email: Option<Email>,
+    pub event_type: Option<AuditEventType>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub before_id: Option<i64>,
+    pub limit: u32,
+}
+
+#[derive(Debug, Error)]
+pub enum AuditLogError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
 #[async_trait::async_trait]
 pub trait TwoFACodeStore {
+    // Only the hash is stored, so a leaked store doesn't hand out codes that are still valid
     async fn add_code(
         &mut self,
         email: Email,
         login_attempt_id: LoginAttemptId,
-        code: TwoFACode,
+        code_hash: TwoFACodeHash,
     ) -> Result<(), TwoFACodeStoreError>;
     
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError>;
+    // Returns false when no code was stored, e.g. because a concurrent verification consumed it
+    async fn remove_code(&mut self, email: &Email) -> Result<bool, TwoFACodeStoreError>;
     
     async fn get_code(
         &self,
         email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
+    ) -> Result<(LoginAttemptId, TwoFACodeHash), TwoFACodeStoreError>;
+
+    // Reads and deletes the code in one step, so a code can be checked at most once
+    async fn take_code(
+        &mut self,
+        email: &Email,
+    ) -> Result<(LoginAttemptId, TwoFACodeHash), TwoFACodeStoreError>;
+
+    // Counts a wrong guess against the stored code and returns the total so far; the count
+    // starts over whenever `add_code` stores a new code
+    async fn record_failed_attempt(&mut self, email: &Email) -> Result<u32, TwoFACodeStoreError>;
+
+    // Stores that expire entries on their own (e.g. Redis TTLs) don't need sweeping
+    async fn remove_expired_codes(&mut self) -> Result<(), TwoFACodeStoreError> {
+        Ok(())
+    }
 }
 
 #[derive(Debug, Error)]
@@ -87,10 +352,24 @@ impl PartialEq for TwoFACodeStoreError {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct LoginAttemptId(Secret<String>);
 
+// Compared in constant time since both values are submitted by the client during 2FA
+impl PartialEq for LoginAttemptId {
+    fn eq(&self, other: &Self) -> bool {
+        secrets_equal(&self.0, &other.0)
+    }
+}
+
 impl LoginAttemptId {
+    // A fresh random ID for a new 2FA login. There's deliberately no `Default`, so an ID is only
+    // ever minted where a login attempt actually starts.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        LoginAttemptId(Secret::new(Uuid::new_v4().to_string()))
+    }
+
     pub fn parse(id: Secret<String>) -> Result<Self, String> {
         match Uuid::parse_str(id.expose_secret()) {
             Ok(_) => Ok(LoginAttemptId(id)),
@@ -99,38 +378,49 @@ impl LoginAttemptId {
     }
 }
 
-impl Default for LoginAttemptId {
-    fn default() -> Self {
-        LoginAttemptId(Secret::new(Uuid::new_v4().to_string()))
-    }
-}
-
 impl AsRef<Secret<String>> for LoginAttemptId {
     fn as_ref(&self) -> &Secret<String> {
         &self.0
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct TwoFACode(Secret<String>);
 
-impl TwoFACode {
-    pub fn parse(code: Secret<String>) -> Result<Self, String> {
-        if code.expose_secret().len() != 6 || !code.expose_secret().chars().all(|c| c.is_ascii_digit()) {
-            return Err("2FA code must be exactly 6 digits".to_string());
-        }
-        Ok(TwoFACode(code))
+impl PartialEq for TwoFACode {
+    fn eq(&self, other: &Self) -> bool {
+        secrets_equal(&self.0, &other.0)
     }
 }
 
-impl Default for TwoFACode {
-    fn default() -> Self {
+impl TwoFACode {
+    // A fresh random code from the thread-local CSPRNG. This is the only way to mint a code, so a
+    // placeholder can't be created by accident and mistaken for a real one.
+    pub fn generate() -> Self {
         let code = rand::thread_rng()
             .gen_range(0..=999999)
             .to_string()
             .pad_left(6, '0');
         TwoFACode(Secret::new(code))
     }
+
+    pub fn parse(code: Secret<String>) -> Result<Self, String> {
+        if code.expose_secret().len() != 6 || !code.expose_secret().chars().all(|c| c.is_ascii_digit()) {
+            return Err("2FA code must be exactly 6 digits".to_string());
+        }
+        Ok(TwoFACode(code))
+    }
+
+    // For codes submitted by users, who paste them with surrounding spaces or copy them from
+    // authenticators that group the digits as `123 456` or `123-456`
+    pub fn parse_submitted(code: Secret<String>) -> Result<Self, String> {
+        let digits: String = code
+            .expose_secret()
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .collect();
+        Self::parse(Secret::new(digits))
+    }
 }
 
 impl AsRef<Secret<String>> for TwoFACode {
@@ -139,6 +429,50 @@ impl AsRef<Secret<String>> for TwoFACode {
     }
 }
 
+impl TwoFACode {
+    // Salted with the login attempt ID, so equal codes from different logins hash differently.
+    // Six digits are quick to brute-force from a plain hash, so the hash is an HMAC keyed with
+    // the pepper, which can't be reversed without it.
+    pub fn hash(&self, login_attempt_id: &LoginAttemptId, pepper: &Secret<String>) -> TwoFACodeHash {
+        let salted = format!("{}:{}", login_attempt_id.as_ref().expose_secret(), self.expose_code());
+        let mut mac = Hmac::<Sha256>::new_from_slice(pepper.expose_secret().as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(salted.as_bytes());
+        TwoFACodeHash(Secret::new(format!("{:x}", mac.finalize().into_bytes())))
+    }
+}
+
+// Hex-encoded HMAC-SHA256 of a 2FA code, as produced by `TwoFACode::hash`
+#[derive(Clone, Debug)]
+pub struct TwoFACodeHash(Secret<String>);
+
+impl PartialEq for TwoFACodeHash {
+    fn eq(&self, other: &Self) -> bool {
+        secrets_equal(&self.0, &other.0)
+    }
+}
+
+impl TwoFACodeHash {
+    // For hashes read back from a store
+    pub fn parse(hash: Secret<String>) -> Result<Self, String> {
+        let hash_str = hash.expose_secret();
+        if hash_str.len() != 64 || !hash_str.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) {
+            return Err("2FA code hash must be 64 lowercase hex digits".to_string());
+        }
+        Ok(TwoFACodeHash(hash))
+    }
+}
+
+impl AsRef<Secret<String>> for TwoFACodeHash {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+fn secrets_equal(a: &Secret<String>, b: &Secret<String>) -> bool {
+    bool::from(a.expose_secret().as_bytes().ct_eq(b.expose_secret().as_bytes()))
+}
+
 trait PadLeft {
     fn pad_left(self, width: usize, pad_char: char) -> String;
 }
@@ -154,8 +488,78 @@ impl PadLeft for String {
     }
 }
 
-impl fmt::Display for TwoFACode {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0.expose_secret())
+impl TwoFACode {
+    // There's deliberately no Display impl, so the code can't end up in a log line through a
+    // stray `{}`; call this only where the code must reach the user, e.g. the 2FA email body
+    pub fn expose_code(&self) -> &str {
+        self.0.expose_secret()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_fa_code_debug_does_not_contain_code() {
+        let code = TwoFACode::parse(Secret::new("123456".to_owned())).unwrap();
+        assert!(!format!("{:?}", code).contains("123456"));
+        assert_eq!(code.expose_code(), "123456");
+    }
+
+    #[test]
+    fn submitted_code_ignores_spaces_and_hyphens() {
+        let expected = TwoFACode::parse(Secret::new("123456".to_owned())).unwrap();
+        for submitted in ["123 456", "123-456", " 123456 ", "\t123 - 456\n"] {
+            let code = TwoFACode::parse_submitted(Secret::new(submitted.to_owned())).unwrap();
+            assert!(code == expected, "{:?} was not normalized", submitted);
+        }
+    }
+
+    #[test]
+    fn submitted_code_still_needs_six_digits() {
+        for submitted in ["123 45", "1234-567", "12a 456", "--", "１２３４５６"] {
+            assert!(TwoFACode::parse_submitted(Secret::new(submitted.to_owned())).is_err());
+        }
+    }
+
+    #[test]
+    fn generated_values_are_valid_and_distinct() {
+        let code = TwoFACode::generate();
+        assert!(TwoFACode::parse(Secret::new(code.expose_code().to_owned())).is_ok());
+
+        let (first, second) = (LoginAttemptId::new(), LoginAttemptId::new());
+        assert!(LoginAttemptId::parse(first.as_ref().clone()).is_ok());
+        assert!(first != second);
+    }
+
+    #[test]
+    fn code_hash_depends_on_login_attempt_and_pepper() {
+        let code = TwoFACode::parse(Secret::new("123456".to_owned())).unwrap();
+        let login_attempt_id = LoginAttemptId::new();
+        let pepper = Secret::new("pepper".to_owned());
+
+        let hash = code.hash(&login_attempt_id, &pepper);
+        assert!(hash == code.hash(&login_attempt_id, &pepper));
+        assert!(TwoFACodeHash::parse(hash.as_ref().clone()).is_ok());
+        assert!(!hash.as_ref().expose_secret().contains("123456"));
+
+        assert!(hash != code.hash(&LoginAttemptId::new(), &pepper));
+        assert!(hash != code.hash(&login_attempt_id, &Secret::new("other-pepper".to_owned())));
+        let other_code = TwoFACode::parse(Secret::new("123457".to_owned())).unwrap();
+        assert!(hash != other_code.hash(&login_attempt_id, &pepper));
+    }
+
+    #[test]
+    fn code_hash_rejects_plain_codes() {
+        for stored in ["123456", "", &"A".repeat(64), &"g".repeat(64)] {
+            assert!(TwoFACodeHash::parse(Secret::new(stored.to_owned())).is_err());
+        }
+    }
+
+    #[test]
+    fn login_attempt_id_debug_does_not_contain_id() {
+        let id = LoginAttemptId::new();
+        assert!(!format!("{:?}", id).contains(id.as_ref().expose_secret().as_str()));
     }
 }
\ No newline at end of file
@@ -0,0 +1,55 @@
+use color_eyre::eyre::{eyre, Result};
+
+// Counted in characters rather than bytes so non-Latin names get the same allowance
+pub const MAX_DISPLAY_NAME_LENGTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayName(String);
+
+impl DisplayName {
+    pub fn parse(name: String) -> Result<DisplayName> {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_DISPLAY_NAME_LENGTH {
+            return Err(eyre!(
+                "Display name must be between 1 and {} characters",
+                MAX_DISPLAY_NAME_LENGTH
+            ));
+        }
+        // Newlines and escape sequences would let a name spoof other lines in UIs and logs
+        if name.chars().any(char::is_control) {
+            return Err(eyre!("Display name must not contain control characters"));
+        }
+        Ok(Self(name.to_owned()))
+    }
+}
+
+impl AsRef<str> for DisplayName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_name_is_trimmed() {
+        let name = DisplayName::parse("  Ada Lovelace ".to_owned()).unwrap();
+        assert_eq!(name.as_ref(), "Ada Lovelace");
+    }
+
+    #[test]
+    fn display_name_length_is_counted_in_characters() {
+        assert!(DisplayName::parse("é".repeat(MAX_DISPLAY_NAME_LENGTH)).is_ok());
+        assert!(DisplayName::parse("a".repeat(MAX_DISPLAY_NAME_LENGTH + 1)).is_err());
+        assert!(DisplayName::parse("   ".to_owned()).is_err());
+    }
+
+    #[test]
+    fn display_name_with_control_characters_is_rejected() {
+        for name in ["Ada\nLovelace", "Ada\u{1b}[31m", "Ada\u{0}"] {
+            assert!(DisplayName::parse(name.to_owned()).is_err(), "accepted {:?}", name);
+        }
+    }
+}
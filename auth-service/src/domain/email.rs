@@ -14,6 +14,20 @@ impl PartialEq for Email {
 
 impl Eq for Email {}
 
+// Providers known to deliver `user+tag@domain` to `user@domain`; elsewhere `+` may be a
+// meaningful part of the mailbox name, so it is never stripped
+const PLUS_ADDRESSING_PROVIDERS: [&str; 9] = [
+    "gmail.com",
+    "googlemail.com",
+    "outlook.com",
+    "hotmail.com",
+    "live.com",
+    "icloud.com",
+    "fastmail.com",
+    "protonmail.com",
+    "proton.me",
+];
+
 impl Email {
     pub fn parse(s: Secret<String>) -> Result<Email> {
         if s.expose_secret().contains('@') {
@@ -22,6 +36,44 @@ impl Email {
             Err(eyre!("Invalid email address"))
         }
     }
+
+    // With `normalize_plus_addressing`, `user+tag@gmail.com` parses as `user@gmail.com` so tagged
+    // addresses can't be used to open several accounts on one mailbox
+    pub fn parse_with(s: Secret<String>, normalize_plus_addressing: bool) -> Result<Email> {
+        let email = Self::parse(s)?;
+        if !normalize_plus_addressing {
+            return Ok(email);
+        }
+
+        let address = email.0.expose_secret();
+        let Some((local, domain)) = address.rsplit_once('@') else {
+            return Ok(email);
+        };
+        let is_known_provider = PLUS_ADDRESSING_PROVIDERS
+            .iter()
+            .any(|provider| provider.eq_ignore_ascii_case(domain));
+        match local.split_once('+') {
+            Some((user, _tag)) if is_known_provider && !user.is_empty() => {
+                Ok(Email(Secret::new(format!("{}@{}", user, domain))))
+            }
+            _ => Ok(email),
+        }
+    }
+}
+
+impl Email {
+    // Keeps the domain and the first character of the mailbox, e.g. `u***@example.com`, so logs
+    // can tell addresses apart roughly without identifying anyone
+    pub fn masked(&self) -> String {
+        let address = self.0.expose_secret();
+        match address.rsplit_once('@') {
+            Some((local, domain)) => match local.chars().next() {
+                Some(first) => format!("{}***@{}", first, domain),
+                None => format!("***@{}", domain),
+            },
+            None => "***".to_owned(),
+        }
+    }
 }
 
 impl AsRef<Secret<String>> for Email {
@@ -58,4 +110,38 @@ mod tests {
         let email = Secret::new("testexample.com".to_string());
         assert!(Email::parse(email).is_err());
     }
+
+    fn parse_with(email: &str, normalize_plus_addressing: bool) -> String {
+        Email::parse_with(Secret::new(email.to_string()), normalize_plus_addressing)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn masked_hides_local_part() {
+        let email = Email::parse(Secret::new("user.name+tag@example.com".to_string())).unwrap();
+        assert_eq!(email.masked(), "u***@example.com");
+
+        let email = Email::parse(Secret::new("@example.com".to_string())).unwrap();
+        assert_eq!(email.masked(), "***@example.com");
+    }
+
+    #[test]
+    fn keeps_plus_tag_when_normalization_is_off() {
+        assert_eq!(parse_with("user+tag@gmail.com", false), "user+tag@gmail.com");
+    }
+
+    #[test]
+    fn strips_plus_tag_for_known_providers() {
+        assert_eq!(parse_with("user+tag@gmail.com", true), "user@gmail.com");
+        assert_eq!(parse_with("user+a+b@Outlook.com", true), "user@Outlook.com");
+        assert_eq!(parse_with("user@gmail.com", true), "user@gmail.com");
+    }
+
+    #[test]
+    fn keeps_plus_tag_for_other_domains() {
+        assert_eq!(parse_with("user+tag@example.com", true), "user+tag@example.com");
+        // Nothing is left of the mailbox name once the tag is removed
+        assert_eq!(parse_with("+tag@gmail.com", true), "+tag@gmail.com");
+    }
 }
\ No newline at end of file
@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 use color_eyre::eyre::Result;
 use super::email::Email;
+use super::locale::Locale;
 
 #[async_trait]
 pub trait EmailClient {
@@ -10,4 +11,16 @@ pub trait EmailClient {
         subject: &str,
         content: &str,
     ) -> Result<()>;
-}
\ No newline at end of file
+
+    // For messages written in `locale`. Clients that can tell the recipient's mail program which
+    // language a message is in override this; others send it like any other email.
+    async fn send_localized_email(
+        &self,
+        recipient: &Email,
+        _locale: Locale,
+        subject: &str,
+        content: &str,
+    ) -> Result<()> {
+        self.send_email(recipient, subject, content).await
+    }
+}
@@ -6,18 +6,84 @@ pub enum AuthAPIError {
     #[error("User already exists")]
     UserAlreadyExists,
     
+    // Holds the JSON name of the required field that was sent empty
+    #[error("{0} must not be empty")]
+    EmptyField(&'static str),
+    
+    // Holds the name of the field or parameter that failed to parse
+    #[error("{0} is invalid")]
+    InvalidField(&'static str),
+    
     #[error("Invalid credentials")]
     InvalidCredentials,
     
+    #[error("Invalid display name")]
+    InvalidDisplayName,
+    
+    #[error("Unsupported locale")]
+    UnsupportedLocale,
+    
+    #[error("Unsupported media type")]
+    UnsupportedMediaType,
+    
+    #[error("Missing request body")]
+    MissingBody,
+    
+    #[error("Batch too large")]
+    BatchTooLarge,
+    
+    #[error("Invalid token cutoff")]
+    InvalidTokenCutoff,
+    
     #[error("Incorrect credentials")]
     IncorrectCredentials,
     
+    // A wrong 2FA code; the code is invalidated once no attempts remain
+    #[error("Incorrect 2FA code")]
+    IncorrectTwoFACode { attempts_remaining: u32 },
+    
     #[error("Missing token")]
     MissingToken,
     
     #[error("Invalid token")]
     InvalidToken,
     
+    #[error("Invalid admin key")]
+    InvalidAdminKey,
+    
+    #[error("Forbidden")]
+    Forbidden,
+    
+    #[error("Reauthentication required")]
+    ReauthenticationRequired,
+    
+    #[error("Account disabled")]
+    AccountDisabled,
+    
+    #[error("Password change required")]
+    PasswordChangeRequired,
+    
+    #[error("User not found")]
+    UserNotFound,
+    
+    #[error("Not found")]
+    NotFound,
+    
+    #[error("Too many requests")]
+    TooManyRequests,
+    
+    #[error("Banned token not found")]
+    BannedTokenNotFound,
+    
+    #[error("Service under maintenance")]
+    MaintenanceMode,
+    
+    #[error("Too many requests in flight")]
+    Overloaded,
+    
+    #[error("Email delivery failed")]
+    EmailDeliveryFailed(#[source] Report),
+    
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
@@ -28,6 +94,6 @@ impl AuthAPIError {
     }
 
     pub fn unexpected_msg(msg: &str) -> Self {
-        Self::UnexpectedError(eyre!(msg))
+        Self::UnexpectedError(eyre!(msg.to_owned()))
     }
 }
\ No newline at end of file
@@ -0,0 +1,10 @@
+use async_trait::async_trait;
+use color_eyre::eyre::Result;
+
+// A dependency the service can't handle traffic without; `/readyz` reports each one by name
+#[async_trait]
+pub trait HealthCheck {
+    fn name(&self) -> &'static str;
+
+    async fn check(&self) -> Result<()>;
+}
@@ -0,0 +1,68 @@
+// Languages the service has messages in. Stored per user for emails and matched against
+// `Accept-Language` for error messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    En,
+    Es,
+}
+
+impl Locale {
+    // Matches on the primary language subtag, so `es-MX` is Spanish
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split('-').next()?.trim();
+        match primary.to_ascii_lowercase().as_str() {
+            "en" => Some(Self::En),
+            "es" => Some(Self::Es),
+            _ => None,
+        }
+    }
+
+    // For a preference the user picks, where the whole tag has to be well-formed rather than
+    // just its primary subtag
+    pub fn parse(tag: &str) -> Option<Self> {
+        let well_formed = tag.split('-').all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.bytes().all(|b| b.is_ascii_alphanumeric())
+        });
+        if !well_formed {
+            return None;
+        }
+        Self::from_tag(tag)
+    }
+
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::Es => "es",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tags_by_primary_language() {
+        assert_eq!(Locale::from_tag("es"), Some(Locale::Es));
+        assert_eq!(Locale::from_tag("ES-mx"), Some(Locale::Es));
+        assert_eq!(Locale::from_tag("en-GB"), Some(Locale::En));
+        assert_eq!(Locale::from_tag("fr"), None);
+    }
+
+    #[test]
+    fn parse_rejects_malformed_and_unsupported_tags() {
+        assert_eq!(Locale::parse("es-MX"), Some(Locale::Es));
+        assert_eq!(Locale::parse("en"), Some(Locale::En));
+        for tag in ["", "fr", "es-", "es_MX", " es", "es-!!", "es-abcdefghi"] {
+            assert_eq!(Locale::parse(tag), None, "Accepted {:?}", tag);
+        }
+    }
+
+    #[test]
+    fn tag_round_trips() {
+        for locale in [Locale::En, Locale::Es] {
+            assert_eq!(Locale::from_tag(locale.tag()), Some(locale));
+        }
+    }
+}
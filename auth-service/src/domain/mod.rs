@@ -4,6 +4,13 @@ pub mod data_stores;
 pub mod email;
 pub mod password;
 pub mod email_client;  
+pub mod password_hasher;
+pub mod health_check;
+pub mod session_ttl;
+pub mod display_name;
+pub mod locale;
 
 pub use error::AuthAPIError;
-pub use email_client::EmailClient; 
\ No newline at end of file
+pub use email_client::EmailClient; 
+pub use password_hasher::PasswordHasher;
+pub use health_check::HealthCheck;
\ No newline at end of file
@@ -11,20 +11,23 @@ impl PartialEq for Password {
     }
 }
 
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+// Caps the input Argon2 has to hash so oversized passwords can't be used to burn CPU
+pub const MAX_PASSWORD_LENGTH: usize = 256;
+
 impl Password {
     pub fn parse(s: Secret<String>) -> Result<Password> {
-        if validate_password(&s) {
-            Ok(Self(s))
+        let length = s.expose_secret().len();
+        if length < MIN_PASSWORD_LENGTH {
+            Err(eyre!("Password must be at least {} characters long", MIN_PASSWORD_LENGTH))
+        } else if length > MAX_PASSWORD_LENGTH {
+            Err(eyre!("Password must be at most {} bytes long", MAX_PASSWORD_LENGTH))
         } else {
-            Err(eyre!("Password must be at least 8 characters long"))
+            Ok(Self(s))
         }
     }
 }
 
-fn validate_password(s: &Secret<String>) -> bool {
-    s.expose_secret().len() >= 8
-}
-
 impl AsRef<Secret<String>> for Password {
     fn as_ref(&self) -> &Secret<String> {
         &self.0
@@ -53,4 +56,16 @@ mod tests {
         let password = Secret::new("short".to_string());
         assert!(Password::parse(password).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn password_at_max_length_is_accepted() {
+        let password = Secret::new("a".repeat(MAX_PASSWORD_LENGTH));
+        assert!(Password::parse(password).is_ok());
+    }
+
+    #[test]
+    fn password_over_max_length_is_rejected() {
+        let password = Secret::new("a".repeat(MAX_PASSWORD_LENGTH + 1));
+        assert!(Password::parse(password).is_err());
+    }
+}
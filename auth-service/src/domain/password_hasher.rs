@@ -0,0 +1,28 @@
+use async_trait::async_trait;
+use color_eyre::eyre::Result;
+use secrecy::Secret;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordVerification {
+    Verified,
+    // The password is correct but its stored hash is in an outdated format or weaker than the
+    // current parameters, and should be replaced
+    VerifiedNeedsRehash,
+}
+
+// Transforms applied by a legacy system before its own hashing, e.g. when importing users whose
+// stored hash is Argon2 over a SHA-256 digest of the password
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegacyPreHash {
+    Sha256,
+}
+
+#[async_trait]
+pub trait PasswordHasher {
+    async fn compute_password_hash(&self, password: Secret<String>) -> Result<Secret<String>>;
+    async fn verify_password_hash(
+        &self,
+        expected_password_hash: &Secret<String>,
+        password_candidate: Secret<String>,
+    ) -> Result<PasswordVerification>;
+}
@@ -0,0 +1,43 @@
+use color_eyre::eyre::{eyre, Result};
+
+pub const MIN_SESSION_TTL_SECONDS: u64 = 60;
+// Banned tokens are remembered for this long, so no session may outlive it
+pub const MAX_SESSION_TTL_SECONDS: u64 = 60 * 60 * 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionTtl(u64);
+
+impl SessionTtl {
+    pub fn parse(seconds: u64) -> Result<SessionTtl> {
+        if !(MIN_SESSION_TTL_SECONDS..=MAX_SESSION_TTL_SECONDS).contains(&seconds) {
+            return Err(eyre!(
+                "Session TTL must be between {} and {} seconds",
+                MIN_SESSION_TTL_SECONDS,
+                MAX_SESSION_TTL_SECONDS
+            ));
+        }
+        Ok(Self(seconds))
+    }
+
+    pub fn as_secs(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_ttl_within_bounds_is_accepted() {
+        assert!(SessionTtl::parse(MIN_SESSION_TTL_SECONDS).is_ok());
+        assert!(SessionTtl::parse(MAX_SESSION_TTL_SECONDS).is_ok());
+    }
+
+    #[test]
+    fn session_ttl_out_of_bounds_is_rejected() {
+        assert!(SessionTtl::parse(0).is_err());
+        assert!(SessionTtl::parse(MIN_SESSION_TTL_SECONDS - 1).is_err());
+        assert!(SessionTtl::parse(MAX_SESSION_TTL_SECONDS + 1).is_err());
+    }
+}
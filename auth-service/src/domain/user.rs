@@ -1,19 +1,61 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 use crate::domain::email::Email;
 use crate::domain::password::Password;
+use crate::domain::session_ttl::SessionTtl;
+use crate::domain::display_name::DisplayName;
+use crate::domain::locale::Locale;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct User {
+    // Stays the same if the email changes, so other records should refer to the user by it
+    pub id: Uuid,
     pub email: Email,
     pub password: Password,
     pub requires_2fa: bool,
+    // Overrides the default auth token lifetime when set
+    pub session_ttl: Option<SessionTtl>,
+    pub display_name: Option<DisplayName>,
+    pub is_admin: bool,
+    // Disabled accounts can't log in; cleared by an admin rather than deleting the user
+    pub is_active: bool,
+    // Login is refused until the password is changed through `/change_password`
+    pub must_change_password: bool,
+    // Language emails are sent in; English when unset
+    pub locale: Option<Locale>,
 }
 
 impl User {
     pub fn new(email: Email, password: Password, requires_2fa: bool) -> Self {
         Self {
+            id: Uuid::new_v4(),
             email,
             password,
             requires_2fa,
+            session_ttl: None,
+            display_name: None,
+            is_admin: false,
+            is_active: true,
+            must_change_password: false,
+            locale: None,
         }
     }
-}
\ No newline at end of file
+
+    pub fn role(&self) -> Role {
+        if self.is_admin {
+            Role::Admin
+        } else {
+            Role::User
+        }
+    }
+}
+
+// Carried in the JWT `role` claim; tokens issued before roles existed have no claim and count
+// as regular users
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Admin,
+}
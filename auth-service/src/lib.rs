@@ -3,78 +3,202 @@ pub mod domain;
 pub mod services;
 pub mod app_state;
 pub mod utils;
+pub mod client;
 
 // Re-export important types at the crate root
 pub use routes::login::{LoginResponse, TwoFactorAuthResponse};
 pub use domain::error::AuthAPIError;
 
 use axum::{
+    handler::HandlerWithoutStateExt,
     serve::Serve, 
     Router, 
     response::{IntoResponse, Response, Json}, 
-    http::{StatusCode, Method, HeaderName}, 
-    routing::post
+    http::{header::{CACHE_CONTROL, PRAGMA}, HeaderValue, StatusCode}, 
+    routing::{delete, get, patch, post, put},
+    extract::{connect_info::IntoMakeServiceWithConnectInfo, ConnectInfo},
+    middleware::{self, AddExtension},
+    Extension,
 };
+use std::borrow::Cow;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use jsonwebtoken::Algorithm;
 use std::error::Error;
-use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
+use tower::ServiceBuilder;
+use tower_http::{
+    compression::{predicate::SizeAbove, CompressionLayer, DefaultPredicate, Predicate},
+    services::ServeDir,
+    set_header::SetResponseHeaderLayer,
+    trace::TraceLayer,
+};
 use app_state::AppState;
 use serde::{Deserialize, Serialize};
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use redis::{Client, RedisResult};
-use utils::tracing::{make_span_with_request_id, on_request, on_response};
+use utils::{
+    constants::{
+        MAX_VERIFY_TOKEN_BATCH_SIZE, API_CACHE_CONTROL, COMPRESSION_MIN_SIZE_BYTES, RATE_LIMIT_PRUNE_INTERVAL,
+        STATIC_ASSETS_CACHE_CONTROL,
+    },
+    config::Config,
+    extractors::ClientSettings,
+    i18n::{localize_errors, ErrorCode},
+    load_shed::load_shed,
+    maintenance::maintenance_gate,
+    rate_limit::{rate_limit, RateLimiter},
+    sliding_session::sliding_session,
+    tracing::{make_span_with_request_id, on_request, on_response},
+};
+
+type Server = Serve<
+    IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
+    AddExtension<Router, ConnectInfo<SocketAddr>>,
+>;
 
 pub struct Application {
-    server: Serve<Router, Router>,
+    server: Server,
     pub address: String,
     state: AppState,
 }
 
 impl Application {
-    pub fn new(server: Serve<Router, Router>, address: String, state: AppState) -> Self {
+    pub fn new(server: Server, address: String, state: AppState) -> Self {
         Self { server, address, state }
     }
 
-    pub async fn build(state: AppState, address: &str) -> Result<Self, Box<dyn Error>> {
-        // Allow the app service(running on our local machine and in production) to call the auth service
-        let allowed_origins = [
-            "http://localhost:8000".parse()?,
-            "http://68.183.141.53:8000".parse()?,
-        ];
-
-        let cors = CorsLayer::new()
-            .allow_methods([Method::GET, Method::POST])
-            .allow_credentials(true)
-            .allow_headers([
-                HeaderName::from_static("content-type"),
-                HeaderName::from_static("cookie"),
-                HeaderName::from_static("authorization"),
-            ])
-            .expose_headers([
-                HeaderName::from_static("set-cookie"),
-                HeaderName::from_static("authorization"),
-            ])
-            .allow_origin(allowed_origins);
-
-        let router = Router::new()
-            .nest_service("/", ServeDir::new("assets"))
-            .route("/signup", post(routes::signup))
-            .route("/login", post(routes::login))
+    pub async fn build(state: AppState, config: &Config, address: &str) -> Result<Self, Box<dyn Error>> {
+        let cors = config.cors.layer();
+        let assets_dir = PathBuf::from(&config.assets_dir);
+        let not_found = {
+            let assets_dir = assets_dir.clone();
+            move |request| async move { routes::not_found(&assets_dir, request).await }
+        };
+
+        // ServeDir answers HEAD and conditional requests via Last-Modified; browsers may
+        // additionally cache the UI assets for a short while. Paths matching no asset fall
+        // through to `not_found`.
+        let assets = ServiceBuilder::new()
+            .layer(SetResponseHeaderLayer::if_not_present(
+                CACHE_CONTROL,
+                HeaderValue::from_static(STATIC_ASSETS_CACHE_CONTROL),
+            ))
+            .service(ServeDir::new(assets_dir).fallback(not_found.into_service()));
+
+        // Endpoints that change state are rejected while maintenance mode is on
+        let maintenance_gate = middleware::from_fn_with_state(state.maintenance_mode.clone(), maintenance_gate);
+
+        // Single and batch verification share one quota, so splitting checks between them gains
+        // a client nothing
+        let verify_token_limiter = rate_limiter(RateLimiter::per_minute(config.verify_token_rate_limit));
+
+        let mut router = Router::new()
+            .fallback_service(assets)
+            .route(
+                "/signup",
+                post(routes::signup)
+                    .layer(middleware::from_fn_with_state(
+                        rate_limiter(RateLimiter::per_minute_with_burst(
+                            config.signup_rate_limit,
+                            config.signup_rate_limit_burst,
+                        )),
+                        rate_limit,
+                    ))
+                    .layer(maintenance_gate.clone()),
+            )
+            .route("/login", post(routes::login).layer(maintenance_gate.clone()))
+            .route("/change_password", post(routes::change_password).layer(maintenance_gate.clone()))
             .route("/logout", post(routes::logout))
-            .route("/verify_2fa", post(routes::verify_2fa))
-            .route("/verify_token", post(routes::verify_token))
-            .route("/test", axum::routing::get(|| async { "Test route" }))
+            .route("/me", get(routes::me))
+            .route("/events", get(routes::events))
+            .route(
+                "/account/profile",
+                patch(routes::update_profile).layer(maintenance_gate.clone()),
+            )
+            .route(
+                "/verify_2fa",
+                post(routes::verify_2fa)
+                    .layer(middleware::from_fn_with_state(
+                        rate_limiter(RateLimiter::per_minute(config.verify_2fa_rate_limit)),
+                        rate_limit,
+                    ))
+                    .layer(maintenance_gate),
+            )
+            .route(
+                "/verify_token",
+                post(routes::verify_token).layer(middleware::from_fn_with_state(
+                    verify_token_limiter.clone(),
+                    rate_limit,
+                )),
+            )
+            // The batch handler charges the rest of its tokens to the same limiter
+            .route(
+                "/verify_token/batch",
+                post(routes::verify_token_batch)
+                    .layer(middleware::from_fn_with_state(verify_token_limiter.clone(), rate_limit))
+                    .layer(Extension(verify_token_limiter)),
+            )
+            .route("/version", get(routes::version))
+            .route("/admin/stats", get(routes::admin::stats))
+            .route("/admin/maintenance", put(routes::admin::set_maintenance_mode))
+            .route("/admin/rotate_key", post(routes::admin::rotate_key))
+            .route("/admin/banned_tokens", get(routes::admin::list_banned_tokens))
+            .route("/admin/banned_tokens/:id", delete(routes::admin::unban_token))
+            .route("/admin/token_cutoff", put(routes::admin::set_token_cutoff))
+            .route("/admin/users/:email/admin", put(routes::admin::set_user_admin))
+            .route("/admin/users/:email/active", put(routes::admin::set_user_active))
+            .route("/admin/users/:email/must_change_password", put(routes::admin::set_must_change_password))
+            .route("/admin/session", get(routes::admin::session))
+            .route("/admin/audit", get(routes::admin::audit));
+
+        // The public key is only meaningful when tokens are signed with RS256
+        if config.jwt_algorithm == Algorithm::RS256 {
+            router = router.route("/.well-known/jwks.json", get(routes::jwks));
+        }
+
+        // Only wraps the routes registered so far, which leaves the health probes and metrics below unlimited
+        if let Some(concurrency_limit) = state.concurrency_limit.clone() {
+            router = router.layer(middleware::from_fn_with_state(concurrency_limit, load_shed));
+        }
+        let router = router
+            .route("/livez", get(routes::livez))
+            .route("/readyz", get(routes::readyz))
+            .route("/metrics", get(routes::metrics))
+            // API responses carry tokens and codes, so no browser or shared cache may keep them.
+            // As a route layer it leaves the static assets from the fallback cacheable.
+            .route_layer(
+                ServiceBuilder::new()
+                    .layer(SetResponseHeaderLayer::overriding(
+                        CACHE_CONTROL,
+                        HeaderValue::from_static(API_CACHE_CONTROL),
+                    ))
+                    // For HTTP/1.0 caches that predate Cache-Control
+                    .layer(SetResponseHeaderLayer::overriding(PRAGMA, HeaderValue::from_static("no-cache"))),
+            )
+            .layer(middleware::from_fn_with_state(state.clone(), sliding_session))
             .with_state(state.clone())
+            .layer(middleware::from_fn(localize_errors))
+            // Outside localization, which needs to read the uncompressed error body
+            .layer(CompressionLayer::new().compress_when(
+                DefaultPredicate::new().and(SizeAbove::new(COMPRESSION_MIN_SIZE_BYTES)),
+            ))
             .layer(cors)
             .layer(
                 TraceLayer::new_for_http()
                     .make_span_with(make_span_with_request_id)
                     .on_request(on_request)
-                    .on_response(on_response),
-            );
+                    .on_response(on_response(config.slow_request_threshold)),
+            )
+            // Outermost so the rate limiter and sliding sessions see it as well as the handlers
+            .layer(Extension(ClientSettings::from_config(config)));
 
         let listener = tokio::net::TcpListener::bind(address).await?;
         let address = listener.local_addr()?.to_string();
-        let server = axum::serve(listener, router);
+        // Connect info gives the rate limiter the peer address when no proxy header is present
+        let server = axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        );
 
         Ok(Self::new(server, address, state))
     }
@@ -85,9 +209,39 @@ impl Application {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+// Each limiter keeps pruning idle IPs for as long as its route exists
+fn rate_limiter(limiter: RateLimiter) -> RateLimiter {
+    limiter.spawn_pruner(RATE_LIMIT_PRUNE_INTERVAL);
+    limiter
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub error: String,
+    /// Stable, machine-readable identifier for the error; clients should branch on this rather than `error`.
+    pub code: String,
+    /// Fields specific to the error, e.g. `attempts_remaining` on a wrong 2FA code; omitted when empty.
+    #[serde(flatten, default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub details: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Envelope shared by every successful JSON response: `{ "data": ..., "message": ... }`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiResponse<T> {
+    pub data: Option<T>,
+    pub message: String,
+}
+
+impl<T> ApiResponse<T> {
+    pub fn new(data: T, message: impl Into<String>) -> Self {
+        Self { data: Some(data), message: message.into() }
+    }
+}
+
+impl ApiResponse<()> {
+    pub fn message(message: impl Into<String>) -> Self {
+        Self { data: None, message: message.into() }
+    }
 }
 
 fn log_error_chain(e: &(dyn Error + 'static)) {
@@ -107,35 +261,116 @@ impl IntoResponse for AuthAPIError {
     fn into_response(self) -> Response {
         log_error_chain(&self);
         
-        let (status, error_message) = match self {
+        let mut details = serde_json::Map::new();
+        if let AuthAPIError::IncorrectTwoFACode { attempts_remaining } = self {
+            details.insert("attempts_remaining".to_owned(), attempts_remaining.into());
+            details.insert("invalidated".to_owned(), (attempts_remaining == 0).into());
+        }
+
+        let (status, code, error_message): (StatusCode, &str, Cow<'static, str>) = match self {
             AuthAPIError::UserAlreadyExists => {
-                (StatusCode::CONFLICT, "User already exists")
+                (StatusCode::CONFLICT, "user_already_exists", "User already exists".into())
+            },
+            AuthAPIError::EmptyField(field) => {
+                (StatusCode::BAD_REQUEST, "empty_field", format!("{} must not be empty", field).into())
+            },
+            AuthAPIError::InvalidField(field) => {
+                (StatusCode::BAD_REQUEST, "invalid_field", format!("{} is invalid", field).into())
             },
             AuthAPIError::InvalidCredentials => {
-                (StatusCode::BAD_REQUEST, "Invalid credentials")
+                (StatusCode::BAD_REQUEST, "invalid_credentials", "Invalid credentials".into())
+            },
+            AuthAPIError::InvalidDisplayName => {
+                (StatusCode::BAD_REQUEST, "invalid_display_name", "Invalid display name".into())
+            },
+            AuthAPIError::UnsupportedLocale => {
+                (StatusCode::BAD_REQUEST, "unsupported_locale", "Locale must be one of: en, es".into())
+            },
+            AuthAPIError::InvalidTokenCutoff => {
+                (StatusCode::BAD_REQUEST, "invalid_token_cutoff", "Token cutoff must not be in the future".into())
+            },
+            AuthAPIError::UnsupportedMediaType => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported_media_type", "Content-Type must be application/json".into())
             },
-            AuthAPIError::IncorrectCredentials => {
-                (StatusCode::UNAUTHORIZED, "Incorrect credentials")
+            AuthAPIError::MissingBody => {
+                (StatusCode::UNPROCESSABLE_ENTITY, "missing_body", "Request body must be a JSON object".into())
+            },
+            AuthAPIError::BatchTooLarge => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "batch_too_large",
+                format!("At most {} tokens per batch", MAX_VERIFY_TOKEN_BATCH_SIZE).into(),
+            ),
+            // Shares the code with IncorrectCredentials so existing clients keep working
+            AuthAPIError::IncorrectCredentials | AuthAPIError::IncorrectTwoFACode { .. } => {
+                (StatusCode::UNAUTHORIZED, "incorrect_credentials", "Incorrect credentials".into())
             },
             AuthAPIError::MissingToken => {
-                (StatusCode::BAD_REQUEST, "Missing token")
+                (StatusCode::BAD_REQUEST, "missing_token", "Missing token".into())
             },
             AuthAPIError::InvalidToken => {
-                (StatusCode::UNAUTHORIZED, "Invalid token")
+                (StatusCode::UNAUTHORIZED, "invalid_token", "Invalid token".into())
+            },
+            AuthAPIError::BannedTokenNotFound => {
+                (StatusCode::NOT_FOUND, "banned_token_not_found", "Banned token not found".into())
+            },
+            AuthAPIError::InvalidAdminKey => {
+                (StatusCode::UNAUTHORIZED, "invalid_admin_key", "Invalid admin key".into())
+            },
+            AuthAPIError::Forbidden => {
+                (StatusCode::FORBIDDEN, "forbidden", "Admin role required".into())
+            },
+            // The session is valid but too old for the operation; clients should send the user
+            // back through login and retry
+            AuthAPIError::ReauthenticationRequired => {
+                (StatusCode::FORBIDDEN, "reauthentication_required", "Please log in again to continue".into())
+            },
+            AuthAPIError::AccountDisabled => {
+                (StatusCode::FORBIDDEN, "account_disabled", "Account is disabled".into())
+            },
+            // Clients should send the user to `/change_password`, then log in again
+            AuthAPIError::PasswordChangeRequired => {
+                (StatusCode::FORBIDDEN, "password_change_required", "Password must be changed before logging in".into())
+            },
+            AuthAPIError::UserNotFound => {
+                (StatusCode::NOT_FOUND, "user_not_found", "User not found".into())
+            },
+            AuthAPIError::NotFound => {
+                (StatusCode::NOT_FOUND, "not_found", "Not found".into())
+            },
+            AuthAPIError::TooManyRequests => {
+                (StatusCode::TOO_MANY_REQUESTS, "too_many_requests", "Too many requests".into())
+            },
+            AuthAPIError::MaintenanceMode => {
+                (StatusCode::SERVICE_UNAVAILABLE, "maintenance_mode", "Service is under maintenance, try again later".into())
+            },
+            AuthAPIError::Overloaded => {
+                (StatusCode::SERVICE_UNAVAILABLE, "overloaded", "Service is busy, try again shortly".into())
+            },
+            // Usually a transient provider outage, so tell the client it's safe to retry
+            AuthAPIError::EmailDeliveryFailed(_) => {
+                (StatusCode::SERVICE_UNAVAILABLE, "email_delivery_failed", "Could not send verification email, try again".into())
             },
             AuthAPIError::UnexpectedError(_) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, "Unexpected error")
+                (StatusCode::INTERNAL_SERVER_ERROR, "unexpected_error", "Unexpected error".into())
             },
         };
 
         let body = Json(ErrorResponse {
-            error: error_message.to_string(),
+            error: error_message.into_owned(),
+            code: code.to_string(),
+            details,
         });
 
-        (status, body).into_response()
+        let mut response = (status, body).into_response();
+        response.extensions_mut().insert(ErrorCode(code));
+        response
     }
 }
 
+pub async fn run_migrations(pool: &PgPool) -> Result<(), sqlx::migrate::MigrateError> {
+    sqlx::migrate!().run(pool).await
+}
+
 pub async fn get_postgres_pool(url: &str) -> Result<PgPool, sqlx::Error> {
     PgPoolOptions::new()
         .max_connections(5)
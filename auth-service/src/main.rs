@@ -1,47 +1,153 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
+use clap::{Parser, Subcommand};
 use tokio::sync::RwLock;
 use sqlx::PgPool;
 use reqwest::Client;
+use redis::aio::ConnectionManager;
+use color_eyre::eyre::{Context, Result};
 use secrecy::{ExposeSecret, Secret};
 use auth_service::{
     Application, 
-    app_state::AppState, 
+    app_state::{AppState, EmailClientType},
     services::data_stores::{  
+        PostgresAuditLog,
+        PostgresTwoFaDeliveryLog,
         PostgresUserStore,
+        RedisAdminKeyStore,
         RedisBannedTokenStore,
+        RedisLoginFailureStore,
+        RedisRevocationNotifier,
+        RedisTrustedDeviceStore,
         RedisTwoFACodeStore,
     },
-    services::postmark_email_client::PostmarkEmailClient,
+    services::{
+        argon2_password_hasher::Argon2PasswordHasher,
+        circuit_breaker_email_client::CircuitBreakerEmailClient,
+        health_checks::{PostgresHealthCheck, RedisHealthCheck},
+        mock_email_client::MockEmailClient,
+        postmark_email_client::PostmarkEmailClient,
+        smtp_email_client::SmtpEmailClient,
+    },
     domain::email::Email,
-    utils::{constants::{DATABASE_URL, REDIS_HOST_NAME, POSTMARK_AUTH_TOKEN, prod}, tracing::init_tracing},
+    utils::{
+        config::{self, Config, EmailSettings, EnvVars},
+        constants::prod,
+        startup::{check_assets_dir, init_concurrently},
+        tracing::{init_tracing, set_log_pii},
+    },
     get_postgres_pool,
     get_redis_client,
+    run_migrations,
 };
 
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run migrations and serve the API (the default)
+    Serve,
+    /// Apply pending database migrations and exit, e.g. from an init container
+    Migrate,
+}
+
 #[tokio::main]
 async fn main() {
-    init_tracing();
+    init_tracing().expect("Failed to initialize tracing");
+
+    match Cli::parse().command.unwrap_or(Command::Serve) {
+        Command::Serve => serve().await,
+        Command::Migrate => migrate().await,
+    }
+}
+
+// Only needs the database, so it doesn't require the rest of the configuration
+async fn migrate() {
+    let database_url = match config::database_url(&EnvVars::from_env()) {
+        Ok(database_url) => database_url,
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    tracing::info!("Running database migrations...");
+    configure_postgresql(&database_url).await.expect("Failed to set up Postgres");
+    tracing::info!("Database migrations complete");
+}
+
+async fn serve() {
+    // Fail fast, listing every missing or invalid variable, instead of on the first request
+    // that reads a bad one
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    set_log_pii(config.log_pii);
+    check_assets_dir(Path::new(&config.assets_dir), config.assets_dir_required)
+        .expect("Failed to find the assets directory");
     
     tracing::info!("Starting application...");
     
-    let pg_pool = configure_postgresql().await;
-    let redis_connection = Arc::new(RwLock::new(configure_redis()));
-    
-    let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
-    let banned_token_store = Arc::new(RwLock::new(RedisBannedTokenStore::new(
-        redis_connection.clone(),
-    )));
-    let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_connection)));
-    let email_client = Arc::new(configure_email_client());
+    // Migrations are the slow part of startup, so Redis connects in the meantime
+    let (pg_pool, (redis_client, redis_connection_manager)) =
+        init_concurrently(configure_postgresql(&config.database_url), configure_redis(&config.redis_host_name))
+            .await
+            .expect("Failed to initialize data stores");
     
-    let app_state = AppState::new(
-        user_store,
-        banned_token_store,
-        two_fa_code_store,
-        email_client,
+    let password_hasher = Arc::new(
+        Argon2PasswordHasher::new(config.password_pepper.clone(), config.password_legacy_pre_hash)
+            .with_params(config.argon2_params.clone()),
+    );
+    let user_store = Arc::new(PostgresUserStore::new(pg_pool.clone(), password_hasher));
+    let banned_token_store = Arc::new(RwLock::new(
+        RedisBannedTokenStore::new(redis_connection_manager.clone()).with_key_prefix(config.redis_key_prefix.as_str()),
+    ));
+    let two_fa_code_store = Arc::new(RwLock::new(
+        RedisTwoFACodeStore::new(redis_connection_manager.clone())
+            .with_key_prefix(config.redis_key_prefix.as_str()),
+    ));
+    let trusted_device_store = Arc::new(
+        RedisTrustedDeviceStore::new(redis_connection_manager.clone())
+            .with_key_prefix(config.redis_key_prefix.as_str()),
+    );
+    let login_failure_store = Arc::new(
+        RedisLoginFailureStore::new(redis_connection_manager.clone())
+            .with_key_prefix(config.redis_key_prefix.as_str()),
     );
+    let admin_key_store = Arc::new(
+        RedisAdminKeyStore::new(redis_connection_manager.clone())
+            .with_key_prefix(config.redis_key_prefix.as_str()),
+    );
+    let revocation_notifier = Arc::new(
+        RedisRevocationNotifier::new(redis_client, redis_connection_manager.clone())
+            .with_key_prefix(config.redis_key_prefix.as_str()),
+    );
+    let email_client = configure_email_client(&config);
+    
+    // ADMIN_API_KEY only bootstraps admin access; a key rotated in through the API takes over
+    let mut app_state = AppState::new(&config, user_store, banned_token_store, email_client)
+    .with_two_fa_code_store(two_fa_code_store)
+    .with_trusted_device_store(trusted_device_store)
+    .with_login_failure_store(login_failure_store)
+    .with_admin_key_store(admin_key_store)
+    .with_revocation_notifier(revocation_notifier)
+    .with_health_check(Arc::new(PostgresHealthCheck::new(pg_pool.clone())))
+    .with_health_check(Arc::new(RedisHealthCheck::new(redis_connection_manager)));
+    if config.two_fa_delivery_log_enabled {
+        app_state = app_state.with_two_fa_delivery_log(Arc::new(PostgresTwoFaDeliveryLog::new(pg_pool.clone())));
+    }
+    if config.audit_log_enabled {
+        app_state = app_state.with_audit_log(Arc::new(PostgresAuditLog::new(pg_pool)));
+    }
     
-    let app = match Application::build(app_state, prod::APP_ADDRESS).await {
+    let app = match Application::build(app_state, &config, prod::APP_ADDRESS).await {
         Ok(app) => {
             tracing::info!("Application built successfully. Listening on {}", app.address);
             app
@@ -58,7 +164,31 @@ async fn main() {
     }
 }
 
-fn configure_email_client() -> PostmarkEmailClient {
+fn configure_email_client(config: &Config) -> EmailClientType {
+    tracing::info!("Using {:?} email provider", config.email.provider());
+    match &config.email {
+        EmailSettings::Postmark { auth_token } => Arc::new(with_circuit_breaker(
+            config,
+            configure_postmark_email_client(auth_token.clone()),
+        )),
+        EmailSettings::Smtp { host, port, username, password } => Arc::new(with_circuit_breaker(
+            config,
+            configure_smtp_email_client(host, *port, username.clone().map(|username| (username, password.clone()))),
+        )),
+        EmailSettings::Mock => Arc::new(MockEmailClient),
+    }
+}
+
+// A provider that is down would otherwise be retried, and waited on, by every 2FA login
+fn with_circuit_breaker<C>(config: &Config, email_client: C) -> CircuitBreakerEmailClient<C> {
+    CircuitBreakerEmailClient::new(
+        email_client,
+        config.email_circuit_breaker_failure_threshold,
+        config.email_circuit_breaker_cooldown,
+    )
+}
+
+fn configure_postmark_email_client(auth_token: Secret<String>) -> PostmarkEmailClient {
     let sender_email = Email::parse(Secret::new(prod::email_client::SENDER.to_owned()))
         .expect("Invalid sender email address.");
     let timeout = prod::email_client::TIMEOUT;
@@ -70,27 +200,45 @@ fn configure_email_client() -> PostmarkEmailClient {
     PostmarkEmailClient::new(
         prod::email_client::BASE_URL.to_owned(),
         sender_email,
-        POSTMARK_AUTH_TOKEN.clone(),
+        auth_token,
         http_client,
     )
 }
 
-async fn configure_postgresql() -> PgPool {
-    let pg_pool = get_postgres_pool(&DATABASE_URL)
+fn configure_smtp_email_client(
+    host: &str,
+    port: u16,
+    credentials: Option<(String, Secret<String>)>,
+) -> SmtpEmailClient {
+    let sender_email = Email::parse(Secret::new(prod::email_client::SENDER.to_owned()))
+        .expect("Invalid sender email address.");
+
+    SmtpEmailClient::new(host, port, credentials, sender_email)
+        .expect("Failed to build SMTP email client")
+}
+
+async fn configure_postgresql(database_url: &Secret<String>) -> Result<PgPool> {
+    let pg_pool = get_postgres_pool(database_url.expose_secret())
         .await
-        .expect("Failed to create Postgres connection pool!");
+        .wrap_err("Failed to create Postgres connection pool")?;
 
-    sqlx::migrate!()
-        .run(&pg_pool)
+    run_migrations(&pg_pool)
         .await
-        .expect("Failed to run migrations");
+        .wrap_err("Failed to run migrations")?;
 
-    pg_pool
+    Ok(pg_pool)
 }
 
-fn configure_redis() -> redis::Connection {
-    get_redis_client(REDIS_HOST_NAME.expose_secret().to_owned())
-        .expect("Failed to get Redis client")
-        .get_connection()
-        .expect("Failed to get Redis connection")
-}
\ No newline at end of file
+// The Redis stores share one multiplexed connection manager, which reconnects on its own. The
+// client is kept for pub/sub, which needs a dedicated connection.
+async fn configure_redis(redis_host_name: &Secret<String>) -> Result<(redis::Client, ConnectionManager)> {
+    let client = get_redis_client(redis_host_name.expose_secret().to_owned())
+        .wrap_err("Failed to get Redis client")?;
+
+    let connection_manager = client
+        .get_connection_manager()
+        .await
+        .wrap_err("Failed to get Redis connection manager")?;
+
+    Ok((client, connection_manager))
+}
@@ -0,0 +1,122 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Deserializer, Serialize};
+use secrecy::ExposeSecret;
+use crate::{
+    app_state::AppState,
+    ApiResponse,
+    domain::{
+        data_stores::UserStoreError,
+        display_name::DisplayName,
+        error::AuthAPIError,
+        locale::Locale,
+        user::User,
+    },
+    utils::extractors::AuthenticatedUser,
+};
+
+// The caller's own, non-sensitive view of their account
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserProfile {
+    pub email: String,
+    #[serde(rename = "requires2FA")]
+    pub requires_2fa: bool,
+    #[serde(rename = "displayName")]
+    pub display_name: Option<String>,
+    // The stored preference; null when emails follow the client's `Accept-Language`
+    pub locale: Option<String>,
+}
+
+impl From<&User> for UserProfile {
+    fn from(user: &User) -> Self {
+        Self {
+            email: user.email.as_ref().expose_secret().to_owned(),
+            requires_2fa: user.requires_2fa,
+            display_name: user.display_name.as_ref().map(|name| name.as_ref().to_owned()),
+            locale: user.locale.map(|locale| locale.tag().to_owned()),
+        }
+    }
+}
+
+pub type ProfileResponse = ApiResponse<UserProfile>;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateProfileRequest {
+    // Fields left out are kept; null clears them
+    #[serde(rename = "displayName", default, deserialize_with = "present")]
+    pub display_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    pub locale: Option<Option<String>>,
+}
+
+// Only called for fields in the body, so a null comes back as `Some(None)` rather than
+// looking the same as a missing field
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+#[tracing::instrument(name = "Me", skip_all)]
+pub async fn me(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user = user.load_user(&state).await?;
+    Ok((StatusCode::OK, Json(ProfileResponse::new(UserProfile::from(&user), "Profile retrieved"))))
+}
+
+#[tracing::instrument(name = "Update profile", skip_all)]
+pub async fn update_profile(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Json(request): Json<UpdateProfileRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    // Changes what the account's emails say and who they appear to come from, so a session
+    // kept alive long after its login isn't enough
+    user.require_fresh_session(state.fresh_session_max_age)?;
+
+    // Everything is validated before anything is written, so a rejected request changes nothing
+    let display_name = request
+        .display_name
+        .map(|name| name.map(DisplayName::parse).transpose())
+        .transpose()
+        .map_err(|e| {
+            tracing::warn!("Rejected display name: {}", e);
+            AuthAPIError::InvalidDisplayName
+        })?;
+    let locale = request
+        .locale
+        .map(|tag| tag.map(|tag| Locale::parse(&tag).ok_or(AuthAPIError::UnsupportedLocale)).transpose())
+        .transpose()?;
+
+    if let Some(display_name) = display_name {
+        state
+            .user_store
+            .set_display_name(&user.email, display_name)
+            .await
+            .map_err(profile_error)?;
+    }
+    if let Some(locale) = locale {
+        state
+            .user_store
+            .set_locale(&user.email, locale)
+            .await
+            .map_err(profile_error)?;
+    }
+
+    let user = user.load_user(&state).await?;
+    Ok((StatusCode::OK, Json(ProfileResponse::new(UserProfile::from(&user), "Profile updated"))))
+}
+
+fn profile_error(e: UserStoreError) -> AuthAPIError {
+    match e {
+        UserStoreError::UserNotFound => {
+            tracing::warn!("Token belongs to a user that no longer exists");
+            AuthAPIError::InvalidToken
+        }
+        e => AuthAPIError::UnexpectedError(e.into()),
+    }
+}
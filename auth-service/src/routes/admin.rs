@@ -0,0 +1,416 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::header::CACHE_CONTROL,
+    response::IntoResponse,
+    Json,
+};
+use chrono::Utc;
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use secrecy::{ExposeSecret, Secret};
+use crate::{
+    app_state::AppState,
+    ApiResponse,
+    domain::{
+        data_stores::{admin_key_hash, ALL_SESSIONS_REVOKED, AuditEvent, AuditEventType, AuditQuery, BannedTokenEntry, UserStoreError},
+        email::Email,
+        error::AuthAPIError,
+    },
+    utils::{
+        constants::{DEFAULT_AUDIT_PAGE_SIZE, MAX_AUDIT_PAGE_SIZE},
+        extractors::{AdminGuard, AdminUser},
+    },
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminStatsResponse {
+    pub total_users: u64,
+}
+
+#[tracing::instrument(name = "Admin stats", skip_all)]
+pub async fn stats(
+    _admin: AdminGuard,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    tracing::debug!("Counting users");
+    let total_users = state.user_store.count_users().await
+        .map_err(|e| {
+            tracing::error!("Failed to count users: {:?}", e);
+            AuthAPIError::UnexpectedError(e.into())
+        })?;
+
+    Ok(Json(ApiResponse::new(AdminStatsResponse { total_users }, "Stats retrieved")))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaintenanceRequest {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MaintenanceResponse {
+    pub enabled: bool,
+}
+
+#[tracing::instrument(name = "Admin set maintenance mode", skip(_admin, state))]
+pub async fn set_maintenance_mode(
+    _admin: AdminGuard,
+    State(state): State<AppState>,
+    Json(request): Json<MaintenanceRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    state.maintenance_mode.set(request.enabled);
+    tracing::warn!("Maintenance mode {}", if request.enabled { "enabled" } else { "disabled" });
+
+    Ok(Json(ApiResponse::new(
+        MaintenanceResponse { enabled: request.enabled },
+        "Maintenance mode updated",
+    )))
+}
+
+#[tracing::instrument(name = "Admin list banned tokens", skip_all)]
+pub async fn list_banned_tokens(
+    _admin: AdminGuard,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    tracing::debug!("Listing banned tokens");
+    let banned_tokens: Vec<BannedTokenEntry> = state.banned_token_store.read().await.list_tokens().await
+        .map_err(|e| {
+            tracing::error!("Failed to list banned tokens: {:?}", e);
+            AuthAPIError::UnexpectedError(e.into())
+        })?;
+
+    Ok(Json(ApiResponse::new(banned_tokens, "Banned tokens retrieved")))
+}
+
+#[tracing::instrument(name = "Admin unban token", skip(_admin, state))]
+pub async fn unban_token(
+    _admin: AdminGuard,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    tracing::debug!("Removing banned token");
+    let removed = state.banned_token_store.write().await.remove_token(&id).await
+        .map_err(|e| {
+            tracing::error!("Failed to remove banned token: {:?}", e);
+            AuthAPIError::UnexpectedError(e.into())
+        })?;
+
+    if !removed {
+        tracing::warn!("No banned token with id {}", id);
+        return Err(AuthAPIError::BannedTokenNotFound);
+    }
+
+    tracing::info!("Banned token removed");
+    Ok(Json(ApiResponse::message("Token unbanned")))
+}
+
+// Unix seconds; defaults to now
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenCutoffRequest {
+    #[serde(default)]
+    pub not_valid_before: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenCutoffResponse {
+    pub not_valid_before: usize,
+}
+
+// Revokes every token issued before the cutoff, e.g. after a signing secret leaks. Setting an
+// earlier cutoff relaxes a previous one. Other instances may accept a token they verified in the
+// last few seconds until their verify-token cache expires.
+#[tracing::instrument(name = "Admin set token cutoff", skip(_admin, state))]
+pub async fn set_token_cutoff(
+    _admin: AdminGuard,
+    State(state): State<AppState>,
+    Json(request): Json<TokenCutoffRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let now: usize = Utc::now()
+        .timestamp()
+        .try_into()
+        .map_err(AuthAPIError::unexpected)?;
+    let not_valid_before = request.not_valid_before.unwrap_or(now);
+    // A future cutoff would also reject every token issued until then, locking everyone out
+    if not_valid_before > now {
+        return Err(AuthAPIError::InvalidTokenCutoff);
+    }
+
+    // Under the write lock, like logout, so no in-flight verification caches a token after the
+    // cache is cleared
+    let banned_token_store = state.banned_token_store.write().await;
+    banned_token_store.set_not_valid_before(not_valid_before).await
+        .map_err(|e| {
+            tracing::error!("Failed to set token cutoff: {:?}", e);
+            AuthAPIError::UnexpectedError(e.into())
+        })?;
+    state.verified_token_cache.clear();
+    drop(banned_token_store);
+    if let Err(e) = state.revocation_notifier.publish(ALL_SESSIONS_REVOKED).await {
+        tracing::warn!("Failed to announce token cutoff: {:?}", e);
+    }
+
+    Ok(Json(ApiResponse::new(
+        TokenCutoffResponse { not_valid_before },
+        "Token cutoff updated",
+    )))
+}
+
+const ADMIN_KEY_LENGTH: usize = 48;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateAdminKeyResponse {
+    pub admin_key: String,
+}
+
+#[tracing::instrument(name = "Admin rotate key", skip_all)]
+pub async fn rotate_key(
+    _admin: AdminGuard,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let admin_key = Secret::new(
+        rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(ADMIN_KEY_LENGTH)
+            .map(char::from)
+            .collect::<String>(),
+    );
+
+    // Only the hash is stored, so this response is the one chance to read the new key
+    state.admin_key_store.set_key_hash(admin_key_hash(&admin_key)).await
+        .map_err(|e| {
+            tracing::error!("Failed to store admin key hash: {:?}", e);
+            AuthAPIError::UnexpectedError(e.into())
+        })?;
+    tracing::warn!("Admin API key rotated");
+
+    Ok((
+        [(CACHE_CONTROL, "no-store")],
+        Json(ApiResponse::new(
+            RotateAdminKeyResponse { admin_key: admin_key.expose_secret().to_owned() },
+            "Admin key rotated",
+        )),
+    ))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SetAdminRequest {
+    pub is_admin: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetAdminResponse {
+    pub email: String,
+    pub is_admin: bool,
+}
+
+// Tokens already issued keep their role claim until they expire, but `AdminUser` also checks the
+// stored flag, so a revoke is enforced immediately
+#[tracing::instrument(name = "Admin set user role", skip(_admin, state, email))]
+pub async fn set_user_admin(
+    _admin: AdminGuard,
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    Json(request): Json<SetAdminRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse_with(Secret::new(email), state.feature_flags.normalize_plus_addressing()).map_err(|_| AuthAPIError::InvalidField("email"))?;
+
+    state.user_store.set_admin(&email, request.is_admin).await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::UserNotFound,
+            e => {
+                tracing::error!("Failed to update admin flag: {:?}", e);
+                AuthAPIError::UnexpectedError(e.into())
+            }
+        })?;
+    tracing::warn!("Admin role {}", if request.is_admin { "granted" } else { "revoked" });
+
+    Ok(Json(ApiResponse::new(
+        SetAdminResponse { email: email.as_ref().expose_secret().to_owned(), is_admin: request.is_admin },
+        "Admin role updated",
+    )))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SetActiveRequest {
+    pub is_active: bool,
+    // Only applies when disabling
+    #[serde(default = "default_revoke_sessions")]
+    pub revoke_sessions: bool,
+}
+
+fn default_revoke_sessions() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetActiveResponse {
+    pub email: String,
+    pub is_active: bool,
+}
+
+// Suspends or restores an account without deleting it. Disabling blocks new logins; revoking also
+// rejects every token the user already holds, via a per-user token cutoff. Re-enabling doesn't
+// bring those sessions back.
+#[tracing::instrument(name = "Admin set user active", skip(_admin, state, email))]
+pub async fn set_user_active(
+    _admin: AdminGuard,
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    Json(request): Json<SetActiveRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse_with(Secret::new(email), state.feature_flags.normalize_plus_addressing()).map_err(|_| AuthAPIError::InvalidField("email"))?;
+
+    state.user_store.set_active(&email, request.is_active).await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::UserNotFound,
+            e => {
+                tracing::error!("Failed to update active flag: {:?}", e);
+                AuthAPIError::UnexpectedError(e.into())
+            }
+        })?;
+    tracing::warn!("Account {}", if request.is_active { "enabled" } else { "disabled" });
+
+    if !request.is_active && request.revoke_sessions {
+        state.revoke_user_sessions(&email).await
+            .map_err(|e| {
+                tracing::error!("Failed to revoke user sessions: {:?}", e);
+                AuthAPIError::UnexpectedError(e.into())
+            })?;
+        tracing::warn!("Sessions revoked for disabled account");
+    }
+
+    Ok(Json(ApiResponse::new(
+        SetActiveResponse { email: email.as_ref().expose_secret().to_owned(), is_active: request.is_active },
+        "Account status updated",
+    )))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SetMustChangePasswordRequest {
+    pub must_change_password: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetMustChangePasswordResponse {
+    pub email: String,
+    pub must_change_password: bool,
+}
+
+// Flags a compromised account: login is refused until the user picks a new password through
+// `/change_password`. Sessions the attacker may already hold are revoked along with the flag.
+#[tracing::instrument(name = "Admin set must change password", skip(_admin, state, email))]
+pub async fn set_must_change_password(
+    _admin: AdminGuard,
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    Json(request): Json<SetMustChangePasswordRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse_with(Secret::new(email), state.feature_flags.normalize_plus_addressing()).map_err(|_| AuthAPIError::InvalidField("email"))?;
+
+    state.user_store.set_must_change_password(&email, request.must_change_password).await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::UserNotFound,
+            e => {
+                tracing::error!("Failed to update must_change_password flag: {:?}", e);
+                AuthAPIError::UnexpectedError(e.into())
+            }
+        })?;
+    tracing::warn!("Password change {}", if request.must_change_password { "required" } else { "no longer required" });
+
+    if request.must_change_password {
+        state.revoke_user_sessions(&email).await
+            .map_err(|e| {
+                tracing::error!("Failed to revoke user sessions: {:?}", e);
+                AuthAPIError::UnexpectedError(e.into())
+            })?;
+    }
+
+    Ok(Json(ApiResponse::new(
+        SetMustChangePasswordResponse {
+            email: email.as_ref().expose_secret().to_owned(),
+            must_change_password: request.must_change_password,
+        },
+        "Password change requirement updated",
+    )))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminSessionResponse {
+    pub email: String,
+}
+
+// Lets a frontend check whether the signed-in user may see admin tools
+#[tracing::instrument(name = "Admin session", skip_all)]
+pub async fn session(AdminUser(user): AdminUser) -> impl IntoResponse {
+    Json(ApiResponse::new(
+        AdminSessionResponse { email: user.email.as_ref().expose_secret().to_owned() },
+        "Admin session",
+    ))
+}
+
+// Timestamps are Unix seconds; `cursor` is the `nextCursor` of the previous page
+#[derive(Debug, Deserialize)]
+pub struct AuditQueryParams {
+    pub email: Option<String>,
+    pub event_type: Option<AuditEventType>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub cursor: Option<i64>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditPage {
+    pub events: Vec<AuditEvent>,
+    // Absent on the last page
+    #[serde(rename = "nextCursor", default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<i64>,
+}
+
+#[tracing::instrument(name = "Admin query audit log", skip_all)]
+pub async fn audit(
+    _admin: AdminGuard,
+    State(state): State<AppState>,
+    Query(params): Query<AuditQueryParams>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let Some(audit_log) = &state.audit_log else {
+        tracing::warn!("Audit log queried but not configured");
+        return Err(AuthAPIError::NotFound);
+    };
+
+    let email = params
+        .email
+        .map(|email| Email::parse_with(Secret::new(email), state.feature_flags.normalize_plus_addressing()))
+        .transpose()
+        .map_err(|_| AuthAPIError::InvalidField("email"))?;
+    let limit = params.limit.unwrap_or(DEFAULT_AUDIT_PAGE_SIZE).clamp(1, MAX_AUDIT_PAGE_SIZE);
+
+    // One extra row tells whether another page follows without a separate count
+    let query = AuditQuery {
+        email,
+        event_type: params.event_type,
+        from: params.from,
+        to: params.to,
+        before_id: params.cursor,
+        limit: limit + 1,
+    };
+    let mut events = audit_log.query(&query).await
+        .map_err(|e| {
+            tracing::error!("Failed to query audit log: {:?}", e);
+            AuthAPIError::UnexpectedError(e.into())
+        })?;
+
+    let next_cursor = if events.len() > limit as usize {
+        events.truncate(limit as usize);
+        events.last().map(|event| event.id)
+    } else {
+        None
+    };
+
+    Ok(Json(ApiResponse::new(AuditPage { events, next_cursor }, "Audit events retrieved")))
+}
@@ -0,0 +1,121 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use serde::Deserialize;
+use secrecy::Secret;
+use crate::{
+    app_state::AppState,
+    ApiResponse,
+    AuthAPIError,
+    domain::{
+        data_stores::{AuditEventType, TwoFACode},
+        email::Email,
+        password::Password,
+    },
+    routes::{
+        login::{check_password, handle_2fa},
+        verify_2fa::consume_two_fa_code,
+    },
+    utils::{
+        extractors::ClientContext,
+        validation::{require_non_empty, Validate, ValidatedJson},
+    },
+};
+
+// Authenticated by the current password rather than a session, since an account that must
+// change its password can't log in to get one. Accounts with 2FA also need a code, so a leaked
+// password alone can't be used to take the account over.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChangePasswordRequest {
+    pub email: Secret<String>,
+    pub password: Secret<String>,
+    #[serde(rename = "newPassword", alias = "new_password")]
+    pub new_password: Secret<String>,
+    // Required for accounts with 2FA; sent without one, the request starts a 2FA challenge
+    #[serde(rename = "2FACode", alias = "two_fa_code", default)]
+    pub two_fa_code: Option<Secret<String>>,
+}
+
+impl Validate for ChangePasswordRequest {
+    fn validate(&self) -> Result<(), AuthAPIError> {
+        require_non_empty("email", &self.email)?;
+        require_non_empty("password", &self.password)?;
+        require_non_empty("newPassword", &self.new_password)?;
+        match &self.two_fa_code {
+            Some(two_fa_code) => require_non_empty("2FACode", two_fa_code),
+            None => Ok(()),
+        }
+    }
+}
+
+// Also revokes the account's existing sessions, so whoever knew the old password is logged out.
+// The user logs in again with the new password afterwards.
+#[tracing::instrument(name = "Change password", skip_all)]
+pub async fn change_password(
+    State(state): State<AppState>,
+    client: ClientContext,
+    jar: CookieJar,
+    ValidatedJson(request): ValidatedJson<ChangePasswordRequest>,
+) -> Result<Response, AuthAPIError> {
+    let email = Email::parse_with(request.email, state.feature_flags.normalize_plus_addressing())
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let password = Password::parse(request.password)
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let new_password = Password::parse(request.new_password)
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let two_fa_code = request
+        .two_fa_code
+        .map(TwoFACode::parse_submitted)
+        .transpose()
+        .map_err(|e| {
+            tracing::warn!("Invalid 2FA code: {:?}", e);
+            AuthAPIError::InvalidCredentials
+        })?;
+
+    // Keeping the old password would defeat a forced reset
+    if new_password == password {
+        tracing::warn!("New password is the same as the current one");
+        return Err(AuthAPIError::InvalidCredentials);
+    }
+
+    check_password(&state, &email, &password).await?;
+
+    let user = state.user_store.get_user(&email).await
+        .map_err(|e| {
+            tracing::error!("Failed to get user: {:?}", e);
+            AuthAPIError::UnexpectedError(e.into())
+        })?;
+    if !user.is_active {
+        tracing::warn!("Password change for disabled account");
+        return Err(AuthAPIError::AccountDisabled);
+    }
+
+    // Unlike login, a trusted device doesn't skip the code
+    if user.requires_2fa {
+        let Some(two_fa_code) = two_fa_code else {
+            let (jar, response) = handle_2fa(&email, user.locale.unwrap_or(client.locale), &state, jar).await?;
+            return Ok((jar, response).into_response());
+        };
+        tracing::debug!("Verifying 2FA code");
+        consume_two_fa_code(&state, &email, None, &two_fa_code).await?;
+    }
+
+    state.user_store.update_password(&email, new_password).await
+        .map_err(|e| {
+            tracing::error!("Failed to update password: {:?}", e);
+            AuthAPIError::UnexpectedError(e.into())
+        })?;
+    state.revoke_user_sessions(&email).await
+        .map_err(|e| {
+            tracing::error!("Failed to revoke user sessions: {:?}", e);
+            AuthAPIError::UnexpectedError(e.into())
+        })?;
+
+    tracing::info!("Password changed");
+    state.record_audit_event(&email, AuditEventType::PasswordChanged).await;
+    Ok(Json(ApiResponse::message("Password changed")).into_response())
+}
@@ -0,0 +1,178 @@
+use std::{convert::Infallible, ops::Deref};
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::{future, stream, Stream, StreamExt};
+use secrecy::ExposeSecret;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use crate::{
+    app_state::AppState,
+    domain::data_stores::{banned_token_id, user_revocation_id, ALL_SESSIONS_REVOKED},
+    utils::{
+        auth::{validate_tokens_with_cache, TokenRejection},
+        extractors::AuthenticatedUser,
+    },
+};
+
+pub const LOGOUT_EVENT: &str = "logout";
+
+// A stream of session events for the caller's token. It sends a single `logout` event once the
+// token is revoked, e.g. by a logout in another tab, an admin disabling the account or the token
+// cutoff, and then ends; clients should drop the session when they receive it.
+#[tracing::instrument(name = "Session events", skip_all)]
+pub async fn events(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    // Subscribed before the response is returned, so a revocation right after the stream opens
+    // isn't missed
+    let revocations = state.revocation_notifier.subscribe();
+    let logout = stream::once(wait_for_revocation(state, user, revocations))
+        .filter_map(future::ready);
+
+    Sse::new(logout).keep_alive(KeepAlive::default())
+}
+
+// `None` when no further revocations can arrive, which ends the stream without an event
+async fn wait_for_revocation(
+    state: AppState,
+    user: AuthenticatedUser,
+    mut revocations: Receiver<String>,
+) -> Option<Result<Event, Infallible>> {
+    let token_id = banned_token_id(&user.token);
+    let user_id = user_revocation_id(user.email.as_ref().expose_secret());
+    loop {
+        match revocations.recv().await {
+            Ok(revoked) if revoked == token_id => break,
+            // Cutoffs may not cover this token, e.g. an admin cutoff set in the past, so ask
+            // the store
+            Ok(revoked) if revoked == user_id || revoked == ALL_SESSIONS_REVOKED => {
+                if is_revoked(&state, &user).await {
+                    break;
+                }
+            }
+            Ok(_) => continue,
+            // Missed notifications may include ours
+            Err(RecvError::Lagged(missed)) => {
+                tracing::debug!("Session event subscriber missed {} revocations", missed);
+                if is_revoked(&state, &user).await {
+                    break;
+                }
+            }
+            Err(RecvError::Closed) => return None,
+        }
+    }
+
+    tracing::info!("Notifying subscriber of session revocation");
+    Some(Ok(Event::default()
+        .event(LOGOUT_EVENT)
+        .data(r#"{"message":"Session revoked"}"#)))
+}
+
+// Whether the token is banned or issued before a cutoff. A failed lookup counts as not revoked;
+// the subscriber keeps waiting rather than logging the session out over a store error.
+async fn is_revoked(state: &AppState, user: &AuthenticatedUser) -> bool {
+    let banned_token_store = state.banned_token_store.read().await;
+    // The revocation may come from another instance, which only evicts its own cache, so this
+    // instance's entry for the token can't be trusted anymore
+    state.verified_token_cache.invalidate(&user.jti);
+    let results = validate_tokens_with_cache(
+        &state.jwt,
+        vec![user.token.clone()],
+        banned_token_store.deref(),
+        &state.verified_token_cache,
+    )
+    .await;
+    match results.as_deref() {
+        Ok([Err(TokenRejection::Banned)]) => true,
+        Ok(_) => false,
+        Err(e) => {
+            tracing::warn!("Failed to check token after revocation: {:?}", e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+    use chrono::Utc;
+    use secrecy::Secret;
+    use tokio::sync::RwLock;
+    use super::*;
+    use crate::{
+        domain::{data_stores::BannedTokenStore, email::Email, user::Role},
+        services::{
+            data_stores::{
+                hashmap_user_store::HashmapUserStore,
+                hashset_banned_token_store::HashsetBannedTokenStore,
+            },
+            mock_email_client::MockEmailClient,
+        },
+        utils::{
+            auth::{decode_token, generate_auth_cookie, validate_token_with_cache},
+            config::Config,
+        },
+    };
+
+    #[tokio::test]
+    async fn notices_revocation_of_a_token_cached_before_it() {
+        let state = AppState::new(
+            &Config::for_tests(),
+            Arc::new(HashmapUserStore::default()),
+            Arc::new(RwLock::new(HashsetBannedTokenStore::new())),
+            Arc::new(MockEmailClient),
+        );
+        let email = Email::parse(Secret::new("user@example.com".to_owned())).unwrap();
+        let cookie = generate_auth_cookie(&state.jwt, &email, None, Role::User, false).await.unwrap();
+        let token = Secret::new(cookie.value().to_owned());
+        let claims = decode_token(&state.jwt, &token).unwrap();
+
+        // A `/verify_token` call on this instance caches the token
+        validate_token_with_cache(
+            &state.jwt,
+            &token,
+            state.banned_token_store.read().await.deref(),
+            &state.verified_token_cache,
+        )
+        .await
+        .unwrap();
+        assert!(state.verified_token_cache.contains(&claims.jti));
+
+        let user = AuthenticatedUser {
+            email,
+            token,
+            jti: claims.jti.clone(),
+            role: Role::User,
+            auth_time: claims.auth_time,
+        };
+        let subscriber = tokio::spawn(wait_for_revocation(
+            state.clone(),
+            user,
+            state.revocation_notifier.subscribe(),
+        ));
+
+        // Another instance revokes the user's sessions: the shared store and the notification
+        // change, but this instance's cache doesn't
+        let cutoff = Utc::now().timestamp_millis() as usize + 1000;
+        state
+            .banned_token_store
+            .read()
+            .await
+            .set_user_not_valid_before(&claims.sub, cutoff)
+            .await
+            .unwrap();
+        state
+            .revocation_notifier
+            .publish(&user_revocation_id(&claims.sub))
+            .await
+            .unwrap();
+
+        let event = tokio::time::timeout(Duration::from_secs(5), subscriber)
+            .await
+            .expect("Subscriber was not notified")
+            .unwrap();
+        assert!(event.is_some());
+    }
+}
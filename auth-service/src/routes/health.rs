@@ -0,0 +1,53 @@
+use std::collections::BTreeMap;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use crate::{app_state::AppState, utils::constants::READINESS_CHECK_TIMEOUT, ApiResponse};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadinessReport {
+    pub checks: BTreeMap<String, CheckStatus>,
+}
+
+pub type ReadinessResponse = ApiResponse<ReadinessReport>;
+
+// Liveness only says the process is serving requests; a failing dependency must not get it
+// restarted, so nothing is checked here
+pub async fn livez() -> impl IntoResponse {
+    Json(ApiResponse::message("Alive"))
+}
+
+// Readiness gates traffic: 503 until every dependency answers
+#[tracing::instrument(name = "Readiness", skip_all)]
+pub async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let results = join_all(state.health_checks.iter().map(|health_check| async move {
+        let status = match tokio::time::timeout(READINESS_CHECK_TIMEOUT, health_check.check()).await {
+            Ok(Ok(())) => CheckStatus::Up,
+            Ok(Err(e)) => {
+                tracing::warn!("{} is down: {:?}", health_check.name(), e);
+                CheckStatus::Down
+            }
+            Err(_) => {
+                tracing::warn!("{} did not answer within {:?}", health_check.name(), READINESS_CHECK_TIMEOUT);
+                CheckStatus::Down
+            }
+        };
+        (health_check.name().to_owned(), status)
+    }))
+    .await;
+
+    let checks: BTreeMap<_, _> = results.into_iter().collect();
+    let (status, message) = match checks.values().all(|status| *status == CheckStatus::Up) {
+        true => (StatusCode::OK, "Ready"),
+        false => (StatusCode::SERVICE_UNAVAILABLE, "Not ready"),
+    };
+
+    (status, Json(ReadinessResponse::new(ReadinessReport { checks }, message)))
+}
@@ -0,0 +1,19 @@
+use axum::{extract::State, response::IntoResponse, Json};
+use color_eyre::eyre::eyre;
+use crate::{app_state::AppState, domain::error::AuthAPIError, utils::jwks::build_jwks};
+
+#[tracing::instrument(name = "JWKS", skip(state))]
+pub async fn jwks(State(state): State<AppState>) -> Result<impl IntoResponse, AuthAPIError> {
+    tracing::debug!("Building JWKS document");
+    let public_key = state.jwt.rsa_public_key().ok_or_else(|| {
+        tracing::error!("JWKS requested without an RSA public key configured");
+        AuthAPIError::UnexpectedError(eyre!("JWT_RSA_PUBLIC_KEY is not set"))
+    })?;
+    let jwks = build_jwks(public_key, state.jwt.key_id())
+        .map_err(|e| {
+            tracing::error!("Failed to build JWKS: {:?}", e);
+            AuthAPIError::UnexpectedError(e)
+        })?;
+
+    Ok(Json(jwks))
+}
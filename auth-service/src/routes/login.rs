@@ -1,52 +1,88 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use std::time::Instant;
+use axum::{
+    extract::State,
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
 use axum_extra::extract::CookieJar;
 use serde::{Deserialize, Serialize};
-use color_eyre::eyre::Context;
-use secrecy::Secret;
+use secrecy::{ExposeSecret, Secret};
 use crate::{
     app_state::AppState,
+    ApiResponse,
     AuthAPIError,
     domain::{
         email::Email,
+        locale::Locale,
         password::Password,
-        data_stores::{LoginAttemptId, TwoFACode},
+        user::User,
+        data_stores::{AuditEventType, LoginAttemptId, TwoFACode},
+    },
+    routes::verify_2fa::consume_two_fa_code,
+    utils::{
+        auth::generate_auth_cookie,
+        constants::{LOGIN_ATTEMPT_ID_HEADER, LOGIN_FAILURE_WINDOW, TWO_FA_REQUIRED_HEADER},
+        device_trust::is_trusted_device,
+        extractors::ClientContext,
+        i18n::two_fa_email,
+        links::build_link,
+        tracing::log_email,
+        validation::{require_non_empty, Validate, ValidatedJson},
     },
-    utils::auth::generate_auth_cookie,
 };
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct LoginRequest {
     pub email: Secret<String>,
     pub password: Secret<String>,
+    // A code from an earlier 2FA challenge, letting API clients finish 2FA in the login call
+    // instead of through `/verify_2fa`
+    #[serde(rename = "2FACode", alias = "two_fa_code", default)]
+    pub two_fa_code: Option<Secret<String>>,
+}
+
+impl Validate for LoginRequest {
+    fn validate(&self) -> Result<(), AuthAPIError> {
+        require_non_empty("email", &self.email)?;
+        require_non_empty("password", &self.password)?;
+        match &self.two_fa_code {
+            Some(two_fa_code) => require_non_empty("2FACode", two_fa_code),
+            None => Ok(()),
+        }
+    }
 }
 
+// Always serialized with the documented names; the aliases only widen what clients can parse
 #[derive(Debug, Serialize, Clone, PartialEq, Deserialize)]
 pub struct TwoFactorAuthResponse {
-    pub message: String,
-    #[serde(rename = "loginAttemptId")]
+    #[serde(rename = "loginAttemptId", alias = "login_attempt_id")]
     pub login_attempt_id: String,
-    #[serde(rename = "2FACode")]
-    pub two_fa_code: String,
 }
 
-#[tracing::instrument(name = "Login handler", skip(state, jar, request))]
+/// `data` is only present when the user must complete 2FA.
+pub type LoginResponse = ApiResponse<TwoFactorAuthResponse>;
+
+#[tracing::instrument(name = "Login handler", skip(state, client, jar, request))]
 pub async fn login(
     State(state): State<AppState>,
+    client: ClientContext,
     jar: CookieJar,
-    Json(request): Json<LoginRequest>,
-) -> impl IntoResponse {
-    let (jar, result) = process_login(state, jar, request).await;
-    (jar, result)
+    ValidatedJson(request): ValidatedJson<LoginRequest>,
+) -> Result<(CookieJar, Response), AuthAPIError> {
+    process_login(state, client, jar, request).await
 }
 
-#[tracing::instrument(name = "Process login", skip(state, jar, request))]
+#[tracing::instrument(name = "Process login", skip(state, client, jar, request))]
 async fn process_login(
     state: AppState,
+    client: ClientContext,
     jar: CookieJar,
     request: LoginRequest,
-) -> (CookieJar, Result<(StatusCode, Json<LoginResponse>), AuthAPIError>) {
+) -> Result<(CookieJar, Response), AuthAPIError> {
     tracing::debug!("Parsing credentials");
-    let email = Email::parse(request.email)
+    let email = Email::parse_with(request.email, state.feature_flags.normalize_plus_addressing())
         .map_err(|e| {
             tracing::warn!("Invalid email format: {:?}", e);
             AuthAPIError::InvalidCredentials
@@ -58,93 +94,373 @@ async fn process_login(
             AuthAPIError::InvalidCredentials
         })?;
 
-    tracing::debug!("Validating user credentials");
-    let user_store = state.user_store.read().await;
-    user_store.validate_user(&email, &password).await
+    let two_fa_code = request
+        .two_fa_code
+        .map(TwoFACode::parse_submitted)
+        .transpose()
         .map_err(|e| {
-            tracing::warn!("Invalid credentials: {:?}", e);
-            AuthAPIError::IncorrectCredentials
+            tracing::warn!("Invalid 2FA code: {:?}", e);
+            AuthAPIError::InvalidCredentials
         })?;
 
+    check_password(&state, &email, &password).await?;
+
     tracing::debug!("Getting user details");
-    let user = user_store.get_user(&email).await
+    let user = state.user_store.get_user(&email).await
         .map_err(|e| {
             tracing::error!("Failed to get user: {:?}", e);
             AuthAPIError::UnexpectedError(e.into())
         })?;
 
+    // Only checked after the password, so the 403 doesn't reveal the account's state to anyone else
+    if !user.is_active {
+        tracing::warn!("Login attempt for disabled account");
+        return Err(AuthAPIError::AccountDisabled);
+    }
+
+    if user.must_change_password {
+        tracing::warn!("Login attempt for account that must change its password");
+        return Err(AuthAPIError::PasswordChangeRequired);
+    }
+
     tracing::debug!("Checking 2FA requirement");
-    match user.requires_2fa {
-        true => handle_2fa(&email, &state, jar).await,
-        false => handle_no_2fa(&email, jar).await,
+    // A device the user completed 2FA on and asked to remember skips the challenge
+    if user.requires_2fa && !is_trusted_device(&state.jwt, &state.trusted_device_store, &jar, &email).await {
+        let Some(two_fa_code) = two_fa_code else {
+            return handle_2fa(&email, user.locale.unwrap_or(client.locale), &state, jar).await;
+        };
+        // The password already proves the login, so the code is checked without an attempt ID
+        tracing::debug!("Verifying inline 2FA code");
+        consume_two_fa_code(&state, &email, None, &two_fa_code).await?;
+    }
+    let response = handle_no_2fa(&state, &user, &client, jar).await?;
+    state.record_audit_event(&email, AuditEventType::LoginSucceeded).await;
+    Ok(response)
+}
+
+// Checks the password with login's delay and failure counting, so no endpoint that takes a
+// password can be used to guess it faster than login allows
+#[tracing::instrument(name = "Check password", skip_all, fields(email = %log_email(email)))]
+pub(crate) async fn check_password(
+    state: &AppState,
+    email: &Email,
+    password: &Password,
+) -> Result<(), AuthAPIError> {
+    // Every attempt counts as a failure until the password checks out, so concurrent guesses each
+    // see a higher count instead of all waiting out the same delay. Unknown emails are counted too,
+    // so the delay doesn't reveal which accounts exist.
+    // The delay is a soft protection, so a failing failure store only costs the delay, not the login
+    let failures = state.login_failure_store.record_failure(email, LOGIN_FAILURE_WINDOW).await
+        .map(|attempts| attempts.saturating_sub(1))
+        .unwrap_or_else(|e| {
+            tracing::error!("Failed to record login attempt: {:?}", e);
+            0
+        });
+    let delay = state.login_delay.delay_for(failures);
+    if !delay.is_zero() {
+        tracing::info!(failures, delay_ms = delay.as_millis() as u64, "Delaying login after failed attempts");
+        tokio::time::sleep(delay).await;
     }
+
+    tracing::debug!("Validating user credentials");
+    if let Err(e) = state.user_store.validate_user(email, password).await {
+        tracing::warn!("Invalid credentials: {:?}", e);
+        state.record_audit_event(email, AuditEventType::LoginFailed).await;
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    if let Err(e) = state.login_failure_store.reset(email).await {
+        tracing::error!("Failed to reset login failures: {:?}", e);
+    }
+    Ok(())
 }
 
-#[tracing::instrument(name = "Handle 2FA login", skip(state, jar))]
-async fn handle_2fa(
+#[tracing::instrument(name = "Handle 2FA login", skip_all, fields(email = %log_email(email)))]
+pub(crate) async fn handle_2fa(
     email: &Email,
+    locale: Locale,
     state: &AppState,
     jar: CookieJar,
-) -> (
-    CookieJar,
-    Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
-) {
+) -> Result<(CookieJar, Response), AuthAPIError> {
     tracing::debug!("Generating 2FA credentials");
-    let login_attempt_id = LoginAttemptId::default();
-    let two_fa_code = TwoFACode::default();
+    let login_attempt_id = LoginAttemptId::new();
+    let two_fa_code = TwoFACode::generate();
 
     tracing::debug!("Storing 2FA code");
     let mut two_fa_store = state.two_fa_code_store.write().await;
     two_fa_store
-        .add_code(email.clone(), login_attempt_id.clone(), two_fa_code.clone())
+        .add_code(
+            email.clone(),
+            login_attempt_id.clone(),
+            two_fa_code.hash(&login_attempt_id, &state.two_fa_code_pepper),
+        )
         .await
         .map_err(|e| {
             tracing::error!("Failed to store 2FA code: {:?}", e);
             AuthAPIError::UnexpectedError(e.into())
         })?;
+    drop(two_fa_store);
 
     tracing::debug!("Sending 2FA email");
-    state.email_client
-        .send_email(
-            email,
-            "Your 2FA Code",
-            &format!("Your verification code is: {}", two_fa_code.clone()),
-        )
-        .await
-        .map_err(|e| {
-            tracing::error!("Failed to send 2FA email: {:?}", e);
-            AuthAPIError::UnexpectedError(e.into())
-        })?;
+    let started = Instant::now();
+    // The UI's login form, where the code is entered, is the app's root
+    let link = state
+        .public_app_url
+        .as_ref()
+        .map(|public_app_url| build_link(public_app_url, "/", &[]))
+        .transpose()
+        .map_err(AuthAPIError::UnexpectedError)?;
+    let (subject, content) = two_fa_email(locale, two_fa_code.expose_code(), link.as_ref());
+    let delivery = state.email_client
+        .send_localized_email(email, locale, subject, &content)
+        .await;
+    state.metrics.record_email_send(delivery.is_ok(), started.elapsed());
+
+    if let Some(delivery_log) = &state.two_fa_delivery_log {
+        tracing::debug!("Recording 2FA delivery");
+        // A missing receipt shouldn't lock the user out, so failures are only logged
+        if let Err(e) = delivery_log.record_delivery(email, delivery.is_ok()).await {
+            tracing::error!("Failed to record 2FA delivery: {:?}", e);
+        }
+    }
+
+    delivery.map_err(|e| {
+        tracing::error!("Failed to send 2FA email: {:?}", e);
+        AuthAPIError::EmailDeliveryFailed(e)
+    })?;
+
+    // Lets the browser UI switch to the code entry form without parsing the body
+    let mut headers = HeaderMap::new();
+    headers.insert(TWO_FA_REQUIRED_HEADER, HeaderValue::from_static("true"));
+    headers.insert(
+        LOGIN_ATTEMPT_ID_HEADER,
+        HeaderValue::from_str(login_attempt_id.as_ref().expose_secret())
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?,
+    );
 
     tracing::info!("2FA setup successful");
-    let response = Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
-        message: "2FA required".to_owned(),
-        login_attempt_id: login_attempt_id.as_ref().to_string(),
-        two_fa_code: two_fa_code.to_string(),
-    }));
+    let response = Json(LoginResponse::new(
+        TwoFactorAuthResponse {
+            login_attempt_id: login_attempt_id.as_ref().expose_secret().to_owned(),
+        },
+        "2FA required",
+    ));
 
-    (jar, Ok((StatusCode::PARTIAL_CONTENT, response)))
+    Ok((jar, (StatusCode::PARTIAL_CONTENT, headers, response).into_response()))
 }
 
-#[tracing::instrument(name = "Handle non-2FA login", skip(jar))]
+#[tracing::instrument(name = "Handle non-2FA login", skip_all)]
 async fn handle_no_2fa(
-    email: &Email,
+    state: &AppState,
+    user: &User,
+    client: &ClientContext,
     jar: CookieJar,
-) -> (
-    CookieJar,
-    Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
-) {
+) -> Result<(CookieJar, Response), AuthAPIError> {
     tracing::debug!("Generating auth cookie");
-    let cookie = generate_auth_cookie(email)
+    let cookie = generate_auth_cookie(&state.jwt, &user.email, user.session_ttl, user.role(), client.secure_cookies())
         .await
         .map_err(|e| {
             tracing::error!("Failed to generate auth cookie: {:?}", e);
-            AuthAPIError::UnexpectedError(e.into())
+            AuthAPIError::UnexpectedError(e)
         })?;
 
     tracing::info!("Login successful");
     let jar = jar.add(cookie);
-    let response = Json(LoginResponse::RegularAuth);
+    let response = Json(LoginResponse {
+        data: None,
+        message: "Login successful".to_owned(),
+    });
     
-    (jar, Ok((StatusCode::OK, response)))
-}
\ No newline at end of file
+    Ok((jar, (StatusCode::OK, response).into_response()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        net::{IpAddr, Ipv4Addr},
+        sync::{Arc, Mutex},
+    };
+    use tokio::sync::RwLock;
+    use tracing::Level;
+    use super::*;
+    use crate::{
+        domain::data_stores::UserStore,
+        utils::{
+            config::Config,
+            constants::{test, CookieSecure},
+            feature_flags::FeatureFlags,
+            login_delay::LoginDelay,
+        },
+    };
+    use crate::services::{
+        data_stores::{
+            hashmap_user_store::HashmapUserStore,
+            hashset_banned_token_store::HashsetBannedTokenStore,
+        },
+        mock_email_client::MockEmailClient,
+    };
+
+    const PASSWORD: &str = "correct-horse-battery";
+    const TWO_FA_CODE: &str = "493817";
+
+    // Collects everything the fmt subscriber writes, span fields included
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    async fn app_state() -> AppState {
+        let user_store = Arc::new(HashmapUserStore::default());
+        let users = [("plain@example.com", false), ("2fa@example.com", true), ("user@gmail.com", false)];
+        for (email, requires_2fa) in users {
+            let email = Email::parse(Secret::new(email.to_owned())).unwrap();
+            let password = Password::parse(Secret::new(PASSWORD.to_owned())).unwrap();
+            user_store.add_user(User::new(email, password, requires_2fa)).await.unwrap();
+        }
+
+        AppState::new(
+            &Config::for_tests(),
+            user_store,
+            Arc::new(RwLock::new(HashsetBannedTokenStore::new())),
+            Arc::new(MockEmailClient),
+        )
+    }
+
+    fn client() -> ClientContext {
+        ClientContext {
+            ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            https: false,
+            locale: Locale::En,
+            auth_cookie_secure: CookieSecure::Never,
+        }
+    }
+
+    fn request(email: &str, password: &str, two_fa_code: Option<&str>) -> LoginRequest {
+        LoginRequest {
+            email: Secret::new(email.to_owned()),
+            password: Secret::new(password.to_owned()),
+            two_fa_code: two_fa_code.map(|code| Secret::new(code.to_owned())),
+        }
+    }
+
+    #[tokio::test]
+    async fn login_honors_injected_feature_flags() {
+        for normalize_plus_addressing in [false, true] {
+            let flags = FeatureFlags::default().with_normalize_plus_addressing(normalize_plus_addressing);
+            let state = app_state().await.with_feature_flags(flags);
+
+            let request = request("user+tag@gmail.com", PASSWORD, None);
+            let result = login(State(state), client(), CookieJar::new(), ValidatedJson(request)).await;
+            match result {
+                Ok((_, response)) => {
+                    assert!(normalize_plus_addressing);
+                    assert_eq!(response.status(), StatusCode::OK);
+                }
+                Err(e) => {
+                    assert!(!normalize_plus_addressing);
+                    assert!(matches!(e, AuthAPIError::IncorrectCredentials));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn debug_output_redacts_credentials() {
+        let output = format!("{:?}", request("plain@example.com", PASSWORD, Some(TWO_FA_CODE)));
+        assert!(!output.contains(PASSWORD));
+        assert!(!output.contains(TWO_FA_CODE));
+    }
+
+    // Single-threaded so the handler runs where the capturing subscriber is the default
+    #[tokio::test(flavor = "current_thread")]
+    async fn login_spans_do_not_capture_credentials() {
+        let state = app_state().await;
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(Level::TRACE)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let requests = [
+            request("plain@example.com", PASSWORD, None),
+            request("plain@example.com", "wrong-password", None),
+            request("2fa@example.com", PASSWORD, None),
+            request("2fa@example.com", PASSWORD, Some(TWO_FA_CODE)),
+        ];
+        for request in requests {
+            let _ = login(State(state.clone()), client(), CookieJar::new(), ValidatedJson(request)).await;
+        }
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("Login handler"), "expected login spans in the captured logs");
+        assert!(!logs.contains(PASSWORD));
+        assert!(!logs.contains("wrong-password"));
+        assert!(!logs.contains(TWO_FA_CODE));
+        // LOG_PII is off, so addresses only show up masked
+        assert!(!logs.contains("2fa@example.com"));
+        assert!(logs.contains("2***@example.com"));
+    }
+
+    // Single-threaded so every attempt runs where the capturing subscriber is the default
+    #[tokio::test(flavor = "current_thread")]
+    async fn concurrent_wrong_logins_each_see_a_higher_failure_count() {
+        let state = app_state()
+            .await
+            .with_login_delay(LoginDelay::new(test::LOGIN_DELAY_BASE, test::LOGIN_DELAY_MAX));
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(Level::INFO)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let attempts = (0..4).map(|_| {
+            let request = request("plain@example.com", "wrong-password", None);
+            login(State(state.clone()), client(), CookieJar::new(), ValidatedJson(request))
+        });
+        for result in futures_util::future::join_all(attempts).await {
+            assert!(matches!(result, Err(AuthAPIError::IncorrectCredentials)));
+        }
+
+        // The first attempt had no earlier failures to wait for; the rest each waited on one more
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let mut failures: Vec<u32> = logs
+            .lines()
+            .filter(|line| line.contains("Delaying login after failed attempts"))
+            .map(|line| {
+                let value = line.split("failures=").nth(1).unwrap();
+                value.split_whitespace().next().unwrap().parse().unwrap()
+            })
+            .collect();
+        failures.sort_unstable();
+        assert_eq!(failures, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn two_factor_auth_response_accepts_snake_case_but_serializes_documented_names() {
+        let expected = TwoFactorAuthResponse {
+            login_attempt_id: "attempt-id".to_owned(),
+        };
+        let documented = serde_json::json!({ "loginAttemptId": "attempt-id" });
+        let snake_case = serde_json::json!({ "login_attempt_id": "attempt-id" });
+
+        for body in [documented.clone(), snake_case] {
+            assert_eq!(serde_json::from_value::<TwoFactorAuthResponse>(body).unwrap(), expected);
+        }
+        assert_eq!(serde_json::to_value(&expected).unwrap(), documented);
+    }
+}
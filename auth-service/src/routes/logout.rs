@@ -2,60 +2,62 @@ use axum::{
     http::StatusCode, 
     response::IntoResponse,
     extract::State,  
+    Json,
 };
 use axum_extra::extract::{cookie, CookieJar};
 use time::Duration;
 use crate::{
-    domain::error::AuthAPIError,
-    utils::{auth::validate_token, constants::JWT_COOKIE_NAME},
+    domain::{
+        data_stores::{banned_token_id, AuditEventType},
+        error::AuthAPIError,
+    },
+    utils::{constants::JWT_COOKIE_NAME, extractors::AuthenticatedUser},
     app_state::AppState,  
+    ApiResponse,
 };
-use std::ops::Deref;
 
-#[tracing::instrument(name = "Logout", skip(state, jar))]
+#[tracing::instrument(name = "Logout", skip_all)]
 pub async fn logout(
     State(state): State<AppState>,
+    user: AuthenticatedUser,
     jar: CookieJar,
 ) -> Result<(CookieJar, impl IntoResponse), AuthAPIError> {
-    tracing::debug!("Getting JWT cookie");
-    let cookie = jar
-        .get(JWT_COOKIE_NAME)
-        .ok_or_else(|| {
-            tracing::warn!("No JWT cookie found");
-            AuthAPIError::MissingToken
-        })?;
-    
-    let token = cookie.value();
-    
-    tracing::debug!("Validating token");
-    let banned_token_store = state.banned_token_store.read().await;
-    validate_token(token, banned_token_store.deref())
-        .await
-        .map_err(|e| {
-            tracing::warn!("Token validation failed: {:?}", e);
-            AuthAPIError::InvalidToken
-        })?;
-    drop(banned_token_store);
-
     tracing::debug!("Banning token");
+    let token_id = banned_token_id(&user.token);
+    // The write lock waits out in-flight `/verify_token` calls, so none of them can cache the
+    // token after the eviction below. The token is banned before the response, and the cookie
+    // removal with it, goes out.
     let banned_token_store = state.banned_token_store.write().await;
     banned_token_store
-        .store_token(token.to_string())
+        .store_token(user.token)
         .await
         .map_err(|e| {
             tracing::error!("Failed to ban token: {:?}", e);
             AuthAPIError::UnexpectedError(e.into())
         })?;
+    // Otherwise /verify_token could keep accepting the token until the cache entry expires
+    state.verified_token_cache.invalidate(&user.jti);
+    drop(banned_token_store);
+    // Other tabs sharing the session drop it straight away instead of on their next request. The
+    // token is already banned, so a failed notification only delays that.
+    if let Err(e) = state.revocation_notifier.publish(&token_id).await {
+        tracing::warn!("Failed to announce token revocation: {:?}", e);
+    }
+    state.record_audit_event(&user.email, AuditEventType::Logout).await;
         
-    tracing::debug!("Removing JWT cookie");
-    let removal_cookie = cookie::Cookie::build((JWT_COOKIE_NAME, ""))
-        .path("/")
-        .max_age(Duration::ZERO)
-        .http_only(true)
-        .build();
-    
-    let jar = jar.remove(removal_cookie);
+    // Bearer-only clients have no cookie to clear
+    let jar = if jar.get(JWT_COOKIE_NAME).is_some() {
+        tracing::debug!("Removing JWT cookie");
+        let removal_cookie = cookie::Cookie::build((JWT_COOKIE_NAME, ""))
+            .path("/")
+            .max_age(Duration::ZERO)
+            .http_only(true)
+            .build();
+        jar.remove(removal_cookie)
+    } else {
+        jar
+    };
     
     tracing::info!("Logout successful");
-    Ok((jar, StatusCode::OK))
+    Ok((jar, (StatusCode::OK, Json(ApiResponse::message("Logout successful")))))
 }
\ No newline at end of file
@@ -0,0 +1,9 @@
+use axum::{extract::State, http::header::CONTENT_TYPE, response::IntoResponse};
+use crate::app_state::AppState;
+
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// Scraped by Prometheus; like the health probes it is exempt from load shedding
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    ([(CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)], state.metrics.render())
+}
@@ -1,11 +1,28 @@
+pub mod account;
+pub mod admin;
+pub mod change_password;
+pub mod events;
+pub mod health;
+pub mod jwks;
 pub mod login;
 pub mod logout;
+pub mod metrics;
+pub mod not_found;
 pub mod signup;
 pub mod verify_2fa;
 pub mod verify_token;
+pub mod version;
 
+pub use account::{me, update_profile, ProfileResponse, UserProfile};
+pub use change_password::change_password;
+pub use events::events;
+pub use health::{livez, readyz, CheckStatus, ReadinessResponse};
+pub use jwks::jwks;
 pub use login::{login, LoginResponse, TwoFactorAuthResponse, LoginRequest}; 
 pub use logout::logout;
-pub use signup::signup;
+pub use metrics::metrics;
+pub use not_found::not_found;
+pub use signup::{signup, SignupResponse};
 pub use verify_2fa::verify_2fa;
-pub use verify_token::verify_token;
+pub use verify_token::{verify_token, verify_token_batch, TokenVerification};
+pub use version::{version, VersionResponse};
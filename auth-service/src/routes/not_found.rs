@@ -0,0 +1,65 @@
+use axum::{
+    extract::Request,
+    http::{header::{ACCEPT, CACHE_CONTROL}, HeaderMap, Method},
+    response::{IntoResponse, Response},
+};
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+use std::path::Path;
+use crate::domain::error::AuthAPIError;
+
+// Reached when neither a route nor a static asset matches. The UI routes on the client, so a
+// browser navigation gets index.html; anything else is an API client asking for something that
+// doesn't exist and gets a JSON 404.
+#[tracing::instrument(name = "Not found", skip_all, fields(path = %request.uri().path()))]
+pub async fn not_found(assets_dir: &Path, request: Request) -> Response {
+    if is_browser_navigation(request.method(), request.headers()) {
+        tracing::debug!("Serving the UI for a client-side route");
+        return match ServeFile::new(assets_dir.join("index.html")).oneshot(request).await {
+            Ok(response) => response.into_response(),
+            Err(e) => match e {},
+        };
+    }
+
+    // The static-asset cache header would otherwise apply; a missing path may exist after a deploy
+    tracing::debug!("No route or asset matched");
+    ([(CACHE_CONTROL, "no-store")], AuthAPIError::NotFound).into_response()
+}
+
+// Browsers ask for HTML when navigating; API clients ask for JSON or send `*/*`
+fn is_browser_navigation(method: &Method, headers: &HeaderMap) -> bool {
+    let accept = headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    (method == Method::GET || method == Method::HEAD)
+        && accept.contains("text/html")
+        && !accept.contains("application/json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn accept(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn only_html_get_requests_are_navigations() {
+        let browser = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+        assert!(is_browser_navigation(&Method::GET, &accept(browser)));
+        assert!(is_browser_navigation(&Method::HEAD, &accept(browser)));
+
+        assert!(!is_browser_navigation(&Method::POST, &accept(browser)));
+        assert!(!is_browser_navigation(&Method::GET, &accept("application/json")));
+        assert!(!is_browser_navigation(&Method::GET, &accept("*/*")));
+        assert!(!is_browser_navigation(&Method::GET, &HeaderMap::new()));
+    }
+}
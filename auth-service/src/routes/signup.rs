@@ -1,57 +1,201 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
-use serde::{Deserialize, Serialize};
+use axum::{
+    extract::State,
+    http::{header::LOCATION, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use serde::Deserialize;
 use color_eyre::eyre;
 use secrecy::Secret;
 use crate::{ 
     app_state::AppState, 
+    ApiResponse,
     domain::{
         error::AuthAPIError, 
-        user::User, 
+        user::{Role, User},
         email::Email, 
         password::Password,
-        data_stores::UserStoreError,
+        data_stores::{AuditEventType, UserStoreError},
+        display_name::DisplayName,
+        locale::Locale,
+    },
+    routes::{account::UserProfile, login::handle_2fa},
+    utils::{
+        auth::generate_auth_cookie,
+        extractors::ClientContext,
+        validation::{require_non_empty, Validate, ValidatedJson},
     },
 };
 
+pub const CREATED_USER_LOCATION: &str = "/me";
+
 #[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct SignupRequest {
     pub email: Secret<String>,
     pub password: Secret<String>,
-    #[serde(rename = "requires2FA")]
-    pub requires_2fa: bool,
+    // Falls back to the deployment's default when left out
+    #[serde(rename = "requires2FA", default)]
+    pub requires_2fa: Option<bool>,
+    #[serde(rename = "displayName", default)]
+    pub display_name: Option<String>,
+    // Emails follow `Accept-Language` until the user picks one
+    #[serde(default)]
+    pub locale: Option<String>,
+}
+
+/// `data` describes the created user, except when auto-login starts 2FA and the body is a [`LoginResponse`](crate::routes::LoginResponse).
+pub type SignupResponse = ApiResponse<UserProfile>;
+
+impl Validate for SignupRequest {
+    fn validate(&self) -> Result<(), AuthAPIError> {
+        require_non_empty("email", &self.email)?;
+        require_non_empty("password", &self.password)
+    }
 }
 
-#[tracing::instrument(name = "Signup", skip(state, request))]
+#[tracing::instrument(name = "Signup", skip(state, client, jar, request))]
 pub async fn signup(
     State(state): State<AppState>,
-    Json(request): Json<SignupRequest>, 
-) -> Result<impl IntoResponse, AuthAPIError> {
-    let email = Email::parse(request.email)
+    client: ClientContext,
+    jar: CookieJar,
+    ValidatedJson(request): ValidatedJson<SignupRequest>,
+) -> Result<(CookieJar, Response), AuthAPIError> {
+    let email = Email::parse_with(request.email, state.feature_flags.normalize_plus_addressing())
         .map_err(|_| AuthAPIError::InvalidCredentials)?;
     
     let password = Password::parse(request.password)
         .map_err(|_| AuthAPIError::InvalidCredentials)?;
 
-    let user = User::new(email, password, request.requires_2fa);
+    let display_name = request
+        .display_name
+        .map(DisplayName::parse)
+        .transpose()
+        .map_err(|_| AuthAPIError::InvalidDisplayName)?;
+
+    let locale = request
+        .locale
+        .map(|tag| Locale::parse(&tag).ok_or(AuthAPIError::UnsupportedLocale))
+        .transpose()?;
+
+    let requires_2fa = request.requires_2fa.unwrap_or(state.feature_flags.default_requires_2fa());
+    let mut user = User::new(email.clone(), password, requires_2fa);
+    user.display_name = display_name;
+    user.locale = locale;
+    let created_user = UserProfile::from(&user);
+    let locale = locale.unwrap_or(client.locale);
     
-    let mut user_store = state.user_store.write().await;
+    // Unlike other audit events, the signup row is written with the user: if either fails, the
+    // transaction is dropped and neither is kept
+    let mut tx = state.user_store.add_user_tx(user).await.map_err(signup_error)?;
+    if let Some(audit_log) = &state.audit_log {
+        audit_log
+            .record_tx(tx.as_mut(), &email, AuditEventType::Signup)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to record signup audit event: {:?}", e);
+                AuthAPIError::UnexpectedError(e.into())
+            })?;
+    }
+    tx.commit().await.map_err(signup_error)?;
 
-    if let Err(e) = user_store.add_user(user).await {
-        return match e {
-            UserStoreError::UserAlreadyExists => Err(AuthAPIError::UserAlreadyExists),
-            UserStoreError::UnexpectedError(e) => Err(AuthAPIError::UnexpectedError(e)),
-            _ => Err(AuthAPIError::UnexpectedError(eyre::eyre!("Unexpected error during signup")))
-        };
+    if !state.feature_flags.auto_login_on_signup() {
+        return Ok((jar, created(created_user)));
     }
 
-    let response = Json(SignupResponse {
-        message: "User created successfully!".to_string(),
-    });
+    // Same outcome as an immediate login: 2FA users get a code instead of a session
+    if requires_2fa {
+        return handle_2fa(&email, locale, &state, jar).await;
+    }
 
-    Ok((StatusCode::CREATED, response))
+    // New users start on the default session length
+    let cookie = generate_auth_cookie(&state.jwt, &email, None, Role::User, client.secure_cookies())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to generate auth cookie: {:?}", e);
+            AuthAPIError::UnexpectedError(e)
+        })?;
+
+    Ok((jar.add(cookie), created(created_user)))
 }
 
-#[derive(Serialize)]
-pub struct SignupResponse {
-    pub message: String,
-}
\ No newline at end of file
+fn signup_error(e: UserStoreError) -> AuthAPIError {
+    match e {
+        UserStoreError::UserAlreadyExists => AuthAPIError::UserAlreadyExists,
+        UserStoreError::UnexpectedError(e) => AuthAPIError::UnexpectedError(e),
+        _ => AuthAPIError::UnexpectedError(eyre::eyre!("Unexpected error during signup")),
+    }
+}
+
+// The new account is only reachable as the caller's own, so `Location` points at `/me`
+fn created(user: UserProfile) -> Response {
+    let response = Json(SignupResponse::new(user, "User created successfully!"));
+    (StatusCode::CREATED, [(LOCATION, CREATED_USER_LOCATION)], response).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        sync::Arc,
+    };
+    use async_trait::async_trait;
+    use tokio::sync::RwLock;
+    use super::*;
+    use crate::{
+        domain::data_stores::{AuditEvent, AuditLog, AuditLogError, AuditQuery, UserStore},
+        services::{
+            data_stores::{
+                hashmap_user_store::HashmapUserStore,
+                hashset_banned_token_store::HashsetBannedTokenStore,
+            },
+            mock_email_client::MockEmailClient,
+        },
+        utils::{config::Config, constants::CookieSecure},
+    };
+
+    struct FailingAuditLog;
+
+    #[async_trait]
+    impl AuditLog for FailingAuditLog {
+        async fn record(&self, _email: &Email, _event_type: AuditEventType) -> Result<(), AuditLogError> {
+            Err(AuditLogError::UnexpectedError(eyre::eyre!("audit log unavailable")))
+        }
+
+        async fn query(&self, _query: &AuditQuery) -> Result<Vec<AuditEvent>, AuditLogError> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_audit_write_rolls_back_the_user() {
+        let user_store = Arc::new(HashmapUserStore::default());
+        let state = AppState::new(
+            &Config::for_tests(),
+            user_store.clone(),
+            Arc::new(RwLock::new(HashsetBannedTokenStore::new())),
+            Arc::new(MockEmailClient),
+        )
+        .with_audit_log(Arc::new(FailingAuditLog));
+
+        let request = SignupRequest {
+            email: Secret::new("new@example.com".to_owned()),
+            password: Secret::new("password123".to_owned()),
+            requires_2fa: Some(false),
+            display_name: None,
+            locale: None,
+        };
+        let client = ClientContext {
+            ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            https: false,
+            locale: Locale::En,
+            auth_cookie_secure: CookieSecure::Never,
+        };
+        let result = signup(State(state), client, CookieJar::new(), ValidatedJson(request)).await;
+        assert!(matches!(result, Err(AuthAPIError::UnexpectedError(_))));
+
+        let email = Email::parse(Secret::new("new@example.com".to_owned())).unwrap();
+        assert_eq!(user_store.get_user(&email).await, Err(UserStoreError::UserNotFound));
+    }
+}
@@ -1,33 +1,56 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use axum_extra::extract::CookieJar;
 use serde::Deserialize;
+use secrecy::Secret;
+use uuid::Uuid;
 use crate::{
     app_state::AppState,
+    ApiResponse,
     AuthAPIError,
     domain::{
         email::Email,
-        data_stores::{LoginAttemptId, TwoFACode},
+        data_stores::{AuditEventType, LoginAttemptId, TwoFACode},
+    },
+    utils::{
+        auth::generate_auth_cookie,
+        device_trust::generate_device_trust_cookie,
+        extractors::ClientContext,
+        tracing::log_email,
+        validation::{require_non_empty, Validate, ValidatedJson},
     },
-    utils::auth::generate_auth_cookie,
 };
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Verify2FARequest {
-    pub email: String,
-    #[serde(rename = "loginAttemptId")]
-    pub login_attempt_id: String,
-    #[serde(rename = "2FACode")]
-    pub two_fa_code: String,
+    pub email: Secret<String>,
+    // The snake_case aliases are for clients that can't easily produce the documented names
+    #[serde(rename = "loginAttemptId", alias = "login_attempt_id")]
+    pub login_attempt_id: Secret<String>,
+    #[serde(rename = "2FACode", alias = "two_fa_code")]
+    pub two_fa_code: Secret<String>,
+    // Skip 2FA on this device for `AppState::device_trust_ttl`
+    #[serde(rename = "rememberDevice", default)]
+    pub remember_device: bool,
+}
+
+impl Validate for Verify2FARequest {
+    fn validate(&self) -> Result<(), AuthAPIError> {
+        require_non_empty("email", &self.email)?;
+        require_non_empty("loginAttemptId", &self.login_attempt_id)?;
+        require_non_empty("2FACode", &self.two_fa_code)
+    }
 }
 
-#[tracing::instrument(name = "Verify 2FA", skip(state, jar))]
+#[tracing::instrument(name = "Verify 2FA", skip(state, client, jar, request))]
 pub async fn verify_2fa(
     State(state): State<AppState>,
+    client: ClientContext,
     jar: CookieJar,
-    Json(request): Json<Verify2FARequest>,
-) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    ValidatedJson(request): ValidatedJson<Verify2FARequest>,
+) -> Result<(CookieJar, impl IntoResponse), AuthAPIError> {
     tracing::debug!("Parsing email");
-    let email = Email::parse(request.email)
+    let email = Email::parse_with(request.email, state.feature_flags.normalize_plus_addressing())
         .map_err(|e| {
             tracing::warn!("Invalid email format: {:?}", e);
             AuthAPIError::InvalidCredentials
@@ -41,44 +64,126 @@ pub async fn verify_2fa(
         })?;
 
     tracing::debug!("Parsing 2FA code");
-    let two_fa_code = TwoFACode::parse(request.two_fa_code)
+    let two_fa_code = TwoFACode::parse_submitted(request.two_fa_code)
         .map_err(|e| {
             tracing::warn!("Invalid 2FA code: {:?}", e);
             AuthAPIError::InvalidCredentials
         })?;
 
-    tracing::debug!("Getting 2FA code store");
-    let mut two_fa_store = state.two_fa_code_store.write().await;
+    consume_two_fa_code(&state, &email, Some(&login_attempt_id), &two_fa_code).await?;
 
-    tracing::debug!("Retrieving stored 2FA code");
-    let (stored_id, stored_code) = two_fa_store.get_code(&email).await
+    tracing::debug!("Getting user details");
+    let user = state.user_store.get_user(&email).await
         .map_err(|e| {
-            tracing::warn!("Failed to get stored 2FA code: {:?}", e);
-            AuthAPIError::IncorrectCredentials
+            tracing::error!("Failed to get user: {:?}", e);
+            AuthAPIError::UnexpectedError(e.into())
         })?;
 
-    tracing::debug!("Verifying 2FA code");
-    if stored_id.as_ref() != login_attempt_id.as_ref() || stored_code.as_ref() != two_fa_code.as_ref() {
-        tracing::warn!("2FA code mismatch");
-        return (jar, Err(AuthAPIError::IncorrectCredentials));
+    // The account may have been disabled after the code was sent
+    if !user.is_active {
+        tracing::warn!("2FA verification for disabled account");
+        return Err(AuthAPIError::AccountDisabled);
+    }
+    if user.must_change_password {
+        tracing::warn!("2FA verification for account that must change its password");
+        return Err(AuthAPIError::PasswordChangeRequired);
     }
-
-    tracing::debug!("Removing used 2FA code");
-    two_fa_store.remove_code(&email).await
-        .map_err(|e| {
-            tracing::error!("Failed to remove 2FA code: {:?}", e);
-            AuthAPIError::UnexpectedError(e.into())
-        })?;
 
     tracing::debug!("Generating auth cookie");
-    let cookie = generate_auth_cookie(&email).await
+    let cookie = generate_auth_cookie(&state.jwt, &email, user.session_ttl, user.role(), client.secure_cookies()).await
         .map_err(|e| {
             tracing::error!("Failed to generate auth cookie: {:?}", e);
             AuthAPIError::UnexpectedError(e.into())
         })?;
 
     tracing::info!("2FA verification successful");
-    let jar = jar.add(cookie);
+    state.record_audit_event(&email, AuditEventType::LoginSucceeded).await;
+    let mut jar = jar.add(cookie);
+
+    if request.remember_device && !state.device_trust_ttl.is_zero() {
+        tracing::debug!("Trusting device");
+        let device_id = Uuid::new_v4().to_string();
+        state.trusted_device_store.add_device(&email, &device_id, state.device_trust_ttl).await
+            .map_err(|e| {
+                tracing::error!("Failed to store trusted device: {:?}", e);
+                AuthAPIError::UnexpectedError(e.into())
+            })?;
+        let device_trust_cookie = generate_device_trust_cookie(&state.jwt, &email, &device_id, state.device_trust_ttl)
+            .map_err(|e| {
+                tracing::error!("Failed to generate device trust cookie: {:?}", e);
+                AuthAPIError::UnexpectedError(e)
+            })?;
+        jar = jar.add(device_trust_cookie);
+    }
     
-    (jar, Ok(StatusCode::OK.into_response()))
+    Ok((jar, (StatusCode::OK, Json(ApiResponse::message("2FA verification successful")))))
+}
+
+// Checks a submitted code against the one stored for `email` and consumes it on a match. A wrong
+// code uses up one of the login attempt's tries. Callers that already proved the user's identity
+// another way, like the password on an inline login, pass no login attempt ID.
+#[tracing::instrument(name = "Consume 2FA code", skip_all, fields(email = %log_email(email)))]
+pub(crate) async fn consume_two_fa_code(
+    state: &AppState,
+    email: &Email,
+    login_attempt_id: Option<&LoginAttemptId>,
+    two_fa_code: &TwoFACode,
+) -> Result<(), AuthAPIError> {
+    // The store stays locked until the code is either consumed or its failed attempt is counted,
+    // so concurrent submissions for the same email are checked one at a time
+    let mut two_fa_code_store = state.two_fa_code_store.write().await;
+
+    tracing::debug!("Getting stored 2FA code");
+    let (stored_id, stored_hash) = two_fa_code_store.get_code(email).await
+        .map_err(|e| {
+            tracing::warn!("Failed to get stored 2FA code: {:?}", e);
+            AuthAPIError::IncorrectCredentials
+        })?;
+
+    // Codes are stored per email, so the login attempt must be the one started for this email
+    // before the code is considered; a valid attempt ID or code for another user is rejected
+    tracing::debug!("Verifying login attempt ID belongs to email");
+    if login_attempt_id.is_some_and(|login_attempt_id| *login_attempt_id != stored_id) {
+        tracing::warn!("Login attempt ID does not match the one issued for this email");
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    tracing::debug!("Verifying 2FA code");
+    let submitted_hash = two_fa_code.hash(&stored_id, &state.two_fa_code_pepper);
+    if stored_hash != submitted_hash {
+        tracing::warn!("2FA code mismatch");
+        let failed_attempts = two_fa_code_store.record_failed_attempt(email).await
+            .map_err(|e| {
+                tracing::warn!("Failed to record failed 2FA attempt: {:?}", e);
+                AuthAPIError::IncorrectCredentials
+            })?;
+        let attempts_remaining = state.two_fa_max_attempts.saturating_sub(failed_attempts);
+
+        // Once the attempts run out the user has to log in again for a new code
+        if attempts_remaining == 0 {
+            tracing::warn!("2FA attempts exhausted, invalidating code");
+            two_fa_code_store.remove_code(email).await
+                .map_err(|e| {
+                    tracing::error!("Failed to remove 2FA code: {:?}", e);
+                    AuthAPIError::UnexpectedError(e.into())
+                })?;
+        }
+        return Err(AuthAPIError::IncorrectTwoFACode { attempts_remaining });
+    }
+
+    // Taking the code deletes it, so it can't be replayed or raced by another instance; if the
+    // code changed since it was read, the submission is stale and rejected
+    tracing::debug!("Taking stored 2FA code");
+    match two_fa_code_store.take_code(email).await {
+        Ok((taken_id, taken_hash)) if taken_id == stored_id && taken_hash == submitted_hash => {},
+        Ok(_) => {
+            tracing::warn!("2FA code was replaced before it could be consumed");
+            return Err(AuthAPIError::IncorrectCredentials);
+        },
+        Err(e) => {
+            tracing::warn!("Failed to take stored 2FA code: {:?}", e);
+            return Err(AuthAPIError::IncorrectCredentials);
+        },
+    }
+    Ok(())
 }
@@ -1,37 +1,91 @@
 use axum::{
-    http::StatusCode,
-    response::IntoResponse,
+    async_trait,
+    body::{Body, Bytes},
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::{IntoResponse, Response},
     Json,
-    extract::State,
+    extract::{FromRequest, Request, State},
+    Extension,
 };
+use axum_extra::extract::CookieJar;
+use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
 use crate::{
     domain::error::AuthAPIError,
-    utils::auth::validate_token,
+    utils::{
+        auth::{validate_token_with_cache, validate_tokens_with_cache, TokenRejection},
+        constants::{JWT_COOKIE_NAME, MAX_VERIFY_TOKEN_BATCH_SIZE},
+        extractors::ClientContext,
+        rate_limit::RateLimiter,
+        validation::{json_rejection_response, Validate, ValidatedJson},
+    },
     app_state::AppState,
+    ApiResponse,
 };
-use std::ops::Deref;
+use std::{num::NonZeroU32, ops::Deref};
 
+// Deliberately lenient: other services call this endpoint and may send extra fields
+// as their clients evolve, unlike the forms posted by the auth UI
 #[derive(Deserialize)]
 pub struct VerifyTokenRequest {
-    token: String,
+    #[serde(default)]
+    token: Option<Secret<String>>,
 }
 
-#[derive(Serialize)]
-pub struct VerifyTokenResponse {
-    message: String,
+// The token from the body, if it has one. An empty body, a body without a Content-Type, `{}`
+// and an empty `token` all count as no token; a body that isn't JSON is still rejected.
+pub struct BodyToken(Option<Secret<String>>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequest<S> for BodyToken {
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let (parts, body) = request.into_parts();
+        // The body limit layer records its limit in the extensions
+        let mut body_request = Request::new(body);
+        *body_request.extensions_mut() = parts.extensions.clone();
+        let bytes = Bytes::from_request(body_request, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        if bytes.trim_ascii().is_empty() || !parts.headers.contains_key(CONTENT_TYPE) {
+            return Ok(Self(None));
+        }
+
+        let request = Request::from_parts(parts, Body::from(bytes));
+        let Json(payload) = Json::<VerifyTokenRequest>::from_request(request, state)
+            .await
+            .map_err(json_rejection_response)?;
+        Ok(Self(payload.token.filter(|token| !token.expose_secret().trim().is_empty())))
+    }
 }
 
-#[tracing::instrument(name = "Verify token", skip(state))]
+// The token is read from the JSON body. Requests without one (e.g. an empty POST from the
+// browser) fall back to the JWT cookie.
+#[tracing::instrument(name = "Verify token", skip_all)]
 pub async fn verify_token(
     State(state): State<AppState>,
-    Json(payload): Json<VerifyTokenRequest>,
+    jar: CookieJar,
+    BodyToken(token): BodyToken,
 ) -> Result<impl IntoResponse, AuthAPIError> {
+    let token = match token {
+        Some(token) => token,
+        None => {
+            tracing::debug!("No token in body, falling back to JWT cookie");
+            jar.get(JWT_COOKIE_NAME)
+                .map(|cookie| Secret::new(cookie.value().to_owned()))
+                .ok_or_else(|| {
+                    tracing::warn!("No token in body or JWT cookie");
+                    AuthAPIError::MissingToken
+                })?
+        }
+    };
+
     tracing::debug!("Getting banned token store");
     let banned_token_store = state.banned_token_store.read().await;
 
     tracing::debug!("Validating token");
-    validate_token(&payload.token, banned_token_store.deref())
+    validate_token_with_cache(&state.jwt, &token, banned_token_store.deref(), &state.verified_token_cache)
         .await
         .map_err(|e| {
             tracing::warn!("Token validation failed: {:?}", e);
@@ -41,8 +95,77 @@ pub async fn verify_token(
     tracing::info!("Token validated successfully");
     Ok((
         StatusCode::OK,
-        Json(VerifyTokenResponse {
-            message: "Token is valid".to_string()
+        Json(ApiResponse::message("Token is valid"))
+    ).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct VerifyTokenBatchRequest {
+    tokens: Vec<Secret<String>>,
+}
+
+impl Validate for VerifyTokenBatchRequest {
+    fn validate(&self) -> Result<(), AuthAPIError> {
+        if self.tokens.len() > MAX_VERIFY_TOKEN_BATCH_SIZE {
+            return Err(AuthAPIError::BatchTooLarge);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TokenVerification {
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<TokenRejection>,
+}
+
+// Results are in the same order as the request's tokens. Each token costs as much of the
+// `/verify_token` quota as a single check; the rate limit layer has charged the first.
+#[tracing::instrument(name = "Verify token batch", skip_all)]
+pub async fn verify_token_batch(
+    State(state): State<AppState>,
+    Extension(limiter): Extension<RateLimiter>,
+    client: ClientContext,
+    ValidatedJson(request): ValidatedJson<VerifyTokenBatchRequest>,
+) -> Result<Response, AuthAPIError> {
+    // At most `MAX_VERIFY_TOKEN_BATCH_SIZE`, so the count fits
+    let rate_limit = NonZeroU32::new(request.tokens.len().saturating_sub(1) as u32)
+        .map(|rest| limiter.check_n(client.ip, rest));
+    if let Some(status) = rate_limit.filter(|status| !status.allowed) {
+        tracing::warn!("Rate limit exceeded by token batch");
+        let mut response = AuthAPIError::TooManyRequests.into_response();
+        status.insert_headers(response.headers_mut());
+        return Ok(response);
+    }
+
+    tracing::debug!("Validating {} tokens", request.tokens.len());
+    let banned_token_store = state.banned_token_store.read().await;
+    let results = validate_tokens_with_cache(
+        &state.jwt,
+        request.tokens,
+        banned_token_store.deref(),
+        &state.verified_token_cache,
+    )
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to validate token batch: {:?}", e);
+        AuthAPIError::UnexpectedError(e)
+    })?;
+
+    let verifications: Vec<TokenVerification> = results
+        .into_iter()
+        .map(|result| match result {
+            Ok(claims) => TokenVerification { valid: true, sub: Some(claims.sub), reason: None },
+            Err(reason) => TokenVerification { valid: false, sub: None, reason: Some(reason) },
         })
-    ))
-}
\ No newline at end of file
+        .collect();
+
+    let mut response = Json(ApiResponse::new(verifications, "Tokens checked")).into_response();
+    if let Some(status) = rate_limit {
+        status.insert_headers(response.headers_mut());
+    }
+    Ok(response)
+}
@@ -0,0 +1,21 @@
+use axum::{response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionResponse {
+    pub name: String,
+    pub version: String,
+    pub git_sha: String,
+    pub build_time: String,
+}
+
+// GIT_SHA and BUILD_TIME are set by build.rs
+#[tracing::instrument(name = "Version")]
+pub async fn version() -> impl IntoResponse {
+    Json(VersionResponse {
+        name: env!("CARGO_PKG_NAME").to_owned(),
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        git_sha: env!("GIT_SHA").to_owned(),
+        build_time: env!("BUILD_TIME").to_owned(),
+    })
+}
@@ -0,0 +1,273 @@
+use argon2::{
+    password_hash::SaltString,
+    Algorithm,
+    Argon2,
+    Params,
+    PasswordHash,
+    PasswordHasher as _,
+    PasswordVerifier,
+    Version,
+};
+use async_trait::async_trait;
+use color_eyre::eyre::{Context, Result};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    domain::password_hasher::{LegacyPreHash, PasswordHasher, PasswordVerification},
+    utils::constants::{DEFAULT_ARGON2_ITERATIONS, DEFAULT_ARGON2_MEMORY_KIB, DEFAULT_ARGON2_PARALLELISM},
+};
+
+pub struct Argon2PasswordHasher {
+    pepper: Option<Secret<String>>,
+    legacy_pre_hash: Option<LegacyPreHash>,
+    params: Params,
+}
+
+impl Argon2PasswordHasher {
+    pub fn new(pepper: Option<Secret<String>>, legacy_pre_hash: Option<LegacyPreHash>) -> Self {
+        Self {
+            pepper,
+            legacy_pre_hash,
+            params: Params::new(DEFAULT_ARGON2_MEMORY_KIB, DEFAULT_ARGON2_ITERATIONS, DEFAULT_ARGON2_PARALLELISM, None)
+                .expect("Default Argon2 parameters are valid"),
+        }
+    }
+
+    // Cost of new hashes; stored hashes below it in any dimension verify as needing a rehash
+    pub fn with_params(mut self, params: Params) -> Self {
+        self.params = params;
+        self
+    }
+}
+
+impl Default for Argon2PasswordHasher {
+    fn default() -> Self {
+        Self::new(None, None)
+    }
+}
+
+#[async_trait]
+impl PasswordHasher for Argon2PasswordHasher {
+    #[tracing::instrument(name = "Computing password hash", skip_all)]
+    async fn compute_password_hash(&self, password: Secret<String>) -> Result<Secret<String>> {
+        let pepper = self.pepper.clone();
+        let params = self.params.clone();
+        let current_span: tracing::Span = tracing::Span::current();
+        let result = tokio::task::spawn_blocking(move || {
+            current_span.in_scope(|| {
+                let password = apply_pepper(&password, pepper.as_ref())?;
+                let salt: SaltString = SaltString::generate(&mut rand::thread_rng());
+                let password_hash = Argon2::new(
+                    Algorithm::Argon2id,
+                    Version::V0x13,
+                    params,
+                )
+                .hash_password(&password, &salt)?
+                .to_string();
+
+                Ok(Secret::new(password_hash))
+            })
+        })
+        .await;
+
+        result?
+    }
+
+    #[tracing::instrument(name = "Verifying password hash", skip_all)]
+    async fn verify_password_hash(
+        &self,
+        expected_password_hash: &Secret<String>,
+        password_candidate: Secret<String>,
+    ) -> Result<PasswordVerification> {
+        let pepper = self.pepper.clone();
+        let legacy_pre_hash = self.legacy_pre_hash;
+        let params = self.params.clone();
+        let current_span: tracing::Span = tracing::Span::current();
+        let expected_hash = expected_password_hash.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            current_span.in_scope(|| {
+                let expected_password_hash: PasswordHash<'_> =
+                    PasswordHash::new(expected_hash.expose_secret())?;
+
+                let peppered_candidate = apply_pepper(&password_candidate, pepper.as_ref())?;
+                let verified = Argon2::default()
+                    .verify_password(&peppered_candidate, &expected_password_hash)
+                    .wrap_err("failed to verify password hash");
+
+                match (verified, legacy_pre_hash) {
+                    (Ok(()), _) if is_weaker_than(&expected_password_hash, &params) => {
+                        Ok(PasswordVerification::VerifiedNeedsRehash)
+                    }
+                    (Ok(()), _) => Ok(PasswordVerification::Verified),
+                    // Legacy hashes were created before the pepper existed, so only the pre-hash is applied
+                    (Err(_), Some(pre_hash)) => {
+                        let legacy_candidate = apply_legacy_pre_hash(&password_candidate, pre_hash);
+                        Argon2::default()
+                            .verify_password(legacy_candidate.as_bytes(), &expected_password_hash)
+                            .wrap_err("failed to verify legacy password hash")?;
+                        Ok(PasswordVerification::VerifiedNeedsRehash)
+                    }
+                    (Err(e), None) => Err(e),
+                }
+            })
+        })
+        .await;
+
+        result?
+    }
+}
+
+// Mixes the server-side pepper into the password with HMAC-SHA256 so a leaked database alone
+// isn't enough to brute-force hashes. Without a pepper the password bytes are used unchanged,
+// which keeps hashes created before the pepper was introduced verifiable.
+fn apply_pepper(password: &Secret<String>, pepper: Option<&Secret<String>>) -> Result<Vec<u8>> {
+    match pepper {
+        Some(pepper) => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(pepper.expose_secret().as_bytes())
+                .wrap_err("failed to initialise password pepper")?;
+            mac.update(password.expose_secret().as_bytes());
+            Ok(mac.finalize().into_bytes().to_vec())
+        }
+        None => Ok(password.expose_secret().as_bytes().to_vec()),
+    }
+}
+
+// Hashes whose parameters can't be read are left alone rather than rehashed on every login
+fn is_weaker_than(hash: &PasswordHash<'_>, params: &Params) -> bool {
+    match Params::try_from(hash) {
+        Ok(stored) => {
+            stored.m_cost() < params.m_cost()
+                || stored.t_cost() < params.t_cost()
+                || stored.p_cost() < params.p_cost()
+        }
+        Err(e) => {
+            tracing::warn!("Failed to read stored password hash parameters: {:?}", e);
+            false
+        }
+    }
+}
+
+fn apply_legacy_pre_hash(password: &Secret<String>, pre_hash: LegacyPreHash) -> String {
+    match pre_hash {
+        LegacyPreHash::Sha256 => format!("{:x}", Sha256::digest(password.expose_secret().as_bytes())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secret(value: &str) -> Secret<String> {
+        Secret::new(value.to_owned())
+    }
+
+    fn hasher(pepper: Option<&str>) -> Argon2PasswordHasher {
+        Argon2PasswordHasher::new(pepper.map(secret), None)
+    }
+
+    // Simulates a hash imported from a system that stored Argon2 over the hex SHA-256 of the password
+    async fn legacy_sha256_hash(password: &str) -> Secret<String> {
+        let pre_hashed = apply_legacy_pre_hash(&secret(password), LegacyPreHash::Sha256);
+        hasher(None).compute_password_hash(Secret::new(pre_hashed)).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn verifies_hash_without_pepper() {
+        let hasher = hasher(None);
+        let hash = hasher.compute_password_hash(secret("password123")).await.unwrap();
+
+        assert_eq!(
+            hasher.verify_password_hash(&hash, secret("password123")).await.unwrap(),
+            PasswordVerification::Verified
+        );
+        assert!(hasher.verify_password_hash(&hash, secret("wrongpassword")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn verifies_hash_with_pepper() {
+        let hasher = hasher(Some("pepper"));
+        let hash = hasher.compute_password_hash(secret("password123")).await.unwrap();
+
+        assert_eq!(
+            hasher.verify_password_hash(&hash, secret("password123")).await.unwrap(),
+            PasswordVerification::Verified
+        );
+        assert!(hasher.verify_password_hash(&hash, secret("wrongpassword")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn pepper_must_match_the_one_used_for_hashing() {
+        let hash = hasher(Some("pepper")).compute_password_hash(secret("password123")).await.unwrap();
+
+        assert!(hasher(None).verify_password_hash(&hash, secret("password123")).await.is_err());
+        assert!(hasher(Some("other")).verify_password_hash(&hash, secret("password123")).await.is_err());
+
+        let unpeppered_hash = hasher(None).compute_password_hash(secret("password123")).await.unwrap();
+        assert!(hasher(Some("pepper")).verify_password_hash(&unpeppered_hash, secret("password123")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn legacy_hash_verifies_and_needs_rehash_when_enabled() {
+        let hasher = Argon2PasswordHasher::new(Some(secret("pepper")), Some(LegacyPreHash::Sha256));
+        let hash = legacy_sha256_hash("password123").await;
+
+        assert_eq!(
+            hasher.verify_password_hash(&hash, secret("password123")).await.unwrap(),
+            PasswordVerification::VerifiedNeedsRehash
+        );
+        assert!(hasher.verify_password_hash(&hash, secret("wrongpassword")).await.is_err());
+
+        let upgraded_hash = hasher.compute_password_hash(secret("password123")).await.unwrap();
+        assert_eq!(
+            hasher.verify_password_hash(&upgraded_hash, secret("password123")).await.unwrap(),
+            PasswordVerification::Verified
+        );
+    }
+
+    #[tokio::test]
+    async fn legacy_hash_is_rejected_when_disabled() {
+        let hash = legacy_sha256_hash("password123").await;
+
+        assert!(hasher(None).verify_password_hash(&hash, secret("password123")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn hash_weaker_than_current_params_needs_rehash() {
+        let weak_hash = hasher(Some("pepper"))
+            .with_params(Params::new(1024, 1, 1, None).unwrap())
+            .compute_password_hash(secret("password123"))
+            .await
+            .unwrap();
+        let hasher = hasher(Some("pepper"));
+
+        assert_eq!(
+            hasher.verify_password_hash(&weak_hash, secret("password123")).await.unwrap(),
+            PasswordVerification::VerifiedNeedsRehash
+        );
+        assert!(hasher.verify_password_hash(&weak_hash, secret("wrongpassword")).await.is_err());
+
+        let upgraded_hash = hasher.compute_password_hash(secret("password123")).await.unwrap();
+        let upgraded_params = Params::try_from(&PasswordHash::new(upgraded_hash.expose_secret()).unwrap()).unwrap();
+        assert_eq!(upgraded_params.m_cost(), DEFAULT_ARGON2_MEMORY_KIB);
+        assert_eq!(
+            hasher.verify_password_hash(&upgraded_hash, secret("password123")).await.unwrap(),
+            PasswordVerification::Verified
+        );
+    }
+
+    #[tokio::test]
+    async fn hash_stronger_than_current_params_is_kept() {
+        let strong_hash = hasher(None)
+            .with_params(Params::new(DEFAULT_ARGON2_MEMORY_KIB, DEFAULT_ARGON2_ITERATIONS + 1, 1, None).unwrap())
+            .compute_password_hash(secret("password123"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            hasher(None).verify_password_hash(&strong_hash, secret("password123")).await.unwrap(),
+            PasswordVerification::Verified
+        );
+    }
+}
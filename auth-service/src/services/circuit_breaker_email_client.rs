@@ -0,0 +1,204 @@
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+use async_trait::async_trait;
+use color_eyre::eyre::{eyre, Result};
+use tokio::time::Instant;
+use crate::domain::{email::Email, locale::Locale, EmailClient};
+
+// Stops calling a provider that keeps failing. After `failure_threshold` consecutive failures the
+// breaker opens and sends fail immediately; once `cooldown` has passed a single send is let
+// through as a probe, which closes the breaker on success or re-opens it on failure.
+pub struct CircuitBreakerEmailClient<C> {
+    inner: C,
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+}
+
+impl<C> CircuitBreakerEmailClient<C> {
+    pub fn new(inner: C, failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            inner,
+            failure_threshold,
+            cooldown,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    // Returns false while the breaker is open. The first caller after the cooldown becomes the
+    // probe; re-arming `opened_at` keeps everyone else out until the probe has finished.
+    fn allow_request(&self) -> Result<bool> {
+        let mut state = self.state.lock().map_err(|e| eyre!(e.to_string()))?;
+        match state.opened_at {
+            Some(opened_at) if opened_at.elapsed() < self.cooldown => Ok(false),
+            Some(_) => {
+                state.opened_at = Some(Instant::now());
+                Ok(true)
+            }
+            None => Ok(true),
+        }
+    }
+
+    async fn guard(&self, send: impl Future<Output = Result<()>>) -> Result<()> {
+        if !self.allow_request()? {
+            return Err(eyre!("Email circuit breaker is open, not contacting the provider"));
+        }
+
+        let result = send.await;
+        self.record(result.is_ok())?;
+        result
+    }
+
+    fn record(&self, succeeded: bool) -> Result<()> {
+        let mut state = self.state.lock().map_err(|e| eyre!(e.to_string()))?;
+        if succeeded {
+            *state = BreakerState::default();
+            return Ok(());
+        }
+
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        if state.consecutive_failures >= self.failure_threshold {
+            if state.opened_at.is_none() {
+                tracing::warn!(
+                    "Email provider failed {} times in a row, opening circuit breaker",
+                    state.consecutive_failures
+                );
+            }
+            state.opened_at = Some(Instant::now());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<C> EmailClient for CircuitBreakerEmailClient<C>
+where
+    C: EmailClient + Send + Sync,
+{
+    #[tracing::instrument(name = "Sending email through circuit breaker", skip_all)]
+    async fn send_email(&self, recipient: &Email, subject: &str, content: &str) -> Result<()> {
+        self.guard(self.inner.send_email(recipient, subject, content)).await
+    }
+
+    #[tracing::instrument(name = "Sending localized email through circuit breaker", skip_all)]
+    async fn send_localized_email(
+        &self,
+        recipient: &Email,
+        locale: Locale,
+        subject: &str,
+        content: &str,
+    ) -> Result<()> {
+        self.guard(self.inner.send_localized_email(recipient, locale, subject, content)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    };
+    use secrecy::Secret;
+
+    const COOLDOWN: Duration = Duration::from_secs(30);
+
+    // Shared handles let a test flip the provider's health and count the calls that reached it
+    #[derive(Clone, Default)]
+    struct FlakyEmailClient {
+        healthy: Arc<AtomicBool>,
+        calls: Arc<AtomicU32>,
+    }
+
+    #[async_trait]
+    impl EmailClient for FlakyEmailClient {
+        async fn send_email(&self, _recipient: &Email, _subject: &str, _content: &str) -> Result<()> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            match self.healthy.load(Ordering::SeqCst) {
+                true => Ok(()),
+                false => Err(eyre!("Provider is down")),
+            }
+        }
+    }
+
+    async fn send(client: &CircuitBreakerEmailClient<FlakyEmailClient>) -> Result<()> {
+        let recipient = Email::parse(Secret::new("user@example.com".to_owned())).unwrap();
+        client.send_email(&recipient, "Subject", "Content").await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn opens_after_consecutive_failures() {
+        let provider = FlakyEmailClient::default();
+        let client = CircuitBreakerEmailClient::new(provider.clone(), 3, COOLDOWN);
+
+        for _ in 0..3 {
+            assert!(send(&client).await.is_err());
+        }
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 3);
+
+        // Open: fails fast without reaching the provider, even once it has recovered
+        provider.healthy.store(true, Ordering::SeqCst);
+        assert!(send(&client).await.is_err());
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn successful_probe_after_cooldown_closes_breaker() {
+        let provider = FlakyEmailClient::default();
+        let client = CircuitBreakerEmailClient::new(provider.clone(), 2, COOLDOWN);
+
+        for _ in 0..2 {
+            assert!(send(&client).await.is_err());
+        }
+
+        provider.healthy.store(true, Ordering::SeqCst);
+        tokio::time::advance(COOLDOWN).await;
+
+        assert!(send(&client).await.is_ok());
+        assert!(send(&client).await.is_ok());
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_probe_reopens_breaker() {
+        let provider = FlakyEmailClient::default();
+        let client = CircuitBreakerEmailClient::new(provider.clone(), 2, COOLDOWN);
+
+        for _ in 0..2 {
+            assert!(send(&client).await.is_err());
+        }
+
+        tokio::time::advance(COOLDOWN).await;
+        assert!(send(&client).await.is_err());
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 3);
+
+        // The failed probe starts a fresh cooldown
+        provider.healthy.store(true, Ordering::SeqCst);
+        tokio::time::advance(COOLDOWN / 2).await;
+        assert!(send(&client).await.is_err());
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn success_resets_failure_count() {
+        let provider = FlakyEmailClient::default();
+        let client = CircuitBreakerEmailClient::new(provider.clone(), 2, COOLDOWN);
+
+        assert!(send(&client).await.is_err());
+        provider.healthy.store(true, Ordering::SeqCst);
+        assert!(send(&client).await.is_ok());
+        provider.healthy.store(false, Ordering::SeqCst);
+        assert!(send(&client).await.is_err());
+
+        // Two failures in total, but never two in a row
+        provider.healthy.store(true, Ordering::SeqCst);
+        assert!(send(&client).await.is_ok());
+    }
+}
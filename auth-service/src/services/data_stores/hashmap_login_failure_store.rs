@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use color_eyre::eyre::eyre;
+use secrecy::ExposeSecret;
+use crate::domain::{
+    data_stores::{LoginFailureStore, LoginFailureStoreError},
+    email::Email,
+};
+
+// The value is the failure count and when it is forgotten
+#[derive(Default)]
+pub struct HashmapLoginFailureStore {
+    failures: RwLock<HashMap<String, (u32, Instant)>>,
+}
+
+#[async_trait]
+impl LoginFailureStore for HashmapLoginFailureStore {
+    async fn record_failure(&self, email: &Email, window: Duration) -> Result<u32, LoginFailureStoreError> {
+        let mut failures = self
+            .failures
+            .write()
+            .map_err(|e| LoginFailureStoreError::UnexpectedError(eyre!(e.to_string())))?;
+        let now = Instant::now();
+        failures.retain(|_, (_, expires_at)| *expires_at > now);
+
+        let entry = failures
+            .entry(email.as_ref().expose_secret().to_owned())
+            .or_insert((0, now));
+        *entry = (entry.0.saturating_add(1), now + window);
+        Ok(entry.0)
+    }
+
+    async fn failure_count(&self, email: &Email) -> Result<u32, LoginFailureStoreError> {
+        let failures = self
+            .failures
+            .read()
+            .map_err(|e| LoginFailureStoreError::UnexpectedError(eyre!(e.to_string())))?;
+        Ok(failures
+            .get(email.as_ref().expose_secret())
+            .filter(|(_, expires_at)| *expires_at > Instant::now())
+            .map_or(0, |(count, _)| *count))
+    }
+
+    async fn reset(&self, email: &Email) -> Result<(), LoginFailureStoreError> {
+        self.failures
+            .write()
+            .map_err(|e| LoginFailureStoreError::UnexpectedError(eyre!(e.to_string())))?
+            .remove(email.as_ref().expose_secret());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::Secret;
+
+    #[tokio::test]
+    async fn test_counts_failures_until_reset() {
+        let store = HashmapLoginFailureStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let window = Duration::from_secs(60);
+
+        assert_eq!(store.failure_count(&email).await.unwrap(), 0);
+        assert_eq!(store.record_failure(&email, window).await.unwrap(), 1);
+        assert_eq!(store.record_failure(&email, window).await.unwrap(), 2);
+        assert_eq!(store.failure_count(&email).await.unwrap(), 2);
+
+        store.reset(&email).await.unwrap();
+        assert_eq!(store.failure_count(&email).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_forgets_failures_after_window() {
+        let store = HashmapLoginFailureStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+
+        store.record_failure(&email, Duration::ZERO).await.unwrap();
+        assert_eq!(store.failure_count(&email).await.unwrap(), 0);
+        assert_eq!(store.record_failure(&email, Duration::from_secs(60)).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use color_eyre::eyre::eyre;
+use secrecy::ExposeSecret;
+use crate::domain::{
+    data_stores::{TrustedDeviceStore, TrustedDeviceStoreError},
+    email::Email,
+};
+
+// Keyed by (email, device id); the value is when the device stops being trusted
+#[derive(Default)]
+pub struct HashmapTrustedDeviceStore {
+    devices: RwLock<HashMap<(String, String), Instant>>,
+}
+
+#[async_trait]
+impl TrustedDeviceStore for HashmapTrustedDeviceStore {
+    async fn add_device(
+        &self,
+        email: &Email,
+        device_id: &str,
+        ttl: Duration,
+    ) -> Result<(), TrustedDeviceStoreError> {
+        let mut devices = self
+            .devices
+            .write()
+            .map_err(|e| TrustedDeviceStoreError::UnexpectedError(eyre!(e.to_string())))?;
+        // Expired entries would otherwise pile up for users who never log in again
+        let now = Instant::now();
+        devices.retain(|_, expires_at| *expires_at > now);
+        devices.insert(
+            (email.as_ref().expose_secret().to_owned(), device_id.to_owned()),
+            now + ttl,
+        );
+        Ok(())
+    }
+
+    async fn contains_device(&self, email: &Email, device_id: &str) -> Result<bool, TrustedDeviceStoreError> {
+        let devices = self
+            .devices
+            .read()
+            .map_err(|e| TrustedDeviceStoreError::UnexpectedError(eyre!(e.to_string())))?;
+        let key = (email.as_ref().expose_secret().to_owned(), device_id.to_owned());
+        Ok(devices.get(&key).is_some_and(|expires_at| *expires_at > Instant::now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::Secret;
+
+    #[tokio::test]
+    async fn test_trusts_device_only_for_its_user_until_expiry() {
+        let store = HashmapTrustedDeviceStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let other_email = Email::parse(Secret::new("other@example.com".to_string())).unwrap();
+
+        store.add_device(&email, "device-1", Duration::from_secs(60)).await.unwrap();
+        assert!(store.contains_device(&email, "device-1").await.unwrap());
+        assert!(!store.contains_device(&email, "device-2").await.unwrap());
+        assert!(!store.contains_device(&other_email, "device-1").await.unwrap());
+
+        store.add_device(&email, "device-2", Duration::ZERO).await.unwrap();
+        assert!(!store.contains_device(&email, "device-2").await.unwrap());
+    }
+}
@@ -1,15 +1,34 @@
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use async_trait::async_trait;
 use secrecy::{ExposeSecret, Secret};
 use crate::domain::{
-    data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
+    data_stores::{LoginAttemptId, TwoFACodeHash, TwoFACodeStore, TwoFACodeStoreError},
     email::Email,
 };
 
-#[derive(Default)]
+// Matches the TTL the Redis store sets on its keys
+const DEFAULT_CODE_TTL: Duration = Duration::from_secs(600);
+
 pub struct HashmapTwoFACodeStore {
-    // The HashMap stores Email as key and a tuple of (LoginAttemptId, TwoFACode) as value
-    codes: HashMap<String, (LoginAttemptId, TwoFACode)>,
+    // The HashMap stores Email as key and a tuple of (LoginAttemptId, TwoFACodeHash, expiry, failed attempts) as value
+    codes: HashMap<String, (LoginAttemptId, TwoFACodeHash, Instant, u32)>,
+    ttl: Duration,
+}
+
+impl HashmapTwoFACodeStore {
+    pub fn with_ttl(ttl: Duration) -> Self {
+        Self {
+            codes: HashMap::new(),
+            ttl,
+        }
+    }
+}
+
+impl Default for HashmapTwoFACodeStore {
+    fn default() -> Self {
+        Self::with_ttl(DEFAULT_CODE_TTL)
+    }
 }
 
 #[async_trait]
@@ -18,38 +37,78 @@ impl TwoFACodeStore for HashmapTwoFACodeStore {
         &mut self,
         email: Email,
         login_attempt_id: LoginAttemptId,
-        code: TwoFACode,
+        code_hash: TwoFACodeHash,
     ) -> Result<(), TwoFACodeStoreError> {
-        self.codes.insert(email.as_ref().expose_secret().to_string(), (login_attempt_id, code));
+        let expires_at = Instant::now() + self.ttl;
+        self.codes.insert(
+            email.as_ref().expose_secret().to_string(),
+            (login_attempt_id, code_hash, expires_at, 0),
+        );
         Ok(())
     }
 
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        self.codes.remove(email.as_ref().expose_secret());
-        Ok(())
+    async fn remove_code(&mut self, email: &Email) -> Result<bool, TwoFACodeStoreError> {
+        Ok(self.codes.remove(email.as_ref().expose_secret()).is_some())
     }
 
     async fn get_code(
         &self,
         email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
+    ) -> Result<(LoginAttemptId, TwoFACodeHash), TwoFACodeStoreError> {
         self.codes
             .get(email.as_ref().expose_secret())
-            .map(|(id, code)| (id.clone(), code.clone()))
+            .filter(|(_, _, expires_at, _)| *expires_at > Instant::now())
+            .map(|(id, code, _, _)| (id.clone(), code.clone()))
             .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)
     }
+
+    async fn take_code(
+        &mut self,
+        email: &Email,
+    ) -> Result<(LoginAttemptId, TwoFACodeHash), TwoFACodeStoreError> {
+        self.codes
+            .remove(email.as_ref().expose_secret())
+            .filter(|(_, _, expires_at, _)| *expires_at > Instant::now())
+            .map(|(id, code, _, _)| (id, code))
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)
+    }
+
+    async fn record_failed_attempt(&mut self, email: &Email) -> Result<u32, TwoFACodeStoreError> {
+        let (_, _, _, failed_attempts) = self
+            .codes
+            .get_mut(email.as_ref().expose_secret())
+            .filter(|(_, _, expires_at, _)| *expires_at > Instant::now())
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+        *failed_attempts += 1;
+        Ok(*failed_attempts)
+    }
+
+    async fn remove_expired_codes(&mut self) -> Result<(), TwoFACodeStoreError> {
+        let now = Instant::now();
+        self.codes.retain(|_, (_, _, expires_at, _)| *expires_at > now);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::data_stores::TwoFACode;
+
+    fn pepper() -> Secret<String> {
+        Secret::new("test-2fa-code-pepper".to_owned())
+    }
+
+    fn code_hash(code: &str) -> TwoFACodeHash {
+        TwoFACode::parse(Secret::new(code.to_owned())).unwrap().hash(&LoginAttemptId::new(), &pepper())
+    }
 
     #[tokio::test]
     async fn should_store_and_retrieve_code() {
         let mut store = HashmapTwoFACodeStore::default();
         let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
-        let login_attempt_id = LoginAttemptId::default();
-        let code = TwoFACode::parse(Secret::new("123456".to_string())).unwrap();
+        let login_attempt_id = LoginAttemptId::new();
+        let code = code_hash("123456");
 
         store.add_code(email.clone(), login_attempt_id.clone(), code.clone())
             .await
@@ -76,35 +135,42 @@ mod tests {
     async fn should_remove_existing_code() {
         let mut store = HashmapTwoFACodeStore::default();
         let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
-        let login_attempt_id = LoginAttemptId::default();
-        let code = TwoFACode::parse(Secret::new("123456".to_string())).unwrap();
+        let login_attempt_id = LoginAttemptId::new();
+        let code = code_hash("123456");
 
         store.add_code(email.clone(), login_attempt_id, code)
             .await
             .expect("Failed to store code");
 
-        store.remove_code(&email)
+        let removed = store.remove_code(&email)
             .await
             .expect("Failed to remove code");
+        assert!(removed);
 
         let result = store.get_code(&email).await;
 
         assert!(matches!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound)));
+
+        // Only the first removal consumes the code
+        let removed = store.remove_code(&email)
+            .await
+            .expect("Failed to remove code");
+        assert!(!removed);
     }
 
     #[tokio::test]
     async fn should_update_existing_code() {
         let mut store = HashmapTwoFACodeStore::default();
         let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
-        let initial_id = LoginAttemptId::default();
-        let initial_code = TwoFACode::parse(Secret::new("123456".to_string())).unwrap();
+        let initial_id = LoginAttemptId::new();
+        let initial_code = code_hash("123456");
 
         store.add_code(email.clone(), initial_id, initial_code)
             .await
             .expect("Failed to store initial code");
 
-        let new_id = LoginAttemptId::default();
-        let new_code = TwoFACode::parse(Secret::new("654321".to_string())).unwrap();
+        let new_id = LoginAttemptId::new();
+        let new_code = code_hash("654321");
 
         store.add_code(email.clone(), new_id.clone(), new_code.clone())
             .await
@@ -117,4 +183,69 @@ mod tests {
         assert_eq!(stored_id, new_id);
         assert_eq!(stored_code, new_code);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn should_sweep_expired_codes() {
+        let mut store = HashmapTwoFACodeStore::with_ttl(Duration::from_millis(10));
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let login_attempt_id = LoginAttemptId::new();
+        let code = code_hash("123456");
+
+        store.add_code(email.clone(), login_attempt_id, code)
+            .await
+            .expect("Failed to store code");
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        store.remove_expired_codes()
+            .await
+            .expect("Failed to remove expired codes");
+
+        assert!(store.codes.is_empty());
+        let result = store.get_code(&email).await;
+        assert!(matches!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound)));
+    }
+
+    #[tokio::test]
+    async fn should_take_code_only_once() {
+        let mut store = HashmapTwoFACodeStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let login_attempt_id = LoginAttemptId::new();
+        let code = code_hash("123456");
+
+        store.add_code(email.clone(), login_attempt_id.clone(), code.clone())
+            .await
+            .expect("Failed to store code");
+
+        let (taken_id, taken_code) = store.take_code(&email)
+            .await
+            .expect("Failed to take code");
+        assert_eq!(taken_id, login_attempt_id);
+        assert_eq!(taken_code, code);
+
+        let result = store.take_code(&email).await;
+        assert!(matches!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound)));
+    }
+
+    #[tokio::test]
+    async fn should_count_failed_attempts_until_new_code() {
+        let mut store = HashmapTwoFACodeStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let code = code_hash("123456");
+
+        let result = store.record_failed_attempt(&email).await;
+        assert!(matches!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound)));
+
+        store.add_code(email.clone(), LoginAttemptId::new(), code.clone())
+            .await
+            .expect("Failed to store code");
+        assert_eq!(store.record_failed_attempt(&email).await.unwrap(), 1);
+        assert_eq!(store.record_failed_attempt(&email).await.unwrap(), 2);
+
+        // A new login attempt gets a fresh allowance
+        store.add_code(email.clone(), LoginAttemptId::new(), code)
+            .await
+            .expect("Failed to store code");
+        assert_eq!(store.record_failed_attempt(&email).await.unwrap(), 1);
+    }
+}
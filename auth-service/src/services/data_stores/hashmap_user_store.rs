@@ -1,51 +1,277 @@
+use std::any::Any;
 use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 use async_trait::async_trait;
+use color_eyre::eyre::eyre;
 use secrecy::{ExposeSecret, Secret};
+use uuid::Uuid;
+use crate::app_state::PasswordHasherType;
 use crate::domain::{
-    data_stores::{UserStore, UserStoreError},
+    data_stores::{UserStore, UserStoreError, UserStoreTransaction},
     email::Email,
     password::Password,
+    password_hasher::PasswordVerification,
+    session_ttl::SessionTtl,
+    display_name::DisplayName,
+    locale::Locale,
     user::User,
 };
+use crate::services::argon2_password_hasher::Argon2PasswordHasher;
 
-#[derive(Default)]
+// Stores password hashes like the Postgres store, so tests exercise the same verification path
 pub struct HashmapUserStore {
-    users: HashMap<String, User>,
+    users: Arc<RwLock<HashMap<String, User>>>,
+    password_hasher: PasswordHasherType,
+}
+
+impl HashmapUserStore {
+    pub fn new(password_hasher: PasswordHasherType) -> Self {
+        Self {
+            users: Arc::new(RwLock::new(HashMap::new())),
+            password_hasher,
+        }
+    }
+
+    async fn rehash_password(&self, email: &Email, password: &Password) -> Result<(), UserStoreError> {
+        let password_hash = self
+            .password_hasher
+            .compute_password_hash(password.as_ref().to_owned())
+            .await
+            .map_err(UserStoreError::UnexpectedError)?;
+
+        let mut users = self
+            .users
+            .write()
+            .map_err(|e| UserStoreError::UnexpectedError(eyre!(e.to_string())))?;
+        let user = users
+            .get_mut(email.as_ref().expose_secret())
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.password = Password::parse(password_hash).map_err(UserStoreError::UnexpectedError)?;
+        Ok(())
+    }
+}
+
+impl Default for HashmapUserStore {
+    fn default() -> Self {
+        Self::new(Arc::new(Argon2PasswordHasher::default()))
+    }
+}
+
+// Holds the new user back until commit, so a rolled back signup leaves no trace
+struct HashmapUserStoreTransaction {
+    users: Arc<RwLock<HashMap<String, User>>>,
+    user: User,
 }
 
 #[async_trait]
-impl UserStore for HashmapUserStore {
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
-        let email = user.email.as_ref().expose_secret().to_string();
-        if self.users.contains_key(&email) {
+impl UserStoreTransaction for HashmapUserStoreTransaction {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), UserStoreError> {
+        let email = self.user.email.as_ref().expose_secret().to_string();
+        let mut users = self
+            .users
+            .write()
+            .map_err(|e| UserStoreError::UnexpectedError(eyre!(e.to_string())))?;
+        // Checked again in case the same email signed up while this transaction was open
+        if users.contains_key(&email) {
             return Err(UserStoreError::UserAlreadyExists);
         }
-        self.users.insert(email, user);
+        users.insert(email, self.user);
         Ok(())
     }
+}
+
+#[async_trait]
+impl UserStore for HashmapUserStore {
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError> {
+        self.add_user_tx(user).await?.commit().await
+    }
+
+    async fn add_user_tx(&self, mut user: User) -> Result<Box<dyn UserStoreTransaction>, UserStoreError> {
+        let password_hash = self
+            .password_hasher
+            .compute_password_hash(user.password.as_ref().to_owned())
+            .await
+            .map_err(UserStoreError::UnexpectedError)?;
+        user.password = Password::parse(password_hash).map_err(UserStoreError::UnexpectedError)?;
+
+        let exists = self
+            .users
+            .read()
+            .map_err(|e| UserStoreError::UnexpectedError(eyre!(e.to_string())))?
+            .contains_key(user.email.as_ref().expose_secret());
+        if exists {
+            return Err(UserStoreError::UserAlreadyExists);
+        }
+        Ok(Box::new(HashmapUserStoreTransaction { users: self.users.clone(), user }))
+    }
 
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
         self.users
+            .read()
+            .map_err(|e| UserStoreError::UnexpectedError(eyre!(e.to_string())))?
             .get(email.as_ref().expose_secret())
             .cloned()  // Clone the user to return ownership
             .ok_or(UserStoreError::UserNotFound)
     }
 
+    // Keyed by email, so this scans; fine for the test sizes this store is used at
+    async fn get_user_by_id(&self, id: &Uuid) -> Result<User, UserStoreError> {
+        self.users
+            .read()
+            .map_err(|e| UserStoreError::UnexpectedError(eyre!(e.to_string())))?
+            .values()
+            .find(|user| user.id == *id)
+            .cloned()
+            .ok_or(UserStoreError::UserNotFound)
+    }
+
     async fn validate_user(&self, email: &Email, password: &Password) -> Result<(), UserStoreError> {
-        match self.users.get(email.as_ref().expose_secret()) {
-            Some(user) if user.password.as_ref().expose_secret() == password.as_ref().expose_secret() => Ok(()),
-            _ => Err(UserStoreError::InvalidCredentials),
+        // The lock can't be held across the hash check, which runs on the blocking pool
+        let password_hash = self
+            .users
+            .read()
+            .map_err(|e| UserStoreError::UnexpectedError(eyre!(e.to_string())))?
+            .get(email.as_ref().expose_secret())
+            .map(|user| user.password.as_ref().to_owned())
+            .ok_or(UserStoreError::InvalidCredentials)?;
+
+        let verification = self
+            .password_hasher
+            .verify_password_hash(&password_hash, password.as_ref().to_owned())
+            .await
+            .map_err(|_| UserStoreError::InvalidCredentials)?;
+
+        if verification == PasswordVerification::VerifiedNeedsRehash {
+            // A failed upgrade shouldn't block a login with correct credentials
+            if let Err(e) = self.rehash_password(email, password).await {
+                tracing::error!("Failed to upgrade password hash: {:?}", e);
+            }
         }
+
+        Ok(())
+    }
+
+    async fn count_users(&self) -> Result<u64, UserStoreError> {
+        self.users
+            .read()
+            .map_err(|e| UserStoreError::UnexpectedError(eyre!(e.to_string())))
+            .map(|users| users.len() as u64)
+    }
+
+    async fn set_session_ttl(
+        &self,
+        email: &Email,
+        session_ttl: Option<SessionTtl>,
+    ) -> Result<(), UserStoreError> {
+        let mut users = self
+            .users
+            .write()
+            .map_err(|e| UserStoreError::UnexpectedError(eyre!(e.to_string())))?;
+        let user = users
+            .get_mut(email.as_ref().expose_secret())
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.session_ttl = session_ttl;
+        Ok(())
+    }
+
+    async fn set_display_name(
+        &self,
+        email: &Email,
+        display_name: Option<DisplayName>,
+    ) -> Result<(), UserStoreError> {
+        let mut users = self
+            .users
+            .write()
+            .map_err(|e| UserStoreError::UnexpectedError(eyre!(e.to_string())))?;
+        let user = users
+            .get_mut(email.as_ref().expose_secret())
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.display_name = display_name;
+        Ok(())
+    }
+
+    async fn set_locale(&self, email: &Email, locale: Option<Locale>) -> Result<(), UserStoreError> {
+        let mut users = self
+            .users
+            .write()
+            .map_err(|e| UserStoreError::UnexpectedError(eyre!(e.to_string())))?;
+        let user = users
+            .get_mut(email.as_ref().expose_secret())
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.locale = locale;
+        Ok(())
+    }
+
+    async fn set_admin(&self, email: &Email, is_admin: bool) -> Result<(), UserStoreError> {
+        let mut users = self
+            .users
+            .write()
+            .map_err(|e| UserStoreError::UnexpectedError(eyre!(e.to_string())))?;
+        let user = users
+            .get_mut(email.as_ref().expose_secret())
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.is_admin = is_admin;
+        Ok(())
+    }
+
+    async fn set_active(&self, email: &Email, is_active: bool) -> Result<(), UserStoreError> {
+        let mut users = self
+            .users
+            .write()
+            .map_err(|e| UserStoreError::UnexpectedError(eyre!(e.to_string())))?;
+        let user = users
+            .get_mut(email.as_ref().expose_secret())
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.is_active = is_active;
+        Ok(())
+    }
+
+    async fn set_must_change_password(&self, email: &Email, must_change_password: bool) -> Result<(), UserStoreError> {
+        let mut users = self
+            .users
+            .write()
+            .map_err(|e| UserStoreError::UnexpectedError(eyre!(e.to_string())))?;
+        let user = users
+            .get_mut(email.as_ref().expose_secret())
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.must_change_password = must_change_password;
+        Ok(())
+    }
+
+    async fn update_password(&self, email: &Email, password: Password) -> Result<(), UserStoreError> {
+        let password_hash = self
+            .password_hasher
+            .compute_password_hash(password.as_ref().to_owned())
+            .await
+            .map_err(UserStoreError::UnexpectedError)?;
+
+        let mut users = self
+            .users
+            .write()
+            .map_err(|e| UserStoreError::UnexpectedError(eyre!(e.to_string())))?;
+        let user = users
+            .get_mut(email.as_ref().expose_secret())
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.password = Password::parse(password_hash).map_err(UserStoreError::UnexpectedError)?;
+        user.must_change_password = false;
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use argon2::Params;
+    use sha2::{Digest, Sha256};
+    use crate::domain::password_hasher::{LegacyPreHash, PasswordHasher};
 
     #[tokio::test]
     async fn test_add_user() {
-        let mut store = HashmapUserStore::default();
+        let store = HashmapUserStore::default();
         let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
         let password = Password::parse(Secret::new("password123".to_string())).unwrap();
         let user = User::new(email.clone(), password, false);
@@ -54,9 +280,21 @@ mod tests {
         assert_eq!(store.add_user(user).await, Err(UserStoreError::UserAlreadyExists));
     }
 
+    #[tokio::test]
+    async fn test_add_user_stores_password_hash() {
+        let store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_string())).unwrap();
+        store.add_user(User::new(email.clone(), password, false)).await.unwrap();
+
+        let stored_password = store.get_user(&email).await.unwrap().password;
+        assert_ne!(stored_password.as_ref().expose_secret(), "password123");
+        assert!(stored_password.as_ref().expose_secret().starts_with("$argon2id$"));
+    }
+
     #[tokio::test]
     async fn test_get_user() {
-        let mut store = HashmapUserStore::default();
+        let store = HashmapUserStore::default();
         let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
         let password = Password::parse(Secret::new("password123".to_string())).unwrap();
         let user = User::new(email.clone(), password, false);
@@ -69,18 +307,253 @@ mod tests {
         assert_eq!(store.get_user(&nonexistent_email).await, Err(UserStoreError::UserNotFound));
     }
 
+    #[tokio::test]
+    async fn test_add_user_tx_only_adds_user_on_commit() {
+        let store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_string())).unwrap();
+
+        let tx = store.add_user_tx(User::new(email.clone(), password.clone(), false)).await.unwrap();
+        drop(tx);
+        assert_eq!(store.get_user(&email).await, Err(UserStoreError::UserNotFound));
+
+        let tx = store.add_user_tx(User::new(email.clone(), password, false)).await.unwrap();
+        tx.commit().await.unwrap();
+        assert!(store.get_user(&email).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_get_user_by_id() {
+        let store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_string())).unwrap();
+        let user = User::new(email.clone(), password, false);
+        store.add_user(user.clone()).await.unwrap();
+
+        assert_eq!(store.get_user(&email).await.unwrap().id, user.id);
+        assert_eq!(store.get_user_by_id(&user.id).await.unwrap().email, email);
+        assert_eq!(store.get_user_by_id(&Uuid::new_v4()).await, Err(UserStoreError::UserNotFound));
+    }
+
     #[tokio::test]
     async fn test_validate_user() {
-        let mut store = HashmapUserStore::default();
+        let store = HashmapUserStore::default();
         let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
         let password = Password::parse(Secret::new("password123".to_string())).unwrap();
         let user = User::new(email.clone(), password.clone(), false);
         store.add_user(user).await.unwrap();
         
+        // Checked against the stored hash rather than the plaintext
         assert!(store.validate_user(&email, &password).await.is_ok());
         let wrong_password = Password::parse(Secret::new("wrongpassword".to_string())).unwrap();
         assert_eq!(store.validate_user(&email, &wrong_password).await, Err(UserStoreError::InvalidCredentials));
         let nonexistent_email = Email::parse(Secret::new("nonexistent@example.com".to_string())).unwrap();
         assert_eq!(store.validate_user(&nonexistent_email, &password).await, Err(UserStoreError::InvalidCredentials));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_validate_user_upgrades_legacy_hash() {
+        let legacy_hasher = Arc::new(Argon2PasswordHasher::new(None, Some(LegacyPreHash::Sha256)));
+        let store = HashmapUserStore::new(legacy_hasher.clone());
+        let email = Email::parse(Secret::new("legacy@example.com".to_string())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_string())).unwrap();
+
+        // Seed the user the way a legacy import would, bypassing add_user's hashing
+        let pre_hashed = format!("{:x}", Sha256::digest(b"password123"));
+        let legacy_hash = Argon2PasswordHasher::default()
+            .compute_password_hash(Secret::new(pre_hashed))
+            .await
+            .unwrap();
+        store.users.write().unwrap().insert(
+            "legacy@example.com".to_string(),
+            User::new(email.clone(), Password::parse(legacy_hash.clone()).unwrap(), false),
+        );
+
+        assert_eq!(store.validate_user(&email, &password).await, Ok(()));
+
+        let stored_hash = store.get_user(&email).await.unwrap().password;
+        assert_ne!(stored_hash.as_ref().expose_secret(), legacy_hash.expose_secret());
+        assert_eq!(
+            legacy_hasher
+                .verify_password_hash(stored_hash.as_ref(), password.as_ref().to_owned())
+                .await
+                .unwrap(),
+            PasswordVerification::Verified
+        );
+        assert_eq!(store.validate_user(&email, &password).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_validate_user_upgrades_under_parameterized_hash() {
+        let store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("weak@example.com".to_string())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_string())).unwrap();
+
+        // Hashed before the parameters were raised to the current ones
+        let weak_hash = Argon2PasswordHasher::default()
+            .with_params(Params::new(1024, 1, 1, None).unwrap())
+            .compute_password_hash(password.as_ref().to_owned())
+            .await
+            .unwrap();
+        store.users.write().unwrap().insert(
+            "weak@example.com".to_string(),
+            User::new(email.clone(), Password::parse(weak_hash.clone()).unwrap(), false),
+        );
+
+        assert_eq!(store.validate_user(&email, &password).await, Ok(()));
+
+        let stored_hash = store.get_user(&email).await.unwrap().password;
+        assert_ne!(stored_hash.as_ref().expose_secret(), weak_hash.expose_secret());
+        assert_eq!(
+            Argon2PasswordHasher::default()
+                .verify_password_hash(stored_hash.as_ref(), password.as_ref().to_owned())
+                .await
+                .unwrap(),
+            PasswordVerification::Verified
+        );
+    }
+
+    #[tokio::test]
+    async fn test_count_users() {
+        let store = HashmapUserStore::default();
+        assert_eq!(store.count_users().await.unwrap(), 0);
+
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_string())).unwrap();
+        store.add_user(User::new(email, password, false)).await.unwrap();
+
+        assert_eq!(store.count_users().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_set_session_ttl() {
+        let store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_string())).unwrap();
+        store.add_user(User::new(email.clone(), password, false)).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().session_ttl, None);
+
+        let ttl = SessionTtl::parse(300).unwrap();
+        store.set_session_ttl(&email, Some(ttl)).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().session_ttl, Some(ttl));
+
+        let nonexistent_email = Email::parse(Secret::new("nonexistent@example.com".to_string())).unwrap();
+        assert_eq!(store.set_session_ttl(&nonexistent_email, Some(ttl)).await, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_set_display_name() {
+        let store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_string())).unwrap();
+        store.add_user(User::new(email.clone(), password, false)).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().display_name, None);
+
+        let name = DisplayName::parse("Ada".to_string()).unwrap();
+        store.set_display_name(&email, Some(name.clone())).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().display_name, Some(name.clone()));
+
+        store.set_display_name(&email, None).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().display_name, None);
+
+        let nonexistent_email = Email::parse(Secret::new("nonexistent@example.com".to_string())).unwrap();
+        assert_eq!(store.set_display_name(&nonexistent_email, Some(name)).await, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_set_locale() {
+        let store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_string())).unwrap();
+        store.add_user(User::new(email.clone(), password, false)).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().locale, None);
+
+        store.set_locale(&email, Some(Locale::Es)).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().locale, Some(Locale::Es));
+
+        store.set_locale(&email, None).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().locale, None);
+
+        let nonexistent_email = Email::parse(Secret::new("nonexistent@example.com".to_string())).unwrap();
+        assert_eq!(store.set_locale(&nonexistent_email, Some(Locale::Es)).await, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_set_admin() {
+        let store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_string())).unwrap();
+        store.add_user(User::new(email.clone(), password, false)).await.unwrap();
+        assert!(!store.get_user(&email).await.unwrap().is_admin);
+
+        store.set_admin(&email, true).await.unwrap();
+        assert!(store.get_user(&email).await.unwrap().is_admin);
+
+        store.set_admin(&email, false).await.unwrap();
+        assert!(!store.get_user(&email).await.unwrap().is_admin);
+
+        let nonexistent_email = Email::parse(Secret::new("nonexistent@example.com".to_string())).unwrap();
+        assert_eq!(store.set_admin(&nonexistent_email, true).await, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_set_active() {
+        let store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_string())).unwrap();
+        store.add_user(User::new(email.clone(), password, false)).await.unwrap();
+        assert!(store.get_user(&email).await.unwrap().is_active);
+
+        store.set_active(&email, false).await.unwrap();
+        assert!(!store.get_user(&email).await.unwrap().is_active);
+
+        store.set_active(&email, true).await.unwrap();
+        assert!(store.get_user(&email).await.unwrap().is_active);
+
+        let nonexistent_email = Email::parse(Secret::new("nonexistent@example.com".to_string())).unwrap();
+        assert_eq!(store.set_active(&nonexistent_email, false).await, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_update_password_clears_must_change_password() {
+        let store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let old_password = Password::parse(Secret::new("password123".to_string())).unwrap();
+        let new_password = Password::parse(Secret::new("newpassword456".to_string())).unwrap();
+        store.add_user(User::new(email.clone(), old_password.clone(), false)).await.unwrap();
+
+        store.set_must_change_password(&email, true).await.unwrap();
+        assert!(store.get_user(&email).await.unwrap().must_change_password);
+
+        store.update_password(&email, new_password.clone()).await.unwrap();
+        assert!(!store.get_user(&email).await.unwrap().must_change_password);
+        assert_eq!(store.validate_user(&email, &old_password).await, Err(UserStoreError::InvalidCredentials));
+        assert_eq!(store.validate_user(&email, &new_password).await, Ok(()));
+
+        let nonexistent_email = Email::parse(Secret::new("nonexistent@example.com".to_string())).unwrap();
+        assert_eq!(store.update_password(&nonexistent_email, new_password).await, Err(UserStoreError::UserNotFound));
+        assert_eq!(store.set_must_change_password(&nonexistent_email, true).await, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_add_user() {
+        let store = std::sync::Arc::new(HashmapUserStore::default());
+        let password = Password::parse(Secret::new("password123".to_string())).unwrap();
+
+        let tasks: Vec<_> = (0..20)
+            .map(|i| {
+                let store = store.clone();
+                let password = password.clone();
+                tokio::spawn(async move {
+                    let email = Email::parse(Secret::new(format!("user{}@example.com", i))).unwrap();
+                    store.add_user(User::new(email, password, false)).await
+                })
+            })
+            .collect();
+
+        for result in futures_util::future::join_all(tasks).await {
+            assert!(result.unwrap().is_ok());
+        }
+        assert_eq!(store.count_users().await.unwrap(), 20);
+    }
+}
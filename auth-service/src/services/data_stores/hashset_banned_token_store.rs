@@ -1,20 +1,20 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::RwLock;
 use async_trait::async_trait;
 use color_eyre::eyre::eyre;
-use secrecy::{ExposeSecret, Secret};
-use crate::domain::data_stores::{BannedTokenStore, BannedTokenStoreError};
+use secrecy::Secret;
+use crate::domain::data_stores::{banned_token_id, BannedTokenEntry, BannedTokenStore, BannedTokenStoreError};
 
 #[derive(Default)]
 pub struct HashsetBannedTokenStore {
     tokens: RwLock<HashSet<String>>,
+    not_valid_before: RwLock<Option<usize>>,
+    user_not_valid_before: RwLock<HashMap<String, usize>>,
 }
 
 impl HashsetBannedTokenStore {
     pub fn new() -> Self {
-        Self {
-            tokens: RwLock::new(HashSet::new()),
-        }
+        Self::default()
     }
 }
 
@@ -23,17 +23,73 @@ impl BannedTokenStore for HashsetBannedTokenStore {
     async fn store_token(&self, token: Secret<String>) -> Result<(), BannedTokenStoreError> {
         self.tokens
             .write()
-            .map_err(|e| BannedTokenStoreError::UnexpectedError(eyre!(e).into()))
+            .map_err(|e| BannedTokenStoreError::UnexpectedError(eyre!(e.to_string())))
             .map(|mut tokens| {
-                tokens.insert(token.expose_secret().to_string());
+                tokens.insert(banned_token_id(&token));
             })
     }
 
     async fn contains_token(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError> {
         self.tokens
             .read()
-            .map_err(|e| BannedTokenStoreError::UnexpectedError(eyre!(e).into()))
-            .map(|tokens| tokens.contains(token.expose_secret()))
+            .map_err(|e| BannedTokenStoreError::UnexpectedError(eyre!(e.to_string())))
+            .map(|tokens| tokens.contains(&banned_token_id(token)))
+    }
+
+    async fn contains_tokens(&self, tokens: &[Secret<String>]) -> Result<Vec<bool>, BannedTokenStoreError> {
+        self.tokens
+            .read()
+            .map_err(|e| BannedTokenStoreError::UnexpectedError(eyre!(e.to_string())))
+            .map(|banned| tokens.iter().map(|token| banned.contains(&banned_token_id(token))).collect())
+    }
+
+    async fn list_tokens(&self) -> Result<Vec<BannedTokenEntry>, BannedTokenStoreError> {
+        self.tokens
+            .read()
+            .map_err(|e| BannedTokenStoreError::UnexpectedError(eyre!(e.to_string())))
+            .map(|tokens| {
+                tokens
+                    .iter()
+                    .map(|id| BannedTokenEntry { id: id.clone(), ttl_seconds: None })
+                    .collect()
+            })
+    }
+
+    async fn remove_token(&self, id: &str) -> Result<bool, BannedTokenStoreError> {
+        self.tokens
+            .write()
+            .map_err(|e| BannedTokenStoreError::UnexpectedError(eyre!(e.to_string())))
+            .map(|mut tokens| tokens.remove(id))
+    }
+
+    async fn not_valid_before(&self) -> Result<Option<usize>, BannedTokenStoreError> {
+        self.not_valid_before
+            .read()
+            .map_err(|e| BannedTokenStoreError::UnexpectedError(eyre!(e.to_string())))
+            .map(|cutoff| *cutoff)
+    }
+
+    async fn set_not_valid_before(&self, cutoff: usize) -> Result<(), BannedTokenStoreError> {
+        self.not_valid_before
+            .write()
+            .map_err(|e| BannedTokenStoreError::UnexpectedError(eyre!(e.to_string())))
+            .map(|mut not_valid_before| *not_valid_before = Some(cutoff))
+    }
+
+    async fn user_not_valid_before(&self, subject: &str) -> Result<Option<usize>, BannedTokenStoreError> {
+        self.user_not_valid_before
+            .read()
+            .map_err(|e| BannedTokenStoreError::UnexpectedError(eyre!(e.to_string())))
+            .map(|cutoffs| cutoffs.get(subject).copied())
+    }
+
+    async fn set_user_not_valid_before(&self, subject: &str, cutoff: usize) -> Result<(), BannedTokenStoreError> {
+        self.user_not_valid_before
+            .write()
+            .map_err(|e| BannedTokenStoreError::UnexpectedError(eyre!(e.to_string())))
+            .map(|mut cutoffs| {
+                cutoffs.insert(subject.to_owned(), cutoff);
+            })
     }
 }
 
@@ -81,4 +137,58 @@ mod tests {
         // Non-existent token should not exist
         assert!(!store.contains_token(&Secret::new("nonexistent".to_string())).await.unwrap());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_contains_tokens() {
+        let store = HashsetBannedTokenStore::default();
+        let banned = Secret::new("banned_token".to_string());
+        store.store_token(banned.clone()).await.unwrap();
+
+        let tokens = [Secret::new("other_token".to_string()), banned];
+        assert_eq!(store.contains_tokens(&tokens).await.unwrap(), vec![false, true]);
+    }
+
+    #[tokio::test]
+    async fn test_list_tokens() {
+        let store = HashsetBannedTokenStore::default();
+        let token = Secret::new("test_token".to_string());
+        store.store_token(token.clone()).await.unwrap();
+
+        let entries = store.list_tokens().await.unwrap();
+        assert_eq!(entries, vec![BannedTokenEntry { id: banned_token_id(&token), ttl_seconds: None }]);
+    }
+
+    #[tokio::test]
+    async fn test_remove_token() {
+        let store = HashsetBannedTokenStore::default();
+        let token = Secret::new("test_token".to_string());
+        store.store_token(token.clone()).await.unwrap();
+
+        assert!(store.remove_token(&banned_token_id(&token)).await.unwrap());
+        assert!(!store.contains_token(&token).await.unwrap());
+
+        // Removing an unknown id is not an error
+        assert!(!store.remove_token("unknown").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_not_valid_before() {
+        let store = HashsetBannedTokenStore::default();
+        assert_eq!(store.not_valid_before().await.unwrap(), None);
+
+        store.set_not_valid_before(1_700_000_000).await.unwrap();
+        assert_eq!(store.not_valid_before().await.unwrap(), Some(1_700_000_000));
+    }
+
+    #[tokio::test]
+    async fn test_user_not_valid_before() {
+        let store = HashsetBannedTokenStore::default();
+        assert_eq!(store.user_not_valid_before("ada@example.com").await.unwrap(), None);
+
+        store.set_user_not_valid_before("ada@example.com", 1_700_000_000_500).await.unwrap();
+        assert_eq!(store.user_not_valid_before("ada@example.com").await.unwrap(), Some(1_700_000_000_500));
+        // Other users and the global cutoff are unaffected
+        assert_eq!(store.user_not_valid_before("bob@example.com").await.unwrap(), None);
+        assert_eq!(store.not_valid_before().await.unwrap(), None);
+    }
+}
@@ -0,0 +1,43 @@
+use std::sync::RwLock;
+use async_trait::async_trait;
+use color_eyre::eyre::eyre;
+use crate::domain::data_stores::{AdminKeyStore, AdminKeyStoreError};
+
+// A rotated key only lives as long as the process and isn't shared with other instances
+#[derive(Default)]
+pub struct InMemoryAdminKeyStore {
+    key_hash: RwLock<Option<String>>,
+}
+
+#[async_trait]
+impl AdminKeyStore for InMemoryAdminKeyStore {
+    async fn get_key_hash(&self) -> Result<Option<String>, AdminKeyStoreError> {
+        self.key_hash
+            .read()
+            .map_err(|e| AdminKeyStoreError::UnexpectedError(eyre!(e.to_string())))
+            .map(|key_hash| key_hash.clone())
+    }
+
+    async fn set_key_hash(&self, key_hash: String) -> Result<(), AdminKeyStoreError> {
+        *self
+            .key_hash
+            .write()
+            .map_err(|e| AdminKeyStoreError::UnexpectedError(eyre!(e.to_string())))? = Some(key_hash);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_set_key_hash_replaces_previous_hash() {
+        let store = InMemoryAdminKeyStore::default();
+        assert_eq!(store.get_key_hash().await.unwrap(), None);
+
+        store.set_key_hash("first".to_owned()).await.unwrap();
+        store.set_key_hash("second".to_owned()).await.unwrap();
+        assert_eq!(store.get_key_hash().await.unwrap(), Some("second".to_owned()));
+    }
+}
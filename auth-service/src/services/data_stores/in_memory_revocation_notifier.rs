@@ -0,0 +1,49 @@
+use async_trait::async_trait;
+use tokio::sync::broadcast;
+use crate::{
+    domain::data_stores::{RevocationNotifier, RevocationNotifierError},
+    utils::constants::REVOCATION_EVENT_BUFFER,
+};
+
+// Only reaches subscribers connected to this instance
+pub struct InMemoryRevocationNotifier {
+    sender: broadcast::Sender<String>,
+}
+
+impl Default for InMemoryRevocationNotifier {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(REVOCATION_EVENT_BUFFER);
+        Self { sender }
+    }
+}
+
+#[async_trait]
+impl RevocationNotifier for InMemoryRevocationNotifier {
+    async fn publish(&self, token_id: &str) -> Result<(), RevocationNotifierError> {
+        // Sending only fails when nobody is subscribed, which just means there's no one to tell
+        let _ = self.sender.send(token_id.to_owned());
+        Ok(())
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.sender.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn subscribers_receive_revocations_published_after_subscribing() {
+        let notifier = InMemoryRevocationNotifier::default();
+        notifier.publish("before").await.unwrap();
+
+        let mut first = notifier.subscribe();
+        let mut second = notifier.subscribe();
+        notifier.publish("after").await.unwrap();
+
+        assert_eq!(first.recv().await.unwrap(), "after");
+        assert_eq!(second.recv().await.unwrap(), "after");
+    }
+}
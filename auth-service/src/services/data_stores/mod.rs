@@ -1,13 +1,56 @@
+pub mod hashmap_login_failure_store;
+pub mod hashmap_trusted_device_store;
 pub mod hashmap_two_fa_code_store;
 pub mod hashmap_user_store;
 pub mod hashset_banned_token_store;
+pub mod in_memory_admin_key_store;
+pub mod in_memory_revocation_notifier;
+pub mod postgres_audit_log;
+pub mod postgres_two_fa_delivery_log;
 pub mod postgres_user_store;
+pub mod redis_admin_key_store;
 pub mod redis_banned_token_store;
+pub mod redis_login_failure_store;
+pub mod redis_revocation_notifier;
+pub mod redis_trusted_device_store;
 pub mod redis_two_fa_code_store;
 
+pub use hashmap_login_failure_store::*;
+pub use hashmap_trusted_device_store::*;
 pub use hashmap_two_fa_code_store::*;
 pub use hashmap_user_store::*;
 pub use hashset_banned_token_store::*;
+pub use in_memory_admin_key_store::*;
+pub use in_memory_revocation_notifier::*;
+pub use postgres_audit_log::*;
+pub use postgres_two_fa_delivery_log::*;
 pub use postgres_user_store::*;
+pub use redis_admin_key_store::*;
 pub use redis_banned_token_store::*;
-pub use redis_two_fa_code_store::*;
\ No newline at end of file
+pub use redis_login_failure_store::*;
+pub use redis_revocation_notifier::*;
+pub use redis_trusted_device_store::*;
+pub use redis_two_fa_code_store::*;
+
+// Prepended to every key and channel a Redis store uses, so deployments sharing one Redis instance
+// don't see each other's data. Empty unless REDIS_KEY_PREFIX is set.
+#[derive(Clone, Debug, Default)]
+pub struct RedisKeyPrefix(String);
+
+impl RedisKeyPrefix {
+    pub fn key(&self, key: impl std::fmt::Display) -> String {
+        format!("{}{}", self.0, key)
+    }
+}
+
+impl From<String> for RedisKeyPrefix {
+    fn from(prefix: String) -> Self {
+        Self(prefix)
+    }
+}
+
+impl From<&str> for RedisKeyPrefix {
+    fn from(prefix: &str) -> Self {
+        Self(prefix.to_owned())
+    }
+}
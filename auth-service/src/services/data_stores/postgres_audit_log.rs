@@ -0,0 +1,214 @@
+use async_trait::async_trait;
+use color_eyre::eyre::eyre;
+use secrecy::ExposeSecret;
+use sqlx::{PgExecutor, PgPool};
+use crate::{
+    domain::{
+        data_stores::{AuditEvent, AuditEventType, AuditLog, AuditLogError, AuditQuery, UserStoreTransaction},
+        email::Email,
+    },
+    services::data_stores::postgres_user_store::PostgresUserStoreTransaction,
+};
+
+#[derive(Clone)]
+pub struct PostgresAuditLog {
+    pool: PgPool,
+}
+
+impl PostgresAuditLog {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AuditLog for PostgresAuditLog {
+    #[tracing::instrument(name = "Recording audit event in PostgreSQL", skip_all)]
+    async fn record(&self, email: &Email, event_type: AuditEventType) -> Result<(), AuditLogError> {
+        insert_event(&self.pool, email, event_type).await
+    }
+
+    // Assumes the user store's transaction is in this log's database, as it is when both are
+    // configured from the same DATABASE_URL
+    #[tracing::instrument(name = "Recording audit event in PostgreSQL transaction", skip_all)]
+    async fn record_tx(
+        &self,
+        tx: &mut dyn UserStoreTransaction,
+        email: &Email,
+        event_type: AuditEventType,
+    ) -> Result<(), AuditLogError> {
+        match tx.as_any_mut().downcast_mut::<PostgresUserStoreTransaction>() {
+            Some(tx) => insert_event(tx.connection(), email, event_type).await,
+            None => self.record(email, event_type).await,
+        }
+    }
+
+    // Pages by id rather than offset, so events recorded while paging don't shift later pages.
+    // The filters are served by the (column, created_at) indexes.
+    #[tracing::instrument(name = "Querying audit events in PostgreSQL", skip_all)]
+    async fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEvent>, AuditLogError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, email, user_id, event_type, EXTRACT(EPOCH FROM created_at)::BIGINT AS "timestamp!"
+            FROM audit_events
+            WHERE ($1::TEXT IS NULL OR email = $1 OR user_id = (SELECT id FROM users WHERE email = $1))
+              AND ($2::TEXT IS NULL OR event_type = $2)
+              AND ($3::BIGINT IS NULL OR created_at >= to_timestamp($3))
+              AND ($4::BIGINT IS NULL OR created_at <= to_timestamp($4))
+              AND ($5::BIGINT IS NULL OR id < $5)
+            ORDER BY id DESC
+            LIMIT $6
+            "#,
+            query.email.as_ref().map(|email| email.as_ref().expose_secret().as_str()),
+            query.event_type.map(|event_type| event_type.as_str()),
+            query.from,
+            query.to,
+            query.before_id,
+            i64::from(query.limit)
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AuditLogError::UnexpectedError(e.into()))?;
+
+        rows.into_iter()
+            .map(|row| {
+                let event_type = AuditEventType::parse(&row.event_type).ok_or_else(|| {
+                    AuditLogError::UnexpectedError(eyre!("Unknown audit event type {}", row.event_type))
+                })?;
+                Ok(AuditEvent {
+                    id: row.id,
+                    email: row.email,
+                    user_id: row.user_id,
+                    event_type,
+                    timestamp: row.timestamp,
+                })
+            })
+            .collect()
+    }
+}
+
+// The user is looked up by the email at the time of the event, so later email changes don't
+// detach it
+async fn insert_event<'e>(
+    executor: impl PgExecutor<'e>,
+    email: &Email,
+    event_type: AuditEventType,
+) -> Result<(), AuditLogError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_events (email, user_id, event_type)
+        VALUES ($1, (SELECT id FROM users WHERE email = $1), $2)
+        "#,
+        email.as_ref().expose_secret(),
+        event_type.as_str()
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| AuditLogError::UnexpectedError(e.into()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use secrecy::Secret;
+    use crate::{
+        domain::{data_stores::UserStore, password::Password, user::User},
+        services::{
+            argon2_password_hasher::Argon2PasswordHasher,
+            data_stores::postgres_user_store::PostgresUserStore,
+        },
+    };
+
+    fn email(s: &str) -> Email {
+        Email::parse(Secret::new(s.to_owned())).unwrap()
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn query_filters_and_pages_newest_first(pool: PgPool) {
+        let log = PostgresAuditLog::new(pool);
+        let (ada, bob) = (email("ada@example.com"), email("bob@example.com"));
+        log.record(&ada, AuditEventType::Signup).await.unwrap();
+        log.record(&ada, AuditEventType::LoginFailed).await.unwrap();
+        log.record(&bob, AuditEventType::LoginSucceeded).await.unwrap();
+        log.record(&ada, AuditEventType::LoginSucceeded).await.unwrap();
+
+        let query = AuditQuery { email: Some(ada.clone()), limit: 2, ..Default::default() };
+        let first_page = log.query(&query).await.unwrap();
+        let types: Vec<_> = first_page.iter().map(|event| event.event_type).collect();
+        assert_eq!(types, [AuditEventType::LoginSucceeded, AuditEventType::LoginFailed]);
+
+        let query = AuditQuery { before_id: Some(first_page[1].id), ..query };
+        let second_page = log.query(&query).await.unwrap();
+        let types: Vec<_> = second_page.iter().map(|event| event.event_type).collect();
+        assert_eq!(types, [AuditEventType::Signup]);
+
+        let query = AuditQuery { event_type: Some(AuditEventType::LoginSucceeded), limit: 10, ..Default::default() };
+        let emails: Vec<_> = log.query(&query).await.unwrap().into_iter().map(|event| event.email).collect();
+        assert_eq!(emails, ["ada@example.com", "bob@example.com"]);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn query_filters_by_time_range(pool: PgPool) {
+        let log = PostgresAuditLog::new(pool);
+        log.record(&email("ada@example.com"), AuditEventType::Signup).await.unwrap();
+
+        let now = chrono::Utc::now().timestamp();
+        let query = |from, to| AuditQuery { from, to, limit: 10, ..Default::default() };
+        assert_eq!(log.query(&query(Some(now - 60), Some(now + 60))).await.unwrap().len(), 1);
+        assert!(log.query(&query(Some(now + 60), None)).await.unwrap().is_empty());
+        assert!(log.query(&query(None, Some(now - 60))).await.unwrap().is_empty());
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn events_recorded_in_a_transaction_land_with_the_user(pool: PgPool) {
+        let log = PostgresAuditLog::new(pool.clone());
+        let store = PostgresUserStore::new(pool, Arc::new(Argon2PasswordHasher::default()));
+        let ada = email("ada@example.com");
+        let user = || User::new(ada.clone(), Password::parse(Secret::new("password123".to_owned())).unwrap(), false);
+        let query = AuditQuery { email: Some(ada.clone()), limit: 10, ..Default::default() };
+
+        let mut tx = store.add_user_tx(user()).await.unwrap();
+        log.record_tx(tx.as_mut(), &ada, AuditEventType::Signup).await.unwrap();
+        drop(tx);
+        assert!(log.query(&query).await.unwrap().is_empty());
+
+        let new_user = user();
+        let mut tx = store.add_user_tx(new_user.clone()).await.unwrap();
+        log.record_tx(tx.as_mut(), &ada, AuditEventType::Signup).await.unwrap();
+        tx.commit().await.unwrap();
+        let events = log.query(&query).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].user_id, Some(new_user.id));
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn events_follow_the_user_across_email_changes(pool: PgPool) {
+        let log = PostgresAuditLog::new(pool.clone());
+        let (user_id,): (uuid::Uuid,) = sqlx::query_as(
+            "INSERT INTO users (email, password_hash) VALUES ('old@example.com', 'hash') RETURNING id",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        log.record(&email("old@example.com"), AuditEventType::Signup).await.unwrap();
+        log.record(&email("unknown@example.com"), AuditEventType::LoginFailed).await.unwrap();
+
+        sqlx::query("UPDATE users SET email = 'new@example.com' WHERE id = $1")
+            .bind(user_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let query = AuditQuery { email: Some(email("new@example.com")), limit: 10, ..Default::default() };
+        let events = log.query(&query).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].email, "old@example.com");
+        assert_eq!(events[0].user_id, Some(user_id));
+
+        let query = AuditQuery { email: Some(email("unknown@example.com")), limit: 10, ..Default::default() };
+        assert_eq!(log.query(&query).await.unwrap()[0].user_id, None);
+    }
+}
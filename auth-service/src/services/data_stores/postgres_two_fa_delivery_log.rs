@@ -0,0 +1,60 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+use crate::domain::{
+    data_stores::{delivery_email_hash, TwoFaDeliveryLog, TwoFaDeliveryLogError},
+    email::Email,
+};
+
+#[derive(Clone)]
+pub struct PostgresTwoFaDeliveryLog {
+    pool: PgPool,
+}
+
+impl PostgresTwoFaDeliveryLog {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl TwoFaDeliveryLog for PostgresTwoFaDeliveryLog {
+    #[tracing::instrument(name = "Recording 2FA delivery in PostgreSQL", skip_all)]
+    async fn record_delivery(&self, email: &Email, delivered: bool) -> Result<(), TwoFaDeliveryLogError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO two_fa_deliveries (email_hash, delivered)
+            VALUES ($1, $2)
+            "#,
+            delivery_email_hash(email),
+            delivered
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| TwoFaDeliveryLogError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::{ExposeSecret, Secret};
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn records_hashed_email_and_result(pool: PgPool) {
+        let log = PostgresTwoFaDeliveryLog::new(pool.clone());
+        let email = Email::parse(Secret::new("user@example.com".to_owned())).unwrap();
+
+        log.record_delivery(&email, true).await.unwrap();
+        log.record_delivery(&email, false).await.unwrap();
+
+        let rows: Vec<(String, bool)> =
+            sqlx::query_as("SELECT email_hash, delivered FROM two_fa_deliveries ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(rows, vec![(delivery_email_hash(&email), true), (delivery_email_hash(&email), false)]);
+        assert!(rows.iter().all(|(hash, _)| hash != email.as_ref().expose_secret()));
+    }
+}
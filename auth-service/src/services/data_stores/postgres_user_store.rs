@@ -1,73 +1,198 @@
-use color_eyre::eyre::{eyre, Context, Result};
-use argon2::{
-    password_hash::SaltString, 
-    Algorithm, 
-    Argon2, 
-    Params, 
-    PasswordHash, 
-    PasswordHasher,
-    PasswordVerifier, 
-    Version,
-};
-use sqlx::PgPool;
+use std::any::Any;
+use color_eyre::eyre::eyre;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
 use async_trait::async_trait;
+use uuid::Uuid;
 use secrecy::{ExposeSecret, Secret};
+use crate::app_state::PasswordHasherType;
 use crate::domain::{
-    data_stores::{UserStore, UserStoreError},
+    data_stores::{UserStore, UserStoreError, UserStoreTransaction},
     email::Email,
     password::Password,
+    password_hasher::PasswordVerification,
+    session_ttl::SessionTtl,
+    display_name::DisplayName,
+    locale::Locale,
     user::User,
 };
 
+#[derive(Clone)]
 pub struct PostgresUserStore {
     pool: PgPool,
+    password_hasher: PasswordHasherType,
 }
 
 impl PostgresUserStore {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    pub fn new(pool: PgPool, password_hasher: PasswordHasherType) -> Self {
+        Self { pool, password_hasher }
+    }
+}
+
+// A `users` row as selected by the lookups, before its columns are parsed into domain types
+struct UserRow {
+    id: Uuid,
+    email: String,
+    password_hash: String,
+    requires_2fa: bool,
+    session_ttl_secs: Option<i32>,
+    display_name: Option<String>,
+    is_admin: bool,
+    is_active: bool,
+    must_change_password: bool,
+    locale: Option<String>,
+}
+
+impl TryFrom<UserRow> for User {
+    type Error = UserStoreError;
+
+    fn try_from(user: UserRow) -> Result<Self, Self::Error> {
+        Ok(User {
+            id: user.id,
+            email: Email::parse(Secret::new(user.email))
+                .map_err(|e| UserStoreError::UnexpectedError(eyre!(e)))?,
+            password: Password::parse(Secret::new(user.password_hash))
+                .map_err(|e| UserStoreError::UnexpectedError(eyre!(e)))?,
+            requires_2fa: user.requires_2fa,
+            session_ttl: user
+                .session_ttl_secs
+                .map(|secs| u64::try_from(secs).map_err(|e| eyre!(e)).and_then(SessionTtl::parse))
+                .transpose()
+                .map_err(UserStoreError::UnexpectedError)?,
+            display_name: user
+                .display_name
+                .map(DisplayName::parse)
+                .transpose()
+                .map_err(UserStoreError::UnexpectedError)?,
+            is_admin: user.is_admin,
+            is_active: user.is_active,
+            must_change_password: user.must_change_password,
+            locale: user
+                .locale
+                .map(|tag| {
+                    Locale::from_tag(&tag).ok_or_else(|| UserStoreError::UnexpectedError(eyre!("Unsupported locale {}", tag)))
+                })
+                .transpose()?,
+        })
+    }
+}
+
+// sqlx rolls the transaction back when it's dropped uncommitted
+pub(crate) struct PostgresUserStoreTransaction {
+    tx: Transaction<'static, Postgres>,
+}
+
+impl PostgresUserStoreTransaction {
+    // For other stores in the same database to write through
+    pub(crate) fn connection(&mut self) -> &mut PgConnection {
+        &mut self.tx
+    }
+}
+
+#[async_trait]
+impl UserStoreTransaction for PostgresUserStoreTransaction {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    #[tracing::instrument(name = "Committing user transaction in PostgreSQL", skip_all)]
+    async fn commit(self: Box<Self>) -> Result<(), UserStoreError> {
+        self.tx
+            .commit()
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))
+    }
+}
+
+impl PostgresUserStore {
+    #[tracing::instrument(name = "Upgrading password hash in PostgreSQL", skip_all)]
+    async fn rehash_password(&self, email: &Email, password: &Password) -> Result<(), UserStoreError> {
+        let password_hash = self
+            .password_hasher
+            .compute_password_hash(password.as_ref().to_owned())
+            .await
+            .map_err(UserStoreError::UnexpectedError)?;
+
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET password_hash = $1
+            WHERE email = $2
+            "#,
+            password_hash.expose_secret(),
+            email.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        tracing::info!("Upgraded password hash");
+        Ok(())
     }
 }
 
 #[async_trait]
 impl UserStore for PostgresUserStore {
     #[tracing::instrument(name = "Adding user to PostgreSQL", skip_all)]
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
-        let password_hash = compute_password_hash(user.password.as_ref().to_owned())
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError> {
+        self.add_user_tx(user).await?.commit().await
+    }
+
+    #[tracing::instrument(name = "Adding user to PostgreSQL in a transaction", skip_all)]
+    async fn add_user_tx(&self, user: User) -> Result<Box<dyn UserStoreTransaction>, UserStoreError> {
+        // Hashed before the transaction opens so it isn't held across the slow part
+        let password_hash = self
+            .password_hasher
+            .compute_password_hash(user.password.as_ref().to_owned())
             .await
             .map_err(UserStoreError::UnexpectedError)?;
 
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
         sqlx::query!(
             r#"
-            INSERT INTO users (email, password_hash, requires_2fa)
-            VALUES ($1, $2, $3)
+            INSERT INTO users (id, email, password_hash, requires_2fa, display_name, is_admin, is_active, must_change_password,
+                locale)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
             "#,
+            user.id,
             user.email.as_ref().expose_secret(),
             password_hash.expose_secret(),
-            user.requires_2fa
+            user.requires_2fa,
+            user.display_name.as_ref().map(AsRef::<str>::as_ref),
+            user.is_admin,
+            user.is_active,
+            user.must_change_password,
+            user.locale.map(|locale| locale.tag())
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await
-        .map_err(|e| {
-            if e.as_database_error()
-                .and_then(|e| e.constraint())
-                .unwrap_or_default()
-                == "users_pkey"
-            {
+        .map_err(|e| match e.as_database_error() {
+            // Any unique constraint, not just the email, means the user would clash
+            // with an existing one
+            Some(db_error) if db_error.is_unique_violation() => {
+                tracing::debug!(
+                    "User conflicts with an existing one on {}",
+                    db_error.constraint().unwrap_or("an unnamed constraint")
+                );
                 UserStoreError::UserAlreadyExists
-            } else {
-                UserStoreError::UnexpectedError(e.into())
             }
+            _ => UserStoreError::UnexpectedError(e.into()),
         })?;
 
-        Ok(())
+        Ok(Box::new(PostgresUserStoreTransaction { tx }))
     }
 
     #[tracing::instrument(name = "Retrieving user from PostgreSQL", skip_all)]
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
-        let user = sqlx::query!(
+        let user = sqlx::query_as!(
+            UserRow,
             r#"
-            SELECT email, password_hash, requires_2fa
+            SELECT id, email, password_hash, requires_2fa, session_ttl_secs, display_name, is_admin, is_active,
+                must_change_password, locale
             FROM users
             WHERE email = $1
             "#,
@@ -78,13 +203,27 @@ impl UserStore for PostgresUserStore {
         .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
         .ok_or(UserStoreError::UserNotFound)?;
 
-        Ok(User {
-            email: Email::parse(Secret::new(user.email))
-                .map_err(|e| UserStoreError::UnexpectedError(eyre!(e)))?,
-            password: Password::parse(Secret::new(user.password_hash))
-                .map_err(|e| UserStoreError::UnexpectedError(eyre!(e)))?,
-            requires_2fa: user.requires_2fa,
-        })
+        user.try_into()
+    }
+
+    #[tracing::instrument(name = "Retrieving user by ID from PostgreSQL", skip_all)]
+    async fn get_user_by_id(&self, id: &Uuid) -> Result<User, UserStoreError> {
+        let user = sqlx::query_as!(
+            UserRow,
+            r#"
+            SELECT id, email, password_hash, requires_2fa, session_ttl_secs, display_name, is_admin, is_active,
+                must_change_password, locale
+            FROM users
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        user.try_into()
     }
 
     #[tracing::instrument(name = "Validating user credentials in PostgreSQL", skip_all)]
@@ -102,54 +241,511 @@ impl UserStore for PostgresUserStore {
         .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
         .ok_or(UserStoreError::InvalidCredentials)?;
 
-        verify_password_hash(&Secret::new(stored_user.password_hash), password.as_ref().to_owned())
+        let verification = self
+            .password_hasher
+            .verify_password_hash(
+                &Secret::new(stored_user.password_hash),
+                password.as_ref().to_owned(),
+            )
             .await
             .map_err(|_| UserStoreError::InvalidCredentials)?;
 
+        if verification == PasswordVerification::VerifiedNeedsRehash {
+            // A failed upgrade shouldn't block a login with correct credentials
+            if let Err(e) = self.rehash_password(email, password).await {
+                tracing::error!("Failed to upgrade password hash: {:?}", e);
+            }
+        }
+
         Ok(())
     }
-}
 
-#[tracing::instrument(name = "Verifying password hash", skip_all)]
-async fn verify_password_hash(
-    expected_password_hash: &Secret<String>,
-    password_candidate: Secret<String>,
-) -> Result<()> {
-    let current_span: tracing::Span = tracing::Span::current();
-    let expected_hash = expected_password_hash.clone();
-    let result = tokio::task::spawn_blocking(move || {
-        current_span.in_scope(|| {
-            let expected_password_hash: PasswordHash<'_> =
-                PasswordHash::new(expected_hash.expose_secret())?;
-
-            Argon2::default()
-                .verify_password(password_candidate.expose_secret().as_bytes(), &expected_password_hash)
-                .wrap_err("failed to verify password hash")
-        })
-    })
-    .await;
+    #[tracing::instrument(name = "Counting users in PostgreSQL", skip_all)]
+    async fn count_users(&self) -> Result<u64, UserStoreError> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM users
+            "#
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        count
+            .try_into()
+            .map_err(|e| UserStoreError::UnexpectedError(eyre!("Invalid user count: {}", e)))
+    }
+
+    #[tracing::instrument(name = "Setting session TTL in PostgreSQL", skip_all)]
+    async fn set_session_ttl(
+        &self,
+        email: &Email,
+        session_ttl: Option<SessionTtl>,
+    ) -> Result<(), UserStoreError> {
+        let session_ttl_secs = session_ttl
+            .map(|ttl| i32::try_from(ttl.as_secs()))
+            .transpose()
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET session_ttl_secs = $1
+            WHERE email = $2
+            "#,
+            session_ttl_secs,
+            email.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Setting display name in PostgreSQL", skip_all)]
+    async fn set_display_name(
+        &self,
+        email: &Email,
+        display_name: Option<DisplayName>,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET display_name = $1
+            WHERE email = $2
+            "#,
+            display_name.as_ref().map(AsRef::<str>::as_ref),
+            email.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Setting locale in PostgreSQL", skip_all)]
+    async fn set_locale(&self, email: &Email, locale: Option<Locale>) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET locale = $1
+            WHERE email = $2
+            "#,
+            locale.map(|locale| locale.tag()),
+            email.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Setting admin flag in PostgreSQL", skip_all)]
+    async fn set_admin(&self, email: &Email, is_admin: bool) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET is_admin = $1
+            WHERE email = $2
+            "#,
+            is_admin,
+            email.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Setting active flag in PostgreSQL", skip_all)]
+    async fn set_active(&self, email: &Email, is_active: bool) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET is_active = $1
+            WHERE email = $2
+            "#,
+            is_active,
+            email.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Setting must_change_password in PostgreSQL", skip_all)]
+    async fn set_must_change_password(&self, email: &Email, must_change_password: bool) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET must_change_password = $1
+            WHERE email = $2
+            "#,
+            must_change_password,
+            email.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Updating password in PostgreSQL", skip_all)]
+    async fn update_password(&self, email: &Email, password: Password) -> Result<(), UserStoreError> {
+        let password_hash = self
+            .password_hasher
+            .compute_password_hash(password.as_ref().to_owned())
+            .await
+            .map_err(UserStoreError::UnexpectedError)?;
 
-    result?
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET password_hash = $1, must_change_password = FALSE
+            WHERE email = $2
+            "#,
+            password_hash.expose_secret(),
+            email.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
 }
 
-#[tracing::instrument(name = "Computing password hash", skip_all)]
-async fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>> {
-    let current_span: tracing::Span = tracing::Span::current();
-    let result = tokio::task::spawn_blocking(move || {
-        current_span.in_scope(|| {
-            let salt: SaltString = SaltString::generate(&mut rand::thread_rng());
-            let password_hash = Argon2::new(
-                Algorithm::Argon2id,
-                Version::V0x13,
-                Params::new(15000, 2, 1, None)?,
-            )
-            .hash_password(password.expose_secret().as_bytes(), &salt)?
-            .to_string();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use argon2::Params;
+    use sha2::{Digest, Sha256};
+    use crate::{
+        domain::password_hasher::{LegacyPreHash, PasswordHasher},
+        services::argon2_password_hasher::Argon2PasswordHasher,
+    };
 
-            Ok(Secret::new(password_hash))
-        })
-    })
-    .await;
+    #[sqlx::test(migrations = "./migrations")]
+    async fn legacy_hash_is_upgraded_after_successful_login(pool: PgPool) {
+        let legacy_hasher = Arc::new(Argon2PasswordHasher::new(None, Some(LegacyPreHash::Sha256)));
+        let store = PostgresUserStore::new(pool.clone(), legacy_hasher.clone());
+
+        let email = Email::parse(Secret::new("legacy@example.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_owned())).unwrap();
+
+        // Seed the row the way a legacy import would: Argon2 over the hex SHA-256 of the password
+        let pre_hashed = format!("{:x}", Sha256::digest(b"password123"));
+        let legacy_hash = Argon2PasswordHasher::default()
+            .compute_password_hash(Secret::new(pre_hashed))
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO users (email, password_hash, requires_2fa) VALUES ($1, $2, false)")
+            .bind(email.as_ref().expose_secret())
+            .bind(legacy_hash.expose_secret())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(store.validate_user(&email, &password).await, Ok(()));
+
+        let (stored_hash,): (String,) = sqlx::query_as("SELECT password_hash FROM users WHERE email = $1")
+            .bind(email.as_ref().expose_secret())
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_ne!(stored_hash, *legacy_hash.expose_secret());
+        assert_eq!(
+            legacy_hasher
+                .verify_password_hash(&Secret::new(stored_hash), password.as_ref().to_owned())
+                .await
+                .unwrap(),
+            PasswordVerification::Verified
+        );
+
+        // The upgraded hash keeps working for subsequent logins
+        assert_eq!(store.validate_user(&email, &password).await, Ok(()));
+    }
 
-    result?
-}
\ No newline at end of file
+    #[sqlx::test(migrations = "./migrations")]
+    async fn under_parameterized_hash_is_upgraded_after_successful_login(pool: PgPool) {
+        let store = PostgresUserStore::new(pool.clone(), Arc::new(Argon2PasswordHasher::default()));
+        let email = Email::parse(Secret::new("weak@example.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_owned())).unwrap();
+
+        // Hashed before the parameters were raised to the current ones
+        let weak_hash = Argon2PasswordHasher::default()
+            .with_params(Params::new(1024, 1, 1, None).unwrap())
+            .compute_password_hash(password.as_ref().to_owned())
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO users (email, password_hash, requires_2fa) VALUES ($1, $2, false)")
+            .bind(email.as_ref().expose_secret())
+            .bind(weak_hash.expose_secret())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(store.validate_user(&email, &password).await, Ok(()));
+
+        let (stored_hash,): (String,) = sqlx::query_as("SELECT password_hash FROM users WHERE email = $1")
+            .bind(email.as_ref().expose_secret())
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_ne!(stored_hash, *weak_hash.expose_secret());
+        assert_eq!(
+            Argon2PasswordHasher::default()
+                .verify_password_hash(&Secret::new(stored_hash), password.as_ref().to_owned())
+                .await
+                .unwrap(),
+            PasswordVerification::Verified
+        );
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn concurrent_reads_and_writes_do_not_block_each_other(pool: PgPool) {
+        let store = PostgresUserStore::new(pool, Arc::new(Argon2PasswordHasher::default()));
+        let password = Password::parse(Secret::new("password123".to_owned())).unwrap();
+
+        let existing = Email::parse(Secret::new("existing@example.com".to_owned())).unwrap();
+        store.add_user(User::new(existing.clone(), password.clone(), false)).await.unwrap();
+
+        // Each task gets its own clone of the store; they only share the connection pool
+        let mut tasks = Vec::new();
+        for i in 0..10 {
+            let store = store.clone();
+            let password = password.clone();
+            let existing = existing.clone();
+            tasks.push(tokio::spawn(async move {
+                let email = Email::parse(Secret::new(format!("user{}@example.com", i))).unwrap();
+                store.add_user(User::new(email.clone(), password, false)).await?;
+                store.get_user(&existing).await?;
+                store.get_user(&email).await
+            }));
+        }
+
+        for result in futures_util::future::join_all(tasks).await {
+            assert!(result.unwrap().is_ok());
+        }
+        assert_eq!(store.count_users().await.unwrap(), 11);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn user_can_be_fetched_by_id(pool: PgPool) {
+        let store = PostgresUserStore::new(pool, Arc::new(Argon2PasswordHasher::default()));
+        let email = Email::parse(Secret::new("by-id@example.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_owned())).unwrap();
+
+        let user = User::new(email.clone(), password, false);
+        let id = user.id;
+        store.add_user(user).await.unwrap();
+
+        assert_eq!(store.get_user(&email).await.unwrap().id, id);
+        assert_eq!(store.get_user_by_id(&id).await.unwrap().email, email);
+        assert_eq!(store.get_user_by_id(&Uuid::new_v4()).await, Err(UserStoreError::UserNotFound));
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn failure_after_user_insert_rolls_back_the_user(pool: PgPool) {
+        let store = PostgresUserStore::new(pool, Arc::new(Argon2PasswordHasher::default()));
+        let email = Email::parse(Secret::new("rolled-back@example.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_owned())).unwrap();
+
+        let mut tx = store.add_user_tx(User::new(email.clone(), password.clone(), false)).await.unwrap();
+        // A later signup step fails inside the transaction, and the caller gives up on it
+        let failed_step = sqlx::query("SELECT 1 / 0")
+            .execute(tx.connection().expect("Postgres transactions expose their connection"))
+            .await;
+        assert!(failed_step.is_err());
+        drop(tx);
+        assert_eq!(store.get_user(&email).await, Err(UserStoreError::UserNotFound));
+
+        let tx = store.add_user_tx(User::new(email.clone(), password, false)).await.unwrap();
+        tx.commit().await.unwrap();
+        assert!(store.get_user(&email).await.is_ok());
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn email_change_preserves_id(pool: PgPool) {
+        let store = PostgresUserStore::new(pool.clone(), Arc::new(Argon2PasswordHasher::default()));
+        let old_email = Email::parse(Secret::new("old@example.com".to_owned())).unwrap();
+        let new_email = Email::parse(Secret::new("new@example.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_owned())).unwrap();
+
+        let user = User::new(old_email.clone(), password, false);
+        let id = user.id;
+        store.add_user(user).await.unwrap();
+
+        sqlx::query("UPDATE users SET email = $1 WHERE email = $2")
+            .bind(new_email.as_ref().expose_secret())
+            .bind(old_email.as_ref().expose_secret())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(store.get_user_by_id(&id).await.unwrap().email, new_email);
+        assert_eq!(store.get_user(&new_email).await.unwrap().id, id);
+        assert_eq!(store.get_user(&old_email).await, Err(UserStoreError::UserNotFound));
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn display_name_round_trips(pool: PgPool) {
+        let store = PostgresUserStore::new(pool, Arc::new(Argon2PasswordHasher::default()));
+        let email = Email::parse(Secret::new("named@example.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_owned())).unwrap();
+
+        let mut user = User::new(email.clone(), password, false);
+        user.display_name = Some(DisplayName::parse("Ada".to_owned()).unwrap());
+        store.add_user(user).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().display_name.unwrap().as_ref(), "Ada");
+
+        let renamed = DisplayName::parse("Ada Lovelace".to_owned()).unwrap();
+        store.set_display_name(&email, Some(renamed.clone())).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().display_name, Some(renamed));
+
+        store.set_display_name(&email, None).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().display_name, None);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn duplicate_email_is_reported_as_existing_user(pool: PgPool) {
+        let store = PostgresUserStore::new(pool, Arc::new(Argon2PasswordHasher::default()));
+        let email = Email::parse(Secret::new("taken@example.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_owned())).unwrap();
+
+        store.add_user(User::new(email.clone(), password.clone(), false)).await.unwrap();
+        assert_eq!(
+            store.add_user(User::new(email, password, false)).await,
+            Err(UserStoreError::UserAlreadyExists)
+        );
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn violating_any_unique_constraint_is_reported_as_existing_user(pool: PgPool) {
+        // Stands in for a unique column a later migration might add
+        sqlx::query("ALTER TABLE users ADD CONSTRAINT users_display_name_key UNIQUE (display_name)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let store = PostgresUserStore::new(pool, Arc::new(Argon2PasswordHasher::default()));
+        let password = Password::parse(Secret::new("password123".to_owned())).unwrap();
+
+        let ada = Email::parse(Secret::new("ada@example.com".to_owned())).unwrap();
+        let mut first = User::new(ada, password.clone(), false);
+        first.display_name = Some(DisplayName::parse("Ada".to_owned()).unwrap());
+        store.add_user(first).await.unwrap();
+
+        let lovelace = Email::parse(Secret::new("lovelace@example.com".to_owned())).unwrap();
+        let mut second = User::new(lovelace, password, false);
+        second.display_name = Some(DisplayName::parse("Ada".to_owned()).unwrap());
+        assert_eq!(store.add_user(second).await, Err(UserStoreError::UserAlreadyExists));
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn admin_flag_round_trips(pool: PgPool) {
+        let store = PostgresUserStore::new(pool, Arc::new(Argon2PasswordHasher::default()));
+        let email = Email::parse(Secret::new("admin@example.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_owned())).unwrap();
+
+        store.add_user(User::new(email.clone(), password, false)).await.unwrap();
+        assert!(!store.get_user(&email).await.unwrap().is_admin);
+
+        store.set_admin(&email, true).await.unwrap();
+        assert!(store.get_user(&email).await.unwrap().is_admin);
+
+        store.set_admin(&email, false).await.unwrap();
+        assert!(!store.get_user(&email).await.unwrap().is_admin);
+
+        let unknown = Email::parse(Secret::new("unknown@example.com".to_owned())).unwrap();
+        assert!(matches!(store.set_admin(&unknown, true).await, Err(UserStoreError::UserNotFound)));
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn active_flag_round_trips(pool: PgPool) {
+        let store = PostgresUserStore::new(pool, Arc::new(Argon2PasswordHasher::default()));
+        let email = Email::parse(Secret::new("suspended@example.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_owned())).unwrap();
+
+        store.add_user(User::new(email.clone(), password, false)).await.unwrap();
+        assert!(store.get_user(&email).await.unwrap().is_active);
+
+        store.set_active(&email, false).await.unwrap();
+        assert!(!store.get_user(&email).await.unwrap().is_active);
+
+        store.set_active(&email, true).await.unwrap();
+        assert!(store.get_user(&email).await.unwrap().is_active);
+
+        let unknown = Email::parse(Secret::new("unknown@example.com".to_owned())).unwrap();
+        assert!(matches!(store.set_active(&unknown, false).await, Err(UserStoreError::UserNotFound)));
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn locale_round_trips(pool: PgPool) {
+        let store = PostgresUserStore::new(pool, Arc::new(Argon2PasswordHasher::default()));
+        let email = Email::parse(Secret::new("hablante@example.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_owned())).unwrap();
+        let mut user = User::new(email.clone(), password, false);
+        user.locale = Some(Locale::Es);
+
+        store.add_user(user).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().locale, Some(Locale::Es));
+
+        store.set_locale(&email, None).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().locale, None);
+
+        store.set_locale(&email, Some(Locale::En)).await.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().locale, Some(Locale::En));
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn update_password_clears_must_change_password(pool: PgPool) {
+        let store = PostgresUserStore::new(pool, Arc::new(Argon2PasswordHasher::default()));
+        let email = Email::parse(Secret::new("compromised@example.com".to_owned())).unwrap();
+        let old_password = Password::parse(Secret::new("password123".to_owned())).unwrap();
+        let new_password = Password::parse(Secret::new("newpassword456".to_owned())).unwrap();
+
+        store.add_user(User::new(email.clone(), old_password.clone(), false)).await.unwrap();
+        assert!(!store.get_user(&email).await.unwrap().must_change_password);
+
+        store.set_must_change_password(&email, true).await.unwrap();
+        assert!(store.get_user(&email).await.unwrap().must_change_password);
+
+        store.update_password(&email, new_password.clone()).await.unwrap();
+        assert!(!store.get_user(&email).await.unwrap().must_change_password);
+        assert!(store.validate_user(&email, &old_password).await.is_err());
+        assert!(store.validate_user(&email, &new_password).await.is_ok());
+
+        let unknown = Email::parse(Secret::new("unknown@example.com".to_owned())).unwrap();
+        assert!(matches!(store.update_password(&unknown, new_password).await, Err(UserStoreError::UserNotFound)));
+    }
+}
@@ -0,0 +1,87 @@
+use color_eyre::eyre::Context;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use crate::{
+    domain::data_stores::{AdminKeyStore, AdminKeyStoreError},
+    services::data_stores::RedisKeyPrefix,
+};
+
+// Shared through Redis so a rotation applies to every instance at once
+pub struct RedisAdminKeyStore {
+    conn: ConnectionManager,
+    key_prefix: RedisKeyPrefix,
+}
+
+impl RedisAdminKeyStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self {
+            conn,
+            key_prefix: RedisKeyPrefix::default(),
+        }
+    }
+
+    pub fn with_key_prefix(mut self, key_prefix: impl Into<RedisKeyPrefix>) -> Self {
+        self.key_prefix = key_prefix.into();
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl AdminKeyStore for RedisAdminKeyStore {
+    #[tracing::instrument(name = "Getting admin key hash from Redis", skip_all)]
+    async fn get_key_hash(&self) -> Result<Option<String>, AdminKeyStoreError> {
+        self.conn
+            .clone()
+            .get(get_key(&self.key_prefix))
+            .await
+            .wrap_err("Failed to get admin key hash from Redis")
+            .map_err(AdminKeyStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Setting admin key hash in Redis", skip_all)]
+    async fn set_key_hash(&self, key_hash: String) -> Result<(), AdminKeyStoreError> {
+        // No expiry: the rotated key stays active until the next rotation
+        self.conn
+            .clone()
+            .set(get_key(&self.key_prefix), key_hash)
+            .await
+            .wrap_err("Failed to set admin key hash in Redis")
+            .map_err(AdminKeyStoreError::UnexpectedError)
+    }
+}
+
+const ADMIN_KEY_HASH_KEY: &str = "admin_key_hash";
+
+fn get_key(key_prefix: &RedisKeyPrefix) -> String {
+    key_prefix.key(ADMIN_KEY_HASH_KEY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::Client;
+
+    async fn setup() -> RedisAdminKeyStore {
+        let client = Client::open("redis://127.0.0.1/").expect("Failed to create Redis client");
+        let conn = client
+            .get_connection_manager()
+            .await
+            .expect("Failed to get Redis connection manager");
+        RedisAdminKeyStore::new(conn).with_key_prefix(format!("test:{}:", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn should_apply_key_prefix() {
+        assert_eq!(get_key(&"".into()), "admin_key_hash");
+        assert_eq!(get_key(&"staging:".into()), "staging:admin_key_hash");
+    }
+
+    #[tokio::test]
+    async fn should_replace_key_hash() {
+        let store = setup().await;
+        assert_eq!(store.get_key_hash().await.unwrap(), None);
+
+        store.set_key_hash("first".to_owned()).await.unwrap();
+        store.set_key_hash("second".to_owned()).await.unwrap();
+        assert_eq!(store.get_key_hash().await.unwrap(), Some("second".to_owned()));
+    }
+}
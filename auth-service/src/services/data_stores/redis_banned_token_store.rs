@@ -1,62 +1,285 @@
-use std::sync::Arc;
-use redis::{Commands, Connection};
-use tokio::sync::RwLock;
-use color_eyre::eyre::{Report, Context};
-use secrecy::{ExposeSecret, Secret};
-use crate::domain::data_stores::{BannedTokenStore, BannedTokenStoreError};
+use color_eyre::eyre::Context;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use secrecy::Secret;
+use crate::{
+    domain::{
+        data_stores::{banned_token_id, BannedTokenEntry, BannedTokenStore, BannedTokenStoreError},
+        session_ttl::MAX_SESSION_TTL_SECONDS,
+    },
+    services::data_stores::RedisKeyPrefix,
+};
 
+// The connection manager re-establishes a dropped connection on the next command, so a network
+// blip fails the commands in flight at the time rather than every command after it
 pub struct RedisBannedTokenStore {
-    conn: Arc<RwLock<Connection>>,
+    conn: ConnectionManager,
+    key_prefix: RedisKeyPrefix,
 }
 
 impl RedisBannedTokenStore {
-    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
-        Self { conn }
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self {
+            conn,
+            key_prefix: RedisKeyPrefix::default(),
+        }
     }
+
+    pub fn with_key_prefix(mut self, key_prefix: impl Into<RedisKeyPrefix>) -> Self {
+        self.key_prefix = key_prefix.into();
+        self
+    }
+
 }
 
 #[async_trait::async_trait]
-#[tracing::instrument(name = "Redis banned token store", skip_all)]
 impl BannedTokenStore for RedisBannedTokenStore {
+    #[tracing::instrument(name = "Storing banned token in Redis", skip_all)]
     async fn store_token(&self, token: Secret<String>) -> Result<(), BannedTokenStoreError> {
         tracing::debug!("Storing banned token in Redis");
+        // A banned token only needs to be remembered until it would have expired anyway. Users can
+        // have longer sessions than the default, so cover the longest one allowed.
+        let ttl = MAX_SESSION_TTL_SECONDS;
+
         let _: () = self
             .conn
-            .write()
+            .clone()
+            .set_ex(get_key(&self.key_prefix, &banned_token_id(&token)), true, ttl)
             .await
-            .set(token.expose_secret(), true)
             .wrap_err("Failed to store banned token in Redis")
-            .map_err(|e| BannedTokenStoreError::UnexpectedError(Report::new(e)))?;
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
 
         tracing::info!("Successfully stored banned token");
         Ok(())
     }
 
+    #[tracing::instrument(name = "Checking banned token in Redis", skip_all)]
     async fn contains_token(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError> {
         tracing::debug!("Checking if token is banned in Redis");
         let result: bool = self
             .conn
-            .write()
+            .clone()
+            .exists(get_key(&self.key_prefix, &banned_token_id(token)))
             .await
-            .exists(token.expose_secret())
             .wrap_err("Failed to check token in Redis")
-            .map_err(|e| BannedTokenStoreError::UnexpectedError(Report::new(e)))?;
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
 
         tracing::debug!("Token ban status checked successfully");
         Ok(result)
     }
+
+    #[tracing::instrument(name = "Checking banned tokens in Redis", skip_all)]
+    async fn contains_tokens(&self, tokens: &[Secret<String>]) -> Result<Vec<bool>, BannedTokenStoreError> {
+        if tokens.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Pipelined so a batch costs one round trip rather than one per token
+        let mut pipe = redis::pipe();
+        for token in tokens {
+            pipe.exists(get_key(&self.key_prefix, &banned_token_id(token)));
+        }
+
+        pipe.query_async(&mut self.conn.clone())
+            .await
+            .wrap_err("Failed to check tokens in Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Listing banned tokens in Redis", skip_all)]
+    async fn list_tokens(&self) -> Result<Vec<BannedTokenEntry>, BannedTokenStoreError> {
+        tracing::debug!("Listing banned tokens in Redis");
+        let mut conn = self.conn.clone();
+
+        // SCAN rather than KEYS so a large store doesn't block Redis
+        // Every banned-token key starts with the key for an empty id
+        let key_prefix = get_key(&self.key_prefix, "");
+        let mut keys: Vec<String> = Vec::new();
+        let mut iter = conn
+            .scan_match(format!("{}*", key_prefix))
+            .await
+            .wrap_err("Failed to scan banned tokens in Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+        drop(iter);
+
+        let mut entries = Vec::with_capacity(keys.len());
+        for key in keys {
+            // TTL is -1 for keys without an expiry and -2 if the key expired since the scan
+            let ttl: i64 = conn
+                .ttl(&key)
+                .await
+                .wrap_err("Failed to get banned token TTL from Redis")
+                .map_err(BannedTokenStoreError::UnexpectedError)?;
+            if ttl == -2 {
+                continue;
+            }
+
+            entries.push(BannedTokenEntry {
+                id: key.trim_start_matches(&key_prefix).to_owned(),
+                ttl_seconds: u64::try_from(ttl).ok(),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    #[tracing::instrument(name = "Removing banned token from Redis", skip_all)]
+    async fn remove_token(&self, id: &str) -> Result<bool, BannedTokenStoreError> {
+        tracing::debug!("Removing banned token from Redis");
+        let removed: u64 = self
+            .conn
+            .clone()
+            .del(get_key(&self.key_prefix, id))
+            .await
+            .wrap_err("Failed to remove banned token from Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(removed > 0)
+    }
+
+    #[tracing::instrument(name = "Getting token cutoff from Redis", skip_all)]
+    async fn not_valid_before(&self) -> Result<Option<usize>, BannedTokenStoreError> {
+        self.conn
+            .clone()
+            .get(self.key_prefix.key(NOT_VALID_BEFORE_KEY))
+            .await
+            .wrap_err("Failed to get token cutoff from Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Setting token cutoff in Redis", skip_all)]
+    async fn set_not_valid_before(&self, cutoff: usize) -> Result<(), BannedTokenStoreError> {
+        // Every token issued before the cutoff has expired once the longest session has passed
+        let _: () = self
+            .conn
+            .clone()
+            .set_ex(self.key_prefix.key(NOT_VALID_BEFORE_KEY), cutoff, MAX_SESSION_TTL_SECONDS)
+            .await
+            .wrap_err("Failed to set token cutoff in Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        tracing::warn!("Tokens issued before {} are no longer accepted", cutoff);
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Getting user token cutoff from Redis", skip_all)]
+    async fn user_not_valid_before(&self, subject: &str) -> Result<Option<usize>, BannedTokenStoreError> {
+        let (cutoff, legacy_cutoff): (Option<usize>, Option<usize>) = self
+            .conn
+            .clone()
+            .mget(&[
+                get_user_cutoff_key(&self.key_prefix, subject),
+                get_legacy_user_cutoff_key(&self.key_prefix, subject),
+            ])
+            .await
+            .wrap_err("Failed to get user token cutoff from Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+        Ok(cutoff.max(legacy_cutoff.map(|seconds| seconds * 1000)))
+    }
+
+    #[tracing::instrument(name = "Setting user token cutoff in Redis", skip_all)]
+    async fn set_user_not_valid_before(&self, subject: &str, cutoff: usize) -> Result<(), BannedTokenStoreError> {
+        let _: () = self
+            .conn
+            .clone()
+            .set_ex(get_user_cutoff_key(&self.key_prefix, subject), cutoff, MAX_SESSION_TTL_SECONDS)
+            .await
+            .wrap_err("Failed to set user token cutoff in Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        tracing::warn!("User's tokens issued before {} are no longer accepted", cutoff);
+        Ok(())
+    }
+}
+
+const BANNED_TOKEN_KEY_PREFIX: &str = "banned_token:";
+// Outside the banned-token keys so listing them doesn't pick it up
+const NOT_VALID_BEFORE_KEY: &str = "token_not_valid_before";
+const USER_NOT_VALID_BEFORE_KEY_PREFIX: &str = "user_token_not_valid_before_ms:";
+// Cutoffs in seconds, written before they moved to milliseconds. Still read until they expire.
+const LEGACY_USER_NOT_VALID_BEFORE_KEY_PREFIX: &str = "user_token_not_valid_before:";
+
+fn get_key(key_prefix: &RedisKeyPrefix, id: &str) -> String {
+    key_prefix.key(format_args!("{}{}", BANNED_TOKEN_KEY_PREFIX, id))
+}
+
+fn get_user_cutoff_key(key_prefix: &RedisKeyPrefix, subject: &str) -> String {
+    key_prefix.key(format_args!("{}{}", USER_NOT_VALID_BEFORE_KEY_PREFIX, subject))
+}
+
+fn get_legacy_user_cutoff_key(key_prefix: &RedisKeyPrefix, subject: &str) -> String {
+    key_prefix.key(format_args!("{}{}", LEGACY_USER_NOT_VALID_BEFORE_KEY_PREFIX, subject))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
     use redis::Client;
     use secrecy::Secret;
+    use tokio::{
+        io::copy_bidirectional,
+        net::{TcpListener, TcpStream},
+        task::AbortHandle,
+    };
+
+    // Each test gets its own key prefix so runs against a shared Redis don't see each other's tokens
+    async fn setup_at(url: &str) -> RedisBannedTokenStore {
+        let client = Client::open(url).expect("Failed to create Redis client");
+        let conn = client
+            .get_connection_manager()
+            .await
+            .expect("Failed to get Redis connection manager");
+        RedisBannedTokenStore::new(conn).with_key_prefix(format!("test:{}:", uuid::Uuid::new_v4()))
+    }
 
     async fn setup() -> RedisBannedTokenStore {
-        let client = Client::open("redis://127.0.0.1/").expect("Failed to create Redis client");
-        let conn = client.get_connection().expect("Failed to get Redis connection");
-        RedisBannedTokenStore::new(Arc::new(RwLock::new(conn)))
+        setup_at("redis://127.0.0.1/").await
+    }
+
+    // Forwards connections to the local Redis and can cut them all, like a network blip would
+    struct FlakyProxy {
+        address: SocketAddr,
+        connections: Arc<Mutex<Vec<AbortHandle>>>,
+    }
+
+    impl FlakyProxy {
+        async fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            let connections = Arc::new(Mutex::new(Vec::new()));
+
+            let tracked = connections.clone();
+            tokio::spawn(async move {
+                while let Ok((mut client, _)) = listener.accept().await {
+                    let mut upstream = TcpStream::connect("127.0.0.1:6379").await.unwrap();
+                    let task = tokio::spawn(async move {
+                        let _ = copy_bidirectional(&mut client, &mut upstream).await;
+                    });
+                    tracked.lock().unwrap().push(task.abort_handle());
+                }
+            });
+
+            Self { address, connections }
+        }
+
+        // Aborting the tasks drops both ends of every forwarded connection
+        fn drop_connections(&self) {
+            for connection in self.connections.lock().unwrap().drain(..) {
+                connection.abort();
+            }
+        }
+    }
+
+    #[test]
+    fn test_key_prefix_is_applied() {
+        assert_eq!(get_key(&"".into(), "abc"), "banned_token:abc");
+        assert_eq!(get_key(&"staging:".into(), "abc"), "staging:banned_token:abc");
     }
 
     #[tokio::test]
@@ -99,4 +322,89 @@ mod tests {
         // Non-existent token should not exist
         assert!(!store.contains_token(&Secret::new("nonexistent".to_string())).await.unwrap());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_contains_tokens() {
+        let store = setup().await;
+        let banned = Secret::new("banned_token".to_string());
+        store.store_token(banned.clone()).await.unwrap();
+
+        let tokens = [Secret::new("other_token".to_string()), banned];
+        assert_eq!(store.contains_tokens(&tokens).await.unwrap(), vec![false, true]);
+        assert!(store.contains_tokens(&[]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_and_remove_token() {
+        let store = setup().await;
+        let token = Secret::new("test_token_to_remove".to_string());
+        let id = banned_token_id(&token);
+        store.store_token(token.clone()).await.unwrap();
+
+        let entry = store
+            .list_tokens()
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|entry| entry.id == id)
+            .expect("Banned token should be listed");
+        assert!(entry.ttl_seconds.is_some_and(|ttl| ttl <= MAX_SESSION_TTL_SECONDS));
+
+        assert!(store.remove_token(&id).await.unwrap());
+        assert!(!store.contains_token(&token).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_not_valid_before() {
+        let store = setup().await;
+        assert_eq!(store.not_valid_before().await.unwrap(), None);
+
+        store.set_not_valid_before(1_700_000_000).await.unwrap();
+        assert_eq!(store.not_valid_before().await.unwrap(), Some(1_700_000_000));
+        // The cutoff isn't mistaken for a banned token
+        assert!(store.list_tokens().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_user_not_valid_before() {
+        let store = setup().await;
+        assert_eq!(store.user_not_valid_before("ada@example.com").await.unwrap(), None);
+
+        store.set_user_not_valid_before("ada@example.com", 1_700_000_000_500).await.unwrap();
+        assert_eq!(store.user_not_valid_before("ada@example.com").await.unwrap(), Some(1_700_000_000_500));
+        assert_eq!(store.user_not_valid_before("bob@example.com").await.unwrap(), None);
+        assert_eq!(store.not_valid_before().await.unwrap(), None);
+        assert!(store.list_tokens().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_user_not_valid_before_reads_legacy_cutoff_in_seconds() {
+        let store = setup().await;
+        let _: () = store
+            .conn
+            .clone()
+            .set_ex(get_legacy_user_cutoff_key(&store.key_prefix, "ada@example.com"), 1_700_000_000, 60)
+            .await
+            .unwrap();
+        assert_eq!(store.user_not_valid_before("ada@example.com").await.unwrap(), Some(1_700_000_000_000));
+
+        // Whichever cutoff is later wins
+        store.set_user_not_valid_before("ada@example.com", 1_700_000_000_500).await.unwrap();
+        assert_eq!(store.user_not_valid_before("ada@example.com").await.unwrap(), Some(1_700_000_000_500));
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_after_connection_drop() {
+        let proxy = FlakyProxy::start().await;
+        let store = setup_at(&format!("redis://{}/", proxy.address)).await;
+        let token = Secret::new("test_token".to_string());
+        store.store_token(token.clone()).await.unwrap();
+
+        proxy.drop_connections();
+        // Commands caught by the drop may fail; they trigger the reconnect
+        let _ = store.contains_token(&token).await;
+
+        assert!(store.contains_token(&token).await.unwrap());
+        assert!(!store.contains_token(&Secret::new("other_token".to_string())).await.unwrap());
+    }
+}
@@ -0,0 +1,132 @@
+use std::time::Duration;
+use color_eyre::eyre::Context;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use secrecy::ExposeSecret;
+use crate::{
+    domain::{
+        data_stores::{LoginFailureStore, LoginFailureStoreError},
+        email::Email,
+    },
+    services::data_stores::RedisKeyPrefix,
+};
+
+// Shared through Redis so failures against one instance slow down attempts on all of them
+pub struct RedisLoginFailureStore {
+    conn: ConnectionManager,
+    key_prefix: RedisKeyPrefix,
+}
+
+impl RedisLoginFailureStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self {
+            conn,
+            key_prefix: RedisKeyPrefix::default(),
+        }
+    }
+
+    pub fn with_key_prefix(mut self, key_prefix: impl Into<RedisKeyPrefix>) -> Self {
+        self.key_prefix = key_prefix.into();
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl LoginFailureStore for RedisLoginFailureStore {
+    #[tracing::instrument(name = "Recording login failure in Redis", skip_all)]
+    async fn record_failure(&self, email: &Email, window: Duration) -> Result<u32, LoginFailureStoreError> {
+        let key = get_key(&self.key_prefix, email);
+        let mut conn = self.conn.clone();
+
+        // INCR is atomic, so concurrent failures are all counted; each one restarts the window.
+        // Both run in one transaction so a dropped connection can't leave a counter that never expires.
+        let (count,): (u32,) = redis::pipe()
+            .atomic()
+            .incr(&key, 1)
+            .expire(&key, window.as_secs().max(1) as i64)
+            .ignore()
+            .query_async(&mut conn)
+            .await
+            .wrap_err("Failed to increment login failures in Redis")
+            .map_err(LoginFailureStoreError::UnexpectedError)?;
+
+        Ok(count)
+    }
+
+    #[tracing::instrument(name = "Getting login failures from Redis", skip_all)]
+    async fn failure_count(&self, email: &Email) -> Result<u32, LoginFailureStoreError> {
+        let count: Option<u32> = self
+            .conn
+            .clone()
+            .get(get_key(&self.key_prefix, email))
+            .await
+            .wrap_err("Failed to get login failures from Redis")
+            .map_err(LoginFailureStoreError::UnexpectedError)?;
+        Ok(count.unwrap_or(0))
+    }
+
+    #[tracing::instrument(name = "Resetting login failures in Redis", skip_all)]
+    async fn reset(&self, email: &Email) -> Result<(), LoginFailureStoreError> {
+        self.conn
+            .clone()
+            .del(get_key(&self.key_prefix, email))
+            .await
+            .wrap_err("Failed to reset login failures in Redis")
+            .map_err(LoginFailureStoreError::UnexpectedError)
+    }
+}
+
+const LOGIN_FAILURES_PREFIX: &str = "login_failures:";
+
+fn get_key(key_prefix: &RedisKeyPrefix, email: &Email) -> String {
+    key_prefix.key(format_args!("{}{}", LOGIN_FAILURES_PREFIX, email.as_ref().expose_secret()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::Client;
+    use secrecy::Secret;
+
+    async fn setup() -> RedisLoginFailureStore {
+        let client = Client::open("redis://127.0.0.1/").expect("Failed to create Redis client");
+        let conn = client
+            .get_connection_manager()
+            .await
+            .expect("Failed to get Redis connection manager");
+        RedisLoginFailureStore::new(conn).with_key_prefix(format!("test:{}:", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn should_apply_key_prefix() {
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+
+        assert_eq!(get_key(&"".into(), &email), "login_failures:test@example.com");
+        assert_eq!(get_key(&"staging:".into(), &email), "staging:login_failures:test@example.com");
+    }
+
+    #[tokio::test]
+    async fn should_count_failures_until_reset() {
+        let store = setup().await;
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let window = Duration::from_secs(60);
+
+        assert_eq!(store.failure_count(&email).await.unwrap(), 0);
+        assert_eq!(store.record_failure(&email, window).await.unwrap(), 1);
+        assert_eq!(store.record_failure(&email, window).await.unwrap(), 2);
+        assert_eq!(store.failure_count(&email).await.unwrap(), 2);
+
+        store.reset(&email).await.unwrap();
+        assert_eq!(store.failure_count(&email).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn should_expire_failures_after_the_window() {
+        let store = setup().await;
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+
+        store.record_failure(&email, Duration::from_secs(60)).await.unwrap();
+
+        let ttl: i64 = store.conn.clone().ttl(get_key(&store.key_prefix, &email)).await.unwrap();
+        assert!(ttl > 0 && ttl <= 60, "unexpected TTL {}", ttl);
+    }
+}
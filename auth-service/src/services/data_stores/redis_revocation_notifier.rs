@@ -0,0 +1,147 @@
+use std::sync::{Arc, Once};
+use async_trait::async_trait;
+use color_eyre::eyre::Context;
+use futures_util::StreamExt;
+use redis::{aio::ConnectionManager, AsyncCommands, Client, RedisResult};
+use tokio::sync::broadcast;
+use crate::{
+    domain::data_stores::{RevocationNotifier, RevocationNotifierError},
+    services::data_stores::RedisKeyPrefix,
+    utils::constants::{REVOCATION_EVENT_BUFFER, REVOCATION_RESUBSCRIBE_DELAY},
+};
+
+// Revocations go out over a Redis pub/sub channel so subscribers on every instance hear about
+// them. Each instance relays the channel to its local subscribers, so it holds one Redis
+// subscription however many clients are listening.
+pub struct RedisRevocationNotifier {
+    client: Client,
+    conn: ConnectionManager,
+    key_prefix: RedisKeyPrefix,
+    sender: broadcast::Sender<String>,
+    relay_started: Arc<Once>,
+}
+
+impl RedisRevocationNotifier {
+    // The client opens the dedicated pub/sub connection, which can't be multiplexed
+    pub fn new(client: Client, conn: ConnectionManager) -> Self {
+        let (sender, _) = broadcast::channel(REVOCATION_EVENT_BUFFER);
+        Self {
+            client,
+            conn,
+            key_prefix: RedisKeyPrefix::default(),
+            sender,
+            relay_started: Arc::new(Once::new()),
+        }
+    }
+
+    pub fn with_key_prefix(mut self, key_prefix: impl Into<RedisKeyPrefix>) -> Self {
+        self.key_prefix = key_prefix.into();
+        self
+    }
+
+    fn channel(&self) -> String {
+        self.key_prefix.key(REVOCATION_CHANNEL)
+    }
+}
+
+#[async_trait]
+impl RevocationNotifier for RedisRevocationNotifier {
+    #[tracing::instrument(name = "Publishing token revocation to Redis", skip_all)]
+    async fn publish(&self, token_id: &str) -> Result<(), RevocationNotifierError> {
+        let _: u64 = self
+            .conn
+            .clone()
+            .publish(self.channel(), token_id)
+            .await
+            .wrap_err("Failed to publish token revocation to Redis")
+            .map_err(RevocationNotifierError::UnexpectedError)?;
+        Ok(())
+    }
+
+    // The relay starts with the first subscriber, so instances nobody listens to don't subscribe
+    fn subscribe(&self) -> broadcast::Receiver<String> {
+        let receiver = self.sender.subscribe();
+        self.relay_started.call_once(|| {
+            tokio::spawn(relay(self.client.clone(), self.channel(), self.sender.clone()));
+        });
+        receiver
+    }
+}
+
+const REVOCATION_CHANNEL: &str = "token_revocations";
+
+// Resubscribes whenever the subscription drops; revocations published in the meantime are missed
+async fn relay(client: Client, channel: String, sender: broadcast::Sender<String>) {
+    loop {
+        match forward_messages(&client, &channel, &sender).await {
+            Ok(()) => tracing::warn!("Token revocation subscription closed, resubscribing"),
+            Err(e) => tracing::error!("Token revocation subscription failed: {:?}", e),
+        }
+        tokio::time::sleep(REVOCATION_RESUBSCRIBE_DELAY).await;
+    }
+}
+
+async fn forward_messages(client: &Client, channel: &str, sender: &broadcast::Sender<String>) -> RedisResult<()> {
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.subscribe(channel).await?;
+    tracing::debug!("Subscribed to token revocations");
+
+    let mut messages = pubsub.on_message();
+    while let Some(message) = messages.next().await {
+        match message.get_payload::<String>() {
+            Ok(token_id) => {
+                let _ = sender.send(token_id);
+            }
+            Err(e) => tracing::warn!("Ignoring malformed token revocation: {:?}", e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn setup(key_prefix: &str) -> RedisRevocationNotifier {
+        let client = Client::open("redis://127.0.0.1/").expect("Failed to create Redis client");
+        let conn = client
+            .get_connection_manager()
+            .await
+            .expect("Failed to get Redis connection manager");
+        RedisRevocationNotifier::new(client, conn).with_key_prefix(key_prefix)
+    }
+
+    #[tokio::test]
+    async fn revocations_reach_subscribers_of_other_notifiers() {
+        let key_prefix = format!("test:{}:", uuid::Uuid::new_v4());
+        let (publisher, subscriber) = (setup(&key_prefix).await, setup(&key_prefix).await);
+        let mut revocations = subscriber.subscribe();
+
+        // The relay subscribes in the background, so publish until it is listening
+        let received = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                publisher.publish("token-id").await.unwrap();
+                if let Ok(Ok(token_id)) = tokio::time::timeout(Duration::from_millis(50), revocations.recv()).await {
+                    return token_id;
+                }
+            }
+        })
+        .await
+        .expect("Revocation was never delivered");
+        assert_eq!(received, "token-id");
+    }
+
+    #[tokio::test]
+    async fn revocations_stay_within_key_prefix() {
+        let publisher = setup(&format!("test:{}:", uuid::Uuid::new_v4())).await;
+        let subscriber = setup(&format!("test:{}:", uuid::Uuid::new_v4())).await;
+        let mut revocations = subscriber.subscribe();
+
+        for _ in 0..5 {
+            publisher.publish("token-id").await.unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(revocations.try_recv().is_err());
+    }
+}
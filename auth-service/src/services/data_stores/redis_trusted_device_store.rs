@@ -0,0 +1,106 @@
+use std::time::Duration;
+use color_eyre::eyre::Context;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use secrecy::ExposeSecret;
+use crate::{
+    domain::{
+        data_stores::{TrustedDeviceStore, TrustedDeviceStoreError},
+        email::Email,
+    },
+    services::data_stores::RedisKeyPrefix,
+};
+
+// One key per trusted device, so Redis expires each device's trust on its own
+pub struct RedisTrustedDeviceStore {
+    conn: ConnectionManager,
+    key_prefix: RedisKeyPrefix,
+}
+
+impl RedisTrustedDeviceStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self {
+            conn,
+            key_prefix: RedisKeyPrefix::default(),
+        }
+    }
+
+    pub fn with_key_prefix(mut self, key_prefix: impl Into<RedisKeyPrefix>) -> Self {
+        self.key_prefix = key_prefix.into();
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl TrustedDeviceStore for RedisTrustedDeviceStore {
+    #[tracing::instrument(name = "Adding trusted device to Redis", skip_all)]
+    async fn add_device(
+        &self,
+        email: &Email,
+        device_id: &str,
+        ttl: Duration,
+    ) -> Result<(), TrustedDeviceStoreError> {
+        // SETEX rejects a zero expiry, and a device trusted for no time isn't worth storing
+        if ttl.as_secs() == 0 {
+            return Ok(());
+        }
+
+        self.conn
+            .clone()
+            .set_ex(get_key(&self.key_prefix, email, device_id), true, ttl.as_secs())
+            .await
+            .wrap_err("Failed to set trusted device in Redis")
+            .map_err(TrustedDeviceStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Checking trusted device in Redis", skip_all)]
+    async fn contains_device(&self, email: &Email, device_id: &str) -> Result<bool, TrustedDeviceStoreError> {
+        self.conn
+            .clone()
+            .exists(get_key(&self.key_prefix, email, device_id))
+            .await
+            .wrap_err("Failed to check trusted device in Redis")
+            .map_err(TrustedDeviceStoreError::UnexpectedError)
+    }
+}
+
+const TRUSTED_DEVICE_PREFIX: &str = "trusted_device:";
+
+fn get_key(key_prefix: &RedisKeyPrefix, email: &Email, device_id: &str) -> String {
+    key_prefix.key(format_args!("{}{}:{}", TRUSTED_DEVICE_PREFIX, email.as_ref().expose_secret(), device_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::Client;
+    use secrecy::Secret;
+
+    async fn setup() -> RedisTrustedDeviceStore {
+        let client = Client::open("redis://127.0.0.1/").expect("Failed to create Redis client");
+        let conn = client
+            .get_connection_manager()
+            .await
+            .expect("Failed to get Redis connection manager");
+        RedisTrustedDeviceStore::new(conn).with_key_prefix(format!("test:{}:", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn should_apply_key_prefix() {
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+
+        assert_eq!(get_key(&"".into(), &email, "device-1"), "trusted_device:test@example.com:device-1");
+        assert_eq!(get_key(&"staging:".into(), &email, "device-1"), "staging:trusted_device:test@example.com:device-1");
+    }
+
+    #[tokio::test]
+    async fn should_trust_device_only_for_its_user() {
+        let store = setup().await;
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let other_email = Email::parse(Secret::new("other@example.com".to_string())).unwrap();
+
+        store.add_device(&email, "device-1", Duration::from_secs(60)).await.unwrap();
+        assert!(store.contains_device(&email, "device-1").await.unwrap());
+        assert!(!store.contains_device(&email, "device-2").await.unwrap());
+        assert!(!store.contains_device(&other_email, "device-1").await.unwrap());
+    }
+}
@@ -1,115 +1,231 @@
-use std::sync::Arc;
-use redis::{Commands, Connection};
+use color_eyre::eyre::{eyre, Context};
+use redis::{aio::ConnectionManager, AsyncCommands};
 use serde::{Deserialize, Serialize};
-use tokio::sync::RwLock;
-use secrecy::Secret;
-use crate::domain::{
-    data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
-    email::Email,
+use secrecy::{ExposeSecret, Secret};
+use crate::{
+    domain::{
+        data_stores::{LoginAttemptId, TwoFACodeHash, TwoFACodeStore, TwoFACodeStoreError},
+        email::Email,
+    },
+    services::data_stores::RedisKeyPrefix,
+    utils::tracing::log_email,
 };
 
+// ConnectionManager is a cheaply cloneable multiplexed connection, so each operation works on its
+// own handle and concurrent reads don't queue behind a lock
 pub struct RedisTwoFACodeStore {
-    conn: Arc<RwLock<Connection>>,
+    conn: ConnectionManager,
+    key_prefix: RedisKeyPrefix,
 }
 
 impl RedisTwoFACodeStore {
-    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
-        Self { conn }
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self {
+            conn,
+            key_prefix: RedisKeyPrefix::default(),
+        }
+    }
+
+    pub fn with_key_prefix(mut self, key_prefix: impl Into<RedisKeyPrefix>) -> Self {
+        self.key_prefix = key_prefix.into();
+        self
     }
+
 }
 
 #[async_trait::async_trait]
 impl TwoFACodeStore for RedisTwoFACodeStore {
+    #[tracing::instrument(name = "Adding 2FA code to Redis", skip_all, fields(email = %log_email(&email)))]
     async fn add_code(
         &mut self,
         email: Email,
         login_attempt_id: LoginAttemptId,
-        code: TwoFACode,
+        code_hash: TwoFACodeHash,
     ) -> Result<(), TwoFACodeStoreError> {
-        let key = get_key(&email);
+        let key = get_key(&self.key_prefix, &email);
         
         let data = TwoFATuple(
             login_attempt_id.as_ref().expose_secret().to_owned(),
-            code.as_ref().expose_secret().to_owned(),
+            code_hash.as_ref().expose_secret().to_owned(),
         );
-        let serialized_data = 
-            serde_json::to_string(&data).map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+        let serialized_data = serde_json::to_string(&data)
+            .wrap_err("Failed to serialize 2FA tuple")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
-        let _: () = self
-            .conn
-            .write()
-            .await
+        // A new code starts with a clean slate of attempts
+        let _: () = redis::pipe()
             .set_ex(&key, serialized_data, TEN_MINUTES_IN_SECONDS)
-            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+            .ignore()
+            .del(get_attempts_key(&self.key_prefix, &email))
+            .ignore()
+            .query_async(&mut self.conn.clone())
+            .await
+            .wrap_err("Failed to set 2FA code in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
         Ok(())
     }
 
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        let key = get_key(email);
+    #[tracing::instrument(name = "Removing 2FA code from Redis", skip_all, fields(email = %log_email(email)))]
+    async fn remove_code(&mut self, email: &Email) -> Result<bool, TwoFACodeStoreError> {
+        let key = get_key(&self.key_prefix, email);
 
-        let _: () = self
+        // DEL is atomic, so when several instances race to consume the same code only one of
+        // them sees the key removed
+        let removed: u64 = self
             .conn
-            .write()
-            .await
+            .clone()
             .del(&key)
-            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+            .await
+            .wrap_err("Failed to delete 2FA code from Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
-        Ok(())
+        Ok(removed > 0)
     }
 
+    #[tracing::instrument(name = "Getting 2FA code from Redis", skip_all, fields(email = %log_email(email)))]
     async fn get_code(
         &self,
         email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
-        let key = get_key(email);
+    ) -> Result<(LoginAttemptId, TwoFACodeHash), TwoFACodeStoreError> {
+        let key = get_key(&self.key_prefix, email);
+
+        match self.conn.clone().get::<_, String>(&key).await {
+            Ok(value) => parse_tuple(&value),
+            Err(_) => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+        }
+    }
 
-        match self.conn.write().await.get::<_, String>(&key) {
-            Ok(value) => {
-                let data: TwoFATuple = serde_json::from_str(&value)
-                    .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+    #[tracing::instrument(name = "Taking 2FA code from Redis", skip_all, fields(email = %log_email(email)))]
+    async fn take_code(
+        &mut self,
+        email: &Email,
+    ) -> Result<(LoginAttemptId, TwoFACodeHash), TwoFACodeStoreError> {
+        let key = get_key(&self.key_prefix, email);
 
-                let login_attempt_id = LoginAttemptId::parse(Secret::new(data.0))
-                    .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+        // GETDEL (Redis 6.2+) hands the value to exactly one caller, even across instances
+        let value: Option<String> = self
+            .conn
+            .clone()
+            .get_del(&key)
+            .await
+            .wrap_err("Failed to take 2FA code from Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
-                let email_code = TwoFACode::parse(Secret::new(data.1))
-                    .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+        match value {
+            Some(value) => parse_tuple(&value),
+            None => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+        }
+    }
 
-                Ok((login_attempt_id, email_code))
-            }
-            Err(_) => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+    #[tracing::instrument(name = "Recording failed 2FA attempt in Redis", skip_all, fields(email = %log_email(email)))]
+    async fn record_failed_attempt(&mut self, email: &Email) -> Result<u32, TwoFACodeStoreError> {
+        let exists: bool = self
+            .conn
+            .clone()
+            .exists(get_key(&self.key_prefix, email))
+            .await
+            .wrap_err("Failed to check 2FA code in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        if !exists {
+            return Err(TwoFACodeStoreError::LoginAttemptIdNotFound);
         }
+
+        // INCR is atomic, so concurrent guesses across instances each use up an attempt; the
+        // counter expires with the code it belongs to
+        let (failed_attempts,): (u32,) = redis::pipe()
+            .incr(get_attempts_key(&self.key_prefix, email), 1)
+            .expire(get_attempts_key(&self.key_prefix, email), TEN_MINUTES_IN_SECONDS as i64)
+            .ignore()
+            .query_async(&mut self.conn.clone())
+            .await
+            .wrap_err("Failed to record failed 2FA attempt in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        Ok(failed_attempts)
     }
 }
 
+fn parse_tuple(value: &str) -> Result<(LoginAttemptId, TwoFACodeHash), TwoFACodeStoreError> {
+    let data: TwoFATuple = serde_json::from_str(value)
+        .wrap_err("Failed to deserialize 2FA tuple")
+        .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+    let login_attempt_id = LoginAttemptId::parse(Secret::new(data.0))
+        .map_err(|e| TwoFACodeStoreError::UnexpectedError(eyre!(e)))?;
+
+    // Entries written before codes were hashed fail here, so their logins have to start over
+    let code_hash = TwoFACodeHash::parse(Secret::new(data.1))
+        .map_err(|e| TwoFACodeStoreError::UnexpectedError(eyre!(e)))?;
+
+    Ok((login_attempt_id, code_hash))
+}
+
+// (login attempt ID, 2FA code hash)
 #[derive(Serialize, Deserialize)]
 struct TwoFATuple(pub String, pub String);
 
 const TEN_MINUTES_IN_SECONDS: u64 = 600;
 const TWO_FA_CODE_PREFIX: &str = "two_fa_code:";
+const TWO_FA_ATTEMPTS_PREFIX: &str = "two_fa_attempts:";
+
+fn get_key(key_prefix: &RedisKeyPrefix, email: &Email) -> String {
+    key_prefix.key(format_args!("{}{}", TWO_FA_CODE_PREFIX, email.as_ref().expose_secret()))
+}
 
-fn get_key(email: &Email) -> String {
-    format!("{}{}", TWO_FA_CODE_PREFIX, email.as_ref().expose_secret())
+fn get_attempts_key(key_prefix: &RedisKeyPrefix, email: &Email) -> String {
+    key_prefix.key(format_args!("{}{}", TWO_FA_ATTEMPTS_PREFIX, email.as_ref().expose_secret()))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
     use redis::Client;
-    use secrecy::Secret;
+    use tokio::sync::RwLock;
+    use crate::domain::data_stores::TwoFACode;
 
+    // Each test gets its own key prefix so runs against a shared Redis don't see each other's codes
     async fn setup() -> RedisTwoFACodeStore {
+        setup_with_prefix(format!("test:{}:", uuid::Uuid::new_v4())).await
+    }
+
+    // Unique per test, so tests sharing a prefix or running in parallel never touch the same key
+    fn random_email() -> Email {
+        Email::parse(Secret::new(format!("{}@example.com", uuid::Uuid::new_v4()))).unwrap()
+    }
+
+    fn pepper() -> Secret<String> {
+        Secret::new("test-2fa-code-pepper".to_owned())
+    }
+
+    fn code_hash(code: &str) -> TwoFACodeHash {
+        TwoFACode::parse(Secret::new(code.to_owned())).unwrap().hash(&LoginAttemptId::new(), &pepper())
+    }
+
+    async fn setup_with_prefix(key_prefix: impl Into<RedisKeyPrefix>) -> RedisTwoFACodeStore {
         let client = Client::open("redis://127.0.0.1/").expect("Failed to create Redis client");
-        let conn = client.get_connection().expect("Failed to get Redis connection");
-        RedisTwoFACodeStore::new(Arc::new(RwLock::new(conn)))
+        let conn = client
+            .get_connection_manager()
+            .await
+            .expect("Failed to get Redis connection manager");
+        RedisTwoFACodeStore::new(conn).with_key_prefix(key_prefix)
+    }
+
+    #[test]
+    fn should_apply_key_prefix() {
+        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+
+        assert_eq!(get_key(&"".into(), &email), "two_fa_code:test@example.com");
+        assert_eq!(get_key(&"staging:".into(), &email), "staging:two_fa_code:test@example.com");
     }
 
     #[tokio::test]
     async fn should_store_and_retrieve_code() {
         let mut store = setup().await;
-        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
-        let login_attempt_id = LoginAttemptId::default();
-        let code = TwoFACode::parse(Secret::new("123456".to_string())).unwrap();
+        let email = random_email();
+        let login_attempt_id = LoginAttemptId::new();
+        let code = code_hash("123456");
 
         store.add_code(email.clone(), login_attempt_id.clone(), code.clone())
             .await
@@ -123,10 +239,42 @@ mod tests {
         assert_eq!(stored_code, code);
     }
 
+    #[tokio::test]
+    async fn should_not_store_raw_code() {
+        let mut store = setup().await;
+        let email = random_email();
+        let login_attempt_id = LoginAttemptId::new();
+        let code = TwoFACode::parse(Secret::new("123456".to_string())).unwrap();
+
+        store.add_code(email.clone(), login_attempt_id.clone(), code.hash(&login_attempt_id, &pepper()))
+            .await
+            .expect("Failed to store code");
+
+        let raw: String = store.conn.clone().get(get_key(&store.key_prefix, &email)).await.unwrap();
+        assert!(!raw.contains("123456"));
+        let (_, stored_hash) = store.get_code(&email).await.expect("Failed to retrieve code");
+        assert_eq!(stored_hash, code.hash(&login_attempt_id, &pepper()));
+    }
+
+    #[tokio::test]
+    async fn should_reject_entries_stored_before_hashing() {
+        let store = setup().await;
+        let email = random_email();
+        let legacy = serde_json::to_string(&TwoFATuple(
+            LoginAttemptId::new().as_ref().expose_secret().to_owned(),
+            "123456".to_owned(),
+        ))
+        .unwrap();
+        let _: () = store.conn.clone().set(get_key(&store.key_prefix, &email), legacy).await.unwrap();
+
+        let result = store.get_code(&email).await;
+        assert!(matches!(result, Err(TwoFACodeStoreError::UnexpectedError(_))));
+    }
+
     #[tokio::test]
     async fn should_return_error_for_nonexistent_email() {
         let store = setup().await;
-        let email = Email::parse(Secret::new("nonexistent@example.com".to_string())).unwrap();
+        let email = random_email();
         let result = store.get_code(&email).await;
 
         assert!(matches!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound)));
@@ -135,36 +283,86 @@ mod tests {
     #[tokio::test]
     async fn should_remove_existing_code() {
         let mut store = setup().await;
-        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
-        let login_attempt_id = LoginAttemptId::default();
-        let code = TwoFACode::parse(Secret::new("123456".to_string())).unwrap();
+        let email = random_email();
+        let login_attempt_id = LoginAttemptId::new();
+        let code = code_hash("123456");
 
         store.add_code(email.clone(), login_attempt_id, code)
             .await
             .expect("Failed to store code");
 
-        store.remove_code(&email)
+        let removed = store.remove_code(&email)
             .await
             .expect("Failed to remove code");
+        assert!(removed);
 
         let result = store.get_code(&email).await;
 
         assert!(matches!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound)));
+
+        // Only the first removal consumes the code
+        let removed = store.remove_code(&email)
+            .await
+            .expect("Failed to remove code");
+        assert!(!removed);
+    }
+
+    #[tokio::test]
+    async fn should_take_code_only_once() {
+        let mut store = setup().await;
+        let email = random_email();
+        let login_attempt_id = LoginAttemptId::new();
+        let code = code_hash("123456");
+
+        store.add_code(email.clone(), login_attempt_id.clone(), code.clone())
+            .await
+            .expect("Failed to store code");
+
+        let (taken_id, taken_code) = store.take_code(&email)
+            .await
+            .expect("Failed to take code");
+        assert_eq!(taken_id, login_attempt_id);
+        assert_eq!(taken_code, code);
+
+        let result = store.take_code(&email).await;
+        assert!(matches!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound)));
+    }
+
+    #[tokio::test]
+    async fn should_count_failed_attempts_until_new_code() {
+        let mut store = setup().await;
+        let email = random_email();
+        let code = code_hash("123456");
+
+        let result = store.record_failed_attempt(&email).await;
+        assert!(matches!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound)));
+
+        store.add_code(email.clone(), LoginAttemptId::new(), code.clone())
+            .await
+            .expect("Failed to store code");
+        assert_eq!(store.record_failed_attempt(&email).await.unwrap(), 1);
+        assert_eq!(store.record_failed_attempt(&email).await.unwrap(), 2);
+
+        // A new login attempt gets a fresh allowance
+        store.add_code(email.clone(), LoginAttemptId::new(), code)
+            .await
+            .expect("Failed to store code");
+        assert_eq!(store.record_failed_attempt(&email).await.unwrap(), 1);
     }
 
     #[tokio::test]
     async fn should_update_existing_code() {
         let mut store = setup().await;
-        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
-        let initial_id = LoginAttemptId::default();
-        let initial_code = TwoFACode::parse(Secret::new("123456".to_string())).unwrap();
+        let email = random_email();
+        let initial_id = LoginAttemptId::new();
+        let initial_code = code_hash("123456");
 
         store.add_code(email.clone(), initial_id, initial_code)
             .await
             .expect("Failed to store initial code");
 
-        let new_id = LoginAttemptId::default();
-        let new_code = TwoFACode::parse(Secret::new("654321".to_string())).unwrap();
+        let new_id = LoginAttemptId::new();
+        let new_code = code_hash("654321");
 
         store.add_code(email.clone(), new_id.clone(), new_code.clone())
             .await
@@ -177,4 +375,102 @@ mod tests {
         assert_eq!(stored_id, new_id);
         assert_eq!(stored_code, new_code);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn should_serve_concurrent_reads() {
+        let mut store = setup().await;
+        let email = random_email();
+        let login_attempt_id = LoginAttemptId::new();
+        let code = code_hash("123456");
+
+        store.add_code(email.clone(), login_attempt_id.clone(), code.clone())
+            .await
+            .expect("Failed to store code");
+
+        // Readers share the store through a read lock, as handlers do via AppState
+        let store = Arc::new(RwLock::new(store));
+        let reads = (0..50).map(|_| {
+            let store = store.clone();
+            let email = email.clone();
+            tokio::spawn(async move { store.read().await.get_code(&email).await })
+        });
+
+        for result in futures_util::future::join_all(reads).await {
+            let (stored_id, stored_code) = result
+                .expect("Read task panicked")
+                .expect("Failed to retrieve code");
+            assert_eq!(stored_id, login_attempt_id);
+            assert_eq!(stored_code, code);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_let_only_one_concurrent_removal_consume_code() {
+        let mut store = setup().await;
+        let email = random_email();
+        let code = code_hash("123456");
+
+        store.add_code(email.clone(), LoginAttemptId::new(), code)
+            .await
+            .expect("Failed to store code");
+
+        // Separate stores stand in for separate service instances sharing Redis
+        let key_prefix = store.key_prefix.clone();
+        let removals = (0..10).map(|_| {
+            let email = email.clone();
+            let key_prefix = key_prefix.clone();
+            tokio::spawn(async move { setup_with_prefix(key_prefix).await.remove_code(&email).await })
+        });
+
+        let consumed = futures_util::future::join_all(removals)
+            .await
+            .into_iter()
+            .map(|result| result.expect("Removal task panicked").expect("Failed to remove code"))
+            .filter(|removed| *removed)
+            .count();
+        assert_eq!(consumed, 1);
+    }
+
+    #[tokio::test]
+    async fn should_not_see_codes_under_another_prefix() {
+        let mut store = setup().await;
+        let other_store = setup().await;
+        let email = random_email();
+        let code = code_hash("123456");
+
+        store.add_code(email.clone(), LoginAttemptId::new(), code)
+            .await
+            .expect("Failed to store code");
+
+        assert!(store.get_code(&email).await.is_ok());
+        let result = other_store.get_code(&email).await;
+        assert!(matches!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound)));
+    }
+
+    #[tokio::test]
+    async fn should_keep_codes_apart_when_tests_run_in_parallel() {
+        let store = Arc::new(RwLock::new(setup().await));
+
+        let runs = (0..20).map(|_| {
+            let store = store.clone();
+            tokio::spawn(async move {
+                let email = random_email();
+                let login_attempt_id = LoginAttemptId::new();
+                let code = TwoFACode::generate().hash(&login_attempt_id, &pepper());
+                store.write().await.add_code(email.clone(), login_attempt_id.clone(), code.clone())
+                    .await
+                    .expect("Failed to store code");
+
+                let (stored_id, stored_code) = store.read().await.get_code(&email)
+                    .await
+                    .expect("Failed to retrieve code");
+                assert_eq!(stored_id, login_attempt_id);
+                assert_eq!(stored_code, code);
+            })
+        });
+
+        for result in futures_util::future::join_all(runs).await {
+            result.expect("Parallel run panicked");
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use async_trait::async_trait;
+use color_eyre::eyre::{Context, Result};
+use redis::aio::ConnectionManager;
+use sqlx::PgPool;
+use crate::domain::health_check::HealthCheck;
+
+pub struct PostgresHealthCheck {
+    pool: PgPool,
+}
+
+impl PostgresHealthCheck {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for PostgresHealthCheck {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    #[tracing::instrument(name = "Checking Postgres health", skip_all)]
+    async fn check(&self) -> Result<()> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .wrap_err("Postgres did not answer")?;
+        Ok(())
+    }
+}
+
+pub struct RedisHealthCheck {
+    conn: ConnectionManager,
+}
+
+impl RedisHealthCheck {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for RedisHealthCheck {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    #[tracing::instrument(name = "Checking Redis health", skip_all)]
+    async fn check(&self) -> Result<()> {
+        let _: String = redis::cmd("PING")
+            .query_async(&mut self.conn.clone())
+            .await
+            .wrap_err("Redis did not answer")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use sqlx::postgres::PgPoolOptions;
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn postgres_check_passes_when_reachable(pool: PgPool) {
+        assert!(PostgresHealthCheck::new(pool).check().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn postgres_check_fails_when_unreachable() {
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(500))
+            .connect_lazy("postgres://postgres@127.0.0.1:1/unreachable")
+            .unwrap();
+        assert!(PostgresHealthCheck::new(pool).check().await.is_err());
+    }
+}
@@ -1,8 +1,8 @@
 use async_trait::async_trait;
 use color_eyre::eyre::{eyre, Result};
-use crate::domain::{
-    email::Email,
-    email_client::EmailClient,
+use crate::{
+    domain::{email::Email, email_client::EmailClient},
+    utils::tracing::log_email,
 };
 
 #[derive(Default, Clone)]
@@ -10,18 +10,14 @@ pub struct MockEmailClient;
 
 #[async_trait]
 impl EmailClient for MockEmailClient {
-    #[tracing::instrument(name = "Sending mock email", skip(self, content))]
+    #[tracing::instrument(name = "Sending mock email", skip_all, fields(recipient = %log_email(recipient)))]
     async fn send_email(
         &self,
         recipient: &Email,
         subject: &str,
         content: &str,
     ) -> Result<()> {
-        tracing::debug!(
-            recipient = %recipient,
-            subject = %subject,
-            "Sending mock email"
-        );
+        tracing::debug!(subject = %subject, "Sending mock email");
         Ok(())
     }
 }
@@ -1,3 +1,7 @@
+pub mod argon2_password_hasher;
+pub mod circuit_breaker_email_client;
 pub mod data_stores;
+pub mod health_checks;
 pub mod mock_email_client;
-pub mod postmark_email_client;
\ No newline at end of file
+pub mod postmark_email_client;
+pub mod smtp_email_client;
\ No newline at end of file
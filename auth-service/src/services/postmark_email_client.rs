@@ -1,7 +1,7 @@
 use color_eyre::eyre::Result;
 use reqwest::{Client, Url};
 use secrecy::{ExposeSecret, Secret};
-use crate::domain::{Email, EmailClient};
+use crate::domain::{email::Email, locale::Locale, EmailClient};
 
 pub struct PostmarkEmailClient {
     http_client: Client,
@@ -26,10 +26,8 @@ impl PostmarkEmailClient {
     }
 }
 
-#[async_trait::async_trait]
-impl EmailClient for PostmarkEmailClient {
-    #[tracing::instrument(name = "Sending email", skip_all)]
-    async fn send_email(&self, recipient: &Email, subject: &str, content: &str) -> Result<()> {
+impl PostmarkEmailClient {
+    async fn send(&self, recipient: &Email, locale: Option<Locale>, subject: &str, content: &str) -> Result<()> {
         let base = Url::parse(&self.base_url)?;
         let url = base.join("/email")?;
         
@@ -40,6 +38,10 @@ impl EmailClient for PostmarkEmailClient {
             html_body: content,
             text_body: content,
             message_stream: MESSAGE_STREAM,
+            headers: locale
+                .map(|locale| EmailHeader { name: CONTENT_LANGUAGE_HEADER, value: locale.tag() })
+                .into_iter()
+                .collect(),
         };
 
         let request = self
@@ -56,8 +58,28 @@ impl EmailClient for PostmarkEmailClient {
     }
 }
 
+#[async_trait::async_trait]
+impl EmailClient for PostmarkEmailClient {
+    #[tracing::instrument(name = "Sending email", skip_all)]
+    async fn send_email(&self, recipient: &Email, subject: &str, content: &str) -> Result<()> {
+        self.send(recipient, None, subject, content).await
+    }
+
+    #[tracing::instrument(name = "Sending localized email", skip_all, fields(locale = locale.tag()))]
+    async fn send_localized_email(
+        &self,
+        recipient: &Email,
+        locale: Locale,
+        subject: &str,
+        content: &str,
+    ) -> Result<()> {
+        self.send(recipient, Some(locale), subject, content).await
+    }
+}
+
 const MESSAGE_STREAM: &str = "outbound";
 const POSTMARK_AUTH_HEADER: &str = "X-Postmark-Server-Token";
+const CONTENT_LANGUAGE_HEADER: &str = "Content-Language";
 
 #[derive(serde::Serialize, Debug)]
 #[serde(rename_all = "PascalCase")]
@@ -68,6 +90,15 @@ struct SendEmailRequest<'a> {
     html_body: &'a str,
     text_body: &'a str,
     message_stream: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<EmailHeader<'a>>,
+}
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct EmailHeader<'a> {
+    name: &'a str,
+    value: &'a str,
 }
 
 #[cfg(test)]
@@ -77,7 +108,8 @@ mod tests {
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
-    use wiremock::matchers::{any, header, header_exists, method, path};
+    use serde_json::json;
+    use wiremock::matchers::{any, body_partial_json, header, header_exists, method, path};
     use wiremock::{Mock, MockServer, Request, ResponseTemplate};
 
     fn subject() -> String {
@@ -144,6 +176,27 @@ mod tests {
         assert!(outcome.is_ok());
     }
 
+    #[tokio::test]
+    async fn send_localized_email_labels_the_language() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(body_partial_json(json!({
+                "Headers": [{ "Name": "Content-Language", "Value": "es" }]
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_localized_email(&email(), Locale::Es, &subject(), &content())
+            .await;
+
+        assert!(outcome.is_ok());
+    }
+
     #[tokio::test]
     async fn send_email_fails_if_the_server_returns_500() {
         let mock_server = MockServer::start().await;
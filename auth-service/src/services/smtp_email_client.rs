@@ -0,0 +1,163 @@
+use color_eyre::eyre::{Context, Result};
+use lettre::{
+    message::header::{ContentType, Header, HeaderName, HeaderValue},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use secrecy::{ExposeSecret, Secret};
+use crate::domain::{email::Email, email_client::EmailClient, locale::Locale};
+
+pub struct SmtpEmailClient<T = AsyncSmtpTransport<Tokio1Executor>> {
+    transport: T,
+    sender: Email,
+}
+
+impl SmtpEmailClient {
+    pub fn new(
+        host: &str,
+        port: u16,
+        credentials: Option<(String, Secret<String>)>,
+        sender: Email,
+    ) -> Result<Self> {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+            .wrap_err("Failed to create SMTP transport")?
+            .port(port);
+
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(
+                username,
+                password.expose_secret().to_owned(),
+            ));
+        }
+
+        Ok(Self::with_transport(builder.build(), sender))
+    }
+}
+
+impl<T> SmtpEmailClient<T> {
+    pub fn with_transport(transport: T, sender: Email) -> Self {
+        Self { transport, sender }
+    }
+}
+
+impl<T> SmtpEmailClient<T>
+where
+    T: AsyncTransport + Send + Sync,
+    T::Error: std::error::Error + Send + Sync + 'static,
+{
+    async fn send(&self, recipient: &Email, locale: Option<Locale>, subject: &str, content: &str) -> Result<()> {
+        let mut message = Message::builder()
+            .from(self.sender.as_ref().expose_secret().parse().wrap_err("Invalid sender address")?)
+            .to(recipient.as_ref().expose_secret().parse().wrap_err("Invalid recipient address")?)
+            .subject(subject)
+            .header(ContentType::TEXT_HTML);
+        if let Some(locale) = locale {
+            message = message.header(ContentLanguage(locale.tag()));
+        }
+        let message = message
+            .body(content.to_owned())
+            .wrap_err("Failed to build email message")?;
+
+        self.transport
+            .send(message)
+            .await
+            .wrap_err("Failed to send email via SMTP")?;
+
+        Ok(())
+    }
+}
+
+// lettre has no typed Content-Language header
+#[derive(Clone)]
+struct ContentLanguage(&'static str);
+
+impl Header for ContentLanguage {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("Content-Language")
+    }
+
+    fn parse(s: &str) -> std::result::Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Locale::from_tag(s).map(|locale| Self(locale.tag())).ok_or_else(|| "Unsupported language".into())
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.to_owned())
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> EmailClient for SmtpEmailClient<T>
+where
+    T: AsyncTransport + Send + Sync,
+    T::Error: std::error::Error + Send + Sync + 'static,
+{
+    #[tracing::instrument(name = "Sending email via SMTP", skip_all)]
+    async fn send_email(&self, recipient: &Email, subject: &str, content: &str) -> Result<()> {
+        self.send(recipient, None, subject, content).await
+    }
+
+    #[tracing::instrument(name = "Sending localized email via SMTP", skip_all, fields(locale = locale.tag()))]
+    async fn send_localized_email(
+        &self,
+        recipient: &Email,
+        locale: Locale,
+        subject: &str,
+        content: &str,
+    ) -> Result<()> {
+        self.send(recipient, Some(locale), subject, content).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lettre::transport::stub::AsyncStubTransport;
+
+    fn email(address: &str) -> Email {
+        Email::parse(Secret::new(address.to_owned())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_builds_message_with_subject_and_body() {
+        let transport = AsyncStubTransport::new_ok();
+        let email_client = SmtpEmailClient::with_transport(transport.clone(), email("sender@example.com"));
+
+        let outcome = email_client
+            .send_email(&email("recipient@example.com"), "Your 2FA Code", "Your verification code is: 123456")
+            .await;
+        assert!(outcome.is_ok());
+
+        let messages = transport.messages().await;
+        assert_eq!(messages.len(), 1);
+
+        let (envelope, message) = &messages[0];
+        assert_eq!(envelope.from().map(|a| a.to_string()), Some("sender@example.com".to_owned()));
+        assert_eq!(envelope.to().iter().map(|a| a.to_string()).collect::<Vec<_>>(), vec!["recipient@example.com"]);
+        assert!(message.contains("Subject: Your 2FA Code"));
+        assert!(message.contains("Your verification code is: 123456"));
+    }
+
+    #[tokio::test]
+    async fn send_localized_email_labels_the_language() {
+        let transport = AsyncStubTransport::new_ok();
+        let email_client = SmtpEmailClient::with_transport(transport.clone(), email("sender@example.com"));
+
+        let outcome = email_client
+            .send_localized_email(&email("recipient@example.com"), Locale::Es, "Asunto", "Contenido")
+            .await;
+        assert!(outcome.is_ok());
+
+        let messages = transport.messages().await;
+        assert!(messages[0].1.contains("Content-Language: es"));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_transport_fails() {
+        let email_client = SmtpEmailClient::with_transport(AsyncStubTransport::new_error(), email("sender@example.com"));
+
+        let outcome = email_client
+            .send_email(&email("recipient@example.com"), "Subject", "Content")
+            .await;
+        assert!(outcome.is_err());
+    }
+}
@@ -1,41 +1,129 @@
-use axum_extra::extract::cookie::{Cookie, SameSite};
+use axum::http::{header::AUTHORIZATION, HeaderMap};
+use axum_extra::extract::{cookie::{Cookie, SameSite}, CookieJar};
 use chrono::Utc;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Validation};
+use std::collections::HashMap;
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 use color_eyre::eyre::{eyre, Context, Result};
+use secrecy::{ExposeSecret, Secret};
+use uuid::Uuid;
 
-use crate::domain::{email::Email, data_stores::BannedTokenStore};
-use super::constants::{JWT_SECRET, JWT_COOKIE_NAME};
+use crate::domain::{email::Email, data_stores::BannedTokenStore, session_ttl::SessionTtl, user::Role};
+use super::{config::Config, constants::JWT_COOKIE_NAME, token_cache::VerifiedTokenCache};
 
-// This value determines how long the JWT auth token is valid for
+// This value determines how long the JWT auth token is valid for, unless the user has a session TTL
 pub const TOKEN_TTL_SECONDS: i64 = 600; // 10 minutes
 
-#[tracing::instrument(name = "Generate auth cookie", skip(email))]
-pub async fn generate_auth_cookie(email: &Email) -> Result<Cookie<'static>> {
-    let token = generate_auth_token(email).await?;
-    Ok(create_auth_cookie(token))
+// How tokens are signed and verified, taken from `Config` at startup. The RSA keys are only set
+// with RS256; `Config` has already checked that they parse.
+#[derive(Debug, Clone)]
+pub struct JwtSettings {
+    algorithm: Algorithm,
+    key_id: String,
+    secret: Secret<String>,
+    rsa_private_key: Option<Secret<String>>,
+    rsa_public_key: Option<String>,
+}
+
+impl JwtSettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            algorithm: config.jwt_algorithm,
+            key_id: config.jwt_key_id.clone(),
+            secret: config.jwt_secret.clone(),
+            rsa_private_key: config.jwt_rsa_private_key.clone(),
+            rsa_public_key: config.jwt_rsa_public_key.clone(),
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    // The PEM published through the JWKS endpoint
+    pub fn rsa_public_key(&self) -> Option<&str> {
+        self.rsa_public_key.as_deref()
+    }
+
+    // RS256 tokens carry a `kid` so downstream services can pick the matching key from the JWKS
+    pub(crate) fn header(&self) -> Header {
+        let mut header = Header::new(self.algorithm);
+        if self.algorithm == Algorithm::RS256 {
+            header.kid = Some(self.key_id.clone());
+        }
+        header
+    }
+
+    pub(crate) fn encoding_key(&self) -> Result<EncodingKey> {
+        match (self.algorithm, &self.rsa_private_key) {
+            (Algorithm::RS256, Some(key)) => EncodingKey::from_rsa_pem(key.expose_secret().as_bytes())
+                .wrap_err("Failed to parse RSA private key"),
+            (Algorithm::RS256, None) => Err(eyre!("RS256 requires an RSA private key")),
+            _ => Ok(EncodingKey::from_secret(self.secret.expose_secret().as_bytes())),
+        }
+    }
+
+    pub(crate) fn decoding_key(&self) -> Result<DecodingKey> {
+        match (self.algorithm, &self.rsa_public_key) {
+            (Algorithm::RS256, Some(key)) => DecodingKey::from_rsa_pem(key.as_bytes())
+                .wrap_err("Failed to parse RSA public key"),
+            (Algorithm::RS256, None) => Err(eyre!("RS256 requires an RSA public key")),
+            _ => Ok(DecodingKey::from_secret(self.secret.expose_secret().as_bytes())),
+        }
+    }
+
+    pub(crate) fn validation(&self) -> Validation {
+        Validation::new(self.algorithm)
+    }
+}
+
+// `secure` comes from `ClientContext::secure_cookies`, which applies AUTH_COOKIE_SECURE
+#[tracing::instrument(name = "Generate auth cookie", skip(jwt, email))]
+pub async fn generate_auth_cookie(
+    jwt: &JwtSettings,
+    email: &Email,
+    session_ttl: Option<SessionTtl>,
+    role: Role,
+    secure: bool,
+) -> Result<Cookie<'static>> {
+    let token = generate_auth_token(jwt, email, session_ttl, role).await?;
+    Ok(create_auth_cookie(token, secure))
 }
 
 #[tracing::instrument(name = "Create auth cookie", skip(token))]
-fn create_auth_cookie(token: String) -> Cookie<'static> {
+fn create_auth_cookie(token: Secret<String>, secure: bool) -> Cookie<'static> {
     tracing::debug!("Creating auth cookie");
-    Cookie::build((JWT_COOKIE_NAME, token))
+    Cookie::build((JWT_COOKIE_NAME, token.expose_secret().to_owned()))
         .path("/")
         .http_only(true)
         .same_site(SameSite::Lax)
         .domain("")
-        .secure(false)
+        .secure(secure)
         .build()
 }
 
-#[tracing::instrument(name = "Generate auth token", skip(email))]
-async fn generate_auth_token(email: &Email) -> Result<String> {
+#[tracing::instrument(name = "Generate auth token", skip(jwt, email))]
+async fn generate_auth_token(
+    jwt: &JwtSettings,
+    email: &Email,
+    session_ttl: Option<SessionTtl>,
+    role: Role,
+) -> Result<Secret<String>> {
     tracing::debug!("Generating JWT token");
-    
-    let delta = chrono::Duration::try_seconds(TOKEN_TTL_SECONDS)
-        .ok_or_else(|| eyre!("Failed to create duration from TOKEN_TTL_SECONDS"))?;
 
-    let exp = Utc::now()
+    let ttl_seconds = match session_ttl {
+        Some(ttl) => ttl.as_secs().try_into().wrap_err("Failed to convert session TTL to i64")?,
+        None => TOKEN_TTL_SECONDS,
+    };
+    let delta = chrono::Duration::try_seconds(ttl_seconds)
+        .ok_or_else(|| eyre!("Failed to create duration from token TTL"))?;
+
+    let now = Utc::now();
+    let exp = now
         .checked_add_signed(delta)
         .ok_or_else(|| eyre!("Failed to add duration to current time"))?
         .timestamp();
@@ -43,26 +131,203 @@ async fn generate_auth_token(email: &Email) -> Result<String> {
     let exp: usize = exp
         .try_into()
         .wrap_err("Failed to convert timestamp to usize")?;
+    let iat: usize = now
+        .timestamp()
+        .try_into()
+        .wrap_err("Failed to convert timestamp to usize")?;
+    let iat_ms: usize = now
+        .timestamp_millis()
+        .try_into()
+        .wrap_err("Failed to convert timestamp to usize")?;
+
+    let claims = Claims {
+        sub: email.as_ref().expose_secret().to_owned(),
+        exp,
+        iat: Some(iat),
+        iat_ms: Some(iat_ms),
+        auth_time: Some(iat),
+        jti: Uuid::new_v4().to_string(),
+        role,
+    };
+
+    create_token(jwt, &claims)
+        .map(Secret::new)
+        .wrap_err("Failed to create JWT token")
+}
+
+// Issues a new cookie for the same session with a later expiry. The login time carries over so
+// sliding refreshes stay bound by the absolute session limit.
+#[tracing::instrument(name = "Refresh auth cookie", skip_all)]
+pub fn refresh_auth_cookie(jwt: &JwtSettings, claims: &Claims, exp: usize, secure: bool) -> Result<Cookie<'static>> {
+    let now = Utc::now();
+    let iat: usize = now
+        .timestamp()
+        .try_into()
+        .wrap_err("Failed to convert timestamp to usize")?;
+    let iat_ms: usize = now
+        .timestamp_millis()
+        .try_into()
+        .wrap_err("Failed to convert timestamp to usize")?;
 
-    let sub = email.as_ref().to_owned();
-    let claims = Claims { sub, exp };
+    let refreshed = Claims {
+        sub: claims.sub.clone(),
+        exp,
+        iat: Some(iat),
+        iat_ms: Some(iat_ms),
+        auth_time: claims.auth_time,
+        jti: Uuid::new_v4().to_string(),
+        role: claims.role,
+    };
 
-    create_token(&claims).wrap_err("Failed to create JWT token")
+    let token = create_token(jwt, &refreshed).wrap_err("Failed to create JWT token")?;
+    Ok(create_auth_cookie(Secret::new(token), secure))
 }
 
-#[tracing::instrument(name = "Create token", skip(claims))]
-fn create_token(claims: &Claims) -> Result<String> {
+#[tracing::instrument(name = "Create token", skip_all)]
+fn create_token(jwt: &JwtSettings, claims: &Claims) -> Result<String> {
     tracing::debug!("Encoding JWT token");
-    encode(
-        &jsonwebtoken::Header::default(),
-        claims,
-        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
-    )
-    .wrap_err("Failed to encode JWT token")
+    encode(&jwt.header(), claims, &jwt.encoding_key()?).wrap_err("Failed to encode JWT token")
+}
+
+// Reads the JWT from the auth cookie, falling back to an `Authorization: Bearer` header
+pub fn extract_token(headers: &HeaderMap) -> Option<Secret<String>> {
+    CookieJar::from_headers(headers)
+        .get(JWT_COOKIE_NAME)
+        .map(|cookie| cookie.value().to_owned())
+        .or_else(|| {
+            headers
+                .get(AUTHORIZATION)?
+                .to_str()
+                .ok()?
+                .strip_prefix("Bearer ")
+                .map(|token| token.trim().to_owned())
+        })
+        .filter(|token| !token.is_empty())
+        .map(Secret::new)
+}
+
+#[tracing::instrument(name = "Validate token", skip(jwt, token, banned_token_store))]
+pub async fn validate_token<T>(jwt: &JwtSettings, token: &Secret<String>, banned_token_store: &T) -> Result<Claims>
+where
+    T: BannedTokenStore + ?Sized,
+{
+    // Same order as the cached path, so a token gets the same answer whichever path checks it
+    let claims = decode_token(jwt, token)?;
+    ensure_not_banned(token, banned_token_store).await?;
+    ensure_issued_after_cutoff(&claims, banned_token_store).await?;
+    Ok(claims)
 }
 
-#[tracing::instrument(name = "Validate token", skip(token, banned_token_store))]
-pub async fn validate_token<T>(token: &str, banned_token_store: &T) -> Result<Claims>
+// Like `validate_token`, but a token whose `jti` was verified within the cache's TTL skips the
+// banned-token and cutoff lookups. The signature and expiry are always checked.
+//
+// Callers hold the banned-token store's read lock for the whole call, and revocations (logout,
+// token cutoffs) update the store and evict the cache under its write lock. A token checked
+// before a revocation is therefore cached before the eviction, never after it, and once the
+// revocation returns every later call sees the token as banned. The cache is per instance, so
+// this only holds for calls to the instance that handled the revocation.
+#[tracing::instrument(name = "Validate token with cache", skip_all)]
+pub async fn validate_token_with_cache<T>(
+    jwt: &JwtSettings,
+    token: &Secret<String>,
+    banned_token_store: &T,
+    cache: &VerifiedTokenCache,
+) -> Result<Claims>
+where
+    T: BannedTokenStore + ?Sized,
+{
+    let claims = decode_token(jwt, token)?;
+    if cache.contains(&claims.jti) {
+        tracing::debug!("Token was verified recently, skipping banned check");
+        return Ok(claims);
+    }
+
+    ensure_not_banned(token, banned_token_store).await?;
+    ensure_issued_after_cutoff(&claims, banned_token_store).await?;
+    cache.insert(claims.jti.clone());
+    Ok(claims)
+}
+
+// Why a token in a batch failed validation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenRejection {
+    // Bad signature, expired or malformed
+    Invalid,
+    // Revoked by a logout or issued before the token cutoff
+    Banned,
+}
+
+// Batch form of `validate_token_with_cache`: one result per token, in order. Tokens that need a
+// banned-token lookup are checked together, so a store that supports it answers in one round
+// trip. Only a failing store fails the whole batch.
+#[tracing::instrument(name = "Validate tokens with cache", skip_all)]
+pub async fn validate_tokens_with_cache<T>(
+    jwt: &JwtSettings,
+    tokens: Vec<Secret<String>>,
+    banned_token_store: &T,
+    cache: &VerifiedTokenCache,
+) -> Result<Vec<std::result::Result<Claims, TokenRejection>>>
+where
+    T: BannedTokenStore + ?Sized,
+{
+    let mut results = Vec::with_capacity(tokens.len());
+    let mut unchecked = Vec::new();
+    let mut unchecked_tokens = Vec::new();
+
+    for token in tokens {
+        match decode_token(jwt, &token) {
+            Ok(claims) if cache.contains(&claims.jti) => results.push(Ok(claims)),
+            Ok(claims) => {
+                unchecked.push((results.len(), claims));
+                unchecked_tokens.push(token);
+                // Placeholder until the banned lookup below
+                results.push(Err(TokenRejection::Banned));
+            }
+            Err(e) => {
+                tracing::debug!("Token in batch failed validation: {:?}", e);
+                results.push(Err(TokenRejection::Invalid));
+            }
+        }
+    }
+
+    let banned = banned_token_store
+        .contains_tokens(&unchecked_tokens)
+        .await
+        .wrap_err("Failed to check banned token status")?;
+    let cutoff = if unchecked.is_empty() {
+        None
+    } else {
+        banned_token_store
+            .not_valid_before()
+            .await
+            .wrap_err("Failed to check token cutoff")?
+    };
+
+    // One lookup per distinct subject, since a batch usually carries several tokens per user
+    let mut user_cutoffs = HashMap::new();
+    for (_, claims) in &unchecked {
+        if !user_cutoffs.contains_key(&claims.sub) {
+            let user_cutoff = banned_token_store
+                .user_not_valid_before(&claims.sub)
+                .await
+                .wrap_err("Failed to check user token cutoff")?;
+            user_cutoffs.insert(claims.sub.clone(), user_cutoff);
+        }
+    }
+
+    for ((index, claims), banned) in unchecked.into_iter().zip(banned) {
+        let cutoff = cutoff_ms(cutoff, user_cutoffs.get(&claims.sub).copied().flatten());
+        if !banned && !issued_before(&claims, cutoff) {
+            cache.insert(claims.jti.clone());
+            results[index] = Ok(claims);
+        }
+    }
+
+    Ok(results)
+}
+
+async fn ensure_not_banned<T>(token: &Secret<String>, banned_token_store: &T) -> Result<()>
 where
     T: BannedTokenStore + ?Sized,
 {
@@ -70,22 +335,64 @@ where
     match banned_token_store.contains_token(token).await {
         Ok(true) => {
             tracing::warn!("Token is banned");
-            return Err(eyre!("Token is banned"));
+            Err(eyre!("Token is banned"))
         }
         Ok(false) => {
             tracing::debug!("Token is not banned, proceeding with validation");
+            Ok(())
         }
         Err(e) => {
             tracing::error!("Failed to check if token is banned: {:?}", e);
-            return Err(eyre!("Failed to check banned token status"));
+            Err(eyre!("Failed to check banned token status"))
         }
     }
+}
+
+async fn ensure_issued_after_cutoff<T>(claims: &Claims, banned_token_store: &T) -> Result<()>
+where
+    T: BannedTokenStore + ?Sized,
+{
+    let cutoff = banned_token_store.not_valid_before().await.map_err(|e| {
+        tracing::error!("Failed to get token cutoff: {:?}", e);
+        eyre!("Failed to check token cutoff")
+    })?;
+    let user_cutoff = banned_token_store.user_not_valid_before(&claims.sub).await.map_err(|e| {
+        tracing::error!("Failed to get user token cutoff: {:?}", e);
+        eyre!("Failed to check token cutoff")
+    })?;
+    let cutoff = cutoff_ms(cutoff, user_cutoff);
+
+    if issued_before(claims, cutoff) {
+        tracing::warn!("Token was issued before the cutoff");
+        return Err(eyre!("Token was issued before the cutoff"));
+    }
+    Ok(())
+}
+
+// The later of the global cutoff (seconds) and the user's cutoff (milliseconds), in milliseconds.
+// `None` orders below any cutoff.
+fn cutoff_ms(cutoff: Option<usize>, user_cutoff_ms: Option<usize>) -> Option<usize> {
+    cutoff.map(|seconds| seconds * 1000).max(user_cutoff_ms)
+}
+
+// Tokens without `iat` predate the claim, so they are older than any cutoff. Tokens without
+// `iat_ms` count from the start of their second.
+fn issued_before(claims: &Claims, cutoff_ms: Option<usize>) -> bool {
+    let iat_ms = claims.iat_ms.or(claims.iat.map(|iat| iat * 1000));
+    match (iat_ms, cutoff_ms) {
+        (_, None) => false,
+        (Some(iat_ms), Some(cutoff_ms)) => iat_ms < cutoff_ms,
+        (None, Some(_)) => true,
+    }
+}
 
+// Checks the signature and expiry without consulting the banned-token store
+pub fn decode_token(jwt: &JwtSettings, token: &Secret<String>) -> Result<Claims> {
     tracing::debug!("Decoding and validating JWT token");
     decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(JWT_SECRET.as_bytes()),
-        &Validation::default(),
+        token.expose_secret(),
+        &jwt.decoding_key()?,
+        &jwt.validation(),
     )
     .map(|data| data.claims)
     .wrap_err("Failed to decode or validate JWT token")
@@ -95,17 +402,45 @@ where
 pub struct Claims {
     pub sub: String,
     pub exp: usize,
+    // Older tokens were issued without these two and are never refreshed by sliding sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat: Option<usize>,
+    // `iat` in milliseconds, so a token issued in the same second as a revocation can still be
+    // told apart from the ones it revoked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat_ms: Option<usize>,
+    // When the user logged in; carried over unchanged when a session is refreshed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<usize>,
+    // Unique per token so a single session can be identified without the token itself
+    pub jti: String,
+    #[serde(default)]
+    pub role: Role,
+}
+
+impl Claims {
+    // The subject is always the user's email; parsing it here keeps every reader consistent
+    pub fn email(&self) -> Result<Email> {
+        Email::parse(Secret::new(self.sub.clone()))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore;
+    use crate::{
+        domain::data_stores::{BannedTokenEntry, BannedTokenStoreError},
+        services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore,
+    };
+
+    fn jwt() -> JwtSettings {
+        JwtSettings::from_config(&Config::for_tests())
+    }
 
     #[tokio::test]
     async fn test_generate_auth_cookie() {
-        let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let cookie = generate_auth_cookie(&email).await.unwrap();
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let cookie = generate_auth_cookie(&jwt(), &email, None, Role::User, false).await.unwrap();
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(cookie.value().split('.').count(), 3);
         assert_eq!(cookie.path(), Some("/"));
@@ -115,29 +450,92 @@ mod tests {
 
     #[tokio::test]
     async fn test_create_auth_cookie() {
-        let token = "test_token".to_owned();
-        let cookie = create_auth_cookie(token.clone());
+        let token = Secret::new("test_token".to_owned());
+        let cookie = create_auth_cookie(token.clone(), false);
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
-        assert_eq!(cookie.value(), token);
+        assert_eq!(cookie.value(), token.expose_secret());
         assert_eq!(cookie.path(), Some("/"));
         assert_eq!(cookie.http_only(), Some(true));
         assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+        assert_eq!(cookie.secure(), Some(false));
+    }
+
+    #[tokio::test]
+    async fn test_create_secure_auth_cookie() {
+        let cookie = create_auth_cookie(Secret::new("test_token".to_owned()), true);
+        assert_eq!(cookie.secure(), Some(true));
     }
 
     #[tokio::test]
     async fn test_generate_auth_token() {
-        let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let result = generate_auth_token(&email).await.unwrap();
-        assert_eq!(result.split('.').count(), 3);
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let result = generate_auth_token(&jwt(), &email, None, Role::User).await.unwrap();
+        assert_eq!(result.expose_secret().split('.').count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_generate_auth_token_with_session_ttl() {
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let session_ttl = SessionTtl::parse(60 * 60).unwrap();
+        let token = generate_auth_token(&jwt(), &email, Some(session_ttl), Role::User).await.unwrap();
+
+        let claims = validate_token(&jwt(), &token, &HashsetBannedTokenStore::new()).await.unwrap();
+        let expected_exp = Utc::now().timestamp() as usize + 60 * 60;
+        // Allow for the time taken between generating the token and computing the expectation
+        assert!(claims.exp <= expected_exp && claims.exp + 5 >= expected_exp);
+    }
+
+    #[tokio::test]
+    async fn test_generate_auth_token_carries_role() {
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let token = generate_auth_token(&jwt(), &email, None, Role::Admin).await.unwrap();
+
+        let claims = decode_token(&jwt(), &token).unwrap();
+        assert_eq!(claims.role, Role::Admin);
+    }
+
+    #[test]
+    fn test_claims_without_role_default_to_user() {
+        let claims: Claims = serde_json::from_value(serde_json::json!({
+            "sub": "test@example.com",
+            "exp": 0,
+            "jti": "id"
+        }))
+        .unwrap();
+        assert_eq!(claims.role, Role::User);
+    }
+
+    #[test]
+    fn test_extract_token_prefers_cookie_over_bearer_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert("cookie", format!("{}=cookie_token", JWT_COOKIE_NAME).parse().unwrap());
+        headers.insert(AUTHORIZATION, "Bearer header_token".parse().unwrap());
+        assert_eq!(extract_token(&headers).unwrap().expose_secret(), "cookie_token");
+
+        headers.remove("cookie");
+        assert_eq!(extract_token(&headers).unwrap().expose_secret(), "header_token");
+
+        headers.remove(AUTHORIZATION);
+        assert!(extract_token(&headers).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_claims_email_matches_token_subject() {
+        let email = Email::parse(Secret::new("test+tag@example.com".to_owned())).unwrap();
+        let token = generate_auth_token(&jwt(), &email, None, Role::User).await.unwrap();
+        let banned_token_store = HashsetBannedTokenStore::new();
+
+        let claims = validate_token(&jwt(), &token, &banned_token_store).await.unwrap();
+        assert_eq!(claims.email().unwrap(), email);
     }
 
     #[tokio::test]
     async fn test_validate_token_with_valid_token() {
-        let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let token = generate_auth_token(&email).await.unwrap();
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let token = generate_auth_token(&jwt(), &email, None, Role::User).await.unwrap();
         let banned_token_store = HashsetBannedTokenStore::new();
         
-        let result = validate_token(&token, &banned_token_store).await.unwrap();
+        let result = validate_token(&jwt(), &token, &banned_token_store).await.unwrap();
         assert_eq!(result.sub, "test@example.com");
 
         let exp = Utc::now()
@@ -150,22 +548,180 @@ mod tests {
 
     #[tokio::test]
     async fn test_validate_token_with_invalid_token() {
-        let token = "invalid_token".to_owned();
+        let token = Secret::new("invalid_token".to_owned());
         let banned_token_store = HashsetBannedTokenStore::new();
         
-        let result = validate_token(&token, &banned_token_store).await;
+        let result = validate_token(&jwt(), &token, &banned_token_store).await;
         assert!(result.is_err());
     }
 
     #[tokio::test]
     async fn test_validate_token_with_banned_token() {
-        let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let token = generate_auth_token(&email).await.unwrap();
-        let mut banned_token_store = HashsetBannedTokenStore::new();
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let token = generate_auth_token(&jwt(), &email, None, Role::User).await.unwrap();
+        let banned_token_store = HashsetBannedTokenStore::new();
         
         banned_token_store.store_token(token.clone()).await.unwrap();
         
-        let result = validate_token(&token, &banned_token_store).await;
+        let result = validate_token(&jwt(), &token, &banned_token_store).await;
         assert!(result.is_err());
     }
-}
\ No newline at end of file
+
+    // Counts banned-store lookups so tests can tell when the cache short-circuits them
+    #[derive(Default)]
+    struct CountingBannedTokenStore {
+        inner: HashsetBannedTokenStore,
+        lookups: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl BannedTokenStore for CountingBannedTokenStore {
+        async fn store_token(&self, token: Secret<String>) -> Result<(), BannedTokenStoreError> {
+            self.inner.store_token(token).await
+        }
+
+        async fn contains_token(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError> {
+            self.lookups.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.inner.contains_token(token).await
+        }
+
+        async fn list_tokens(&self) -> Result<Vec<BannedTokenEntry>, BannedTokenStoreError> {
+            self.inner.list_tokens().await
+        }
+
+        async fn remove_token(&self, id: &str) -> Result<bool, BannedTokenStoreError> {
+            self.inner.remove_token(id).await
+        }
+
+        async fn not_valid_before(&self) -> Result<Option<usize>, BannedTokenStoreError> {
+            self.inner.not_valid_before().await
+        }
+
+        async fn set_not_valid_before(&self, cutoff: usize) -> Result<(), BannedTokenStoreError> {
+            self.inner.set_not_valid_before(cutoff).await
+        }
+
+        async fn user_not_valid_before(&self, subject: &str) -> Result<Option<usize>, BannedTokenStoreError> {
+            self.inner.user_not_valid_before(subject).await
+        }
+
+        async fn set_user_not_valid_before(&self, subject: &str, cutoff: usize) -> Result<(), BannedTokenStoreError> {
+            self.inner.set_user_not_valid_before(subject, cutoff).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_with_cache_skips_banned_lookup_within_ttl() {
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let token = generate_auth_token(&jwt(), &email, None, Role::User).await.unwrap();
+        let banned_token_store = CountingBannedTokenStore::default();
+        let cache = VerifiedTokenCache::new(std::time::Duration::from_secs(60));
+
+        validate_token_with_cache(&jwt(), &token, &banned_token_store, &cache).await.unwrap();
+        validate_token_with_cache(&jwt(), &token, &banned_token_store, &cache).await.unwrap();
+        assert_eq!(banned_token_store.lookups.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        // Once invalidated (as on logout) the banned store is consulted again
+        let claims = decode_token(&jwt(), &token).unwrap();
+        cache.invalidate(&claims.jti);
+        banned_token_store.store_token(token.clone()).await.unwrap();
+        assert!(validate_token_with_cache(&jwt(), &token, &banned_token_store, &cache).await.is_err());
+        assert_eq!(banned_token_store.lookups.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_with_cache_rejects_invalid_token() {
+        let token = Secret::new("invalid_token".to_owned());
+        let banned_token_store = CountingBannedTokenStore::default();
+        let cache = VerifiedTokenCache::new(std::time::Duration::from_secs(60));
+
+        assert!(validate_token_with_cache(&jwt(), &token, &banned_token_store, &cache).await.is_err());
+        assert_eq!(banned_token_store.lookups.load(std::sync::atomic::Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_validate_tokens_with_cache_keeps_order_and_skips_cached_tokens() {
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let cached = generate_auth_token(&jwt(), &email, None, Role::User).await.unwrap();
+        let banned = generate_auth_token(&jwt(), &email, None, Role::User).await.unwrap();
+        let banned_token_store = CountingBannedTokenStore::default();
+        banned_token_store.store_token(banned.clone()).await.unwrap();
+        let cache = VerifiedTokenCache::new(std::time::Duration::from_secs(60));
+        validate_token_with_cache(&jwt(), &cached, &banned_token_store, &cache).await.unwrap();
+
+        let tokens = vec![banned, Secret::new("invalid_token".to_owned()), cached];
+        let results = validate_tokens_with_cache(&jwt(), tokens, &banned_token_store, &cache).await.unwrap();
+        assert_eq!(results[0].as_ref().unwrap_err(), &TokenRejection::Banned);
+        assert_eq!(results[1].as_ref().unwrap_err(), &TokenRejection::Invalid);
+        assert_eq!(results[2].as_ref().unwrap().sub, "test@example.com");
+        // The default `contains_tokens` checks one at a time, and only the banned token needed it
+        assert_eq!(banned_token_store.lookups.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    // A token for `test@example.com` issued at `iat_ms`, or without an `iat` at all
+    fn token_issued_at(iat_ms: Option<usize>) -> Secret<String> {
+        let iat = iat_ms.map(|iat_ms| iat_ms / 1000);
+        let claims = Claims {
+            sub: "test@example.com".to_owned(),
+            exp: Utc::now().timestamp() as usize + 600,
+            iat,
+            iat_ms,
+            auth_time: iat,
+            jti: Uuid::new_v4().to_string(),
+            role: Role::User,
+        };
+        Secret::new(create_token(&jwt(), &claims).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_rejects_tokens_issued_before_cutoff() {
+        let cutoff = Utc::now().timestamp() as usize;
+        let banned_token_store = HashsetBannedTokenStore::new();
+        banned_token_store.set_not_valid_before(cutoff).await.unwrap();
+        let cutoff_ms = cutoff * 1000;
+
+        assert!(validate_token(&jwt(), &token_issued_at(Some(cutoff_ms - 1)), &banned_token_store).await.is_err());
+        assert!(validate_token(&jwt(), &token_issued_at(None), &banned_token_store).await.is_err());
+        assert!(validate_token(&jwt(), &token_issued_at(Some(cutoff_ms)), &banned_token_store).await.is_ok());
+
+        let cache = VerifiedTokenCache::new(std::time::Duration::from_secs(60));
+        let tokens = vec![token_issued_at(Some(cutoff_ms - 1)), token_issued_at(Some(cutoff_ms))];
+        let results = validate_tokens_with_cache(&jwt(), tokens, &banned_token_store, &cache).await.unwrap();
+        assert_eq!(results[0].as_ref().unwrap_err(), &TokenRejection::Banned);
+        assert!(results[1].is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_rejects_tokens_issued_before_user_cutoff() {
+        let cutoff = Utc::now().timestamp_millis() as usize;
+        let banned_token_store = HashsetBannedTokenStore::new();
+        banned_token_store.set_user_not_valid_before("test@example.com", cutoff).await.unwrap();
+        // A later cutoff for someone else doesn't reach this user's tokens
+        banned_token_store.set_user_not_valid_before("other@example.com", cutoff + 60_000).await.unwrap();
+
+        assert!(validate_token(&jwt(), &token_issued_at(Some(cutoff - 1)), &banned_token_store).await.is_err());
+        assert!(validate_token(&jwt(), &token_issued_at(Some(cutoff)), &banned_token_store).await.is_ok());
+
+        let cache = VerifiedTokenCache::new(std::time::Duration::from_secs(60));
+        let tokens = vec![token_issued_at(Some(cutoff - 1)), token_issued_at(Some(cutoff))];
+        let results = validate_tokens_with_cache(&jwt(), tokens, &banned_token_store, &cache).await.unwrap();
+        assert_eq!(results[0].as_ref().unwrap_err(), &TokenRejection::Banned);
+        assert!(results[1].is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_accepts_token_issued_later_in_the_same_second_as_user_cutoff() {
+        let second_ms = Utc::now().timestamp() as usize * 1000;
+        let banned_token_store = HashsetBannedTokenStore::new();
+        banned_token_store.set_user_not_valid_before("test@example.com", second_ms + 400).await.unwrap();
+
+        assert!(validate_token(&jwt(), &token_issued_at(Some(second_ms + 100)), &banned_token_store).await.is_err());
+        assert!(validate_token(&jwt(), &token_issued_at(Some(second_ms + 700)), &banned_token_store).await.is_ok());
+
+        // A token without `iat_ms` counts from the start of its second, before the cutoff
+        let mut claims = decode_token(&jwt(), &token_issued_at(Some(second_ms + 700))).unwrap();
+        claims.iat_ms = None;
+        let token = Secret::new(create_token(&jwt(), &claims).unwrap());
+        assert!(validate_token(&jwt(), &token, &banned_token_store).await.is_err());
+    }
+}
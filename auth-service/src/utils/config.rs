@@ -0,0 +1,738 @@
+use std::{collections::HashMap, env as std_env, fmt, num::NonZeroU32, str::FromStr, time::Duration};
+use argon2::Params;
+use dotenvy::dotenv;
+use ipnet::IpNet;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use reqwest::Url;
+use secrecy::Secret;
+use crate::domain::password_hasher::LegacyPreHash;
+use crate::utils::{
+    constants::{
+        env, parse_trusted_proxies, validate_jwt_secret, CookieSecure, EmailProvider,
+        DEFAULT_ARGON2_ITERATIONS, DEFAULT_ARGON2_MEMORY_KIB, DEFAULT_ARGON2_PARALLELISM,
+        DEFAULT_ASSETS_DIR, DEFAULT_CORS_ALLOWED_ORIGINS, DEFAULT_CORS_ALLOW_HEADERS,
+        DEFAULT_CORS_ALLOW_METHODS, DEFAULT_DEVICE_TRUST_TTL_SECONDS,
+        DEFAULT_EMAIL_CIRCUIT_BREAKER_COOLDOWN_SECONDS, DEFAULT_EMAIL_CIRCUIT_BREAKER_FAILURE_THRESHOLD,
+        DEFAULT_FRESH_SESSION_MAX_AGE_SECONDS, DEFAULT_JWT_KEY_ID, DEFAULT_LOGIN_DELAY_BASE_MS,
+        DEFAULT_LOGIN_DELAY_MAX_MS, DEFAULT_MAX_CONCURRENT_REQUESTS, DEFAULT_REDIS_HOSTNAME,
+        DEFAULT_SESSION_ABSOLUTE_MAX_TTL_SECONDS, DEFAULT_SIGNUP_RATE_LIMIT,
+        DEFAULT_SLIDING_SESSION_REFRESH_THRESHOLD_SECONDS, DEFAULT_SLOW_REQUEST_THRESHOLD_MS,
+        DEFAULT_SMTP_PORT, DEFAULT_TRUSTED_PROXIES, DEFAULT_TWO_FA_MAX_ATTEMPTS,
+        DEFAULT_VERIFY_2FA_RATE_LIMIT, DEFAULT_VERIFY_TOKEN_CACHE_TTL_SECONDS,
+        DEFAULT_VERIFY_TOKEN_RATE_LIMIT,
+    },
+    cors::CorsConfig,
+    feature_flags::FeatureFlags,
+    login_delay::LoginDelay,
+    sliding_session::SlidingSessions,
+};
+
+// Everything the service reads from the environment, loaded once at startup. Every variable is
+// checked before anything is reported, so a misconfigured deployment lists all of its problems
+// in one go instead of panicking on whichever setting happens to be read first.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub jwt_secret: Secret<String>,
+    pub database_url: Secret<String>,
+    pub redis_host_name: Secret<String>,
+    // Lets several environments or services share one Redis without key collisions
+    pub redis_key_prefix: String,
+    pub admin_api_key: Option<Secret<String>>,
+    // Hashes created with a pepper only verify while the same pepper is configured, and hashes
+    // created without one stop verifying once a pepper is added. Enabling or rotating the pepper
+    // therefore requires users to reset their passwords.
+    pub password_pepper: Option<Secret<String>>,
+    // Keys the hash 2FA codes are stored under, so a copy of the store doesn't give the codes away.
+    // Changing it only invalidates codes already sent.
+    pub two_fa_code_pepper: Secret<String>,
+    // Lets users imported from a system that pre-hashed passwords log in; their hashes are
+    // upgraded to plain Argon2 on the next successful login
+    pub password_legacy_pre_hash: Option<LegacyPreHash>,
+    // Cost of new password hashes. Raising it upgrades existing hashes as users log in.
+    pub argon2_params: Params,
+    // Where users reach the app; 2FA emails link to it when set
+    pub public_app_url: Option<Url>,
+    // Directory the UI is served from, relative to the working directory unless absolute
+    pub assets_dir: String,
+    // Refuse to start without the UI instead of only warning, for deployments that serve it
+    pub assets_dir_required: bool,
+    pub feature_flags: FeatureFlags,
+    // Wrong codes accepted for one login attempt before its 2FA code is invalidated
+    pub two_fa_max_attempts: u32,
+    // Records a hashed-email receipt in Postgres for every 2FA email send
+    pub two_fa_delivery_log_enabled: bool,
+    // Records signups, logins and logouts in Postgres for `/admin/audit`
+    pub audit_log_enabled: bool,
+    // X-Forwarded-For is only honoured on connections from these networks
+    pub trusted_proxies: Vec<IpNet>,
+    // Whether the auth cookie is marked Secure; `auto` follows X-Forwarded-Proto from a trusted proxy
+    pub auth_cookie_secure: CookieSecure,
+    pub cors: CorsConfig,
+    pub email: EmailSettings,
+    // Consecutive send failures after which the email provider is left alone for the cooldown
+    pub email_circuit_breaker_failure_threshold: u32,
+    pub email_circuit_breaker_cooldown: Duration,
+    pub jwt_algorithm: Algorithm,
+    pub jwt_key_id: String,
+    // Both are set exactly when `jwt_algorithm` is RS256
+    pub jwt_rsa_private_key: Option<Secret<String>>,
+    pub jwt_rsa_public_key: Option<String>,
+    pub signup_rate_limit: NonZeroU32,
+    // Signups one IP may make back to back before the per-minute rate applies; defaults to the rate
+    pub signup_rate_limit_burst: NonZeroU32,
+    pub verify_2fa_rate_limit: NonZeroU32,
+    pub verify_token_rate_limit: NonZeroU32,
+    // How long a verified token skips the banned-token lookup in `/verify_token`; 0 disables caching
+    pub verify_token_cache_ttl: Duration,
+    // Initial state only; admins can toggle maintenance mode at runtime
+    pub maintenance_mode: bool,
+    pub login_delay: LoginDelay,
+    // How long a device stays trusted after the user asks verify_2fa to remember it; 0 disables it
+    pub device_trust_ttl: Duration,
+    // Sensitive operations require a login within this long; see `AuthenticatedUser::require_fresh_session`
+    pub fresh_session_max_age: Duration,
+    // Requests that take longer than this are logged as warnings; 0 disables the warning
+    pub slow_request_threshold: Duration,
+    // Full email addresses only appear in logs when this is on; otherwise they are masked
+    pub log_pii: bool,
+    // `None` keeps the fixed token lifetime; see `SlidingSessions` for how refreshes are bounded
+    pub sliding_sessions: Option<SlidingSessions>,
+    // Requests beyond this many in flight get a 503 instead of queueing; `None` (0) disables the cap
+    pub max_concurrent_requests: Option<usize>,
+}
+
+// The selected provider together with the settings only it needs
+#[derive(Debug, Clone)]
+pub enum EmailSettings {
+    Postmark {
+        auth_token: Secret<String>,
+    },
+    Smtp {
+        host: String,
+        port: u16,
+        username: Option<String>,
+        password: Secret<String>,
+    },
+    Mock,
+}
+
+impl EmailSettings {
+    pub fn provider(&self) -> EmailProvider {
+        match self {
+            EmailSettings::Postmark { .. } => EmailProvider::Postmark,
+            EmailSettings::Smtp { .. } => EmailProvider::Smtp,
+            EmailSettings::Mock => EmailProvider::Mock,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_vars(&EnvVars::from_env())
+    }
+
+    pub fn from_vars(vars: &EnvVars) -> Result<Self, ConfigError> {
+        let mut problems = Vec::new();
+        match Self::load(vars, &mut problems) {
+            Some(config) if problems.is_empty() => Ok(config),
+            _ => Err(ConfigError { problems }),
+        }
+    }
+
+    // Every setting is read before any missing one ends the load, so `problems` ends up with
+    // all of them
+    fn load(vars: &EnvVars, problems: &mut Vec<String>) -> Option<Self> {
+        let jwt_secret = check(problems, jwt_secret(vars));
+        let database_url = check(problems, database_url(vars));
+        let redis_host_name = check(problems, redis_host_name(vars));
+        let redis_key_prefix = check(problems, redis_key_prefix(vars));
+        let admin_api_key = check(problems, admin_api_key(vars));
+        let password_pepper = check(problems, password_pepper(vars));
+        let two_fa_code_pepper = check(problems, two_fa_code_pepper(vars));
+        let password_legacy_pre_hash = check(problems, password_legacy_pre_hash(vars));
+        let argon2_params = check(problems, argon2_params(vars));
+        let public_app_url = check(problems, public_app_url(vars));
+        let assets_dir = check(problems, assets_dir(vars));
+        let assets_dir_required = check(problems, flag(vars, env::ASSETS_DIR_REQUIRED_ENV_VAR));
+        let auto_login_on_signup = check(problems, flag(vars, env::AUTO_LOGIN_ON_SIGNUP_ENV_VAR));
+        let default_requires_2fa = check(problems, flag(vars, env::DEFAULT_REQUIRES_2FA_ENV_VAR));
+        let normalize_plus_addressing = check(problems, flag(vars, env::NORMALIZE_PLUS_ADDRESSING_ENV_VAR));
+        let two_fa_max_attempts = check(problems, two_fa_max_attempts(vars));
+        let two_fa_delivery_log_enabled = check(problems, flag(vars, env::TWO_FA_DELIVERY_LOG_ENABLED_ENV_VAR));
+        let audit_log_enabled = check(problems, flag(vars, env::AUDIT_LOG_ENABLED_ENV_VAR));
+        let trusted_proxies = check(problems, trusted_proxies(vars));
+        let auth_cookie_secure = check(problems, auth_cookie_secure(vars));
+        let cors = check(problems, cors_config(vars));
+        let email = check(problems, email_settings(vars));
+        let email_circuit_breaker_failure_threshold =
+            check(problems, email_circuit_breaker_failure_threshold(vars));
+        let email_circuit_breaker_cooldown = check(problems, email_circuit_breaker_cooldown(vars));
+        let jwt_algorithm = check(problems, jwt_algorithm(vars));
+        let jwt_key_id = check(problems, jwt_key_id(vars));
+        let (jwt_rsa_private_key, jwt_rsa_public_key) = match jwt_algorithm {
+            Some(Algorithm::RS256) => (
+                check(problems, jwt_rsa_private_key(vars)).map(Some),
+                check(problems, jwt_rsa_public_key(vars)).map(Some),
+            ),
+            _ => (Some(None), Some(None)),
+        };
+        let signup_rate_limit = check(problems, signup_rate_limit(vars));
+        let signup_rate_limit_burst = check(problems, signup_rate_limit_burst(vars));
+        let verify_2fa_rate_limit = check(problems, verify_2fa_rate_limit(vars));
+        let verify_token_rate_limit = check(problems, verify_token_rate_limit(vars));
+        let verify_token_cache_ttl = check(problems, verify_token_cache_ttl(vars));
+        let maintenance_mode = check(problems, flag(vars, env::MAINTENANCE_MODE_ENV_VAR));
+        let login_delay = check(problems, login_delay(vars));
+        let device_trust_ttl = check(problems, device_trust_ttl(vars));
+        let fresh_session_max_age = check(problems, fresh_session_max_age(vars));
+        let slow_request_threshold = check(problems, slow_request_threshold(vars));
+        let log_pii = check(problems, flag(vars, env::LOG_PII_ENV_VAR));
+        let sliding_sessions = check(problems, sliding_sessions(vars));
+        let max_concurrent_requests = check(problems, max_concurrent_requests(vars));
+
+        Some(Self {
+            jwt_secret: jwt_secret?,
+            database_url: database_url?,
+            redis_host_name: redis_host_name?,
+            redis_key_prefix: redis_key_prefix?,
+            admin_api_key: admin_api_key?,
+            password_pepper: password_pepper?,
+            two_fa_code_pepper: two_fa_code_pepper?,
+            password_legacy_pre_hash: password_legacy_pre_hash?,
+            argon2_params: argon2_params?,
+            public_app_url: public_app_url?,
+            assets_dir: assets_dir?,
+            assets_dir_required: assets_dir_required?,
+            feature_flags: FeatureFlags::default()
+                .with_auto_login_on_signup(auto_login_on_signup?)
+                .with_default_requires_2fa(default_requires_2fa?)
+                .with_normalize_plus_addressing(normalize_plus_addressing?),
+            two_fa_max_attempts: two_fa_max_attempts?,
+            two_fa_delivery_log_enabled: two_fa_delivery_log_enabled?,
+            audit_log_enabled: audit_log_enabled?,
+            trusted_proxies: trusted_proxies?,
+            auth_cookie_secure: auth_cookie_secure?,
+            cors: cors?,
+            email: email?,
+            email_circuit_breaker_failure_threshold: email_circuit_breaker_failure_threshold?,
+            email_circuit_breaker_cooldown: email_circuit_breaker_cooldown?,
+            jwt_algorithm: jwt_algorithm?,
+            jwt_key_id: jwt_key_id?,
+            jwt_rsa_private_key: jwt_rsa_private_key?,
+            jwt_rsa_public_key: jwt_rsa_public_key?,
+            signup_rate_limit: signup_rate_limit?,
+            signup_rate_limit_burst: signup_rate_limit_burst?,
+            verify_2fa_rate_limit: verify_2fa_rate_limit?,
+            verify_token_rate_limit: verify_token_rate_limit?,
+            verify_token_cache_ttl: verify_token_cache_ttl?,
+            maintenance_mode: maintenance_mode?,
+            login_delay: login_delay?,
+            device_trust_ttl: device_trust_ttl?,
+            fresh_session_max_age: fresh_session_max_age?,
+            slow_request_threshold: slow_request_threshold?,
+            log_pii: log_pii?,
+            sliding_sessions: sliding_sessions?,
+            max_concurrent_requests: max_concurrent_requests?,
+        })
+    }
+}
+
+#[cfg(test)]
+impl Config {
+    // The smallest valid configuration, for unit tests that need one
+    pub(crate) fn for_tests() -> Self {
+        let vars: EnvVars = [
+            (env::JWT_SECRET_ENV_VAR, "unit-test-jwt-secret-at-least-32-bytes"),
+            (env::DATABASE_URL_ENV_VAR, "postgres://localhost/auth"),
+            (env::TWO_FA_CODE_PEPPER_ENV_VAR, "unit-test-2fa-code-pepper"),
+            (env::EMAIL_PROVIDER_ENV_VAR, "mock"),
+        ]
+        .into_iter()
+        .collect();
+        Self::from_vars(&vars).expect("Test configuration should be valid")
+    }
+}
+
+fn check<T>(problems: &mut Vec<String>, result: Result<T, String>) -> Option<T> {
+    result.map_err(|problem| problems.push(problem)).ok()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for problem in &self.problems {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// A snapshot of environment variables. Tests build one from pairs instead of touching the
+// process environment, which other tests read concurrently.
+#[derive(Debug, Clone, Default)]
+pub struct EnvVars(HashMap<String, String>);
+
+impl EnvVars {
+    // Loads `.env` first if there is one. Variables that aren't valid UTF-8 count as unset.
+    pub fn from_env() -> Self {
+        dotenv().ok();
+        std_env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .collect()
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    // Treats an empty value like an unset one, for optional secrets left blank in `.env`
+    fn get_non_empty(&self, name: &str) -> Option<&str> {
+        self.get(name).filter(|value| !value.is_empty())
+    }
+
+    fn parse_or<T: FromStr>(&self, name: &str, default: T, expected: &str) -> Result<T, String> {
+        match self.get(name) {
+            Some(value) => value.parse().map_err(|_| format!("{} must be {}.", name, expected)),
+            None => Ok(default),
+        }
+    }
+}
+
+impl EnvVars {
+    // Overrides one variable, e.g. so tests can pin a setting whatever the environment says
+    pub fn with(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.0.insert(name.to_owned(), value.into());
+        self
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for EnvVars {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self(iter.into_iter().map(|(name, value)| (name.into(), value.into())).collect())
+    }
+}
+
+// One reader per setting, shared by `Config::from_env` and the callers that need just one, like `migrate`
+
+// Boolean switches are off unless set to `true`
+pub fn flag(vars: &EnvVars, name: &str) -> Result<bool, String> {
+    vars.parse_or(name, false, "true or false")
+}
+
+pub fn jwt_secret(vars: &EnvVars) -> Result<Secret<String>, String> {
+    let secret = vars.get(env::JWT_SECRET_ENV_VAR).ok_or("JWT_SECRET must be set.")?;
+    validate_jwt_secret(secret)?;
+    Ok(Secret::new(secret.to_owned()))
+}
+
+pub fn database_url(vars: &EnvVars) -> Result<Secret<String>, String> {
+    let url = vars.get(env::DATABASE_URL_ENV_VAR).ok_or("DATABASE_URL must be set.")?;
+    Ok(Secret::new(url.to_owned()))
+}
+
+pub fn redis_host_name(vars: &EnvVars) -> Result<Secret<String>, String> {
+    let host = vars.get(env::REDIS_HOST_NAME_ENV_VAR).unwrap_or(DEFAULT_REDIS_HOSTNAME);
+    Ok(Secret::new(host.to_owned()))
+}
+
+pub fn redis_key_prefix(vars: &EnvVars) -> Result<String, String> {
+    Ok(vars.get(env::REDIS_KEY_PREFIX_ENV_VAR).unwrap_or_default().to_owned())
+}
+
+// Only needed with the Postmark provider
+pub fn postmark_auth_token(vars: &EnvVars) -> Result<Secret<String>, String> {
+    let token = vars
+        .get(env::POSTMARK_AUTH_TOKEN_ENV_VAR)
+        .ok_or("POSTMARK_AUTH_TOKEN must be set when using the Postmark email provider.")?;
+    Ok(Secret::new(token.to_owned()))
+}
+
+pub fn admin_api_key(vars: &EnvVars) -> Result<Option<Secret<String>>, String> {
+    Ok(vars.get_non_empty(env::ADMIN_API_KEY_ENV_VAR).map(|key| Secret::new(key.to_owned())))
+}
+
+pub fn password_pepper(vars: &EnvVars) -> Result<Option<Secret<String>>, String> {
+    Ok(vars.get_non_empty(env::PASSWORD_PEPPER_ENV_VAR).map(|pepper| Secret::new(pepper.to_owned())))
+}
+
+// Required: six-digit codes hashed without a key are recovered from the hash in milliseconds
+pub fn two_fa_code_pepper(vars: &EnvVars) -> Result<Secret<String>, String> {
+    let pepper = vars
+        .get_non_empty(env::TWO_FA_CODE_PEPPER_ENV_VAR)
+        .ok_or("TWO_FA_CODE_PEPPER must be set.")?;
+    Ok(Secret::new(pepper.to_owned()))
+}
+
+pub fn password_legacy_pre_hash(vars: &EnvVars) -> Result<Option<LegacyPreHash>, String> {
+    match vars.get_non_empty(env::PASSWORD_LEGACY_PRE_HASH_ENV_VAR) {
+        None => Ok(None),
+        Some("sha256") => Ok(Some(LegacyPreHash::Sha256)),
+        Some(_) => Err("PASSWORD_LEGACY_PRE_HASH must be sha256 when set.".to_owned()),
+    }
+}
+
+pub fn argon2_params(vars: &EnvVars) -> Result<Params, String> {
+    let param = |name, default| vars.parse_or(name, default, "a non-negative integer");
+    let memory = param(env::ARGON2_MEMORY_KIB_ENV_VAR, DEFAULT_ARGON2_MEMORY_KIB);
+    let iterations = param(env::ARGON2_ITERATIONS_ENV_VAR, DEFAULT_ARGON2_ITERATIONS);
+    let parallelism = param(env::ARGON2_PARALLELISM_ENV_VAR, DEFAULT_ARGON2_PARALLELISM);
+    let ((memory, iterations), parallelism) = both(both(memory, iterations), parallelism)?;
+    Params::new(memory, iterations, parallelism, None).map_err(|_| {
+        "ARGON2_MEMORY_KIB, ARGON2_ITERATIONS and ARGON2_PARALLELISM must be valid Argon2 parameters.".to_owned()
+    })
+}
+
+pub fn public_app_url(vars: &EnvVars) -> Result<Option<Url>, String> {
+    vars.get_non_empty(env::PUBLIC_APP_URL_ENV_VAR)
+        .map(|url| {
+            // Links are joined onto it, which URLs like `mailto:` can't take
+            Url::parse(url)
+                .ok()
+                .filter(|url| matches!(url.scheme(), "http" | "https"))
+                .ok_or_else(|| "PUBLIC_APP_URL must be a valid absolute http or https URL.".to_owned())
+        })
+        .transpose()
+}
+
+pub fn assets_dir(vars: &EnvVars) -> Result<String, String> {
+    Ok(vars.get(env::ASSETS_DIR_ENV_VAR).unwrap_or(DEFAULT_ASSETS_DIR).to_owned())
+}
+
+pub fn two_fa_max_attempts(vars: &EnvVars) -> Result<u32, String> {
+    positive(vars, env::TWO_FA_MAX_ATTEMPTS_ENV_VAR, DEFAULT_TWO_FA_MAX_ATTEMPTS)
+}
+
+pub fn trusted_proxies(vars: &EnvVars) -> Result<Vec<IpNet>, String> {
+    parse_trusted_proxies(vars.get(env::TRUSTED_PROXIES_ENV_VAR).unwrap_or(DEFAULT_TRUSTED_PROXIES))
+}
+
+pub fn auth_cookie_secure(vars: &EnvVars) -> Result<CookieSecure, String> {
+    match vars.get(env::AUTH_COOKIE_SECURE_ENV_VAR) {
+        None | Some("false") => Ok(CookieSecure::Never),
+        Some("true") => Ok(CookieSecure::Always),
+        Some("auto") => Ok(CookieSecure::Auto),
+        Some(_) => Err("AUTH_COOKIE_SECURE must be one of true, false or auto.".to_owned()),
+    }
+}
+
+pub fn cors_config(vars: &EnvVars) -> Result<CorsConfig, String> {
+    let allowed_origins = vars.get(env::CORS_ALLOWED_ORIGINS_ENV_VAR).unwrap_or(DEFAULT_CORS_ALLOWED_ORIGINS);
+    let allow_methods = vars.get(env::CORS_ALLOW_METHODS_ENV_VAR).unwrap_or(DEFAULT_CORS_ALLOW_METHODS);
+    let allow_headers = vars.get(env::CORS_ALLOW_HEADERS_ENV_VAR).unwrap_or(DEFAULT_CORS_ALLOW_HEADERS);
+    let allow_credentials = vars.parse_or(env::CORS_ALLOW_CREDENTIALS_ENV_VAR, true, "true or false")?;
+    CorsConfig::parse(allowed_origins, allow_methods, allow_headers, allow_credentials)
+}
+
+pub fn email_provider(vars: &EnvVars) -> Result<EmailProvider, String> {
+    match vars.get(env::EMAIL_PROVIDER_ENV_VAR) {
+        None | Some("postmark") => Ok(EmailProvider::Postmark),
+        Some("smtp") => Ok(EmailProvider::Smtp),
+        Some("mock") => Ok(EmailProvider::Mock),
+        Some(_) => Err("EMAIL_PROVIDER must be one of postmark, smtp or mock.".to_owned()),
+    }
+}
+
+// Settings for providers other than the selected one aren't required
+pub fn email_settings(vars: &EnvVars) -> Result<EmailSettings, String> {
+    match email_provider(vars)? {
+        EmailProvider::Postmark => Ok(EmailSettings::Postmark {
+            auth_token: postmark_auth_token(vars)?,
+        }),
+        EmailProvider::Smtp => {
+            let (host, port) = both(smtp_host(vars), smtp_port(vars))?;
+            Ok(EmailSettings::Smtp {
+                host,
+                port,
+                username: smtp_username(vars)?,
+                password: smtp_password(vars)?,
+            })
+        }
+        EmailProvider::Mock => Ok(EmailSettings::Mock),
+    }
+}
+
+pub fn email_circuit_breaker_failure_threshold(vars: &EnvVars) -> Result<u32, String> {
+    positive(
+        vars,
+        env::EMAIL_CIRCUIT_BREAKER_FAILURE_THRESHOLD_ENV_VAR,
+        DEFAULT_EMAIL_CIRCUIT_BREAKER_FAILURE_THRESHOLD,
+    )
+}
+
+pub fn email_circuit_breaker_cooldown(vars: &EnvVars) -> Result<Duration, String> {
+    seconds(vars, env::EMAIL_CIRCUIT_BREAKER_COOLDOWN_ENV_VAR, DEFAULT_EMAIL_CIRCUIT_BREAKER_COOLDOWN_SECONDS)
+}
+
+// Only needed with the SMTP provider
+pub fn smtp_host(vars: &EnvVars) -> Result<String, String> {
+    vars.get(env::SMTP_HOST_ENV_VAR)
+        .map(str::to_owned)
+        .ok_or_else(|| "SMTP_HOST must be set when using the SMTP email provider.".to_owned())
+}
+
+pub fn smtp_port(vars: &EnvVars) -> Result<u16, String> {
+    vars.parse_or(env::SMTP_PORT_ENV_VAR, DEFAULT_SMTP_PORT, "a valid port number")
+}
+
+pub fn smtp_username(vars: &EnvVars) -> Result<Option<String>, String> {
+    Ok(vars.get(env::SMTP_USERNAME_ENV_VAR).map(str::to_owned))
+}
+
+pub fn smtp_password(vars: &EnvVars) -> Result<Secret<String>, String> {
+    Ok(Secret::new(vars.get(env::SMTP_PASSWORD_ENV_VAR).unwrap_or_default().to_owned()))
+}
+
+pub fn jwt_algorithm(vars: &EnvVars) -> Result<Algorithm, String> {
+    match vars.get(env::JWT_ALGORITHM_ENV_VAR) {
+        None | Some("HS256") => Ok(Algorithm::HS256),
+        Some("RS256") => Ok(Algorithm::RS256),
+        Some(_) => Err("JWT_ALGORITHM must be either HS256 or RS256.".to_owned()),
+    }
+}
+
+pub fn jwt_key_id(vars: &EnvVars) -> Result<String, String> {
+    Ok(vars.get(env::JWT_KEY_ID_ENV_VAR).unwrap_or(DEFAULT_JWT_KEY_ID).to_owned())
+}
+
+// The RSA keys are only needed with RS256. They are parsed here so a bad key stops startup
+// rather than failing every login.
+pub fn jwt_rsa_private_key(vars: &EnvVars) -> Result<Secret<String>, String> {
+    let key = vars
+        .get(env::JWT_RSA_PRIVATE_KEY_ENV_VAR)
+        .ok_or("JWT_RSA_PRIVATE_KEY must be set when using RS256.")?;
+    EncodingKey::from_rsa_pem(key.as_bytes())
+        .map_err(|_| "JWT_RSA_PRIVATE_KEY must be a PEM-encoded RSA private key.".to_owned())?;
+    Ok(Secret::new(key.to_owned()))
+}
+
+pub fn jwt_rsa_public_key(vars: &EnvVars) -> Result<String, String> {
+    let key = vars
+        .get(env::JWT_RSA_PUBLIC_KEY_ENV_VAR)
+        .ok_or("JWT_RSA_PUBLIC_KEY must be set when using RS256.")?;
+    DecodingKey::from_rsa_pem(key.as_bytes())
+        .map_err(|_| "JWT_RSA_PUBLIC_KEY must be a PEM-encoded RSA public key.".to_owned())?;
+    Ok(key.to_owned())
+}
+
+// Rate limits are expressed as requests per minute per client IP
+pub fn signup_rate_limit(vars: &EnvVars) -> Result<NonZeroU32, String> {
+    rate_limit(vars, env::SIGNUP_RATE_LIMIT_ENV_VAR, DEFAULT_SIGNUP_RATE_LIMIT)
+}
+
+// Defaults to the rate. An invalid rate is reported by `signup_rate_limit`, not again here.
+pub fn signup_rate_limit_burst(vars: &EnvVars) -> Result<NonZeroU32, String> {
+    let default = signup_rate_limit(vars).map_or(DEFAULT_SIGNUP_RATE_LIMIT, NonZeroU32::get);
+    rate_limit(vars, env::SIGNUP_RATE_LIMIT_BURST_ENV_VAR, default)
+}
+
+pub fn verify_2fa_rate_limit(vars: &EnvVars) -> Result<NonZeroU32, String> {
+    rate_limit(vars, env::VERIFY_2FA_RATE_LIMIT_ENV_VAR, DEFAULT_VERIFY_2FA_RATE_LIMIT)
+}
+
+pub fn verify_token_rate_limit(vars: &EnvVars) -> Result<NonZeroU32, String> {
+    rate_limit(vars, env::VERIFY_TOKEN_RATE_LIMIT_ENV_VAR, DEFAULT_VERIFY_TOKEN_RATE_LIMIT)
+}
+
+pub fn verify_token_cache_ttl(vars: &EnvVars) -> Result<Duration, String> {
+    seconds(vars, env::VERIFY_TOKEN_CACHE_TTL_ENV_VAR, DEFAULT_VERIFY_TOKEN_CACHE_TTL_SECONDS)
+}
+
+pub fn login_delay(vars: &EnvVars) -> Result<LoginDelay, String> {
+    let (base, max) = both(
+        millis(vars, env::LOGIN_DELAY_BASE_ENV_VAR, DEFAULT_LOGIN_DELAY_BASE_MS),
+        millis(vars, env::LOGIN_DELAY_MAX_ENV_VAR, DEFAULT_LOGIN_DELAY_MAX_MS),
+    )?;
+    Ok(LoginDelay::new(base, max))
+}
+
+pub fn device_trust_ttl(vars: &EnvVars) -> Result<Duration, String> {
+    seconds(vars, env::DEVICE_TRUST_TTL_ENV_VAR, DEFAULT_DEVICE_TRUST_TTL_SECONDS)
+}
+
+pub fn fresh_session_max_age(vars: &EnvVars) -> Result<Duration, String> {
+    seconds(vars, env::FRESH_SESSION_MAX_AGE_ENV_VAR, DEFAULT_FRESH_SESSION_MAX_AGE_SECONDS)
+}
+
+pub fn slow_request_threshold(vars: &EnvVars) -> Result<Duration, String> {
+    millis(vars, env::SLOW_REQUEST_THRESHOLD_ENV_VAR, DEFAULT_SLOW_REQUEST_THRESHOLD_MS)
+}
+
+// The durations are only checked when sliding sessions are on
+pub fn sliding_sessions(vars: &EnvVars) -> Result<Option<SlidingSessions>, String> {
+    if !flag(vars, env::SLIDING_SESSIONS_ENV_VAR)? {
+        return Ok(None);
+    }
+
+    let refresh_threshold = seconds(
+        vars,
+        env::SLIDING_SESSION_REFRESH_THRESHOLD_ENV_VAR,
+        DEFAULT_SLIDING_SESSION_REFRESH_THRESHOLD_SECONDS,
+    );
+    let absolute_max = seconds(vars, env::SESSION_ABSOLUTE_MAX_TTL_ENV_VAR, DEFAULT_SESSION_ABSOLUTE_MAX_TTL_SECONDS);
+    let (refresh_threshold, absolute_max) = both(refresh_threshold, absolute_max)?;
+    Ok(Some(SlidingSessions::new(refresh_threshold, absolute_max)))
+}
+
+pub fn max_concurrent_requests(vars: &EnvVars) -> Result<Option<usize>, String> {
+    let max = vars.parse_or(
+        env::MAX_CONCURRENT_REQUESTS_ENV_VAR,
+        DEFAULT_MAX_CONCURRENT_REQUESTS,
+        "a non-negative integer",
+    )?;
+    Ok((max > 0).then_some(max))
+}
+
+pub fn rate_limit(vars: &EnvVars, name: &str, default: u32) -> Result<NonZeroU32, String> {
+    let limit = vars.parse_or(name, default, "a positive integer")?;
+    NonZeroU32::new(limit).ok_or_else(|| format!("{} must be greater than zero.", name))
+}
+
+fn positive(vars: &EnvVars, name: &str, default: u32) -> Result<u32, String> {
+    match vars.parse_or(name, default, "a positive integer")? {
+        0 => Err(format!("{} must be a positive integer.", name)),
+        value => Ok(value),
+    }
+}
+
+// Reports both problems when both values are bad
+fn both<A, B>(a: Result<A, String>, b: Result<B, String>) -> Result<(A, B), String> {
+    match (a, b) {
+        (Ok(a), Ok(b)) => Ok((a, b)),
+        (a, b) => Err([a.err(), b.err()].into_iter().flatten().collect::<Vec<_>>().join(" ")),
+    }
+}
+
+fn seconds(vars: &EnvVars, name: &str, default: u64) -> Result<Duration, String> {
+    vars.parse_or(name, default, "a non-negative integer").map(Duration::from_secs)
+}
+
+fn millis(vars: &EnvVars, name: &str, default: u64) -> Result<Duration, String> {
+    vars.parse_or(name, default, "a non-negative integer").map(Duration::from_millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::constants::MIN_JWT_SECRET_LENGTH;
+
+    fn minimal_vars() -> Vec<(&'static str, String)> {
+        vec![
+            (env::JWT_SECRET_ENV_VAR, "a".repeat(MIN_JWT_SECRET_LENGTH)),
+            (env::DATABASE_URL_ENV_VAR, "postgres://localhost/auth".to_owned()),
+            (env::TWO_FA_CODE_PEPPER_ENV_VAR, "unit-test-2fa-code-pepper".to_owned()),
+            (env::EMAIL_PROVIDER_ENV_VAR, "mock".to_owned()),
+        ]
+    }
+
+    fn problems(vars: EnvVars) -> Vec<String> {
+        Config::from_vars(&vars).expect_err("config should be invalid").problems
+    }
+
+    #[test]
+    fn loads_defaults_from_minimal_env() {
+        let config = Config::from_vars(&minimal_vars().into_iter().collect()).unwrap();
+
+        assert!(matches!(config.email, EmailSettings::Mock));
+        assert_eq!(config.jwt_algorithm, Algorithm::HS256);
+        assert!(config.jwt_rsa_private_key.is_none());
+        assert_eq!(config.two_fa_max_attempts, DEFAULT_TWO_FA_MAX_ATTEMPTS);
+        assert_eq!(config.signup_rate_limit_burst, config.signup_rate_limit);
+        assert_eq!(config.feature_flags, FeatureFlags::default());
+        assert!(config.sliding_sessions.is_none());
+    }
+
+    #[test]
+    fn reports_every_missing_variable_at_once() {
+        let vars = [
+            (env::EMAIL_PROVIDER_ENV_VAR, "smtp"),
+            (env::JWT_ALGORITHM_ENV_VAR, "RS256"),
+        ];
+
+        let problems = problems(vars.into_iter().collect());
+
+        assert_eq!(
+            problems,
+            vec![
+                "JWT_SECRET must be set.",
+                "DATABASE_URL must be set.",
+                "TWO_FA_CODE_PEPPER must be set.",
+                "SMTP_HOST must be set when using the SMTP email provider.",
+                "JWT_RSA_PRIVATE_KEY must be set when using RS256.",
+                "JWT_RSA_PUBLIC_KEY must be set when using RS256.",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_values_alongside_missing_ones() {
+        let vars = [
+            (env::JWT_SECRET_ENV_VAR, "too-short"),
+            (env::TWO_FA_MAX_ATTEMPTS_ENV_VAR, "0"),
+            (env::LOGIN_DELAY_BASE_ENV_VAR, "soon"),
+            (env::LOG_PII_ENV_VAR, "yes"),
+        ];
+
+        let problems = problems(vars.into_iter().collect());
+
+        assert!(problems.iter().any(|p| p.starts_with("JWT_SECRET must be at least")));
+        assert!(problems.contains(&"DATABASE_URL must be set.".to_owned()));
+        assert!(problems.contains(&"TWO_FA_MAX_ATTEMPTS must be a positive integer.".to_owned()));
+        assert!(problems.contains(&"LOGIN_DELAY_BASE_MS must be a non-negative integer.".to_owned()));
+        assert!(problems.contains(&"LOG_PII must be true or false.".to_owned()));
+        // The default provider is Postmark
+        assert!(problems.contains(&"POSTMARK_AUTH_TOKEN must be set when using the Postmark email provider.".to_owned()));
+        assert_eq!(problems.len(), 6);
+    }
+
+    #[test]
+    fn rejects_rsa_keys_that_are_not_pem() {
+        let mut vars = minimal_vars();
+        vars.push((env::JWT_ALGORITHM_ENV_VAR, "RS256".to_owned()));
+        vars.push((env::JWT_RSA_PRIVATE_KEY_ENV_VAR, "not-a-key".to_owned()));
+        vars.push((env::JWT_RSA_PUBLIC_KEY_ENV_VAR, "not-a-key".to_owned()));
+
+        let problems = problems(vars.into_iter().collect());
+
+        assert!(problems.contains(&"JWT_RSA_PRIVATE_KEY must be a PEM-encoded RSA private key.".to_owned()));
+        assert!(problems.contains(&"JWT_RSA_PUBLIC_KEY must be a PEM-encoded RSA public key.".to_owned()));
+    }
+
+    #[test]
+    fn rejects_public_app_url_that_links_cannot_be_built_on() {
+        for url in ["app.example.com", "mailto:support@example.com"] {
+            let mut vars = minimal_vars();
+            vars.push((env::PUBLIC_APP_URL_ENV_VAR, url.to_owned()));
+
+            let problems = problems(vars.into_iter().collect());
+
+            assert_eq!(problems, vec!["PUBLIC_APP_URL must be a valid absolute http or https URL."]);
+        }
+    }
+
+    #[test]
+    fn only_requires_settings_for_the_selected_provider() {
+        let mut vars = minimal_vars();
+        vars.push((env::EMAIL_PROVIDER_ENV_VAR, "postmark".to_owned()));
+        vars.push((env::POSTMARK_AUTH_TOKEN_ENV_VAR, "token".to_owned()));
+
+        let config = Config::from_vars(&vars.into_iter().collect()).unwrap();
+
+        assert_eq!(config.email.provider(), EmailProvider::Postmark);
+    }
+
+    #[test]
+    fn error_lists_each_problem_on_its_own_line() {
+        let error = ConfigError {
+            problems: vec!["A must be set.".to_owned(), "B must be set.".to_owned()],
+        };
+
+        assert_eq!(error.to_string(), "Invalid configuration:\n  - A must be set.\n  - B must be set.");
+    }
+}
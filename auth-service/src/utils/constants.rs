@@ -1,49 +1,266 @@
-use dotenvy::dotenv;
-use lazy_static::lazy_static;
-use std::env as std_env;
-use secrecy::{Secret, ExposeSecret};
+use std::ops::Deref;
+use std::sync::OnceLock;
 use std::time::Duration;
+use std::num::NonZeroU32;
+use ipnet::IpNet;
+use argon2::Params;
+use jsonwebtoken::Algorithm;
+use reqwest::Url;
+use secrecy::Secret;
+use crate::domain::password_hasher::LegacyPreHash;
+use crate::utils::config::{self, EnvVars};
+use crate::utils::cors::CorsConfig;
+use crate::utils::login_delay::LoginDelay;
+use crate::utils::sliding_session::SlidingSessions;
 
-lazy_static! {
-    pub static ref JWT_SECRET: Secret<String> = Secret::new(set_token());
-    pub static ref DATABASE_URL: Secret<String> = Secret::new(set_database_url());
-    pub static ref REDIS_HOST_NAME: Secret<String> = Secret::new(set_redis_host());
-    pub static ref POSTMARK_AUTH_TOKEN: Secret<String> = Secret::new(set_postmark_auth_token());
+// Deprecated shim over `Config`, kept for one release so code outside this crate that still
+// reads settings from these statics has time to move. Each one is parsed on first use by the
+// same function `Config::from_env` uses.
+pub struct Setting<T> {
+    value: OnceLock<T>,
+    read: fn(&EnvVars) -> Result<T, String>,
 }
 
-fn set_token() -> String {
-    dotenv().ok();
-    let secret = std_env::var(env::JWT_SECRET_ENV_VAR).expect("JWT_SECRET must be set.");
-    if secret.is_empty() {
-        panic!("JWT_SECRET must not be empty.");
+impl<T> Setting<T> {
+    const fn new(read: fn(&EnvVars) -> Result<T, String>) -> Self {
+        Self { value: OnceLock::new(), read }
     }
-    secret
 }
 
-fn set_database_url() -> String {
-    dotenv().ok();
-    std_env::var(env::DATABASE_URL_ENV_VAR).expect("DATABASE_URL must be set.")
+impl<T> Deref for Setting<T> {
+    type Target = T;
+
+    // Panics, since a static has no other way to report a bad value
+    fn deref(&self) -> &T {
+        self.value
+            .get_or_init(|| (self.read)(&EnvVars::from_env()).unwrap_or_else(|e| panic!("{}", e)))
+    }
+}
+
+// Not `lazy_static!`, which puts attributes on its generated type rather than the static, so
+// callers would never see the deprecation
+macro_rules! deprecated_settings {
+    ($($name:ident: $ty:ty = $read:expr;)*) => {
+        $(
+            #[deprecated(note = "read settings from `Config`; these statics go away in the next release")]
+            pub static $name: Setting<$ty> = Setting::new($read);
+        )*
+    };
 }
 
-fn set_redis_host() -> String {
-    dotenv().ok();
-    std_env::var(env::REDIS_HOST_NAME_ENV_VAR).unwrap_or(DEFAULT_REDIS_HOSTNAME.to_owned())
+deprecated_settings! {
+    JWT_SECRET: Secret<String> = config::jwt_secret;
+    DATABASE_URL: Secret<String> = config::database_url;
+    REDIS_HOST_NAME: Secret<String> = config::redis_host_name;
+    REDIS_KEY_PREFIX: String = config::redis_key_prefix;
+    POSTMARK_AUTH_TOKEN: Secret<String> = config::postmark_auth_token;
+    ADMIN_API_KEY: Option<Secret<String>> = config::admin_api_key;
+    PASSWORD_PEPPER: Option<Secret<String>> = config::password_pepper;
+    TWO_FA_CODE_PEPPER: Secret<String> = config::two_fa_code_pepper;
+    PASSWORD_LEGACY_PRE_HASH: Option<LegacyPreHash> = config::password_legacy_pre_hash;
+    ARGON2_PARAMS: Params = config::argon2_params;
+    PUBLIC_APP_URL: Option<Url> = config::public_app_url;
+    ASSETS_DIR: String = config::assets_dir;
+    ASSETS_DIR_REQUIRED: bool = |vars| config::flag(vars, env::ASSETS_DIR_REQUIRED_ENV_VAR);
+    AUTO_LOGIN_ON_SIGNUP: bool = |vars| config::flag(vars, env::AUTO_LOGIN_ON_SIGNUP_ENV_VAR);
+    NORMALIZE_PLUS_ADDRESSING: bool = |vars| config::flag(vars, env::NORMALIZE_PLUS_ADDRESSING_ENV_VAR);
+    DEFAULT_REQUIRES_2FA: bool = |vars| config::flag(vars, env::DEFAULT_REQUIRES_2FA_ENV_VAR);
+    TWO_FA_MAX_ATTEMPTS: u32 = config::two_fa_max_attempts;
+    TWO_FA_DELIVERY_LOG_ENABLED: bool = |vars| config::flag(vars, env::TWO_FA_DELIVERY_LOG_ENABLED_ENV_VAR);
+    AUDIT_LOG_ENABLED: bool = |vars| config::flag(vars, env::AUDIT_LOG_ENABLED_ENV_VAR);
+    TRUSTED_PROXIES: Vec<IpNet> = config::trusted_proxies;
+    AUTH_COOKIE_SECURE: CookieSecure = config::auth_cookie_secure;
+    CORS_CONFIG: CorsConfig = config::cors_config;
+    EMAIL_PROVIDER: EmailProvider = config::email_provider;
+    EMAIL_CIRCUIT_BREAKER_FAILURE_THRESHOLD: u32 = config::email_circuit_breaker_failure_threshold;
+    EMAIL_CIRCUIT_BREAKER_COOLDOWN: Duration = config::email_circuit_breaker_cooldown;
+    SMTP_HOST: String = config::smtp_host;
+    SMTP_PORT: u16 = config::smtp_port;
+    SMTP_USERNAME: Option<String> = config::smtp_username;
+    SMTP_PASSWORD: Secret<String> = config::smtp_password;
+    JWT_ALGORITHM: Algorithm = config::jwt_algorithm;
+    JWT_KEY_ID: String = config::jwt_key_id;
+    JWT_RSA_PRIVATE_KEY: Secret<String> = config::jwt_rsa_private_key;
+    JWT_RSA_PUBLIC_KEY: String = config::jwt_rsa_public_key;
+    SIGNUP_RATE_LIMIT: NonZeroU32 = config::signup_rate_limit;
+    SIGNUP_RATE_LIMIT_BURST: NonZeroU32 = config::signup_rate_limit_burst;
+    VERIFY_2FA_RATE_LIMIT: NonZeroU32 = config::verify_2fa_rate_limit;
+    VERIFY_TOKEN_RATE_LIMIT: NonZeroU32 = config::verify_token_rate_limit;
+    VERIFY_TOKEN_CACHE_TTL: Duration = config::verify_token_cache_ttl;
+    MAINTENANCE_MODE: bool = |vars| config::flag(vars, env::MAINTENANCE_MODE_ENV_VAR);
+    LOGIN_DELAY: LoginDelay = config::login_delay;
+    DEVICE_TRUST_TTL: Duration = config::device_trust_ttl;
+    FRESH_SESSION_MAX_AGE: Duration = config::fresh_session_max_age;
+    SLOW_REQUEST_THRESHOLD: Duration = config::slow_request_threshold;
+    LOG_PII: bool = |vars| config::flag(vars, env::LOG_PII_ENV_VAR);
+    SLIDING_SESSIONS: Option<SlidingSessions> = config::sliding_sessions;
+    MAX_CONCURRENT_REQUESTS: Option<usize> = config::max_concurrent_requests;
 }
 
-fn set_postmark_auth_token() -> String {
-    dotenv().ok();
-    std_env::var(env::POSTMARK_AUTH_TOKEN_ENV_VAR).expect("POSTMARK_AUTH_TOKEN must be set")
+// HS256 keys shorter than the hash output are trivially brute-forced offline
+pub fn validate_jwt_secret(secret: &str) -> Result<(), String> {
+    if secret.len() < MIN_JWT_SECRET_LENGTH {
+        return Err(format!(
+            "JWT_SECRET must be at least {} bytes long, got {}.",
+            MIN_JWT_SECRET_LENGTH,
+            secret.len()
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailProvider {
+    Postmark,
+    Smtp,
+    Mock,
+}
+
+pub fn parse_trusted_proxies(proxies: &str) -> Result<Vec<IpNet>, String> {
+    proxies
+        .split(',')
+        .map(str::trim)
+        .filter(|cidr| !cidr.is_empty())
+        .map(|cidr| {
+            cidr.parse()
+                .map_err(|_| format!("TRUSTED_PROXIES entry '{}' is not a valid CIDR.", cidr))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CookieSecure {
+    Always,
+    Never,
+    Auto,
+}
+
+impl CookieSecure {
+    // Behind a TLS-terminating proxy the app itself only sees plain HTTP, so `auto` relies on
+    // the proxy saying whether the client connected over HTTPS
+    pub fn is_secure(self, forwarded_https: bool) -> bool {
+        match self {
+            CookieSecure::Always => true,
+            CookieSecure::Never => false,
+            CookieSecure::Auto => forwarded_https,
+        }
+    }
 }
 
 pub mod env {
     pub const JWT_SECRET_ENV_VAR: &str = "JWT_SECRET";
     pub const DATABASE_URL_ENV_VAR: &str = "DATABASE_URL";
     pub const REDIS_HOST_NAME_ENV_VAR: &str = "REDIS_HOST_NAME";
+    pub const REDIS_KEY_PREFIX_ENV_VAR: &str = "REDIS_KEY_PREFIX";
     pub const POSTMARK_AUTH_TOKEN_ENV_VAR: &str = "POSTMARK_AUTH_TOKEN";
+    pub const ADMIN_API_KEY_ENV_VAR: &str = "ADMIN_API_KEY";
+    pub const PASSWORD_PEPPER_ENV_VAR: &str = "PASSWORD_PEPPER";
+    pub const TWO_FA_CODE_PEPPER_ENV_VAR: &str = "TWO_FA_CODE_PEPPER";
+    pub const PASSWORD_LEGACY_PRE_HASH_ENV_VAR: &str = "PASSWORD_LEGACY_PRE_HASH";
+    pub const ARGON2_MEMORY_KIB_ENV_VAR: &str = "ARGON2_MEMORY_KIB";
+    pub const ARGON2_ITERATIONS_ENV_VAR: &str = "ARGON2_ITERATIONS";
+    pub const ARGON2_PARALLELISM_ENV_VAR: &str = "ARGON2_PARALLELISM";
+    pub const PUBLIC_APP_URL_ENV_VAR: &str = "PUBLIC_APP_URL";
+    pub const ASSETS_DIR_ENV_VAR: &str = "ASSETS_DIR";
+    pub const ASSETS_DIR_REQUIRED_ENV_VAR: &str = "ASSETS_DIR_REQUIRED";
+    pub const AUTO_LOGIN_ON_SIGNUP_ENV_VAR: &str = "AUTO_LOGIN_ON_SIGNUP";
+    pub const NORMALIZE_PLUS_ADDRESSING_ENV_VAR: &str = "NORMALIZE_PLUS_ADDRESSING";
+    pub const DEFAULT_REQUIRES_2FA_ENV_VAR: &str = "DEFAULT_REQUIRES_2FA";
+    pub const TWO_FA_MAX_ATTEMPTS_ENV_VAR: &str = "TWO_FA_MAX_ATTEMPTS";
+    pub const TWO_FA_DELIVERY_LOG_ENABLED_ENV_VAR: &str = "TWO_FA_DELIVERY_LOG_ENABLED";
+    pub const AUDIT_LOG_ENABLED_ENV_VAR: &str = "AUDIT_LOG_ENABLED";
+    pub const TRUSTED_PROXIES_ENV_VAR: &str = "TRUSTED_PROXIES";
+    pub const AUTH_COOKIE_SECURE_ENV_VAR: &str = "AUTH_COOKIE_SECURE";
+    pub const CORS_ALLOWED_ORIGINS_ENV_VAR: &str = "CORS_ALLOWED_ORIGINS";
+    pub const CORS_ALLOW_METHODS_ENV_VAR: &str = "CORS_ALLOW_METHODS";
+    pub const CORS_ALLOW_HEADERS_ENV_VAR: &str = "CORS_ALLOW_HEADERS";
+    pub const CORS_ALLOW_CREDENTIALS_ENV_VAR: &str = "CORS_ALLOW_CREDENTIALS";
+    pub const EMAIL_PROVIDER_ENV_VAR: &str = "EMAIL_PROVIDER";
+    pub const EMAIL_CIRCUIT_BREAKER_FAILURE_THRESHOLD_ENV_VAR: &str = "EMAIL_CIRCUIT_BREAKER_FAILURE_THRESHOLD";
+    pub const EMAIL_CIRCUIT_BREAKER_COOLDOWN_ENV_VAR: &str = "EMAIL_CIRCUIT_BREAKER_COOLDOWN_SECONDS";
+    pub const SMTP_HOST_ENV_VAR: &str = "SMTP_HOST";
+    pub const SMTP_PORT_ENV_VAR: &str = "SMTP_PORT";
+    pub const SMTP_USERNAME_ENV_VAR: &str = "SMTP_USERNAME";
+    pub const SMTP_PASSWORD_ENV_VAR: &str = "SMTP_PASSWORD";
+    pub const JWT_ALGORITHM_ENV_VAR: &str = "JWT_ALGORITHM";
+    pub const JWT_KEY_ID_ENV_VAR: &str = "JWT_KEY_ID";
+    pub const JWT_RSA_PRIVATE_KEY_ENV_VAR: &str = "JWT_RSA_PRIVATE_KEY";
+    pub const JWT_RSA_PUBLIC_KEY_ENV_VAR: &str = "JWT_RSA_PUBLIC_KEY";
+    pub const SIGNUP_RATE_LIMIT_ENV_VAR: &str = "SIGNUP_RATE_LIMIT_PER_MINUTE";
+    pub const SIGNUP_RATE_LIMIT_BURST_ENV_VAR: &str = "SIGNUP_RATE_LIMIT_BURST";
+    pub const VERIFY_2FA_RATE_LIMIT_ENV_VAR: &str = "VERIFY_2FA_RATE_LIMIT_PER_MINUTE";
+    pub const VERIFY_TOKEN_RATE_LIMIT_ENV_VAR: &str = "VERIFY_TOKEN_RATE_LIMIT_PER_MINUTE";
+    pub const VERIFY_TOKEN_CACHE_TTL_ENV_VAR: &str = "VERIFY_TOKEN_CACHE_TTL_SECONDS";
+    pub const MAINTENANCE_MODE_ENV_VAR: &str = "MAINTENANCE_MODE";
+    pub const LOGIN_DELAY_BASE_ENV_VAR: &str = "LOGIN_DELAY_BASE_MS";
+    pub const LOGIN_DELAY_MAX_ENV_VAR: &str = "LOGIN_DELAY_MAX_MS";
+    pub const DEVICE_TRUST_TTL_ENV_VAR: &str = "DEVICE_TRUST_TTL_SECONDS";
+    pub const FRESH_SESSION_MAX_AGE_ENV_VAR: &str = "FRESH_SESSION_MAX_AGE_SECONDS";
+    pub const SLOW_REQUEST_THRESHOLD_ENV_VAR: &str = "SLOW_REQUEST_THRESHOLD_MS";
+    pub const LOG_PII_ENV_VAR: &str = "LOG_PII";
+    pub const SLIDING_SESSIONS_ENV_VAR: &str = "SLIDING_SESSIONS";
+    pub const SLIDING_SESSION_REFRESH_THRESHOLD_ENV_VAR: &str = "SLIDING_SESSION_REFRESH_THRESHOLD_SECONDS";
+    pub const SESSION_ABSOLUTE_MAX_TTL_ENV_VAR: &str = "SESSION_ABSOLUTE_MAX_TTL_SECONDS";
+    pub const MAX_CONCURRENT_REQUESTS_ENV_VAR: &str = "MAX_CONCURRENT_REQUESTS";
 }
 
 pub const JWT_COOKIE_NAME: &str = "jwt";
+pub const DEVICE_TRUST_COOKIE_NAME: &str = "device_trust";
+pub const STATIC_ASSETS_CACHE_CONTROL: &str = "public, max-age=3600";
+pub const API_CACHE_CONTROL: &str = "no-store";
+pub const DEFAULT_ASSETS_DIR: &str = "assets";
+// Smaller bodies, like error responses, gain less from compression than it costs
+pub const COMPRESSION_MIN_SIZE_BYTES: u16 = 1024;
+pub const MIN_JWT_SECRET_LENGTH: usize = 32;
+pub const ADMIN_API_KEY_HEADER: &str = "x-admin-key";
+pub const TWO_FA_REQUIRED_HEADER: &str = "x-2fa-required";
+pub const LOGIN_ATTEMPT_ID_HEADER: &str = "x-login-attempt-id";
 pub const DEFAULT_REDIS_HOSTNAME: &str = "127.0.0.1";
+pub const TWO_FA_CODE_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+pub const RATE_LIMIT_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+pub const DEFAULT_TWO_FA_MAX_ATTEMPTS: u32 = 3;
+pub const DEFAULT_ARGON2_MEMORY_KIB: u32 = 15000;
+pub const DEFAULT_ARGON2_ITERATIONS: u32 = 2;
+pub const DEFAULT_ARGON2_PARALLELISM: u32 = 1;
+pub const DEFAULT_SMTP_PORT: u16 = 587;
+pub const DEFAULT_EMAIL_CIRCUIT_BREAKER_FAILURE_THRESHOLD: u32 = 5;
+pub const DEFAULT_EMAIL_CIRCUIT_BREAKER_COOLDOWN_SECONDS: u64 = 30;
+pub const DEFAULT_JWT_KEY_ID: &str = "auth-service-key";
+pub const DEFAULT_SIGNUP_RATE_LIMIT: u32 = 10;
+pub const DEFAULT_VERIFY_2FA_RATE_LIMIT: u32 = 10;
+pub const DEFAULT_VERIFY_TOKEN_RATE_LIMIT: u32 = 120;
+pub const DEFAULT_VERIFY_TOKEN_CACHE_TTL_SECONDS: u64 = 5;
+// A batch counts as one request against the rate limit, so its size is capped separately
+pub const MAX_VERIFY_TOKEN_BATCH_SIZE: usize = 100;
+pub const MAINTENANCE_RETRY_AFTER_SECONDS: u64 = 300;
+// Overload is expected to pass quickly, unlike maintenance
+pub const OVERLOADED_RETRY_AFTER_SECONDS: u64 = 1;
+pub const DEFAULT_AUDIT_PAGE_SIZE: u32 = 50;
+pub const MAX_AUDIT_PAGE_SIZE: u32 = 200;
+// Kept under typical orchestrator probe timeouts so a hung dependency reads as down, not as a timed-out probe
+pub const READINESS_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+pub const DEFAULT_SLOW_REQUEST_THRESHOLD_MS: u64 = 500;
+pub const DEFAULT_LOGIN_DELAY_BASE_MS: u64 = 1000;
+pub const DEFAULT_LOGIN_DELAY_MAX_MS: u64 = 8000;
+// Failed logins for an email stop adding to its delay once this long passes without another
+pub const LOGIN_FAILURE_WINDOW: Duration = Duration::from_secs(15 * 60);
+pub const DEFAULT_DEVICE_TRUST_TTL_SECONDS: u64 = 60 * 60 * 24 * 30;
+pub const DEFAULT_FRESH_SESSION_MAX_AGE_SECONDS: u64 = 15 * 60;
+// Revocations a slow `/events` subscriber can fall behind by before it has to re-check the store
+pub const REVOCATION_EVENT_BUFFER: usize = 256;
+pub const REVOCATION_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+// Half the default token lifetime, so an active user's cookie is renewed well before it lapses
+pub const DEFAULT_SLIDING_SESSION_REFRESH_THRESHOLD_SECONDS: u64 = 300;
+pub const DEFAULT_SESSION_ABSOLUTE_MAX_TTL_SECONDS: u64 = 60 * 60 * 12;
+// Well above what the Postgres pool can serve at once, so only a real pile-up is shed
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 512;
+// Only a reverse proxy on the same host is trusted unless TRUSTED_PROXIES says otherwise
+pub const DEFAULT_TRUSTED_PROXIES: &str = "127.0.0.1/32,::1/128";
+// The app service, locally and in production
+pub const DEFAULT_CORS_ALLOWED_ORIGINS: &str = "http://localhost:8000,http://68.183.141.53:8000";
+// PATCH is for the profile endpoint the UI calls
+pub const DEFAULT_CORS_ALLOW_METHODS: &str = "GET,POST,PATCH";
+pub const DEFAULT_CORS_ALLOW_HEADERS: &str = "content-type,cookie,authorization";
 
 pub mod prod {
     pub const APP_ADDRESS: &str = "0.0.0.0:3000";
@@ -57,11 +274,63 @@ pub mod prod {
 }
 
 pub mod test {
+    use std::time::Duration;
     pub const APP_ADDRESS: &str = "127.0.0.1:0";
+    pub const ADMIN_API_KEY: &str = "test-admin-key";
+    pub const TWO_FA_CODE_PEPPER: &str = "test-2fa-code-pepper";
+    // Keeps the failed-login delay in the request path without slowing the test suite down
+    pub const LOGIN_DELAY_BASE: Duration = Duration::from_millis(1);
+    pub const LOGIN_DELAY_MAX: Duration = Duration::from_millis(8);
+    // Pinned so tests don't depend on the environment's TWO_FA_MAX_ATTEMPTS
+    pub const TWO_FA_MAX_ATTEMPTS: u32 = 3;
     
     pub mod email_client {
         use std::time::Duration;
         pub const SENDER: &str = "test@email.com";
         pub const TIMEOUT: Duration = Duration::from_millis(200);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_empty_jwt_secret() {
+        assert!(validate_jwt_secret("").is_err());
+    }
+
+    #[test]
+    fn rejects_short_jwt_secret() {
+        let secret = "a".repeat(MIN_JWT_SECRET_LENGTH - 1);
+        assert!(validate_jwt_secret(&secret).is_err());
+    }
+
+    #[test]
+    fn accepts_jwt_secret_of_minimum_length() {
+        let secret = "a".repeat(MIN_JWT_SECRET_LENGTH);
+        assert!(validate_jwt_secret(&secret).is_ok());
+    }
+
+    #[test]
+    fn parses_trusted_proxy_cidrs() {
+        let proxies = parse_trusted_proxies(" 10.0.0.0/8, ::1/128,").unwrap();
+        assert_eq!(proxies, vec!["10.0.0.0/8".parse::<IpNet>().unwrap(), "::1/128".parse().unwrap()]);
+        assert!(parse_trusted_proxies("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_trusted_proxy_cidr() {
+        assert!(parse_trusted_proxies("10.0.0.0/8,not-a-cidr").is_err());
+        assert!(parse_trusted_proxies("10.0.0.0/33").is_err());
+    }
+
+    #[test]
+    fn auto_cookie_security_follows_forwarded_proto() {
+        assert!(CookieSecure::Auto.is_secure(true));
+        assert!(!CookieSecure::Auto.is_secure(false));
+        // Static settings ignore what the proxy says
+        assert!(CookieSecure::Always.is_secure(false));
+        assert!(!CookieSecure::Never.is_secure(true));
+    }
+}
@@ -0,0 +1,165 @@
+use axum::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+use crate::utils::{
+    constants::{LOGIN_ATTEMPT_ID_HEADER, TWO_FA_REQUIRED_HEADER},
+    rate_limit::{X_RATELIMIT_LIMIT, X_RATELIMIT_REMAINING, X_RATELIMIT_RESET},
+};
+
+// `None` stands for the `*` wildcard
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    pub allowed_origins: Option<Vec<HeaderValue>>,
+    pub allow_methods: Option<Vec<Method>>,
+    pub allow_headers: Option<Vec<HeaderName>>,
+    pub allow_credentials: bool,
+}
+
+impl CorsConfig {
+    pub fn parse(
+        allowed_origins: &str,
+        allow_methods: &str,
+        allow_headers: &str,
+        allow_credentials: bool,
+    ) -> Result<Self, String> {
+        let config = Self {
+            allowed_origins: parse_list(allowed_origins, "CORS_ALLOWED_ORIGINS", |origin| {
+                origin.parse::<HeaderValue>().map_err(|e| e.to_string())
+            })?,
+            allow_methods: parse_list(allow_methods, "CORS_ALLOW_METHODS", |method| {
+                method.to_uppercase().parse::<Method>().map_err(|e| e.to_string())
+            })?,
+            allow_headers: parse_list(allow_headers, "CORS_ALLOW_HEADERS", |header| {
+                header.parse::<HeaderName>().map_err(|e| e.to_string())
+            })?,
+            allow_credentials,
+        };
+
+        // Browsers refuse credentialed responses that use wildcards, and CorsLayer would panic on them
+        if config.allow_credentials {
+            if config.allowed_origins.is_none() {
+                return Err("CORS_ALLOW_CREDENTIALS cannot be combined with a wildcard origin.".to_owned());
+            }
+            if config.allow_methods.is_none() {
+                return Err("CORS_ALLOW_CREDENTIALS cannot be combined with wildcard methods.".to_owned());
+            }
+            if config.allow_headers.is_none() {
+                return Err("CORS_ALLOW_CREDENTIALS cannot be combined with wildcard headers.".to_owned());
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn layer(&self) -> CorsLayer {
+        let allow_origin = match &self.allowed_origins {
+            Some(origins) => AllowOrigin::list(origins.clone()),
+            None => AllowOrigin::any(),
+        };
+        let allow_methods = match &self.allow_methods {
+            Some(methods) => AllowMethods::list(methods.clone()),
+            None => AllowMethods::any(),
+        };
+        let allow_headers = match &self.allow_headers {
+            Some(headers) => AllowHeaders::list(headers.clone()),
+            None => AllowHeaders::any(),
+        };
+
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods(allow_methods)
+            .allow_headers(allow_headers)
+            .allow_credentials(self.allow_credentials)
+            .expose_headers([
+                HeaderName::from_static("set-cookie"),
+                HeaderName::from_static("authorization"),
+                HeaderName::from_static(TWO_FA_REQUIRED_HEADER),
+                HeaderName::from_static(LOGIN_ATTEMPT_ID_HEADER),
+                X_RATELIMIT_LIMIT,
+                X_RATELIMIT_REMAINING,
+                X_RATELIMIT_RESET,
+            ])
+    }
+}
+
+// Comma-separated list where a lone `*` means "any"
+fn parse_list<T>(
+    value: &str,
+    name: &str,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> Result<Option<Vec<T>>, String> {
+    let entries: Vec<&str> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .collect();
+
+    if entries == ["*"] {
+        return Ok(None);
+    }
+
+    entries
+        .into_iter()
+        .map(|entry| parse(entry).map_err(|e| format!("{} entry '{}' is invalid: {}", name, entry, e)))
+        .collect::<Result<Vec<T>, String>>()
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{header, Request},
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    #[test]
+    fn parses_lists_and_wildcards() {
+        let config = CorsConfig::parse("http://localhost:8000, http://example.com", "get,post,OPTIONS", "*", false)
+            .unwrap();
+        assert_eq!(config.allowed_origins.unwrap().len(), 2);
+        assert_eq!(config.allow_methods.unwrap(), vec![Method::GET, Method::POST, Method::OPTIONS]);
+        assert!(config.allow_headers.is_none());
+    }
+
+    #[test]
+    fn rejects_credentials_with_wildcards() {
+        assert!(CorsConfig::parse("*", "GET", "content-type", true).is_err());
+        assert!(CorsConfig::parse("http://localhost:8000", "*", "content-type", true).is_err());
+        assert!(CorsConfig::parse("http://localhost:8000", "GET", "*", true).is_err());
+        assert!(CorsConfig::parse("*", "*", "*", false).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        assert!(CorsConfig::parse("http://localhost:8000", "GET,NOT A METHOD", "content-type", true).is_err());
+        assert!(CorsConfig::parse("http://localhost:8000", "GET", "bad header", true).is_err());
+    }
+
+    #[tokio::test]
+    async fn layer_answers_preflight_from_config() {
+        let config = CorsConfig::parse("http://localhost:8000", "GET,DELETE", "content-type", true).unwrap();
+        let app = Router::new().route("/", get(|| async {})).layer(config.layer());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method(Method::OPTIONS)
+                    .uri("/")
+                    .header(header::ORIGIN, "http://localhost:8000")
+                    .header(header::ACCESS_CONTROL_REQUEST_METHOD, "DELETE")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "http://localhost:8000");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET,DELETE");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS], "content-type");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+    }
+}
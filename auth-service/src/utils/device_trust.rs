@@ -0,0 +1,124 @@
+use std::time::Duration;
+use axum_extra::extract::{cookie::{Cookie, SameSite}, CookieJar};
+use chrono::Utc;
+use color_eyre::eyre::{eyre, Context, Result};
+use jsonwebtoken::{decode, encode};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use crate::{
+    app_state::TrustedDeviceStoreType,
+    domain::email::Email,
+};
+use super::{auth::JwtSettings, constants::DEVICE_TRUST_COOKIE_NAME};
+
+// Signed with the JWT key so the cookie can't be forged or moved to another account. The
+// device must also still be in the trusted device store, which bounds how long trust lasts.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceTrustClaims {
+    pub sub: String,
+    pub device_id: String,
+    pub exp: usize,
+}
+
+#[tracing::instrument(name = "Generate device trust cookie", skip_all)]
+pub fn generate_device_trust_cookie(
+    jwt: &JwtSettings,
+    email: &Email,
+    device_id: &str,
+    ttl: Duration,
+) -> Result<Cookie<'static>> {
+    let exp = Utc::now().timestamp() as u64 + ttl.as_secs();
+    let claims = DeviceTrustClaims {
+        sub: email.as_ref().expose_secret().to_owned(),
+        device_id: device_id.to_owned(),
+        exp: exp.try_into().wrap_err("Failed to convert timestamp to usize")?,
+    };
+    let token = encode(&jwt.header(), &claims, &jwt.encoding_key()?)
+        .wrap_err("Failed to encode device trust token")?;
+
+    let max_age = time::Duration::try_from(ttl).wrap_err("Failed to convert device trust TTL")?;
+    Ok(Cookie::build((DEVICE_TRUST_COOKIE_NAME, token))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(max_age)
+        .build())
+}
+
+// The device id from the trust cookie, if the cookie is present, validly signed and issued to `email`
+fn trusted_device_id(jwt: &JwtSettings, jar: &CookieJar, email: &Email) -> Result<Option<String>> {
+    let Some(cookie) = jar.get(DEVICE_TRUST_COOKIE_NAME) else {
+        return Ok(None);
+    };
+
+    let claims = decode::<DeviceTrustClaims>(cookie.value(), &jwt.decoding_key()?, &jwt.validation())
+        .wrap_err("Failed to decode or validate device trust token")?
+        .claims;
+    if claims.sub != *email.as_ref().expose_secret() {
+        return Err(eyre!("Device trust token was issued to another user"));
+    }
+    Ok(Some(claims.device_id))
+}
+
+// Any failure means the device isn't trusted, so the user is challenged as usual
+#[tracing::instrument(name = "Check trusted device", skip_all)]
+pub async fn is_trusted_device(
+    jwt: &JwtSettings,
+    store: &TrustedDeviceStoreType,
+    jar: &CookieJar,
+    email: &Email,
+) -> bool {
+    let device_id = match trusted_device_id(jwt, jar, email) {
+        Ok(Some(device_id)) => device_id,
+        Ok(None) => return false,
+        Err(e) => {
+            tracing::warn!("Ignoring device trust cookie: {:?}", e);
+            return false;
+        }
+    };
+
+    match store.contains_device(email, &device_id).await {
+        Ok(trusted) => trusted,
+        Err(e) => {
+            tracing::error!("Failed to check trusted device: {:?}", e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::Secret;
+    use crate::utils::config::Config;
+
+    fn jwt() -> JwtSettings {
+        JwtSettings::from_config(&Config::for_tests())
+    }
+
+    fn jar_with(cookie: Cookie<'static>) -> CookieJar {
+        CookieJar::new().add(cookie)
+    }
+
+    #[test]
+    fn trust_cookie_is_bound_to_its_user() {
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let other_email = Email::parse(Secret::new("other@example.com".to_owned())).unwrap();
+        let cookie = generate_device_trust_cookie(&jwt(), &email, "device-1", Duration::from_secs(60)).unwrap();
+        assert_eq!(cookie.http_only(), Some(true));
+
+        let jar = jar_with(cookie);
+        assert_eq!(trusted_device_id(&jwt(), &jar, &email).unwrap(), Some("device-1".to_owned()));
+        assert!(trusted_device_id(&jwt(), &jar, &other_email).is_err());
+        assert_eq!(trusted_device_id(&jwt(), &CookieJar::new(), &email).unwrap(), None);
+    }
+
+    #[test]
+    fn tampered_trust_cookie_is_rejected() {
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let cookie = generate_device_trust_cookie(&jwt(), &email, "device-1", Duration::from_secs(60)).unwrap();
+        let tampered = Cookie::new(DEVICE_TRUST_COOKIE_NAME, format!("{}x", cookie.value()));
+
+        assert!(trusted_device_id(&jwt(), &jar_with(tampered), &email).is_err());
+    }
+}
@@ -0,0 +1,534 @@
+use std::{
+    convert::Infallible,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    ops::Deref,
+    time::Duration,
+};
+use ipnet::IpNet;
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{request::Parts, Extensions, HeaderMap},
+};
+use chrono::Utc;
+use secrecy::{ExposeSecret, Secret};
+use subtle::ConstantTimeEq;
+use crate::{
+    app_state::AppState,
+    domain::{
+        data_stores::{admin_key_hash, UserStoreError},
+        email::Email,
+        error::AuthAPIError,
+        locale::Locale,
+        user::{Role, User},
+    },
+    utils::{
+        auth::{extract_token, validate_token},
+        config::Config,
+        constants::{parse_trusted_proxies, CookieSecure, ADMIN_API_KEY_HEADER, DEFAULT_TRUSTED_PROXIES},
+    },
+};
+
+pub const X_FORWARDED_FOR: &str = "x-forwarded-for";
+pub const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+
+// The proxy and cookie settings `ClientContext` applies. `Application::build` adds them to every
+// request as an extension, since the extractor also runs in middleware without the app state.
+#[derive(Debug, Clone)]
+pub struct ClientSettings {
+    // X-Forwarded-For is only honoured on connections from these networks
+    pub trusted_proxies: Vec<IpNet>,
+    // Whether the auth cookie is marked Secure; `auto` follows X-Forwarded-Proto from a trusted proxy
+    pub auth_cookie_secure: CookieSecure,
+}
+
+impl ClientSettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            trusted_proxies: config.trusted_proxies.clone(),
+            auth_cookie_secure: config.auth_cookie_secure,
+        }
+    }
+}
+
+// The same as an unset TRUSTED_PROXIES and AUTH_COOKIE_SECURE, for requests built outside the app
+impl Default for ClientSettings {
+    fn default() -> Self {
+        Self {
+            trusted_proxies: parse_trusted_proxies(DEFAULT_TRUSTED_PROXIES).expect("Default trusted proxies are valid"),
+            auth_cookie_secure: CookieSecure::Never,
+        }
+    }
+}
+
+// Information about the caller that isn't part of the request body, used for rate limiting
+// and for deciding how cookies are set
+#[derive(Debug, Clone)]
+pub struct ClientContext {
+    pub ip: IpAddr,
+    // Whether a trusted proxy reported the client's connection as HTTPS
+    pub https: bool,
+    // From `Accept-Language`, for users who haven't picked a language
+    pub locale: Locale,
+    // From `ClientSettings`; see `secure_cookies`
+    pub auth_cookie_secure: CookieSecure,
+}
+
+impl ClientContext {
+    // For middleware, which has the whole request rather than its parts
+    pub fn from_headers(headers: &HeaderMap, extensions: &Extensions) -> Self {
+        let peer_ip = extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let settings = extensions.get::<ClientSettings>().cloned().unwrap_or_default();
+
+        Self {
+            ip: client_ip(headers, peer_ip, &settings.trusted_proxies),
+            https: forwarded_https(headers, peer_ip, &settings.trusted_proxies),
+            locale: Locale::from_headers(headers),
+            auth_cookie_secure: settings.auth_cookie_secure,
+        }
+    }
+
+    pub fn secure_cookies(&self) -> bool {
+        self.auth_cookie_secure.is_secure(self.https)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientContext
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_headers(&parts.headers, &parts.extensions))
+    }
+}
+
+fn is_trusted_proxy(peer_ip: IpAddr, trusted_proxies: &[IpNet]) -> bool {
+    trusted_proxies.iter().any(|proxy| proxy.contains(&peer_ip))
+}
+
+// X-Forwarded-For can be set by anyone, so it's only used when the connection comes from a
+// trusted proxy; otherwise the peer address is the client
+fn client_ip(headers: &HeaderMap, peer_ip: Option<IpAddr>, trusted_proxies: &[IpNet]) -> IpAddr {
+    match peer_ip {
+        Some(peer_ip) if is_trusted_proxy(peer_ip, trusted_proxies) => {
+            forwarded_for(headers, trusted_proxies).unwrap_or(peer_ip)
+        }
+        Some(peer_ip) => peer_ip,
+        None => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+    }
+}
+
+// Same trust rule as X-Forwarded-For. The left-most entry is the proxy nearest the client.
+fn forwarded_https(headers: &HeaderMap, peer_ip: Option<IpAddr>, trusted_proxies: &[IpNet]) -> bool {
+    if !peer_ip.is_some_and(|peer_ip| is_trusted_proxy(peer_ip, trusted_proxies)) {
+        return false;
+    }
+    headers
+        .get(X_FORWARDED_PROTO)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"))
+}
+
+// Each proxy appends the address it received the request from, so only entries added by trusted
+// proxies can be believed. The right-most untrusted entry is the client; anything to its left was
+// written by the client itself.
+fn forwarded_for(headers: &HeaderMap, trusted_proxies: &[IpNet]) -> Option<IpAddr> {
+    let value = headers.get(X_FORWARDED_FOR)?.to_str().ok()?;
+    for entry in value.rsplit(',') {
+        let ip: IpAddr = entry.trim().parse().ok()?;
+        if !is_trusted_proxy(ip, trusted_proxies) {
+            return Some(ip);
+        }
+    }
+    None
+}
+
+// Guards admin endpoints behind the admin API key sent in the `X-Admin-Key` header: the key
+// rotated in last if there is one, otherwise the configured bootstrap key
+#[derive(Debug, Clone)]
+pub struct AdminGuard;
+
+#[async_trait]
+impl FromRequestParts<AppState> for AdminGuard {
+    type Rejection = AuthAPIError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let rotated_key_hash = state.admin_key_store.get_key_hash().await.map_err(|e| {
+            tracing::error!("Failed to read admin key hash: {:?}", e);
+            AuthAPIError::UnexpectedError(e.into())
+        })?;
+
+        if rotated_key_hash.is_none() && state.admin_api_key.is_none() {
+            tracing::warn!("Admin endpoint called but no admin key is configured");
+            return Err(AuthAPIError::InvalidAdminKey);
+        }
+
+        let provided_key = parts
+            .headers
+            .get(ADMIN_API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| Secret::new(value.to_owned()))
+            .ok_or(AuthAPIError::InvalidAdminKey)?;
+
+        let valid = match (rotated_key_hash, &state.admin_api_key) {
+            // Once a key has been rotated in, the bootstrap key stops working
+            (Some(key_hash), _) => secrets_match(&admin_key_hash(&provided_key), &key_hash),
+            (None, Some(expected_key)) => {
+                secrets_match(provided_key.expose_secret(), expected_key.expose_secret())
+            }
+            (None, None) => false,
+        };
+        if !valid {
+            tracing::warn!("Invalid admin key provided");
+            return Err(AuthAPIError::InvalidAdminKey);
+        }
+
+        Ok(Self)
+    }
+}
+
+fn secrets_match(provided: &str, expected: &str) -> bool {
+    bool::from(provided.as_bytes().ct_eq(expected.as_bytes()))
+}
+
+// The caller identified by a valid, non-banned JWT from the auth cookie or bearer header
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub email: Email,
+    pub token: Secret<String>,
+    pub jti: String,
+    pub role: Role,
+    // When the user last logged in with their credentials; `None` for tokens issued before
+    // tokens carried it
+    pub auth_time: Option<usize>,
+}
+
+impl AuthenticatedUser {
+    // Guards operations a long-lived session shouldn't be able to perform on its own, like
+    // changing credentials: the user must have logged in within `max_age`. Measured from the
+    // login rather than `iat`, which a sliding refresh resets, and tokens without a login time
+    // never count as fresh.
+    pub fn require_fresh_session(&self, max_age: Duration) -> Result<(), AuthAPIError> {
+        let now = Utc::now().timestamp() as usize;
+        match self.auth_time {
+            Some(auth_time) if now.saturating_sub(auth_time) as u64 <= max_age.as_secs() => Ok(()),
+            _ => {
+                tracing::info!("Session is too old for this operation, asking the user to log in again");
+                Err(AuthAPIError::ReauthenticationRequired)
+            }
+        }
+    }
+
+    // Not every handler needs the full user record, so loading it is opt-in
+    pub async fn load_user(&self, state: &AppState) -> Result<User, AuthAPIError> {
+        state.user_store.get_user(&self.email).await
+            .map_err(|e| match e {
+                UserStoreError::UserNotFound => {
+                    tracing::warn!("Token belongs to a user that no longer exists");
+                    AuthAPIError::InvalidToken
+                }
+                e => AuthAPIError::UnexpectedError(e.into()),
+            })
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for AuthenticatedUser {
+    type Rejection = AuthAPIError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let token = extract_token(&parts.headers).ok_or_else(|| {
+            tracing::warn!("No JWT cookie or bearer token found");
+            AuthAPIError::MissingToken
+        })?;
+
+        let banned_token_store = state.banned_token_store.read().await;
+        let claims = validate_token(&state.jwt, &token, banned_token_store.deref())
+            .await
+            .map_err(|e| {
+                tracing::warn!("Token validation failed: {:?}", e);
+                AuthAPIError::InvalidToken
+            })?;
+        drop(banned_token_store);
+
+        let email = claims.email().map_err(|e| {
+            tracing::warn!("Token subject is not a valid email: {:?}", e);
+            AuthAPIError::InvalidToken
+        })?;
+
+        Ok(Self { email, token, jti: claims.jti, role: claims.role, auth_time: claims.auth_time })
+    }
+}
+
+// An authenticated user whose token carries the admin role. The stored flag is checked too, so
+// revoking admin takes effect straight away rather than when the token expires.
+#[derive(Debug, Clone)]
+pub struct AdminUser(pub AuthenticatedUser);
+
+#[async_trait]
+impl FromRequestParts<AppState> for AdminUser {
+    type Rejection = AuthAPIError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let user = AuthenticatedUser::from_request_parts(parts, state).await?;
+        if user.role != Role::Admin {
+            tracing::warn!("Token does not carry the admin role");
+            return Err(AuthAPIError::Forbidden);
+        }
+
+        if !user.load_user(state).await?.is_admin {
+            tracing::warn!("Admin role has been revoked since the token was issued");
+            return Err(AuthAPIError::Forbidden);
+        }
+
+        Ok(Self(user))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use axum::http::{header::AUTHORIZATION, HeaderValue, Request};
+    use tokio::sync::RwLock;
+    use crate::{
+        domain::password::Password,
+        services::{
+            data_stores::{
+                hashmap_user_store::HashmapUserStore,
+                hashset_banned_token_store::HashsetBannedTokenStore,
+            },
+            mock_email_client::MockEmailClient,
+        },
+        utils::auth::{generate_auth_cookie, refresh_auth_cookie, Claims, TOKEN_TTL_SECONDS},
+    };
+
+    fn app_state() -> AppState {
+        AppState::new(
+            &Config::for_tests(),
+            Arc::new(HashmapUserStore::default()),
+            Arc::new(RwLock::new(HashsetBannedTokenStore::new())),
+            Arc::new(MockEmailClient),
+        )
+    }
+
+    fn parts_with_bearer(token: Option<&str>) -> Parts {
+        let mut builder = Request::builder();
+        if let Some(token) = token {
+            builder = builder.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        builder.body(()).unwrap().into_parts().0
+    }
+
+    async fn valid_token() -> String {
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        generate_auth_cookie(&app_state().jwt, &email, None, Role::User, false).await.unwrap().value().to_owned()
+    }
+
+    #[tokio::test]
+    async fn authenticated_user_resolves_email_from_valid_token() {
+        let token = valid_token().await;
+        let mut parts = parts_with_bearer(Some(&token));
+
+        let user = AuthenticatedUser::from_request_parts(&mut parts, &app_state()).await.unwrap();
+        assert_eq!(user.email.to_string(), "test@example.com");
+        assert_eq!(user.token.expose_secret(), &token);
+    }
+
+    #[tokio::test]
+    async fn authenticated_user_rejects_missing_token() {
+        let mut parts = parts_with_bearer(None);
+
+        let result = AuthenticatedUser::from_request_parts(&mut parts, &app_state()).await;
+        assert!(matches!(result, Err(AuthAPIError::MissingToken)));
+    }
+
+    #[tokio::test]
+    async fn authenticated_user_rejects_invalid_token() {
+        let mut parts = parts_with_bearer(Some("invalid_token"));
+
+        let result = AuthenticatedUser::from_request_parts(&mut parts, &app_state()).await;
+        assert!(matches!(result, Err(AuthAPIError::InvalidToken)));
+    }
+
+    #[tokio::test]
+    async fn authenticated_user_rejects_banned_token() {
+        let state = app_state();
+        let token = valid_token().await;
+        state.banned_token_store.write().await.store_token(Secret::new(token.clone())).await.unwrap();
+        let mut parts = parts_with_bearer(Some(&token));
+
+        let result = AuthenticatedUser::from_request_parts(&mut parts, &state).await;
+        assert!(matches!(result, Err(AuthAPIError::InvalidToken)));
+    }
+
+    #[tokio::test]
+    async fn admin_user_rejects_token_once_admin_is_revoked() {
+        let state = app_state();
+        let email = Email::parse(Secret::new("admin@example.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_owned())).unwrap();
+        let mut user = User::new(email.clone(), password, false);
+        user.is_admin = true;
+        state.user_store.add_user(user).await.unwrap();
+
+        let token = generate_auth_cookie(&state.jwt, &email, None, Role::Admin, false).await.unwrap().value().to_owned();
+        let mut parts = parts_with_bearer(Some(&token));
+        assert!(AdminUser::from_request_parts(&mut parts, &state).await.is_ok());
+
+        state.user_store.set_admin(&email, false).await.unwrap();
+        let mut parts = parts_with_bearer(Some(&token));
+        let result = AdminUser::from_request_parts(&mut parts, &state).await;
+        assert!(matches!(result, Err(AuthAPIError::Forbidden)));
+    }
+
+    // A valid token for a session that logged in `age` ago
+    fn token_logged_in_ago(age: Duration, auth_time_claim: bool) -> String {
+        let now = Utc::now().timestamp() as usize;
+        let logged_in = now - age.as_secs() as usize;
+        let claims = Claims {
+            sub: "test@example.com".to_owned(),
+            exp: now + TOKEN_TTL_SECONDS as usize,
+            iat: Some(logged_in),
+            iat_ms: None,
+            auth_time: auth_time_claim.then_some(logged_in),
+            jti: "id".to_owned(),
+            role: Role::User,
+        };
+        refresh_auth_cookie(&app_state().jwt, &claims, claims.exp, false).unwrap().value().to_owned()
+    }
+
+    async fn authenticated_user(token: &str) -> AuthenticatedUser {
+        let mut parts = parts_with_bearer(Some(token));
+        AuthenticatedUser::from_request_parts(&mut parts, &app_state()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn fresh_session_is_accepted() {
+        let user = authenticated_user(&valid_token().await).await;
+        assert!(user.require_fresh_session(Duration::from_secs(5 * 60)).is_ok());
+
+        let user = authenticated_user(&token_logged_in_ago(Duration::from_secs(4 * 60), true)).await;
+        assert!(user.require_fresh_session(Duration::from_secs(5 * 60)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn old_session_requires_reauthentication() {
+        // Still a valid token, e.g. one kept alive by sliding refreshes
+        let user = authenticated_user(&token_logged_in_ago(Duration::from_secs(60 * 60), true)).await;
+        let result = user.require_fresh_session(Duration::from_secs(5 * 60));
+        assert!(matches!(result, Err(AuthAPIError::ReauthenticationRequired)));
+    }
+
+    #[tokio::test]
+    async fn session_without_login_time_requires_reauthentication() {
+        let user = authenticated_user(&token_logged_in_ago(Duration::ZERO, false)).await;
+        let result = user.require_fresh_session(Duration::from_secs(5 * 60));
+        assert!(matches!(result, Err(AuthAPIError::ReauthenticationRequired)));
+    }
+
+    #[tokio::test]
+    async fn uses_forwarded_for_header_when_present() {
+        let (mut parts, _) = Request::builder()
+            .header(X_FORWARDED_FOR, HeaderValue::from_static("10.0.0.1, 10.0.0.2"))
+            .body(())
+            .unwrap()
+            .into_parts();
+        parts
+            .extensions
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 3000))));
+
+        let context = ClientContext::from_request_parts(&mut parts, &()).await.unwrap();
+        assert_eq!(context.ip, "10.0.0.2".parse::<IpAddr>().unwrap());
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_peer_address() {
+        let (mut parts, _) = Request::builder().body(()).unwrap().into_parts();
+        parts
+            .extensions
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 3000))));
+
+        let context = ClientContext::from_request_parts(&mut parts, &()).await.unwrap();
+        assert_eq!(context.ip, "127.0.0.1".parse::<IpAddr>().unwrap());
+        assert!(!context.https);
+    }
+
+    fn forwarded_headers(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(X_FORWARDED_FOR, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn trusts_forwarded_for_from_trusted_proxy() {
+        let trusted: Vec<IpNet> = vec!["10.1.0.0/16".parse().unwrap()];
+        let ip = client_ip(&forwarded_headers("203.0.113.7"), Some("10.1.2.3".parse().unwrap()), &trusted);
+        assert_eq!(ip, "203.0.113.7".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn ignores_forged_forwarded_for_entries_left_of_the_client() {
+        let trusted: Vec<IpNet> = vec!["10.1.0.0/16".parse().unwrap()];
+        let proxy = Some("10.1.2.3".parse().unwrap());
+
+        // The client sent `X-Forwarded-For: 192.0.2.1` and the proxy appended the real address
+        let ip = client_ip(&forwarded_headers("192.0.2.1, 203.0.113.7"), proxy, &trusted);
+        assert_eq!(ip, "203.0.113.7".parse::<IpAddr>().unwrap());
+
+        // Entries added by a chain of trusted proxies are skipped
+        let ip = client_ip(&forwarded_headers("192.0.2.1, 203.0.113.7, 10.1.9.9"), proxy, &trusted);
+        assert_eq!(ip, "203.0.113.7".parse::<IpAddr>().unwrap());
+
+        // Every hop trusted leaves nothing to believe but the peer
+        let ip = client_ip(&forwarded_headers("10.1.9.9"), proxy, &trusted);
+        assert_eq!(ip, "10.1.2.3".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn ignores_forwarded_for_from_untrusted_peer() {
+        let trusted: Vec<IpNet> = vec!["10.1.0.0/16".parse().unwrap()];
+        let ip = client_ip(&forwarded_headers("203.0.113.7"), Some("198.51.100.9".parse().unwrap()), &trusted);
+        assert_eq!(ip, "198.51.100.9".parse::<IpAddr>().unwrap());
+
+        // No proxies configured means nothing is trusted
+        let ip = client_ip(&forwarded_headers("203.0.113.7"), Some("10.1.2.3".parse().unwrap()), &[]);
+        assert_eq!(ip, "10.1.2.3".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn falls_back_to_trusted_proxy_address_without_forwarded_for() {
+        let trusted: Vec<IpNet> = vec!["10.1.0.0/16".parse().unwrap()];
+        let ip = client_ip(&forwarded_headers("garbage"), Some("10.1.2.3".parse().unwrap()), &trusted);
+        assert_eq!(ip, "10.1.2.3".parse::<IpAddr>().unwrap());
+    }
+
+    fn proto_headers(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(X_FORWARDED_PROTO, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn trusts_forwarded_https_from_trusted_proxy() {
+        let trusted: Vec<IpNet> = vec!["10.1.0.0/16".parse().unwrap()];
+        let proxy = Some("10.1.2.3".parse().unwrap());
+        assert!(forwarded_https(&proto_headers("https"), proxy, &trusted));
+        assert!(forwarded_https(&proto_headers("HTTPS, http"), proxy, &trusted));
+        assert!(!forwarded_https(&proto_headers("http"), proxy, &trusted));
+        assert!(!forwarded_https(&HeaderMap::new(), proxy, &trusted));
+    }
+
+    #[test]
+    fn ignores_forwarded_https_from_untrusted_peer() {
+        let trusted: Vec<IpNet> = vec!["10.1.0.0/16".parse().unwrap()];
+        let peer = Some("198.51.100.9".parse().unwrap());
+        assert!(!forwarded_https(&proto_headers("https"), peer, &trusted));
+        assert!(!forwarded_https(&proto_headers("https"), None, &trusted));
+    }
+}
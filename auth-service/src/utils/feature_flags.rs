@@ -0,0 +1,41 @@
+// Switches that change how requests are handled. They are read from the environment once at
+// startup and carried on `AppState`, so handlers never consult the environment and tests can
+// inject any combination. Every flag is off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FeatureFlags {
+    auto_login_on_signup: bool,
+    default_requires_2fa: bool,
+    normalize_plus_addressing: bool,
+}
+
+impl FeatureFlags {
+    // Signup returns a session (or starts 2FA) instead of requiring a separate login
+    pub fn auto_login_on_signup(&self) -> bool {
+        self.auto_login_on_signup
+    }
+
+    // 2FA setting for signups that don't say either way
+    pub fn default_requires_2fa(&self) -> bool {
+        self.default_requires_2fa
+    }
+
+    // Emails from requests go through `Email::parse_with` using this setting
+    pub fn normalize_plus_addressing(&self) -> bool {
+        self.normalize_plus_addressing
+    }
+
+    pub fn with_auto_login_on_signup(mut self, enabled: bool) -> Self {
+        self.auto_login_on_signup = enabled;
+        self
+    }
+
+    pub fn with_default_requires_2fa(mut self, enabled: bool) -> Self {
+        self.default_requires_2fa = enabled;
+        self
+    }
+
+    pub fn with_normalize_plus_addressing(mut self, enabled: bool) -> Self {
+        self.normalize_plus_addressing = enabled;
+        self
+    }
+}
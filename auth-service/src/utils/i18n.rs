@@ -0,0 +1,243 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::{
+        header::{ACCEPT_LANGUAGE, CONTENT_LANGUAGE, CONTENT_LENGTH},
+        HeaderMap, HeaderValue,
+    },
+    middleware::Next,
+    response::Response,
+};
+use reqwest::Url;
+use crate::{domain::locale::Locale, ErrorResponse};
+
+// Attached to error responses by `AuthAPIError::into_response` so the message can be localized
+// after the handler has run, without threading the request's headers into every handler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorCode(pub &'static str);
+
+// Error bodies are a message, a code and a few detail fields
+const MAX_ERROR_BODY_BYTES: usize = 16 * 1024;
+
+impl Locale {
+    // Picks the supported language the client prefers most, falling back to English
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let Some(accept_language) = headers.get(ACCEPT_LANGUAGE).and_then(|v| v.to_str().ok()) else {
+            return Self::default();
+        };
+
+        let mut preferences: Vec<(Self, f32)> = accept_language
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let locale = Self::from_tag(parts.next()?)?;
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse().ok())?;
+                (quality > 0.0).then_some((locale, quality))
+            })
+            .collect();
+        // Stable sort keeps the header's order among equal weights
+        preferences.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        preferences.first().map_or_else(Self::default, |(locale, _)| *locale)
+    }
+}
+
+// English messages live with the status mapping in `AuthAPIError::into_response`; this only
+// holds translations, keyed by the stable error code
+fn translate(code: &str, locale: Locale) -> Option<&'static str> {
+    let message = match (locale, code) {
+        (Locale::Es, "user_already_exists") => "El usuario ya existe",
+        (Locale::Es, "empty_field") => "Falta un campo obligatorio",
+        (Locale::Es, "invalid_field") => "Un campo tiene un valor inválido",
+        (Locale::Es, "invalid_credentials") => "Credenciales inválidas",
+        (Locale::Es, "invalid_display_name") => "Nombre visible inválido",
+        (Locale::Es, "unsupported_locale") => "El idioma debe ser uno de: en, es",
+        (Locale::Es, "unsupported_media_type") => "Content-Type debe ser application/json",
+        (Locale::Es, "missing_body") => "El cuerpo de la solicitud debe ser un objeto JSON",
+        (Locale::Es, "batch_too_large") => "Demasiados tokens en el lote",
+        (Locale::Es, "invalid_token_cutoff") => "El límite de emisión de tokens no puede estar en el futuro",
+        (Locale::Es, "incorrect_credentials") => "Credenciales incorrectas",
+        (Locale::Es, "missing_token") => "Falta el token",
+        (Locale::Es, "invalid_token") => "Token inválido",
+        (Locale::Es, "banned_token_not_found") => "Token bloqueado no encontrado",
+        (Locale::Es, "invalid_admin_key") => "Clave de administrador inválida",
+        (Locale::Es, "forbidden") => "Se requiere el rol de administrador",
+        (Locale::Es, "reauthentication_required") => "Vuelve a iniciar sesión para continuar",
+        (Locale::Es, "account_disabled") => "La cuenta está desactivada",
+        (Locale::Es, "password_change_required") => "Debes cambiar la contraseña antes de iniciar sesión",
+        (Locale::Es, "user_not_found") => "Usuario no encontrado",
+        (Locale::Es, "not_found") => "No encontrado",
+        (Locale::Es, "too_many_requests") => "Demasiadas solicitudes",
+        (Locale::Es, "maintenance_mode") => {
+            "El servicio está en mantenimiento, inténtalo más tarde"
+        }
+        (Locale::Es, "overloaded") => "El servicio está ocupado, inténtalo en unos momentos",
+        (Locale::Es, "email_delivery_failed") => {
+            "No se pudo enviar el correo de verificación, inténtalo de nuevo"
+        }
+        (Locale::Es, "unexpected_error") => "Error inesperado",
+        _ => return None,
+    };
+    Some(message)
+}
+
+// Subject and body of the email carrying a 2FA code
+// `link` points back to the app, for users who opened the email away from the login form
+pub fn two_fa_email(locale: Locale, code: &str, link: Option<&Url>) -> (&'static str, String) {
+    let (subject, mut content) = match locale {
+        Locale::En => ("Your 2FA Code", format!("Your verification code is: {}", code)),
+        Locale::Es => (
+            "Tu código de verificación",
+            format!("Tu código de verificación es: {}", code),
+        ),
+    };
+    if let Some(link) = link {
+        let line = match locale {
+            Locale::En => format!("Enter it at {}", link),
+            Locale::Es => format!("Introdúcelo en {}", link),
+        };
+        content = format!("{}\n\n{}", content, line);
+    }
+    (subject, content)
+}
+
+pub async fn localize_errors(request: Request, next: Next) -> Response {
+    let locale = Locale::from_headers(request.headers());
+    let response = next.run(request).await;
+
+    let Some(ErrorCode(code)) = response.extensions().get::<ErrorCode>().copied() else {
+        return response;
+    };
+    let Some(message) = translate(code, locale) else {
+        return response;
+    };
+
+    // Only the message is replaced, so fields specific to the error survive translation
+    let (mut parts, body) = response.into_parts();
+    let mut body = to_bytes(body, MAX_ERROR_BODY_BYTES)
+        .await
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<ErrorResponse>(&bytes).ok())
+        .unwrap_or_else(|| ErrorResponse {
+            error: String::new(),
+            code: code.to_owned(),
+            details: Default::default(),
+        });
+    body.error = message.to_owned();
+    let body = serde_json::to_vec(&body).expect("ErrorResponse always serializes");
+
+    let headers = &mut parts.headers;
+    headers.remove(CONTENT_LENGTH);
+    headers.insert(CONTENT_LANGUAGE, HeaderValue::from_static(locale.tag()));
+    Response::from_parts(parts, Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{middleware, response::IntoResponse, routing::get, Router};
+    use tower::ServiceExt;
+    use crate::AuthAPIError;
+
+    fn headers(accept_language: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static(accept_language));
+        headers
+    }
+
+    #[test]
+    fn defaults_to_english() {
+        assert_eq!(Locale::from_headers(&HeaderMap::new()), Locale::En);
+        assert_eq!(Locale::from_headers(&headers("fr-FR, de;q=0.8")), Locale::En);
+    }
+
+    #[test]
+    fn picks_highest_weighted_supported_language() {
+        assert_eq!(Locale::from_headers(&headers("es-MX")), Locale::Es);
+        assert_eq!(Locale::from_headers(&headers("fr, es;q=0.9, en;q=0.8")), Locale::Es);
+        assert_eq!(Locale::from_headers(&headers("es;q=0.5, en-GB;q=0.7")), Locale::En);
+        assert_eq!(Locale::from_headers(&headers("es;q=0, en")), Locale::En);
+    }
+
+    #[test]
+    fn every_code_has_a_spanish_translation() {
+        for code in [
+            "user_already_exists",
+            "invalid_credentials",
+            "invalid_display_name",
+            "unsupported_locale",
+            "unsupported_media_type",
+            "missing_body",
+            "batch_too_large",
+            "invalid_token_cutoff",
+            "incorrect_credentials",
+            "missing_token",
+            "invalid_token",
+            "banned_token_not_found",
+            "invalid_admin_key",
+            "forbidden",
+            "account_disabled",
+            "password_change_required",
+            "user_not_found",
+            "not_found",
+            "too_many_requests",
+            "email_delivery_failed",
+            "unexpected_error",
+        ] {
+            assert!(translate(code, Locale::Es).is_some(), "missing translation for {}", code);
+        }
+        assert_eq!(translate("invalid_credentials", Locale::En), None);
+    }
+
+    #[test]
+    fn two_fa_email_is_written_in_the_locale() {
+        let (subject, content) = two_fa_email(Locale::En, "123456", None);
+        assert_eq!(subject, "Your 2FA Code");
+        assert_eq!(content, "Your verification code is: 123456");
+
+        let (subject, content) = two_fa_email(Locale::Es, "123456", None);
+        assert_eq!(subject, "Tu código de verificación");
+        assert_eq!(content, "Tu código de verificación es: 123456");
+    }
+
+    #[test]
+    fn two_fa_email_links_to_the_app_when_given_a_link() {
+        let link = Url::parse("https://app.example.com/").unwrap();
+
+        let (_, content) = two_fa_email(Locale::En, "123456", Some(&link));
+        assert_eq!(content, "Your verification code is: 123456\n\nEnter it at https://app.example.com/");
+
+        let (_, content) = two_fa_email(Locale::Es, "123456", Some(&link));
+        assert_eq!(content, "Tu código de verificación es: 123456\n\nIntrodúcelo en https://app.example.com/");
+    }
+
+    #[tokio::test]
+    async fn keeps_error_details_when_translating() {
+        let app = Router::new()
+            .route(
+                "/",
+                get(|| async { AuthAPIError::IncorrectTwoFACode { attempts_remaining: 2 }.into_response() }),
+            )
+            .layer(middleware::from_fn(localize_errors));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/")
+                    .header(ACCEPT_LANGUAGE, "es")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = to_bytes(response.into_body(), MAX_ERROR_BODY_BYTES).await.unwrap();
+        let body: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body.error, "Credenciales incorrectas");
+        assert_eq!(body.code, "incorrect_credentials");
+        assert_eq!(body.details["attempts_remaining"], 2);
+        assert_eq!(body.details["invalidated"], false);
+    }
+}
@@ -0,0 +1,67 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use color_eyre::eyre::{Context, Result};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, Jwk, JwkSet, KeyAlgorithm, PublicKeyUse,
+    RSAKeyParameters, RSAKeyType,
+};
+use rsa::{pkcs1::DecodeRsaPublicKey, pkcs8::DecodePublicKey, traits::PublicKeyParts, RsaPublicKey};
+
+// Builds the JWKS document exposing the RS256 public key so downstream services
+// can validate tokens without sharing the signing secret
+pub fn build_jwks(public_key_pem: &str, key_id: &str) -> Result<JwkSet> {
+    let public_key = RsaPublicKey::from_public_key_pem(public_key_pem)
+        .or_else(|_| RsaPublicKey::from_pkcs1_pem(public_key_pem))
+        .wrap_err("Failed to parse RSA public key")?;
+
+    let jwk = Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(KeyAlgorithm::RS256),
+            key_id: Some(key_id.to_owned()),
+            ..Default::default()
+        },
+        algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+            key_type: RSAKeyType::RSA,
+            n: URL_SAFE_NO_PAD.encode(public_key.n().to_bytes_be()),
+            e: URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be()),
+        }),
+    };
+
+    Ok(JwkSet { keys: vec![jwk] })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_PUBLIC_KEY: &str = "-----BEGIN PUBLIC KEY-----
+MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEAp3/sj0fWBwiUlyYOKzoH
+Xkhme1gorgWJPHskI9XIVcZF2T79dpo+qoXHuIIXsLJvaDu7X5oKG4V8ggf7z+t1
+NiCRQX1ZdJl9Hp/WxOpO/Jn45ipmpkWLJQ84oAKnJIVEUcdGcYvZyB4SwFEieGtN
+mRyoUN0H0zO2exGDb2ghKN8hlueJB/SfUqZzi0rjjFoGhTL63j8wrpz45rPnuz+X
+E36y9QS2FPvCS1rMFxuxhC+//sXFaxvTHMg2wqU2biofMAaUMxd7jrad+HvhClPz
+5OStBA+roP06UoJTR/OkgCHzFdvCxioCF4OqEhUsTYPzhZw3TNHW6hIa/DFNbD2R
+hwIDAQAB
+-----END PUBLIC KEY-----";
+
+    #[test]
+    fn jwks_contains_rsa_key_with_expected_kid() {
+        let jwks = build_jwks(TEST_PUBLIC_KEY, "test-key").unwrap();
+
+        let jwk = jwks.find("test-key").expect("No key with the expected kid");
+        assert_eq!(jwk.common.key_algorithm, Some(KeyAlgorithm::RS256));
+        match &jwk.algorithm {
+            AlgorithmParameters::RSA(params) => {
+                assert_eq!(params.key_type, RSAKeyType::RSA);
+                assert_eq!(params.e, "AQAB");
+                assert!(!params.n.is_empty());
+            }
+            _ => panic!("Expected an RSA key"),
+        }
+    }
+
+    #[test]
+    fn jwks_rejects_invalid_public_key() {
+        assert!(build_jwks("not a pem", "test-key").is_err());
+    }
+}
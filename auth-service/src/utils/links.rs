@@ -0,0 +1,42 @@
+use color_eyre::eyre::{Context, Result};
+use reqwest::Url;
+
+// Emails must link to the URL users reach the app on (`Config::public_app_url`), which the
+// service can't infer from its bind address
+pub fn build_link(base_url: &Url, path: &str, params: &[(&str, &str)]) -> Result<Url> {
+    // Joining onto a base without a trailing slash would drop its last path segment
+    let mut base_url = base_url.clone();
+    if !base_url.path().ends_with('/') {
+        let path = format!("{}/", base_url.path());
+        base_url.set_path(&path);
+    }
+
+    let mut link = base_url
+        .join(path.trim_start_matches('/'))
+        .wrap_err("Failed to build link")?;
+
+    if !params.is_empty() {
+        link.query_pairs_mut().extend_pairs(params);
+    }
+
+    Ok(link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_absolute_link_with_query_params() {
+        let base_url = Url::parse("https://app.example.com").unwrap();
+        let link = build_link(&base_url, "/verify_email", &[("token", "abc def")]).unwrap();
+        assert_eq!(link.as_str(), "https://app.example.com/verify_email?token=abc+def");
+    }
+
+    #[test]
+    fn keeps_base_url_path_prefix() {
+        let base_url = Url::parse("https://example.com/auth").unwrap();
+        let link = build_link(&base_url, "reset_password", &[]).unwrap();
+        assert_eq!(link.as_str(), "https://example.com/auth/reset_password");
+    }
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+use axum::{
+    extract::{Request, State},
+    http::{header::RETRY_AFTER, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::sync::Semaphore;
+use crate::{domain::error::AuthAPIError, utils::constants::OVERLOADED_RETRY_AFTER_SECONDS};
+
+// Caps the number of requests in flight. Requests over the cap are rejected immediately rather
+// than queued, so an overloaded instance stays fast for the requests it does accept.
+#[derive(Clone)]
+pub struct ConcurrencyLimit {
+    permits: Arc<Semaphore>,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_in_flight)),
+        }
+    }
+
+    pub fn available(&self) -> usize {
+        self.permits.available_permits()
+    }
+}
+
+// Layered onto every route except the health probes, which must keep answering while the
+// instance is saturated so it isn't restarted for being busy
+#[tracing::instrument(name = "Load shedding", skip_all)]
+pub async fn load_shed(State(limit): State<ConcurrencyLimit>, request: Request, next: Next) -> Response {
+    let Ok(_permit) = limit.permits.clone().try_acquire_owned() else {
+        tracing::warn!("Shedding request: concurrency limit reached");
+        let mut response = AuthAPIError::Overloaded.into_response();
+        response.headers_mut().insert(
+            RETRY_AFTER,
+            HeaderValue::from(OVERLOADED_RETRY_AFTER_SECONDS),
+        );
+        return response;
+    };
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permits_are_shared_between_clones() {
+        let limit = ConcurrencyLimit::new(2);
+        let permit = limit.clone().permits.try_acquire_owned().unwrap();
+        assert_eq!(limit.available(), 1);
+
+        drop(permit);
+        assert_eq!(limit.available(), 2);
+    }
+}
@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+// Backoff applied to logins for an email after failed attempts: none before the first failure,
+// then `base`, doubling with each further failure up to `max`. Slows online guessing without
+// locking the real user out the way a hard limit would.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoginDelay {
+    base: Duration,
+    max: Duration,
+}
+
+impl LoginDelay {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self { base, max }
+    }
+
+    pub fn delay_for(&self, failures: u32) -> Duration {
+        if failures == 0 {
+            return Duration::ZERO;
+        }
+        let factor = 2u32.checked_pow(failures - 1).unwrap_or(u32::MAX);
+        self.base.checked_mul(factor).map_or(self.max, |delay| delay.min(self.max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_doubles_per_failure_up_to_max() {
+        let delay = LoginDelay::new(Duration::from_secs(1), Duration::from_secs(8));
+        let delays: Vec<u64> = (0..7).map(|failures| delay.delay_for(failures).as_secs()).collect();
+        assert_eq!(delays, [0, 1, 2, 4, 8, 8, 8]);
+    }
+
+    #[test]
+    fn delay_does_not_overflow_after_many_failures() {
+        let delay = LoginDelay::new(Duration::from_secs(1), Duration::from_secs(8));
+        assert_eq!(delay.delay_for(u32::MAX), Duration::from_secs(8));
+    }
+}
@@ -0,0 +1,70 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use axum::{
+    extract::{Request, State},
+    http::{header::RETRY_AFTER, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use crate::{domain::error::AuthAPIError, utils::constants::MAINTENANCE_RETRY_AFTER_SECONDS};
+
+// Process-wide switch; each instance holds its own flag, seeded from MAINTENANCE_MODE
+#[derive(Clone, Default)]
+pub struct MaintenanceMode {
+    enabled: Arc<AtomicBool>,
+}
+
+impl MaintenanceMode {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: Arc::new(AtomicBool::new(enabled)),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+}
+
+// Layered onto endpoints that change state; token verification and admin routes stay up
+#[tracing::instrument(name = "Maintenance gate", skip_all)]
+pub async fn maintenance_gate(
+    State(maintenance_mode): State<MaintenanceMode>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if maintenance_mode.is_enabled() {
+        tracing::info!("Rejecting request during maintenance");
+        let mut response = AuthAPIError::MaintenanceMode.into_response();
+        response.headers_mut().insert(
+            RETRY_AFTER,
+            HeaderValue::from(MAINTENANCE_RETRY_AFTER_SECONDS),
+        );
+        return response;
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toggles_are_visible_through_clones() {
+        let maintenance_mode = MaintenanceMode::new(false);
+        let shared = maintenance_mode.clone();
+
+        shared.set(true);
+        assert!(maintenance_mode.is_enabled());
+
+        shared.set(false);
+        assert!(!maintenance_mode.is_enabled());
+    }
+}
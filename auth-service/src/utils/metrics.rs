@@ -0,0 +1,110 @@
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+// Upper bounds, in seconds, of the email send latency buckets
+const EMAIL_SEND_DURATION_BUCKETS: [f64; 8] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+// Process-wide counters, exported at `/metrics` in the Prometheus text format. Each instance
+// reports its own values; aggregating across instances is left to the scraper.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    email_sends: Arc<EmailSendMetrics>,
+}
+
+#[derive(Default)]
+struct EmailSendMetrics {
+    succeeded: AtomicU64,
+    failed: AtomicU64,
+    // Non-cumulative counts per bucket, plus one for sends slower than the last bound
+    duration_buckets: [AtomicU64; EMAIL_SEND_DURATION_BUCKETS.len() + 1],
+    duration_sum_micros: AtomicU64,
+}
+
+impl Metrics {
+    pub fn record_email_send(&self, succeeded: bool, elapsed: Duration) {
+        let sends = &self.email_sends;
+        let counter = if succeeded { &sends.succeeded } else { &sends.failed };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        let seconds = elapsed.as_secs_f64();
+        let bucket = EMAIL_SEND_DURATION_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(EMAIL_SEND_DURATION_BUCKETS.len());
+        sends.duration_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        sends
+            .duration_sum_micros
+            .fetch_add(elapsed.as_micros().try_into().unwrap_or(u64::MAX), Ordering::Relaxed);
+    }
+
+    pub fn email_sends(&self, succeeded: bool) -> u64 {
+        let sends = &self.email_sends;
+        let counter = if succeeded { &sends.succeeded } else { &sends.failed };
+        counter.load(Ordering::Relaxed)
+    }
+
+    pub fn render(&self) -> String {
+        let sends = &self.email_sends;
+        let mut output = String::new();
+
+        // Writing to a String can't fail
+        let _ = writeln!(output, "# HELP email_send_total Emails handed to the email provider, by outcome.");
+        let _ = writeln!(output, "# TYPE email_send_total counter");
+        let _ = writeln!(output, "email_send_total{{result=\"success\"}} {}", self.email_sends(true));
+        let _ = writeln!(output, "email_send_total{{result=\"failure\"}} {}", self.email_sends(false));
+
+        let _ = writeln!(output, "# HELP email_send_duration_seconds Time taken to send an email, successful or not.");
+        let _ = writeln!(output, "# TYPE email_send_duration_seconds histogram");
+        let mut cumulative = 0;
+        for (bound, count) in EMAIL_SEND_DURATION_BUCKETS.iter().zip(&sends.duration_buckets) {
+            cumulative += count.load(Ordering::Relaxed);
+            let _ = writeln!(output, "email_send_duration_seconds_bucket{{le=\"{}\"}} {}", bound, cumulative);
+        }
+        cumulative += sends.duration_buckets[EMAIL_SEND_DURATION_BUCKETS.len()].load(Ordering::Relaxed);
+        let _ = writeln!(output, "email_send_duration_seconds_bucket{{le=\"+Inf\"}} {}", cumulative);
+        let sum = sends.duration_sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(output, "email_send_duration_seconds_sum {}", sum);
+        let _ = writeln!(output, "email_send_duration_seconds_count {}", cumulative);
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_email_sends_by_outcome() {
+        let metrics = Metrics::default();
+        metrics.record_email_send(true, Duration::from_millis(20));
+        metrics.record_email_send(false, Duration::from_millis(300));
+        metrics.record_email_send(false, Duration::from_secs(30));
+
+        assert_eq!(metrics.email_sends(true), 1);
+        assert_eq!(metrics.email_sends(false), 2);
+
+        let output = metrics.render();
+        assert!(output.contains("email_send_total{result=\"success\"} 1\n"));
+        assert!(output.contains("email_send_total{result=\"failure\"} 2\n"));
+        // Buckets are cumulative, and the slow send only lands in +Inf
+        assert!(output.contains("email_send_duration_seconds_bucket{le=\"0.05\"} 1\n"));
+        assert!(output.contains("email_send_duration_seconds_bucket{le=\"0.5\"} 2\n"));
+        assert!(output.contains("email_send_duration_seconds_bucket{le=\"10\"} 2\n"));
+        assert!(output.contains("email_send_duration_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(output.contains("email_send_duration_seconds_count 3\n"));
+    }
+
+    #[test]
+    fn clones_share_counters() {
+        let metrics = Metrics::default();
+        metrics.clone().record_email_send(true, Duration::ZERO);
+        assert_eq!(metrics.email_sends(true), 1);
+    }
+}
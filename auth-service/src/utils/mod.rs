@@ -1,4 +1,21 @@
 pub mod constants;
+pub mod config;
 pub mod auth;
+pub mod cors;
+pub mod device_trust;
+pub mod extractors;
+pub mod feature_flags;
+pub mod i18n;
+pub mod jwks;
+pub mod links;
+pub mod load_shed;
+pub mod login_delay;
+pub mod maintenance;
+pub mod metrics;
+pub mod rate_limit;
+pub mod sliding_session;
+pub mod startup;
+pub mod token_cache;
 pub mod tracing;
+pub mod validation;
 
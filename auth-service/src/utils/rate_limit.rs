@@ -0,0 +1,254 @@
+use std::{net::IpAddr, num::NonZeroU32, sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use governor::{
+    clock::{Clock, DefaultClock},
+    middleware::StateInformationMiddleware,
+    state::keyed::DefaultKeyedStateStore,
+    Quota,
+};
+use crate::{domain::error::AuthAPIError, utils::extractors::ClientContext};
+
+pub const X_RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+pub const X_RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+pub const X_RATELIMIT_RESET: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+type KeyedRateLimiter =
+    governor::RateLimiter<IpAddr, DefaultKeyedStateStore<IpAddr>, DefaultClock, StateInformationMiddleware>;
+
+// Token-bucket limiter keyed by client IP. Each route gets its own limiter so
+// a burst on one endpoint doesn't eat into the quota of another; routes doing the same work
+// share one through clones.
+#[derive(Clone)]
+pub struct RateLimiter {
+    limiter: Arc<KeyedRateLimiter>,
+    quota: Quota,
+    // The limiter's own clock, needed to turn a rejection into a wait time
+    clock: DefaultClock,
+}
+
+// The caller's quota after a rate limit check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    // Until the full quota is available again
+    pub reset: Duration,
+}
+
+impl RateLimitStatus {
+    pub fn insert_headers(&self, headers: &mut HeaderMap) {
+        // Rounded up so clients waiting for the reset never retry too early
+        let reset_seconds = self.reset.as_secs() + u64::from(self.reset.subsec_nanos() > 0);
+        headers.insert(X_RATELIMIT_LIMIT, HeaderValue::from(self.limit));
+        headers.insert(X_RATELIMIT_REMAINING, HeaderValue::from(self.remaining));
+        headers.insert(X_RATELIMIT_RESET, HeaderValue::from(reset_seconds));
+    }
+}
+
+impl RateLimiter {
+    pub fn per_minute(requests: NonZeroU32) -> Self {
+        Self::per_minute_with_burst(requests, requests)
+    }
+
+    // Up to `burst` requests at once, then `requests` a minute as the bucket refills
+    pub fn per_minute_with_burst(requests: NonZeroU32, burst: NonZeroU32) -> Self {
+        let quota = Quota::per_minute(requests).allow_burst(burst);
+        let clock = DefaultClock::default();
+        Self {
+            limiter: Arc::new(KeyedRateLimiter::new(quota, DefaultKeyedStateStore::default(), &clock)),
+            quota,
+            clock,
+        }
+    }
+
+    pub fn check(&self, ip: IpAddr) -> RateLimitStatus {
+        self.check_n(ip, NonZeroU32::MIN)
+    }
+
+    // Charges `n` requests at once, e.g. one per item of a batch; nothing is charged when they
+    // don't all fit
+    pub fn check_n(&self, ip: IpAddr, n: NonZeroU32) -> RateLimitStatus {
+        let limit = self.quota.burst_size().get();
+        let interval = self.quota.replenish_interval();
+
+        match self.limiter.check_key_n(&ip, n) {
+            // Each used request comes back one replenish interval after the previous one
+            Ok(Ok(snapshot)) => {
+                let remaining = snapshot.remaining_burst_capacity();
+                RateLimitStatus { allowed: true, limit, remaining, reset: interval * (limit - remaining) }
+            }
+            Ok(Err(not_until)) => RateLimitStatus {
+                allowed: false,
+                limit,
+                remaining: 0,
+                reset: not_until.wait_time_from(self.clock.now()) + interval * (limit - 1),
+            },
+            // More than the whole burst, which no amount of waiting makes room for
+            Err(_) => RateLimitStatus { allowed: false, limit, remaining: 0, reset: interval * limit },
+        }
+    }
+
+    pub fn prune(&self) {
+        forget_idle_ips(&self.limiter);
+    }
+
+    // Otherwise every address that ever made a request keeps an entry. The task holds the limiter
+    // weakly and stops once the router owning it is dropped.
+    pub fn spawn_pruner(&self, interval: Duration) -> JoinHandle<()> {
+        let limiter = Arc::downgrade(&self.limiter);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let Some(limiter) = limiter.upgrade() else {
+                    return;
+                };
+                forget_idle_ips(&limiter);
+            }
+        })
+    }
+}
+
+// IPs whose quota has fully refilled are indistinguishable from ones never seen
+fn forget_idle_ips(limiter: &KeyedRateLimiter) {
+    limiter.retain_recent();
+    limiter.shrink_to_fit();
+}
+
+// Every response from a limited route reports the caller's quota, not just the 429
+#[tracing::instrument(name = "Rate limit", skip_all, fields(ip = %client.ip))]
+pub async fn rate_limit(
+    State(limiter): State<RateLimiter>,
+    client: ClientContext,
+    request: Request,
+    next: Next,
+) -> Response {
+    let status = limiter.check(client.ip);
+    let mut response = if status.allowed {
+        next.run(request).await
+    } else {
+        tracing::warn!("Rate limit exceeded");
+        AuthAPIError::TooManyRequests.into_response()
+    };
+
+    // Handlers that charge more of the quota report it themselves
+    if !response.headers().contains_key(X_RATELIMIT_REMAINING) {
+        status.insert_headers(response.headers_mut());
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_requests_over_the_limit() {
+        let limiter = RateLimiter::per_minute(NonZeroU32::new(2).unwrap());
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        assert!(limiter.check(ip).allowed);
+        assert!(limiter.check(ip).allowed);
+        assert!(!limiter.check(ip).allowed);
+    }
+
+    #[test]
+    fn tracks_each_ip_separately() {
+        let limiter = RateLimiter::per_minute(NonZeroU32::new(1).unwrap());
+
+        assert!(limiter.check("10.0.0.1".parse().unwrap()).allowed);
+        assert!(!limiter.check("10.0.0.1".parse().unwrap()).allowed);
+        assert!(limiter.check("10.0.0.2".parse().unwrap()).allowed);
+    }
+
+    #[test]
+    fn allows_burst_independent_of_rate() {
+        let limiter = RateLimiter::per_minute_with_burst(NonZeroU32::new(60).unwrap(), NonZeroU32::new(3).unwrap());
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        for _ in 0..3 {
+            assert!(limiter.check(ip).allowed);
+        }
+        let status = limiter.check(ip);
+        assert!(!status.allowed);
+        assert_eq!(status.limit, 3);
+        // One request comes back every second at 60 a minute
+        assert!(status.reset > Duration::from_secs(2) && status.reset <= Duration::from_secs(3));
+    }
+
+    #[test]
+    fn check_n_charges_every_request_or_none() {
+        let limiter = RateLimiter::per_minute(NonZeroU32::new(5).unwrap());
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        let status = limiter.check_n(ip, NonZeroU32::new(3).unwrap());
+        assert!(status.allowed);
+        assert_eq!(status.remaining, 2);
+
+        // Three more don't fit, so none of them are taken
+        assert!(!limiter.check_n(ip, NonZeroU32::new(3).unwrap()).allowed);
+        assert!(!limiter.check_n(ip, NonZeroU32::new(6).unwrap()).allowed);
+        assert_eq!(limiter.check(ip).remaining, 1);
+    }
+
+    #[test]
+    fn prune_forgets_ips_with_a_full_quota() {
+        // Replenishes every millisecond, so the quota is full again almost immediately
+        let limiter = RateLimiter::per_minute(NonZeroU32::new(60_000).unwrap());
+        limiter.check("10.0.0.1".parse().unwrap());
+        assert_eq!(limiter.limiter.len(), 1);
+
+        std::thread::sleep(Duration::from_millis(10));
+        limiter.prune();
+
+        assert!(limiter.limiter.is_empty());
+    }
+
+    #[test]
+    fn prune_keeps_ips_still_using_their_quota() {
+        let limiter = RateLimiter::per_minute(NonZeroU32::new(2).unwrap());
+        limiter.check("10.0.0.1".parse().unwrap());
+
+        limiter.prune();
+
+        assert_eq!(limiter.limiter.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn pruner_stops_when_the_limiter_is_dropped() {
+        let limiter = RateLimiter::per_minute(NonZeroU32::new(2).unwrap());
+        let pruner = limiter.spawn_pruner(Duration::from_millis(1));
+
+        drop(limiter);
+
+        tokio::time::timeout(Duration::from_secs(1), pruner)
+            .await
+            .expect("Pruner kept running")
+            .unwrap();
+    }
+
+    #[test]
+    fn reports_remaining_quota_and_reset() {
+        let limiter = RateLimiter::per_minute(NonZeroU32::new(2).unwrap());
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        let status = limiter.check(ip);
+        assert_eq!((status.limit, status.remaining), (2, 1));
+        assert_eq!(status.reset, Duration::from_secs(30));
+
+        let status = limiter.check(ip);
+        assert_eq!(status.remaining, 0);
+        assert_eq!(status.reset, Duration::from_secs(60));
+
+        let status = limiter.check(ip);
+        assert_eq!(status.remaining, 0);
+        assert!(status.reset > Duration::from_secs(55) && status.reset <= Duration::from_secs(60));
+    }
+}
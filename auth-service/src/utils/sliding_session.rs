@@ -0,0 +1,170 @@
+use std::{ops::Deref, time::Duration};
+use axum::{
+    extract::{Request, State},
+    http::{header::SET_COOKIE, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use chrono::Utc;
+use secrecy::Secret;
+use crate::{
+    app_state::AppState,
+    utils::{
+        auth::{decode_token, refresh_auth_cookie, validate_token, Claims},
+        constants::JWT_COOKIE_NAME,
+        extractors::ClientContext,
+    },
+};
+
+// Sessions that stay alive while they are used: a cookie within `refresh_threshold` of expiring
+// is reissued with a later expiry, but never past `absolute_max` after the user logged in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlidingSessions {
+    refresh_threshold: Duration,
+    absolute_max: Duration,
+}
+
+impl SlidingSessions {
+    pub fn new(refresh_threshold: Duration, absolute_max: Duration) -> Self {
+        Self { refresh_threshold, absolute_max }
+    }
+
+    // The expiry a refreshed token should get. `None` when the token isn't close enough to
+    // expiring, was issued without the claims refreshing needs, or already reaches the cap.
+    pub fn refreshed_expiry(&self, claims: &Claims, now: usize) -> Option<usize> {
+        let (iat, auth_time) = (claims.iat?, claims.auth_time?);
+        if claims.exp.saturating_sub(now) as u64 > self.refresh_threshold.as_secs() {
+            return None;
+        }
+
+        // The refreshed token keeps the lifetime it was issued with, so session TTLs still apply
+        let lifetime = claims.exp.saturating_sub(iat);
+        let cap = auth_time.saturating_add(self.absolute_max.as_secs() as usize);
+        let exp = now.saturating_add(lifetime).min(cap);
+        (exp > claims.exp).then_some(exp)
+    }
+}
+
+// Reissues the auth cookie after a successful request when sliding sessions are enabled. Only
+// cookie sessions slide; bearer clients hold on to the token they were given. The replaced token
+// is left to expire on its own so concurrent requests still carrying it aren't rejected.
+#[tracing::instrument(name = "Sliding session", skip_all)]
+pub async fn sliding_session(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(sliding_sessions) = state.sliding_sessions else {
+        return next.run(request).await;
+    };
+
+    let token = CookieJar::from_headers(request.headers())
+        .get(JWT_COOKIE_NAME)
+        .map(|cookie| Secret::new(cookie.value().to_owned()));
+    let client = ClientContext::from_headers(request.headers(), request.extensions());
+
+    let mut response = next.run(request).await;
+
+    // Failed requests aren't activity, and a handler that set the cookie itself (login, logout)
+    // has the final say
+    let Some(token) = token else {
+        return response;
+    };
+    if !response.status().is_success() || sets_auth_cookie(&response) {
+        return response;
+    }
+
+    let Ok(claims) = decode_token(&state.jwt, &token) else {
+        return response;
+    };
+    let Some(exp) = sliding_sessions.refreshed_expiry(&claims, Utc::now().timestamp() as usize) else {
+        return response;
+    };
+
+    // Checked after the handler ran so a token banned by this very request isn't refreshed
+    let banned_token_store = state.banned_token_store.read().await;
+    if let Err(e) = validate_token(&state.jwt, &token, banned_token_store.deref()).await {
+        tracing::debug!("Not refreshing session: {:?}", e);
+        return response;
+    }
+    drop(banned_token_store);
+
+    match refresh_auth_cookie(&state.jwt, &claims, exp, client.secure_cookies()) {
+        Ok(cookie) => match HeaderValue::from_str(&cookie.to_string()) {
+            Ok(value) => {
+                tracing::debug!("Refreshed session cookie");
+                response.headers_mut().append(SET_COOKIE, value);
+            }
+            Err(e) => tracing::error!("Refreshed cookie is not a valid header value: {:?}", e),
+        },
+        Err(e) => tracing::error!("Failed to refresh session: {:?}", e),
+    }
+
+    response
+}
+
+fn sets_auth_cookie(response: &Response) -> bool {
+    response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter_map(|value| Cookie::parse(value).ok())
+        .any(|cookie| cookie.name() == JWT_COOKIE_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::user::Role;
+
+    const MINUTE: usize = 60;
+
+    fn token_claims(iat: usize, exp: usize, auth_time: usize) -> Claims {
+        Claims {
+            sub: "test@example.com".to_owned(),
+            exp,
+            iat: Some(iat),
+            iat_ms: None,
+            auth_time: Some(auth_time),
+            jti: "id".to_owned(),
+            role: Role::User,
+        }
+    }
+
+    fn sliding_sessions() -> SlidingSessions {
+        SlidingSessions::new(Duration::from_secs(5 * 60), Duration::from_secs(60 * 60))
+    }
+
+    #[test]
+    fn token_near_expiry_is_extended_by_its_lifetime() {
+        let now = 1_000_000;
+        // Issued 8 minutes ago with a 10 minute lifetime
+        let claims = token_claims(now - 8 * MINUTE, now + 2 * MINUTE, now - 8 * MINUTE);
+        assert_eq!(sliding_sessions().refreshed_expiry(&claims, now), Some(now + 10 * MINUTE));
+    }
+
+    #[test]
+    fn token_outside_threshold_is_left_alone() {
+        let now = 1_000_000;
+        let claims = token_claims(now - MINUTE, now + 9 * MINUTE, now - MINUTE);
+        assert_eq!(sliding_sessions().refreshed_expiry(&claims, now), None);
+    }
+
+    #[test]
+    fn refresh_is_capped_at_absolute_max() {
+        let now = 1_000_000;
+        let logged_in = now - 55 * MINUTE;
+        let claims = token_claims(now - 8 * MINUTE, now + 2 * MINUTE, logged_in);
+        assert_eq!(sliding_sessions().refreshed_expiry(&claims, now), Some(logged_in + 60 * MINUTE));
+
+        // Once a token already expires at the cap there is nothing left to extend
+        let claims = token_claims(now - 3 * MINUTE, logged_in + 60 * MINUTE, logged_in);
+        assert_eq!(sliding_sessions().refreshed_expiry(&claims, now), None);
+    }
+
+    #[test]
+    fn tokens_without_login_time_are_not_refreshed() {
+        let now = 1_000_000;
+        let mut claims = token_claims(now - 8 * MINUTE, now + 2 * MINUTE, now - 8 * MINUTE);
+        claims.auth_time = None;
+        assert_eq!(sliding_sessions().refreshed_expiry(&claims, now), None);
+    }
+}
@@ -0,0 +1,88 @@
+use std::{future::Future, path::Path};
+use color_eyre::eyre::{eyre, Result};
+
+// Runs two independent startup steps at the same time. The first failure is returned straight
+// away and the other step is dropped, so a bad dependency still aborts startup promptly.
+pub async fn init_concurrently<A, B>(
+    first: impl Future<Output = Result<A>>,
+    second: impl Future<Output = Result<B>>,
+) -> Result<(A, B)> {
+    tokio::try_join!(first, second)
+}
+
+// ServeDir answers every request with a 404 when its directory is missing, which looks like a
+// routing bug rather than a packaging one. Returns whether the directory is there; when it isn't,
+// startup fails if `required`, and otherwise carries on without the UI after a warning.
+pub fn check_assets_dir(path: &Path, required: bool) -> Result<bool> {
+    if path.is_dir() {
+        return Ok(true);
+    }
+
+    if required {
+        return Err(eyre!("Assets directory {} does not exist", path.display()));
+    }
+    tracing::warn!(
+        "Assets directory {} does not exist; the UI will not be served. Set ASSETS_DIR to its location.",
+        path.display()
+    );
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use color_eyre::eyre::eyre;
+
+    #[tokio::test(start_paused = true)]
+    async fn returns_both_results_when_both_succeed() {
+        let first = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok("postgres")
+        };
+        let second = async { Ok(6379) };
+
+        assert_eq!(init_concurrently(first, second).await.unwrap(), ("postgres", 6379));
+    }
+
+    // On a paused clock the sleeps take exactly as long as asked, so run one after the other
+    // they would take 200ms
+    #[tokio::test(start_paused = true)]
+    async fn runs_both_steps_at_the_same_time() {
+        let step = || async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok(())
+        };
+        let started = tokio::time::Instant::now();
+
+        init_concurrently(step(), step()).await.unwrap();
+        assert_eq!(started.elapsed(), Duration::from_millis(100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fails_fast_without_waiting_for_the_other_step() {
+        let failing = async { Err::<(), _>(eyre!("Redis is unreachable")) };
+        let never_finishes = std::future::pending::<Result<()>>();
+
+        let result = tokio::time::timeout(Duration::from_secs(1), init_concurrently(never_finishes, failing))
+            .await
+            .expect("Startup should not wait for the other step");
+        assert_eq!(result.unwrap_err().to_string(), "Redis is unreachable");
+    }
+
+    #[test]
+    fn accepts_existing_assets_dir() {
+        assert!(check_assets_dir(Path::new("assets"), true).unwrap());
+    }
+
+    #[test]
+    fn warns_about_missing_assets_dir_by_default() {
+        assert!(!check_assets_dir(Path::new("no-such-assets-dir"), false).unwrap());
+    }
+
+    #[test]
+    fn fails_on_missing_assets_dir_when_required() {
+        let result = check_assets_dir(Path::new("no-such-assets-dir"), true);
+        assert_eq!(result.unwrap_err().to_string(), "Assets directory no-such-assets-dir does not exist");
+    }
+}
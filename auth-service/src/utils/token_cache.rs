@@ -0,0 +1,77 @@
+use std::time::Duration;
+use moka::sync::Cache;
+
+// Upper bound on cached tokens so a flood of distinct valid tokens can't grow memory unbounded
+const MAX_CACHED_TOKENS: u64 = 10_000;
+
+// Remembers recently verified tokens by `jti` so repeated `/verify_token` calls for the same
+// token can skip the banned-token store for a short window. Signature and expiry are still
+// checked on every call; only the banned lookup is cached.
+#[derive(Clone)]
+pub struct VerifiedTokenCache {
+    cache: Cache<String, ()>,
+}
+
+impl VerifiedTokenCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            cache: Cache::builder()
+                .max_capacity(MAX_CACHED_TOKENS)
+                .time_to_live(ttl)
+                .build(),
+        }
+    }
+
+    pub fn contains(&self, jti: &str) -> bool {
+        self.cache.contains_key(jti)
+    }
+
+    pub fn insert(&self, jti: String) {
+        self.cache.insert(jti, ());
+    }
+
+    pub fn invalidate(&self, jti: &str) {
+        self.cache.invalidate(jti);
+    }
+
+    pub fn clear(&self) {
+        self.cache.invalidate_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remembers_tokens_until_invalidated() {
+        let cache = VerifiedTokenCache::new(Duration::from_secs(60));
+        assert!(!cache.contains("jti"));
+
+        cache.insert("jti".to_owned());
+        assert!(cache.contains("jti"));
+
+        cache.invalidate("jti");
+        assert!(!cache.contains("jti"));
+    }
+
+    #[test]
+    fn forgets_all_tokens_when_cleared() {
+        let cache = VerifiedTokenCache::new(Duration::from_secs(60));
+        cache.insert("first".to_owned());
+        cache.insert("second".to_owned());
+
+        cache.clear();
+        assert!(!cache.contains("first"));
+        assert!(!cache.contains("second"));
+    }
+
+    #[test]
+    fn forgets_tokens_after_ttl() {
+        let cache = VerifiedTokenCache::new(Duration::from_millis(50));
+        cache.insert("jti".to_owned());
+
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!cache.contains("jti"));
+    }
+}
@@ -1,10 +1,18 @@
-use std::time::Duration;
-use axum::{body::Body, extract::Request, response::Response};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use axum::{
+    body::Body,
+    extract::{MatchedPath, Request},
+    response::Response,
+};
 use tracing::{Level, Span};
 use color_eyre::eyre::Result;
 use tracing_error::ErrorLayer;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::{fmt, EnvFilter};
+use crate::domain::email::Email;
 
 pub fn init_tracing() -> Result<()> {
     // Create a formatting layer for tracing output with a compact format
@@ -27,13 +35,39 @@ pub fn init_tracing() -> Result<()> {
     Ok(())
 }
 
+// Process-wide because emails are logged from deep inside stores and services; set once at
+// startup from `Config::log_pii`
+static LOG_PII: AtomicBool = AtomicBool::new(false);
+
+pub fn set_log_pii(enabled: bool) {
+    LOG_PII.store(enabled, Ordering::Relaxed);
+}
+
+// For `email` fields in spans and events; the full address is only logged with LOG_PII on
+pub fn log_email(email: &Email) -> String {
+    if LOG_PII.load(Ordering::Relaxed) {
+        email.to_string()
+    } else {
+        email.masked()
+    }
+}
+
+// The `route` of requests that no route matched, such as static assets and 404s
+pub const UNMATCHED_ROUTE: &str = "fallback";
+
+// Spans are labelled with the route template (`/admin/users/:email/admin`) rather than the URI,
+// which keeps the number of distinct values bounded and path parameters like emails out of logs
 pub fn make_span_with_request_id(request: &Request<Body>) -> Span {
     let request_id = uuid::Uuid::new_v4();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(UNMATCHED_ROUTE, MatchedPath::as_str);
     tracing::span!(
         Level::INFO,
         "[REQUEST]",
         method = tracing::field::display(request.method()),
-        uri = tracing::field::display(request.uri()),
+        route = tracing::field::display(route),
         version = tracing::field::debug(request.version()),
         request_id = tracing::field::display(request_id),
     )
@@ -43,7 +77,21 @@ pub fn on_request(_request: &Request<Body>, _span: &Span) {
     tracing::event!(Level::INFO, "[REQUEST START]");
 }
 
-pub fn on_response(response: &Response, latency: Duration, _span: &Span) {
+// A zero `slow_request_threshold` turns the slow request warning off
+pub fn on_response(slow_request_threshold: Duration) -> impl Fn(&Response, Duration, &Span) + Clone {
+    move |response, latency, _span| log_response(response, latency, slow_request_threshold)
+}
+
+fn log_response(response: &Response, latency: Duration, slow_request_threshold: Duration) {
+    // Emitted inside the request span, so the route and request_id fields come along with it
+    if !slow_request_threshold.is_zero() && latency > slow_request_threshold {
+        tracing::warn!(
+            latency_ms = latency.as_millis() as u64,
+            threshold_ms = slow_request_threshold.as_millis() as u64,
+            "[SLOW REQUEST]"
+        );
+    }
+
     let status = response.status();
     let status_code = status.as_u16();
     let status_code_class = status_code / 100;
@@ -65,4 +113,129 @@ pub fn on_response(response: &Response, latency: Duration, _span: &Span) {
             )
         }
     };
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use axum::{routing::get, Router};
+    use tower::ServiceExt;
+    use tower_http::trace::TraceLayer;
+    use tracing::{field::{Field, Visit}, span, Event, Subscriber};
+    use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+    use super::*;
+
+    const SLOW_REQUEST_THRESHOLD: Duration = Duration::from_millis(50);
+
+    #[derive(Default)]
+    struct Fields(HashMap<String, String>);
+
+    impl Visit for Fields {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_owned(), value.to_owned());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0.insert(field.name().to_owned(), format!("{:?}", value));
+        }
+    }
+
+    // Records every event's fields merged with those of the spans it was emitted in
+    #[derive(Clone, Default)]
+    struct CaptureLayer(Arc<Mutex<Vec<HashMap<String, String>>>>);
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for CaptureLayer {
+        fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+            let mut fields = Fields::default();
+            attrs.record(&mut fields);
+            ctx.span(id).unwrap().extensions_mut().insert(fields);
+        }
+
+        fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+            let mut fields = Fields::default();
+            event.record(&mut fields);
+            for span in ctx.event_scope(event).into_iter().flatten() {
+                if let Some(span_fields) = span.extensions().get::<Fields>() {
+                    for (name, value) in &span_fields.0 {
+                        fields.0.entry(name.clone()).or_insert_with(|| value.clone());
+                    }
+                }
+            }
+            fields.0.insert("level".to_owned(), event.metadata().level().to_string());
+            self.0.lock().unwrap().push(fields.0);
+        }
+    }
+
+    async fn request_events(path: &str) -> Vec<HashMap<String, String>> {
+        let slow_for = SLOW_REQUEST_THRESHOLD + Duration::from_millis(100);
+        let router = Router::new()
+            .route("/fast", get(|| async {}))
+            .route("/slow", get(move || async move { tokio::time::sleep(slow_for).await }))
+            .route("/sessions/:jti", get(|| async {}))
+            .layer(
+                TraceLayer::new_for_http()
+                    .make_span_with(make_span_with_request_id)
+                    .on_request(on_request)
+                    .on_response(on_response(SLOW_REQUEST_THRESHOLD)),
+            );
+
+        let capture = CaptureLayer::default();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(capture.clone()));
+        router
+            .oneshot(Request::builder().uri(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let events = capture.0.lock().unwrap().clone();
+        events
+    }
+
+    async fn slow_request_warnings(path: &str) -> Vec<HashMap<String, String>> {
+        request_events(path)
+            .await
+            .into_iter()
+            .filter(|event| event.get("message").map(String::as_str) == Some("[SLOW REQUEST]"))
+            .collect()
+    }
+
+    // Single-threaded so the request is handled where the capturing subscriber is the default
+    #[tokio::test(flavor = "current_thread")]
+    async fn slow_request_is_logged_as_warning() {
+        let warnings = slow_request_warnings("/slow").await;
+        assert_eq!(warnings.len(), 1);
+
+        let warning = &warnings[0];
+        assert_eq!(warning["level"], "WARN");
+        assert_eq!(warning["route"], "/slow");
+        assert!(uuid::Uuid::parse_str(&warning["request_id"]).is_ok());
+        assert!(warning["latency_ms"].parse::<u64>().unwrap() > SLOW_REQUEST_THRESHOLD.as_millis() as u64);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn fast_request_is_not_logged_as_slow() {
+        assert!(slow_request_warnings("/fast").await.is_empty());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn request_is_labelled_with_route_template() {
+        let events = request_events("/sessions/3f2c9a1b").await;
+        let end = events
+            .iter()
+            .find(|event| event.get("message").map(String::as_str) == Some("[REQUEST END]"))
+            .expect("Request end should be logged");
+        assert_eq!(end["route"], "/sessions/:jti");
+        assert!(
+            events.iter().flat_map(HashMap::values).all(|value| !value.contains("3f2c9a1b")),
+            "The concrete path leaked into {:?}",
+            events
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn unmatched_request_shares_one_label() {
+        let events = request_events("/no/such/page").await;
+        assert!(!events.is_empty());
+        assert!(events.iter().all(|event| event["route"] == UNMATCHED_ROUTE));
+    }
+}
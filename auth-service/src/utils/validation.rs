@@ -0,0 +1,148 @@
+use axum::{
+    async_trait,
+    body::{Body, Bytes},
+    extract::{rejection::JsonRejection, FromRequest, Request},
+    response::{IntoResponse, Response},
+    Json,
+};
+use secrecy::{ExposeSecret, Secret};
+use serde::de::DeserializeOwned;
+use crate::domain::error::AuthAPIError;
+
+// Checks a request body has everything it needs before a handler starts parsing domain types
+pub trait Validate {
+    fn validate(&self) -> Result<(), AuthAPIError>;
+}
+
+// Whitespace-only values count as empty
+pub fn require_non_empty(field: &'static str, value: &Secret<String>) -> Result<(), AuthAPIError> {
+    if value.expose_secret().trim().is_empty() {
+        return Err(AuthAPIError::EmptyField(field));
+    }
+    Ok(())
+}
+
+// axum answers a missing or non-JSON Content-Type with a plain-text 415; send the usual
+// ErrorResponse instead so clients can tell what to fix. Other rejections are left to axum.
+pub fn json_rejection_response(rejection: JsonRejection) -> Response {
+    match rejection {
+        JsonRejection::MissingJsonContentType(rejection) => {
+            tracing::warn!("Rejected request body: {}", rejection.body_text());
+            AuthAPIError::UnsupportedMediaType.into_response()
+        }
+        rejection => rejection.into_response(),
+    }
+}
+
+// `Json` that also runs the body's `Validate` impl. Malformed JSON keeps axum's 422 rejection;
+// a well-formed body that fails validation is rejected with the validation error. A request
+// with no body at all gets a 422 saying so, rather than a complaint about its Content-Type.
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let (parts, body) = request.into_parts();
+        // The body limit layer records its limit in the extensions
+        let mut body_request = Request::new(body);
+        *body_request.extensions_mut() = parts.extensions.clone();
+        let bytes = Bytes::from_request(body_request, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        if bytes.is_empty() {
+            tracing::warn!("Rejected request without a body");
+            return Err(AuthAPIError::MissingBody.into_response());
+        }
+
+        let request = Request::from_parts(parts, Body::from(bytes));
+        let Json(value) = Json::<T>::from_request(request, state)
+            .await
+            .map_err(json_rejection_response)?;
+
+        value.validate().map_err(|e| {
+            tracing::warn!("Request validation failed: {}", e);
+            e.into_response()
+        })?;
+
+        Ok(Self(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::header::CONTENT_TYPE;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct NameRequest {
+        name: Secret<String>,
+    }
+
+    impl Validate for NameRequest {
+        fn validate(&self) -> Result<(), AuthAPIError> {
+            require_non_empty("name", &self.name)
+        }
+    }
+
+    async fn extract(body: &'static str) -> Result<ValidatedJson<NameRequest>, Response> {
+        extract_with_content_type(body, "application/json").await
+    }
+
+    async fn extract_with_content_type(
+        body: &'static str,
+        content_type: &'static str,
+    ) -> Result<ValidatedJson<NameRequest>, Response> {
+        let request = Request::builder()
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap();
+        ValidatedJson::<NameRequest>::from_request(request, &()).await
+    }
+
+    #[test]
+    fn rejects_empty_and_whitespace_values() {
+        assert!(require_non_empty("name", &Secret::new("".to_owned())).is_err());
+        assert!(require_non_empty("name", &Secret::new("  ".to_owned())).is_err());
+        assert!(require_non_empty("name", &Secret::new("a".to_owned())).is_ok());
+    }
+
+    #[tokio::test]
+    async fn extractor_validates_after_deserializing() {
+        assert!(extract(r#"{"name": "a"}"#).await.is_ok());
+
+        let rejection = extract(r#"{"name": ""}"#).await.err().unwrap();
+        assert_eq!(rejection.status().as_u16(), 400);
+
+        let rejection = extract(r#"{"nom": "a"}"#).await.err().unwrap();
+        assert_eq!(rejection.status().as_u16(), 422);
+    }
+
+    #[tokio::test]
+    async fn extractor_rejects_non_json_content_type_with_error_response() {
+        let rejection = extract_with_content_type(r#"{"name": "a"}"#, "text/plain").await.err().unwrap();
+        assert_eq!(rejection.status().as_u16(), 415);
+
+        let body = axum::body::to_bytes(rejection.into_body(), usize::MAX).await.unwrap();
+        let error: crate::ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error.code, "unsupported_media_type");
+    }
+
+    #[tokio::test]
+    async fn extractor_rejects_missing_body_regardless_of_content_type() {
+        for content_type in ["application/json", "text/plain"] {
+            let rejection = extract_with_content_type("", content_type).await.err().unwrap();
+            assert_eq!(rejection.status().as_u16(), 422);
+
+            let body = axum::body::to_bytes(rejection.into_body(), usize::MAX).await.unwrap();
+            let error: crate::ErrorResponse = serde_json::from_slice(&body).unwrap();
+            assert_eq!(error.code, "missing_body");
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use auth_service::{routes::ProfileResponse, ErrorResponse};
+use crate::helpers::{get_random_email, TestApp};
+use serde_json::json;
+use std::time::Duration;
+
+async fn signup_and_login(app: &TestApp, body: serde_json::Value) {
+    let response = app.post_signup(&body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&json!({
+        "email": body["email"],
+        "password": body["password"]
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_me().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.patch_profile(&json!({ "displayName": "Ada" })).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+    assert_eq!(error_response.code, "missing_token");
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_profile_from_me() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false,
+        "displayName": "Ada"
+    })).await;
+
+    let response = app.get_me().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let profile = response
+        .json::<ProfileResponse>()
+        .await
+        .expect("Failed to parse profile response")
+        .data
+        .expect("Profile response should include the profile");
+    assert_eq!(profile.email, email);
+    assert!(!profile.requires_2fa);
+    assert_eq!(profile.display_name.as_deref(), Some("Ada"));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_update_and_clear_display_name() {
+    let mut app = TestApp::new().await;
+    signup_and_login(&app, json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+
+    let response = app.patch_profile(&json!({ "displayName": "Ada Lovelace" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let profile = response.json::<ProfileResponse>().await.unwrap().data.unwrap();
+    assert_eq!(profile.display_name.as_deref(), Some("Ada Lovelace"));
+
+    // The change is persisted, not just echoed back
+    let profile = app.get_me().await.json::<ProfileResponse>().await.unwrap().data.unwrap();
+    assert_eq!(profile.display_name.as_deref(), Some("Ada Lovelace"));
+
+    let response = app.patch_profile(&json!({ "displayName": null })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let profile = app.get_me().await.json::<ProfileResponse>().await.unwrap().data.unwrap();
+    assert_eq!(profile.display_name, None);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_display_name_invalid() {
+    let mut app = TestApp::new().await;
+    signup_and_login(&app, json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false,
+        "displayName": "Ada"
+    })).await;
+
+    for display_name in ["   ".to_owned(), "a".repeat(65), "Ada\u{1b}[31m".to_owned()] {
+        let response = app.patch_profile(&json!({ "displayName": display_name })).await;
+        assert_eq!(response.status().as_u16(), 400, "Failed for display name: {:?}", display_name);
+
+        let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+        assert_eq!(error_response.error, "Invalid display name");
+        assert_eq!(error_response.code, "invalid_display_name");
+    }
+
+    // A rejected update leaves the previous name in place
+    let profile = app.get_me().await.json::<ProfileResponse>().await.unwrap().data.unwrap();
+    assert_eq!(profile.display_name.as_deref(), Some("Ada"));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_set_locale_at_signup_and_update_it() {
+    let mut app = TestApp::new().await;
+    signup_and_login(&app, json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false,
+        "displayName": "Ada",
+        "locale": "es-MX"
+    })).await;
+
+    // Stored as the supported language, not the tag as given
+    let profile = app.get_me().await.json::<ProfileResponse>().await.unwrap().data.unwrap();
+    assert_eq!(profile.locale.as_deref(), Some("es"));
+
+    let response = app.patch_profile(&json!({ "locale": "en" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let profile = app.get_me().await.json::<ProfileResponse>().await.unwrap().data.unwrap();
+    assert_eq!(profile.locale.as_deref(), Some("en"));
+    // Fields left out of the update are kept
+    assert_eq!(profile.display_name.as_deref(), Some("Ada"));
+
+    let response = app.patch_profile(&json!({ "locale": null })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let profile = app.get_me().await.json::<ProfileResponse>().await.unwrap().data.unwrap();
+    assert_eq!(profile.locale, None);
+    assert_eq!(profile.display_name.as_deref(), Some("Ada"));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_locale_unsupported() {
+    let mut app = TestApp::new().await;
+    signup_and_login(&app, json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false,
+        "displayName": "Ada",
+        "locale": "es"
+    })).await;
+
+    for locale in ["fr", "es_MX", ""] {
+        let response = app.patch_profile(&json!({ "displayName": "Grace", "locale": locale })).await;
+        assert_eq!(response.status().as_u16(), 400, "Failed for locale: {:?}", locale);
+
+        let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+        assert_eq!(error_response.code, "unsupported_locale");
+    }
+
+    // Nothing in a rejected update is applied
+    let profile = app.get_me().await.json::<ProfileResponse>().await.unwrap().data.unwrap();
+    assert_eq!(profile.locale.as_deref(), Some("es"));
+    assert_eq!(profile.display_name.as_deref(), Some("Ada"));
+
+    let response = app.post_signup(&json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "locale": "de-DE"
+    })).await;
+    assert_eq!(response.status().as_u16(), 400);
+    let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+    assert_eq!(error_response.code, "unsupported_locale");
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_403_if_session_is_not_fresh() {
+    let mut app = TestApp::builder().with_fresh_session_max_age(Duration::ZERO).spawn().await;
+    signup_and_login(&app, json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false,
+        "displayName": "Ada"
+    })).await;
+
+    // Login times are in whole seconds, so this puts the login strictly past the limit
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    let response = app.patch_profile(&json!({ "displayName": "Mallory" })).await;
+    assert_eq!(response.status().as_u16(), 403);
+    let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+    assert_eq!(error_response.code, "reauthentication_required");
+
+    // Reading the profile doesn't need a fresh session, and the update wasn't applied
+    let profile = app.get_me().await.json::<ProfileResponse>().await.unwrap().data.unwrap();
+    assert_eq!(profile.display_name.as_deref(), Some("Ada"));
+    app.clean_up().await;
+}
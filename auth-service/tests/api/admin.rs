@@ -0,0 +1,585 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::{
+    domain::data_stores::{banned_token_id, AuditEventType, BannedTokenEntry},
+    routes::admin::{
+        AdminSessionResponse, AdminStatsResponse, AuditPage, MaintenanceResponse, RotateAdminKeyResponse,
+        SetActiveResponse, SetAdminResponse, TokenCutoffResponse,
+    },
+    utils::constants::{test, JWT_COOKIE_NAME, MAINTENANCE_RETRY_AFTER_SECONDS},
+    ApiResponse,
+    ErrorResponse,
+};
+use secrecy::Secret;
+use serde_json::json;
+
+#[tokio::test]
+async fn should_return_401_if_admin_key_missing_or_invalid() {
+    let mut app = TestApp::new().await;
+
+    for admin_key in ["", "wrong-admin-key"] {
+        let response = app.get_admin_stats(admin_key).await;
+        assert_eq!(response.status().as_u16(), 401, "Failed for key: {:?}", admin_key);
+
+        let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+        assert_eq!(error_response.error, "Invalid admin key");
+        assert_eq!(error_response.code, "invalid_admin_key");
+    }
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_user_count_that_grows_after_signup() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_admin_stats(test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let stats = response
+        .json::<ApiResponse<AdminStatsResponse>>()
+        .await
+        .expect("Failed to parse stats response")
+        .data
+        .expect("Stats response should include data");
+    assert_eq!(stats.total_users, 0);
+
+    let signup_response = app.post_signup(&json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let response = app.get_admin_stats(test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let stats = response
+        .json::<ApiResponse<AdminStatsResponse>>()
+        .await
+        .expect("Failed to parse stats response")
+        .data
+        .expect("Stats response should include data");
+    assert_eq!(stats.total_users, 1);
+    app.clean_up().await;
+}
+
+// Signs up, logs in and logs out a user, returning the now-banned token
+async fn log_in_and_out(app: &TestApp) -> String {
+    let email = get_random_email();
+    let signup_response = app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let login_response = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    })).await;
+    assert_eq!(login_response.status().as_u16(), 200);
+
+    let token = login_response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No JWT cookie found")
+        .value()
+        .to_owned();
+
+    let logout_response = app.logout().await;
+    assert_eq!(logout_response.status().as_u16(), 200);
+
+    token
+}
+
+#[tokio::test]
+async fn should_list_banned_token_after_logout() {
+    let mut app = TestApp::new().await;
+    let token = log_in_and_out(&app).await;
+
+    let response = app.get_banned_tokens(test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let banned_tokens = response
+        .json::<ApiResponse<Vec<BannedTokenEntry>>>()
+        .await
+        .expect("Failed to parse banned tokens response")
+        .data
+        .expect("Banned tokens response should include data");
+    let id = banned_token_id(&Secret::new(token));
+    assert!(banned_tokens.iter().any(|entry| entry.id == id));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_restore_access_after_unbanning_token() {
+    let mut app = TestApp::new().await;
+    let token = log_in_and_out(&app).await;
+
+    let response = app.post_verify_token(&json!({ "token": token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let id = banned_token_id(&Secret::new(token.clone()));
+    let response = app.delete_banned_token(&id, test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_verify_token(&json!({ "token": token })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_404_when_unbanning_unknown_token() {
+    let mut app = TestApp::new().await;
+
+    let response = app.delete_banned_token("unknown", test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+    assert_eq!(error_response.error, "Banned token not found");
+    assert_eq!(error_response.code, "banned_token_not_found");
+
+    let response = app.delete_banned_token("unknown", "wrong-admin-key").await;
+    assert_eq!(response.status().as_u16(), 401);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_503_for_gated_endpoints_in_maintenance_mode() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let credentials = json!({ "email": email, "password": "password123" });
+
+    let response = app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&credentials).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    let response = app.put_maintenance_mode(true, "wrong-admin-key").await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.put_maintenance_mode(true, test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let maintenance = response
+        .json::<ApiResponse<MaintenanceResponse>>()
+        .await
+        .expect("Failed to parse maintenance response")
+        .data
+        .expect("Maintenance response should include data");
+    assert!(maintenance.enabled);
+
+    let gated = [
+        app.post_signup(&json!({
+            "email": get_random_email(),
+            "password": "password123",
+            "requires2FA": false
+        })).await,
+        app.post_login(&credentials).await,
+        app.post_verify_2fa(&json!({
+            "email": email,
+            "loginAttemptId": "123e4567-e89b-12d3-a456-426614174000",
+            "2FACode": "123456"
+        })).await,
+    ];
+    for response in gated {
+        assert_eq!(response.status().as_u16(), 503, "Failed for {}", response.url());
+        assert_eq!(response.headers()["retry-after"], MAINTENANCE_RETRY_AFTER_SECONDS.to_string().as_str());
+
+        let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+        assert_eq!(error_response.code, "maintenance_mode");
+    }
+
+    // Existing sessions keep working
+    let response = app.post_verify_token(&json!({ "token": token })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.get_version().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.put_maintenance_mode(false, test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_login(&credentials).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
+async fn rotate_admin_key(app: &TestApp, admin_key: &str) -> String {
+    let response = app.post_rotate_admin_key(admin_key).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["cache-control"], "no-store");
+
+    response
+        .json::<ApiResponse<RotateAdminKeyResponse>>()
+        .await
+        .expect("Failed to parse rotate key response")
+        .data
+        .expect("Rotate key response should include the new key")
+        .admin_key
+}
+
+#[tokio::test]
+async fn should_accept_only_the_latest_rotated_admin_key() {
+    let mut app = TestApp::new().await;
+
+    let first_key = rotate_admin_key(&app, test::ADMIN_API_KEY).await;
+    assert_ne!(first_key, test::ADMIN_API_KEY);
+    assert_eq!(app.get_admin_stats(&first_key).await.status().as_u16(), 200);
+
+    // The bootstrap key from the environment is retired by the first rotation
+    assert_eq!(app.get_admin_stats(test::ADMIN_API_KEY).await.status().as_u16(), 401);
+    assert_eq!(app.post_rotate_admin_key(test::ADMIN_API_KEY).await.status().as_u16(), 401);
+
+    let second_key = rotate_admin_key(&app, &first_key).await;
+    assert_ne!(second_key, first_key);
+    assert_eq!(app.get_admin_stats(&second_key).await.status().as_u16(), 200);
+    assert_eq!(app.get_admin_stats(&first_key).await.status().as_u16(), 401);
+    app.clean_up().await;
+}
+
+// Logs in as a fresh user, returning their token
+async fn issue_token(app: &TestApp) -> String {
+    let email = get_random_email();
+    signup(app, &email).await;
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No JWT cookie found")
+        .value()
+        .to_owned();
+    token
+}
+
+async fn assert_cutoff_rejects_earlier_tokens(app: &TestApp) {
+    let earlier_token = issue_token(app).await;
+    assert_eq!(app.post_verify_token(&json!({ "token": earlier_token })).await.status().as_u16(), 200);
+
+    // `iat` has second precision, so move past the second the token was issued in
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    let response = app.put_token_cutoff(&json!({}), test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let cutoff = response
+        .json::<ApiResponse<TokenCutoffResponse>>()
+        .await
+        .expect("Failed to parse token cutoff response")
+        .data
+        .expect("Token cutoff response should include data");
+
+    let response = app.post_verify_token(&json!({ "token": earlier_token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Tokens issued from the cutoff on are unaffected
+    let later_token = issue_token(app).await;
+    let response = app.post_verify_token(&json!({ "token": later_token })).await;
+    assert_eq!(response.status().as_u16(), 200, "Token issued after cutoff {}", cutoff.not_valid_before);
+}
+
+#[tokio::test]
+async fn should_reject_tokens_issued_before_cutoff() {
+    let mut app = TestApp::new().await;
+    assert_cutoff_rejects_earlier_tokens(&app).await;
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_store_token_cutoff_in_redis() {
+    let mut app = TestApp::builder().with_redis().spawn().await;
+    assert_cutoff_rejects_earlier_tokens(&app).await;
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_for_future_token_cutoff() {
+    let mut app = TestApp::new().await;
+    let token = issue_token(&app).await;
+
+    let tomorrow = chrono::Utc::now().timestamp() + 24 * 60 * 60;
+    let response = app.put_token_cutoff(&json!({ "not_valid_before": tomorrow }), test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 400);
+    let error_response = response.json::<ErrorResponse>().await.expect("Could not deserialize response body");
+    assert_eq!(error_response.code, "invalid_token_cutoff");
+
+    assert_eq!(app.post_verify_token(&json!({ "token": token })).await.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_share_rotated_admin_key_through_redis() {
+    let mut app = TestApp::builder().with_redis().spawn().await;
+
+    let new_key = rotate_admin_key(&app, test::ADMIN_API_KEY).await;
+    assert_eq!(app.get_admin_stats(&new_key).await.status().as_u16(), 200);
+    assert_eq!(app.get_admin_stats(test::ADMIN_API_KEY).await.status().as_u16(), 401);
+    app.clean_up().await;
+}
+
+async fn signup(app: &TestApp, email: &str) {
+    let response = app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+async fn login(app: &TestApp, email: &str) {
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_accept_admin_token_on_admin_user_routes() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let response = app.put_user_admin(&email, true, test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    signup(&app, &email).await;
+
+    let response = app.put_user_admin(&email, true, test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let updated = response
+        .json::<ApiResponse<SetAdminResponse>>()
+        .await
+        .expect("Failed to parse set admin response")
+        .data
+        .expect("Set admin response should include data");
+    assert!(updated.is_admin);
+
+    // The role is baked into the token at login
+    login(&app, &email).await;
+    let response = app.get_admin_session().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let session = response
+        .json::<ApiResponse<AdminSessionResponse>>()
+        .await
+        .expect("Failed to parse admin session response")
+        .data
+        .expect("Admin session response should include data");
+    assert_eq!(session.email, email);
+
+    // Revoking applies to tokens that were already issued
+    let response = app.put_user_admin(&email, false, test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.get_admin_session().await.status().as_u16(), 403);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_403_for_regular_user_token() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email).await;
+    login(&app, &email).await;
+
+    let response = app.get_admin_session().await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+    assert_eq!(error_response.error, "Admin role required");
+    assert_eq!(error_response.code, "forbidden");
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_require_admin_key_to_grant_admin() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email).await;
+
+    let response = app.put_user_admin(&email, true, "wrong-admin-key").await;
+    assert_eq!(response.status().as_u16(), 401);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_naming_a_malformed_email() {
+    let mut app = TestApp::builder().with_audit_log().spawn().await;
+
+    let responses = [
+        app.put_user_active("not-an-email", &json!({ "is_active": false }), test::ADMIN_API_KEY).await,
+        app.get_audit("email=not-an-email", test::ADMIN_API_KEY).await,
+    ];
+    for response in responses {
+        assert_eq!(response.status().as_u16(), 400);
+        let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+        assert_eq!(error_response.code, "invalid_field");
+        assert_eq!(error_response.error, "email is invalid");
+    }
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_block_login_while_account_is_disabled() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let credentials = json!({ "email": email, "password": "password123" });
+
+    let response = app.put_user_active(&email, &json!({ "is_active": false }), test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    signup(&app, &email).await;
+    let response = app.put_user_active(&email, &json!({ "is_active": false }), test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let updated = response
+        .json::<ApiResponse<SetActiveResponse>>()
+        .await
+        .expect("Failed to parse set active response")
+        .data
+        .expect("Set active response should include data");
+    assert!(!updated.is_active);
+
+    let response = app.post_login(&credentials).await;
+    assert_eq!(response.status().as_u16(), 403);
+    let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+    assert_eq!(error_response.code, "account_disabled");
+
+    // A wrong password still looks like any other failed login
+    let response = app.post_login(&json!({ "email": email, "password": "wrong-password" })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.put_user_active(&email, &json!({ "is_active": true }), test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.post_login(&credentials).await.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_revoke_sessions_when_account_is_disabled() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email).await;
+    login(&app, &email).await;
+    assert_eq!(app.get_me().await.status().as_u16(), 200);
+
+    let response = app.put_user_active(&email, &json!({ "is_active": false }), test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.get_me().await.status().as_u16(), 401);
+
+    // Once re-enabled, a new login works straight away
+    let response = app.put_user_active(&email, &json!({ "is_active": true }), test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    login(&app, &email).await;
+    assert_eq!(app.get_me().await.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_keep_sessions_when_disabling_without_revoke() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email).await;
+    login(&app, &email).await;
+
+    let body = json!({ "is_active": false, "revoke_sessions": false });
+    let response = app.put_user_active(&email, &body, test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.get_me().await.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
+async fn get_audit_page(app: &TestApp, query: &str) -> AuditPage {
+    let response = app.get_audit(query, test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+        .json::<ApiResponse<AuditPage>>()
+        .await
+        .expect("Failed to parse audit response")
+        .data
+        .expect("Audit response should include data")
+}
+
+async fn fail_login(app: &TestApp, email: &str) {
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "wrong-password"
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_filter_audit_events_by_event_type() {
+    let mut app = TestApp::builder().with_audit_log().spawn().await;
+    let (email, other_email) = (get_random_email(), get_random_email());
+    signup(&app, &email).await;
+    login(&app, &email).await;
+    fail_login(&app, &email).await;
+    fail_login(&app, &other_email).await;
+    assert_eq!(app.logout().await.status().as_u16(), 200);
+
+    let page = get_audit_page(&app, &format!("email={}", email)).await;
+    let types: Vec<_> = page.events.iter().map(|event| event.event_type).collect();
+    assert_eq!(
+        types,
+        [AuditEventType::Logout, AuditEventType::LoginFailed, AuditEventType::LoginSucceeded, AuditEventType::Signup]
+    );
+    assert!(page.next_cursor.is_none());
+
+    let page = get_audit_page(&app, "event_type=login_failed").await;
+    let emails: Vec<_> = page.events.iter().map(|event| event.email.as_str()).collect();
+    assert_eq!(emails, [other_email.as_str(), email.as_str()]);
+
+    let page = get_audit_page(&app, &format!("event_type=login_failed&email={}", other_email)).await;
+    assert_eq!(page.events.len(), 1);
+
+    let response = app.get_audit("event_type=password_reset", test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 400);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_page_through_audit_events_with_cursor() {
+    let mut app = TestApp::builder().with_audit_log().spawn().await;
+    let email = get_random_email();
+    for _ in 0..5 {
+        fail_login(&app, &email).await;
+    }
+
+    let mut ids = Vec::new();
+    let mut page_sizes = Vec::new();
+    let mut query = "limit=2".to_owned();
+    loop {
+        let page = get_audit_page(&app, &query).await;
+        page_sizes.push(page.events.len());
+        ids.extend(page.events.iter().map(|event| event.id));
+        match page.next_cursor {
+            Some(cursor) => query = format!("limit=2&cursor={}", cursor),
+            None => break,
+        }
+    }
+
+    assert_eq!(page_sizes, [2, 2, 1]);
+    // Newest first, with every event on exactly one page
+    assert!(ids.windows(2).all(|pair| pair[0] > pair[1]));
+    assert_eq!(ids.len(), 5);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_404_if_audit_log_not_configured() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_audit("", test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    let response = app.get_audit("", "wrong-admin-key").await;
+    assert_eq!(response.status().as_u16(), 401);
+    app.clean_up().await;
+}
@@ -0,0 +1,185 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::{
+    domain::data_stores::AuditEventType,
+    routes::admin::{AuditPage, SetMustChangePasswordResponse},
+    utils::constants::test,
+    ApiResponse,
+    ErrorResponse,
+};
+use serde_json::json;
+
+async fn signup(app: &TestApp, email: &str) {
+    let response = app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+async fn require_password_change(app: &TestApp, email: &str) {
+    let response = app.put_user_must_change_password(email, true, test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let updated = response
+        .json::<ApiResponse<SetMustChangePasswordResponse>>()
+        .await
+        .expect("Failed to parse response")
+        .data
+        .expect("Response should include data");
+    assert!(updated.must_change_password);
+}
+
+#[tokio::test]
+async fn should_return_422_if_malformed_input() {
+    let mut app = TestApp::new().await;
+    let response = app.post_change_password(&json!({ "email": get_random_email() })).await;
+    assert_eq!(response.status().as_u16(), 422);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_block_login_until_password_is_changed() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email).await;
+    require_password_change(&app, &email).await;
+
+    let response = app.post_login(&json!({ "email": email, "password": "password123" })).await;
+    assert_eq!(response.status().as_u16(), 403);
+    let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+    assert_eq!(error_response.code, "password_change_required");
+
+    // The current password is still what authorizes the change
+    let response = app.post_change_password(&json!({
+        "email": email,
+        "password": "wrong-password",
+        "newPassword": "newpassword456"
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_change_password(&json!({
+        "email": email,
+        "password": "password123",
+        "newPassword": "newpassword456"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_login(&json!({ "email": email, "password": "password123" })).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.post_login(&json!({ "email": email, "password": "newpassword456" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    // The change revoked earlier sessions, but not the one started right after it
+    assert_eq!(app.get_me().await.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_reusing_the_current_password() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email).await;
+    require_password_change(&app, &email).await;
+
+    let response = app.post_change_password(&json!({
+        "email": email,
+        "password": "password123",
+        "newPassword": "password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.post_login(&json!({ "email": email, "password": "password123" })).await;
+    assert_eq!(response.status().as_u16(), 403);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_require_2fa_code_to_change_password_of_2fa_account() {
+    let mut app = TestApp::new().await;
+    app.mock_email_delivery(200).await;
+    let email = get_random_email();
+    let response = app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    // The password alone only starts a 2FA challenge; the password is unchanged
+    let change = json!({
+        "email": email,
+        "password": "password123",
+        "newPassword": "newpassword456"
+    });
+    let response = app.post_change_password(&change).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let response = app.post_login(&json!({ "email": email, "password": "newpassword456" })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_change_password(&json!({
+        "email": email,
+        "password": "password123",
+        "newPassword": "newpassword456",
+        "2FACode": "000000"
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let two_fa_code = app.sent_2fa_code(&email).await;
+    let response = app.post_change_password(&json!({
+        "email": email,
+        "password": "password123",
+        "newPassword": "newpassword456",
+        "2FACode": two_fa_code
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_login(&json!({ "email": email, "password": "newpassword456" })).await;
+    assert_eq!(response.status().as_u16(), 206);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_revoke_sessions_when_password_change_is_required() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email).await;
+    let response = app.post_login(&json!({ "email": email, "password": "password123" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.get_me().await.status().as_u16(), 200);
+
+    require_password_change(&app, &email).await;
+    assert_eq!(app.get_me().await.status().as_u16(), 401);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_404_when_flagging_unknown_user() {
+    let mut app = TestApp::new().await;
+    let response = app.put_user_must_change_password(&get_random_email(), true, test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 404);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_record_password_change_in_audit_log() {
+    let mut app = TestApp::builder().with_audit_log().spawn().await;
+    let email = get_random_email();
+    signup(&app, &email).await;
+
+    let response = app.post_change_password(&json!({
+        "email": email,
+        "password": "password123",
+        "newPassword": "newpassword456"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.get_audit(&format!("email={}&event_type=password_changed", email), test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response
+        .json::<ApiResponse<AuditPage>>()
+        .await
+        .expect("Failed to parse audit response")
+        .data
+        .expect("Audit response should include data");
+    assert_eq!(page.events.len(), 1);
+    assert_eq!(page.events[0].event_type, AuditEventType::PasswordChanged);
+    app.clean_up().await;
+}
@@ -0,0 +1,41 @@
+use crate::helpers::TestApp;
+
+// The app service calls the auth service cross-origin, so browsers preflight its JSON requests
+const APP_ORIGIN: &str = "http://localhost:8000";
+
+#[tokio::test]
+async fn should_answer_login_preflight_from_allowed_origin() {
+    let mut app = TestApp::new().await;
+
+    let response = app.preflight("login", APP_ORIGIN, "POST").await;
+    assert!(response.status().is_success(), "Unexpected status: {}", response.status());
+
+    let headers = response.headers();
+    assert_eq!(headers["access-control-allow-origin"], APP_ORIGIN);
+    assert_eq!(headers["access-control-allow-credentials"], "true");
+    let allow_methods = headers["access-control-allow-methods"].to_str().unwrap();
+    assert!(allow_methods.split(',').any(|method| method == "POST"), "Unexpected methods: {}", allow_methods);
+    let allow_headers = headers["access-control-allow-headers"].to_str().unwrap();
+    assert!(allow_headers.split(',').any(|header| header == "content-type"), "Unexpected headers: {}", allow_headers);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_allow_patch_for_profile_updates() {
+    let mut app = TestApp::new().await;
+
+    let response = app.preflight("account/profile", APP_ORIGIN, "PATCH").await;
+    assert!(response.status().is_success(), "Unexpected status: {}", response.status());
+    let allow_methods = response.headers()["access-control-allow-methods"].to_str().unwrap();
+    assert!(allow_methods.split(',').any(|method| method == "PATCH"), "Unexpected methods: {}", allow_methods);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_allow_unknown_origin() {
+    let mut app = TestApp::new().await;
+
+    let response = app.preflight("login", "http://evil.example.com", "POST").await;
+    assert!(!response.headers().contains_key("access-control-allow-origin"));
+    app.clean_up().await;
+}
@@ -0,0 +1,146 @@
+use std::time::Duration;
+use auth_service::{
+    routes::events::LOGOUT_EVENT,
+    utils::constants::{test, JWT_COOKIE_NAME},
+    ErrorResponse,
+};
+use reqwest::Client;
+use serde_json::json;
+use crate::helpers::{get_random_email, TestApp};
+
+// Signs up and logs in, returning the session token the client's cookie jar now holds
+async fn login(app: &TestApp, email: &str) -> String {
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+    token
+}
+
+async fn signup(app: &TestApp) -> String {
+    let email = get_random_email();
+    let response = app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+    email
+}
+
+// The event name of the next event on the stream, skipping keep-alive comments; `None` if none
+// arrives within `wait`
+async fn next_event(events: &mut reqwest::Response, wait: Duration) -> Option<String> {
+    let mut received = String::new();
+    tokio::time::timeout(wait, async {
+        loop {
+            if let Some(line) = received.lines().find(|line| line.starts_with("event:")) {
+                return line.trim_start_matches("event:").trim().to_owned();
+            }
+            let chunk = events.chunk().await.expect("Event stream failed").expect("Event stream ended");
+            received.push_str(&String::from_utf8_lossy(&chunk));
+        }
+    })
+    .await
+    .ok()
+}
+
+#[tokio::test]
+async fn should_send_logout_event_when_session_is_revoked() {
+    let mut app = TestApp::new().await;
+    let email = signup(&app).await;
+    login(&app, &email).await;
+
+    let mut events = app.get_events().await;
+    assert_eq!(events.status().as_u16(), 200);
+    assert_eq!(events.headers()["content-type"], "text/event-stream");
+
+    // Another tab sharing the session logs out
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(next_event(&mut events, Duration::from_secs(5)).await.as_deref(), Some(LOGOUT_EVENT));
+    // The stream ends once the session is gone
+    assert!(events.chunk().await.unwrap().is_none());
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_only_notify_the_revoked_session() {
+    let mut app = TestApp::new().await;
+    let email = signup(&app).await;
+    let first_token = login(&app, &email).await;
+
+    let mut events = Client::new()
+        .get(format!("{}/events", app.address))
+        .bearer_auth(&first_token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(events.status().as_u16(), 200);
+
+    // A second session for the same user logs out; the first one is unaffected
+    login(&app, &email).await;
+    assert_eq!(app.logout().await.status().as_u16(), 200);
+    assert_eq!(next_event(&mut events, Duration::from_millis(300)).await, None);
+
+    assert_eq!(app.logout_with_bearer(&first_token).await.status().as_u16(), 200);
+    assert_eq!(next_event(&mut events, Duration::from_secs(5)).await.as_deref(), Some(LOGOUT_EVENT));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_send_logout_event_when_account_is_disabled() {
+    let mut app = TestApp::new().await;
+    let email = signup(&app).await;
+    login(&app, &email).await;
+    let mut events = app.get_events().await;
+    assert_eq!(events.status().as_u16(), 200);
+
+    let response = app.put_user_active(&email, &json!({ "is_active": false }), test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(next_event(&mut events, Duration::from_secs(5)).await.as_deref(), Some(LOGOUT_EVENT));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_send_logout_event_when_token_cutoff_is_set() {
+    let mut app = TestApp::new().await;
+    let email = signup(&app).await;
+    login(&app, &email).await;
+    let mut events = app.get_events().await;
+    assert_eq!(events.status().as_u16(), 200);
+
+    // A cutoff from before the login leaves the session alone
+    let before_login = chrono::Utc::now().timestamp() - 60;
+    let response = app.put_token_cutoff(&json!({ "not_valid_before": before_login }), test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(next_event(&mut events, Duration::from_millis(300)).await, None);
+
+    // `iat` has second precision, so move past the second the token was issued in
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let response = app.put_token_cutoff(&json!({}), test::ADMIN_API_KEY).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(next_event(&mut events, Duration::from_secs(5)).await.as_deref(), Some(LOGOUT_EVENT));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_without_token() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_events().await;
+    assert_eq!(response.status().as_u16(), 400);
+    let error_response = response.json::<ErrorResponse>().await.expect("Could not deserialize response body");
+    assert_eq!(error_response.code, "missing_token");
+    app.clean_up().await;
+}
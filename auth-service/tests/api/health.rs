@@ -0,0 +1,63 @@
+use crate::helpers::TestApp;
+use auth_service::routes::{CheckStatus, ReadinessResponse};
+
+#[tokio::test]
+async fn livez_returns_200() {
+    let mut app = TestApp::builder().with_unreachable_postgres().spawn().await;
+
+    // A dependency being down is no reason to restart the process
+    let response = app.get_livez().await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn readyz_returns_200_when_dependencies_are_up() {
+    let mut app = TestApp::builder().with_two_fa_delivery_log().spawn().await;
+
+    let response = app.get_readyz().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let report = response
+        .json::<ReadinessResponse>()
+        .await
+        .expect("Failed to parse readiness response")
+        .data
+        .expect("Readiness response should include the checks");
+    assert_eq!(report.checks["postgres"], CheckStatus::Up);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn readyz_checks_redis_when_redis_backed() {
+    let mut app = TestApp::builder().with_redis().spawn().await;
+
+    let response = app.get_readyz().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let report = response
+        .json::<ReadinessResponse>()
+        .await
+        .expect("Failed to parse readiness response")
+        .data
+        .expect("Readiness response should include the checks");
+    assert_eq!(report.checks["redis"], CheckStatus::Up);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn readyz_returns_503_when_a_dependency_is_down() {
+    let mut app = TestApp::builder().with_unreachable_postgres().spawn().await;
+
+    let response = app.get_readyz().await;
+    assert_eq!(response.status().as_u16(), 503);
+
+    let report = response
+        .json::<ReadinessResponse>()
+        .await
+        .expect("Failed to parse readiness response")
+        .data
+        .expect("Readiness response should include the checks");
+    assert_eq!(report.checks["postgres"], CheckStatus::Down);
+    app.clean_up().await;
+}
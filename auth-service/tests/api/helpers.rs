@@ -1,24 +1,51 @@
-use sqlx::{postgres::PgConnectOptions, Connection, PgConnection};
+use sqlx::{postgres::{PgConnectOptions, PgPoolOptions}, Connection, Executor, PgConnection, PgPool};
 use std::str::FromStr;
+use std::time::Duration;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use reqwest::{Client, cookie::Jar};
+use reqwest::{Client, Url, cookie::Jar};
+use redis::Commands;
 use uuid::Uuid;
 use serde::Serialize;
-use wiremock::MockServer;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
 use secrecy::{ExposeSecret, Secret};
-use auth_service::utils::constants::DATABASE_URL;
+use auth_service::utils::{
+    config::{self, Config, EnvVars},
+    constants::env::{
+        ADMIN_API_KEY_ENV_VAR, EMAIL_PROVIDER_ENV_VAR, JWT_ALGORITHM_ENV_VAR, JWT_KEY_ID_ENV_VAR,
+        JWT_RSA_PRIVATE_KEY_ENV_VAR, JWT_RSA_PUBLIC_KEY_ENV_VAR, PUBLIC_APP_URL_ENV_VAR,
+        TWO_FA_CODE_PEPPER_ENV_VAR,
+    },
+};
 use auth_service::{
     Application, 
-    app_state::{AppState},
+    get_postgres_pool,
+    get_redis_client,
+    run_migrations,
+    app_state::{AppState, BannedTokenStoreType, LoginFailureStoreType, TwoFACodeStoreType, UserStoreType},
     services::{
-        hashmap_user_store::HashmapUserStore,
-        hashset_banned_token_store::HashsetBannedTokenStore,
-        hashmap_two_fa_code_store::HashmapTwoFACodeStore,
+        data_stores::{
+            hashmap_user_store::HashmapUserStore,
+            hashset_banned_token_store::HashsetBannedTokenStore,
+            postgres_audit_log::PostgresAuditLog,
+            postgres_two_fa_delivery_log::PostgresTwoFaDeliveryLog,
+            redis_admin_key_store::RedisAdminKeyStore,
+            redis_banned_token_store::RedisBannedTokenStore,
+            redis_login_failure_store::RedisLoginFailureStore,
+            redis_revocation_notifier::RedisRevocationNotifier,
+            redis_trusted_device_store::RedisTrustedDeviceStore,
+            redis_two_fa_code_store::RedisTwoFACodeStore,
+        },
+        health_checks::{PostgresHealthCheck, RedisHealthCheck},
         postmark_email_client::PostmarkEmailClient,
     },
     domain::{email::Email, email_client::EmailClient},
-    utils::constants::test,
+    utils::{
+        constants::test, feature_flags::FeatureFlags, login_delay::LoginDelay, sliding_session::SlidingSessions,
+    },
 };
 
 pub struct TestApp {
@@ -27,28 +54,225 @@ pub struct TestApp {
     pub http_client: Client,
     pub email_server: MockServer,
     pub email_client: Arc<dyn EmailClient + Send + Sync>,
+    // Shared with the running app so tests can inspect and seed store state directly
+    pub user_store: UserStoreType,
+    pub banned_token_store: BannedTokenStoreType,
+    pub two_fa_code_store: TwoFACodeStoreType,
+    pub login_failure_store: LoginFailureStoreType,
+    // What the app was built from, for tests that depend on a configured limit or key
+    pub config: Config,
+    // Only set for apps that need Postgres-backed state
+    pub db_pool: Option<PgPool>,
+    redis_key_prefix: Option<String>,
     db_name: String,         
     clean_up_called: bool,
 }
 
-impl TestApp {
-    pub async fn new() -> Self {
+// Which optional pieces of the app a test needs, e.g.
+// `TestApp::builder().with_redis().with_audit_log().spawn().await`
+#[derive(Default)]
+pub struct TestAppBuilder {
+    auto_login_on_signup: bool,
+    two_fa_delivery_log: bool,
+    audit_log: bool,
+    redis: bool,
+    default_requires_2fa: bool,
+    two_fa_max_attempts: Option<u32>,
+    normalize_plus_addressing: bool,
+    sliding_sessions: Option<SlidingSessions>,
+    max_concurrent_requests: Option<usize>,
+    fresh_session_max_age: Option<Duration>,
+    unreachable_postgres: bool,
+    rs256: bool,
+    public_app_url: Option<&'static str>,
+}
+
+// Key pair for apps that sign tokens with RS256; generated for the tests and used nowhere else
+const TEST_JWT_RSA_PRIVATE_KEY: &str = include_str!("fixtures/jwt_rsa_private.pem");
+const TEST_JWT_RSA_PUBLIC_KEY: &str = include_str!("fixtures/jwt_rsa_public.pem");
+pub const TEST_JWT_KEY_ID: &str = "test-key";
+
+impl TestAppBuilder {
+    pub fn with_auto_login_on_signup(mut self) -> Self {
+        self.auto_login_on_signup = true;
+        self
+    }
+
+    pub fn with_two_fa_delivery_log(mut self) -> Self {
+        self.two_fa_delivery_log = true;
+        self
+    }
+
+    pub fn with_audit_log(mut self) -> Self {
+        self.audit_log = true;
+        self
+    }
+
+    pub fn with_default_requires_2fa(mut self) -> Self {
+        self.default_requires_2fa = true;
+        self
+    }
+
+    pub fn with_normalize_plus_addressing(mut self) -> Self {
+        self.normalize_plus_addressing = true;
+        self
+    }
+
+    pub fn with_two_fa_max_attempts(mut self, two_fa_max_attempts: u32) -> Self {
+        self.two_fa_max_attempts = Some(two_fa_max_attempts);
+        self
+    }
+
+    pub fn with_sliding_sessions(mut self, sliding_sessions: SlidingSessions) -> Self {
+        self.sliding_sessions = Some(sliding_sessions);
+        self
+    }
+
+    pub fn with_max_concurrent_requests(mut self, max_concurrent_requests: usize) -> Self {
+        self.max_concurrent_requests = Some(max_concurrent_requests);
+        self
+    }
+
+    pub fn with_fresh_session_max_age(mut self, fresh_session_max_age: Duration) -> Self {
+        self.fresh_session_max_age = Some(fresh_session_max_age);
+        self
+    }
+
+    // Reports Postgres as a dependency that can't be reached, for readiness checks
+    pub fn with_unreachable_postgres(mut self) -> Self {
+        self.unreachable_postgres = true;
+        self
+    }
+
+    // Signs tokens with RS256 and serves the public key at `/.well-known/jwks.json`
+    pub fn with_rs256(mut self) -> Self {
+        self.rs256 = true;
+        self
+    }
+
+    pub fn with_public_app_url(mut self, public_app_url: &'static str) -> Self {
+        self.public_app_url = Some(public_app_url);
+        self
+    }
+
+    // Uses the Redis stores, namespaced to this app so concurrent tests don't share state
+    pub fn with_redis(mut self) -> Self {
+        self.redis = true;
+        self
+    }
+
+    pub async fn spawn(self) -> TestApp {
         let email_server = MockServer::start().await;
+        let db_name = Uuid::new_v4().to_string();
         
-        let user_store = Arc::new(RwLock::new(HashmapUserStore::default()));
-        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
-        let two_fa_code_store = Arc::new(RwLock::new(HashmapTwoFACodeStore::default()));
+        let user_store: UserStoreType = Arc::new(HashmapUserStore::default());
+        let redis_key_prefix = self.redis.then(|| format!("test:{}:", db_name));
+        let banned_token_store: BannedTokenStoreType = match &redis_key_prefix {
+            Some(key_prefix) => {
+                let conn_manager = redis_client()
+                    .get_connection_manager()
+                    .await
+                    .expect("Failed to get Redis connection manager");
+                Arc::new(RwLock::new(
+                    RedisBannedTokenStore::new(conn_manager).with_key_prefix(key_prefix.as_str()),
+                ))
+            }
+            None => Arc::new(RwLock::new(HashsetBannedTokenStore::default())),
+        };
         let email_client = Arc::new(configure_email_client(email_server.uri()));
-        let db_name = Uuid::new_v4().to_string();
+        // The email client is built above, so the provider setting only needs to pass validation
+        let mut vars = EnvVars::from_env()
+            .with(EMAIL_PROVIDER_ENV_VAR, "mock")
+            .with(ADMIN_API_KEY_ENV_VAR, test::ADMIN_API_KEY)
+            .with(TWO_FA_CODE_PEPPER_ENV_VAR, test::TWO_FA_CODE_PEPPER);
+        if self.rs256 {
+            vars = vars
+                .with(JWT_ALGORITHM_ENV_VAR, "RS256")
+                .with(JWT_KEY_ID_ENV_VAR, TEST_JWT_KEY_ID)
+                .with(JWT_RSA_PRIVATE_KEY_ENV_VAR, TEST_JWT_RSA_PRIVATE_KEY)
+                .with(JWT_RSA_PUBLIC_KEY_ENV_VAR, TEST_JWT_RSA_PUBLIC_KEY);
+        }
+        if let Some(public_app_url) = self.public_app_url {
+            vars = vars.with(PUBLIC_APP_URL_ENV_VAR, public_app_url);
+        }
+        let config = Config::from_vars(&vars).expect("Invalid test configuration");
         
-        let app_state = AppState::new(
-            user_store,
-            banned_token_store,
-            two_fa_code_store,
+        let mut app_state = AppState::new(
+            &config,
+            user_store.clone(),
+            banned_token_store.clone(),
             email_client.clone(),
-        );
+        )
+        // Built from the options alone so the environment's flags don't leak into tests
+        .with_feature_flags(
+            FeatureFlags::default()
+                .with_auto_login_on_signup(self.auto_login_on_signup)
+                .with_default_requires_2fa(self.default_requires_2fa)
+                .with_normalize_plus_addressing(self.normalize_plus_addressing),
+        )
+        .with_login_delay(LoginDelay::new(test::LOGIN_DELAY_BASE, test::LOGIN_DELAY_MAX))
+        .with_two_fa_max_attempts(self.two_fa_max_attempts.unwrap_or(test::TWO_FA_MAX_ATTEMPTS));
+
+        if let Some(sliding_sessions) = self.sliding_sessions {
+            app_state = app_state.with_sliding_sessions(sliding_sessions);
+        }
+        if let Some(max_concurrent_requests) = self.max_concurrent_requests {
+            app_state = app_state.with_max_concurrent_requests(max_concurrent_requests);
+        }
+        if let Some(fresh_session_max_age) = self.fresh_session_max_age {
+            app_state = app_state.with_fresh_session_max_age(fresh_session_max_age);
+        }
 
-        let app = Application::build(app_state, test::APP_ADDRESS)
+        if let Some(key_prefix) = &redis_key_prefix {
+            let conn_manager = redis_client()
+                .get_connection_manager()
+                .await
+                .expect("Failed to get Redis connection manager");
+            app_state = app_state
+                .with_two_fa_code_store(Arc::new(RwLock::new(
+                    RedisTwoFACodeStore::new(conn_manager.clone()).with_key_prefix(key_prefix.as_str()),
+                )))
+                .with_trusted_device_store(Arc::new(
+                    RedisTrustedDeviceStore::new(conn_manager.clone()).with_key_prefix(key_prefix.as_str()),
+                ))
+                .with_login_failure_store(Arc::new(
+                    RedisLoginFailureStore::new(conn_manager.clone()).with_key_prefix(key_prefix.as_str()),
+                ))
+                .with_admin_key_store(Arc::new(
+                    RedisAdminKeyStore::new(conn_manager.clone()).with_key_prefix(key_prefix.as_str()),
+                ))
+                .with_revocation_notifier(Arc::new(
+                    RedisRevocationNotifier::new(redis_client(), conn_manager.clone())
+                        .with_key_prefix(key_prefix.as_str()),
+                ))
+                .with_health_check(Arc::new(RedisHealthCheck::new(conn_manager)));
+        }
+
+        let db_pool = match self.two_fa_delivery_log || self.audit_log {
+            true => Some(configure_postgresql(&db_name).await),
+            false => None,
+        };
+        if let Some(pool) = &db_pool {
+            app_state = app_state.with_health_check(Arc::new(PostgresHealthCheck::new(pool.clone())));
+            if self.two_fa_delivery_log {
+                app_state = app_state.with_two_fa_delivery_log(Arc::new(PostgresTwoFaDeliveryLog::new(pool.clone())));
+            }
+            if self.audit_log {
+                app_state = app_state.with_audit_log(Arc::new(PostgresAuditLog::new(pool.clone())));
+            }
+        }
+        if self.unreachable_postgres {
+            // Nothing listens on port 1, so every connection attempt is refused
+            let pool = PgPoolOptions::new()
+                .acquire_timeout(Duration::from_millis(500))
+                .connect_lazy("postgres://postgres@127.0.0.1:1/unreachable")
+                .expect("Failed to create Postgres pool");
+            app_state = app_state.with_health_check(Arc::new(PostgresHealthCheck::new(pool)));
+        }
+        let two_fa_code_store = app_state.two_fa_code_store.clone();
+        let login_failure_store = app_state.login_failure_store.clone();
+
+        let app = Application::build(app_state, &config, test::APP_ADDRESS)
             .await
             .expect("Failed to build app");
 
@@ -64,16 +288,33 @@ impl TestApp {
             .build()
             .expect("Failed to create HTTP client");
 
-        Self { 
+        TestApp { 
             address,
             cookie_jar,
             http_client,
             email_server,
             email_client,
+            user_store,
+            banned_token_store,
+            two_fa_code_store,
+            login_failure_store,
+            config,
+            db_pool,
+            redis_key_prefix,
             db_name,              
             clean_up_called: false,
         }
     }
+}
+
+impl TestApp {
+    pub async fn new() -> Self {
+        Self::builder().spawn().await
+    }
+
+    pub fn builder() -> TestAppBuilder {
+        TestAppBuilder::default()
+    }
 
     pub async fn get_root(&self) -> reqwest::Response {
         self.http_client
@@ -83,6 +324,92 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn head_root(&self) -> reqwest::Response {
+        self.http_client
+            .head(&format!("{}/", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_asset(&self, path: &str) -> reqwest::Response {
+        self.http_client
+            .get(&format!("{}/{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_with_accept(&self, path: &str, accept: &str) -> reqwest::Response {
+        self.http_client
+            .get(&format!("{}/{}", &self.address, path))
+            .header("Accept", accept)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_with_accept_encoding(&self, path: &str, accept: &str, accept_encoding: &str) -> reqwest::Response {
+        self.http_client
+            .get(&format!("{}/{}", &self.address, path))
+            .header("Accept", accept)
+            .header("Accept-Encoding", accept_encoding)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn preflight(&self, path: &str, origin: &str, method: &str) -> reqwest::Response {
+        self.http_client
+            .request(reqwest::Method::OPTIONS, &format!("{}/{}", &self.address, path))
+            .header("Origin", origin)
+            .header("Access-Control-Request-Method", method)
+            .header("Access-Control-Request-Headers", "content-type")
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_version(&self) -> reqwest::Response {
+        self.http_client
+            .get(&format!("{}/version", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_livez(&self) -> reqwest::Response {
+        self.http_client
+            .get(&format!("{}/livez", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_readyz(&self) -> reqwest::Response {
+        self.http_client
+            .get(&format!("{}/readyz", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_metrics(&self) -> reqwest::Response {
+        self.http_client
+            .get(&format!("{}/metrics", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_jwks(&self) -> reqwest::Response {
+        self.http_client
+            .get(&format!("{}/.well-known/jwks.json", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn signup(&self) -> reqwest::Response {
         self.http_client
             .post(&format!("{}/signup", &self.address))
@@ -103,6 +430,19 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_signup_from_ip<Body>(&self, body: &Body, ip: &str) -> reqwest::Response
+    where
+        Body: Serialize,
+    {
+        self.http_client
+            .post(&format!("{}/signup", &self.address))
+            .header("X-Forwarded-For", ip)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn login(&self) -> reqwest::Response {
         self.http_client
             .post(&format!("{}/login", &self.address))
@@ -123,6 +463,31 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: Serialize,
+    {
+        self.http_client
+            .post(&format!("{}/change_password", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_login_with_language<Body>(&self, body: &Body, accept_language: &str) -> reqwest::Response
+    where
+        Body: Serialize,
+    {
+        self.http_client
+            .post(&format!("{}/login", &self.address))
+            .header("Accept-Language", accept_language)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn logout(&self) -> reqwest::Response {
         self.http_client
             .post(&format!("{}/logout", &self.address))
@@ -131,6 +496,44 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    // Uses a client without the shared cookie jar so only the bearer token is sent
+    pub async fn logout_with_bearer(&self, token: &str) -> reqwest::Response {
+        Client::new()
+            .post(&format!("{}/logout", &self.address))
+            .bearer_auth(token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_me(&self) -> reqwest::Response {
+        self.http_client
+            .get(&format!("{}/me", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_events(&self) -> reqwest::Response {
+        self.http_client
+            .get(&format!("{}/events", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn patch_profile<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: Serialize,
+    {
+        self.http_client
+            .patch(&format!("{}/account/profile", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn verify_2fa(&self) -> reqwest::Response {
         self.http_client
             .post(&format!("{}/verify_2fa", &self.address))
@@ -141,7 +544,7 @@ impl TestApp {
 
     pub async fn post_verify_2fa<Body>(&self, body: &Body) -> reqwest::Response
     where
-        Body: serde::Serialize,
+        Body: Serialize,
     {
         self.http_client
             .post(&format!("{}/verify_2fa", &self.address))
@@ -171,14 +574,185 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_verify_token_batch<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: Serialize,
+    {
+        self.http_client
+            .post(&format!("{}/verify_token/batch", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Sends the body as-is, for checking how endpoints treat non-JSON requests
+    pub async fn post_raw(&self, path: &str, content_type: &str, body: &str) -> reqwest::Response {
+        self.http_client
+            .post(&format!("{}{}", &self.address, path))
+            .header("Content-Type", content_type)
+            .body(body.to_owned())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_banned_tokens(&self, admin_key: &str) -> reqwest::Response {
+        self.http_client
+            .get(&format!("{}/admin/banned_tokens", &self.address))
+            .header("X-Admin-Key", admin_key)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_banned_token(&self, id: &str, admin_key: &str) -> reqwest::Response {
+        self.http_client
+            .delete(&format!("{}/admin/banned_tokens/{}", &self.address, id))
+            .header("X-Admin-Key", admin_key)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_stats(&self, admin_key: &str) -> reqwest::Response {
+        self.http_client
+            .get(&format!("{}/admin/stats", &self.address))
+            .header("X-Admin-Key", admin_key)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_maintenance_mode(&self, enabled: bool, admin_key: &str) -> reqwest::Response {
+        self.http_client
+            .put(&format!("{}/admin/maintenance", &self.address))
+            .header("X-Admin-Key", admin_key)
+            .json(&serde_json::json!({ "enabled": enabled }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_token_cutoff<Body>(&self, body: &Body, admin_key: &str) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .put(&format!("{}/admin/token_cutoff", &self.address))
+            .header("X-Admin-Key", admin_key)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_rotate_admin_key(&self, admin_key: &str) -> reqwest::Response {
+        self.http_client
+            .post(&format!("{}/admin/rotate_key", &self.address))
+            .header("X-Admin-Key", admin_key)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_user_admin(&self, email: &str, is_admin: bool, admin_key: &str) -> reqwest::Response {
+        self.http_client
+            .put(&format!("{}/admin/users/{}/admin", &self.address, email))
+            .header("X-Admin-Key", admin_key)
+            .json(&serde_json::json!({ "is_admin": is_admin }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_user_active<Body: serde::Serialize>(&self, email: &str, body: &Body, admin_key: &str) -> reqwest::Response {
+        self.http_client
+            .put(&format!("{}/admin/users/{}/active", &self.address, email))
+            .header("X-Admin-Key", admin_key)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_user_must_change_password(&self, email: &str, must_change_password: bool, admin_key: &str) -> reqwest::Response {
+        self.http_client
+            .put(&format!("{}/admin/users/{}/must_change_password", &self.address, email))
+            .header("X-Admin-Key", admin_key)
+            .json(&serde_json::json!({ "must_change_password": must_change_password }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // `query` is the raw query string, e.g. `event_type=logout&limit=2`
+    pub async fn get_audit(&self, query: &str, admin_key: &str) -> reqwest::Response {
+        self.http_client
+            .get(&format!("{}/admin/audit?{}", &self.address, query))
+            .header("X-Admin-Key", admin_key)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_session(&self) -> reqwest::Response {
+        self.http_client
+            .get(&format!("{}/admin/session", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Without a mounted mock the email server answers 404, which the client treats as a failed send
+    pub async fn mock_email_delivery(&self, status: u16) {
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(status))
+            .mount(&self.email_server)
+            .await;
+    }
+
+    // The code from the last 2FA email sent to `email`, read back from the mocked Postmark API
+    pub async fn sent_2fa_code(&self, email: &str) -> String {
+        let requests = self.email_server.received_requests().await.expect("Request recording is disabled");
+        requests
+            .iter()
+            .rev()
+            .filter(|request| request.url.path() == "/email")
+            .map(|request| request.body_json::<serde_json::Value>().expect("Email request body isn't JSON"))
+            .find(|body| body["To"] == email)
+            .and_then(|body| {
+                body["TextBody"]
+                    .as_str()?
+                    .split(|c: char| !c.is_ascii_digit())
+                    .find(|digits| digits.len() == 6)
+                    .map(str::to_owned)
+            })
+            .expect("No 2FA email was sent to this address")
+    }
+
+    // Raw value under `key` in this app's Redis namespace; `None` without Redis or if unset
+    pub fn get_redis_value(&self, key: &str) -> Option<String> {
+        let key_prefix = self.redis_key_prefix.as_ref()?;
+        let mut conn = redis_client().get_connection().expect("Failed to get Redis connection");
+        conn.get(format!("{}{}", key_prefix, key)).expect("Failed to get Redis value")
+    }
+
     pub async fn clean_up(&mut self) {
+        if let Some(pool) = self.db_pool.take() {
+            pool.close().await;
+        }
         delete_database(&self.db_name).await;
+        if let Some(key_prefix) = &self.redis_key_prefix {
+            delete_redis_keys(key_prefix);
+        }
         self.clean_up_called = true;
     }
 }
 
-pub fn get_random_email() -> Secret<String> {
-    Secret::new(format!("{}@example.com", Uuid::new_v4()))
+pub fn get_random_email() -> String {
+    format!("{}@example.com", Uuid::new_v4())
 }
 
 fn configure_email_client(base_url: String) -> PostmarkEmailClient {
@@ -198,6 +772,53 @@ fn configure_email_client(base_url: String) -> PostmarkEmailClient {
     )
 }
 
+// Read the way the app reads them, for the helpers that run outside a `TestApp`
+fn database_url() -> Secret<String> {
+    config::database_url(&EnvVars::from_env()).expect("Invalid test configuration")
+}
+
+fn redis_client() -> redis::Client {
+    let redis_host_name = config::redis_host_name(&EnvVars::from_env()).expect("Invalid test configuration");
+    get_redis_client(redis_host_name.expose_secret().to_owned()).expect("Failed to get Redis client")
+}
+
+fn delete_redis_keys(key_prefix: &str) {
+    let mut conn = redis_client().get_connection().expect("Failed to get Redis connection");
+    let keys: Vec<String> = conn
+        .scan_match(format!("{}*", key_prefix))
+        .expect("Failed to scan Redis keys")
+        .collect();
+    if !keys.is_empty() {
+        let _: () = conn.del(keys).expect("Failed to delete Redis keys");
+    }
+}
+
+async fn configure_postgresql(db_name: &str) -> PgPool {
+    let db_url = create_database(db_name).await;
+    let pool = get_postgres_pool(&db_url)
+        .await
+        .expect("Failed to create Postgres connection pool!");
+    run_migrations(&pool).await.expect("Failed to run migrations");
+    pool
+}
+
+// Creates an empty database next to DATABASE_URL's and returns its connection string
+pub async fn create_database(db_name: &str) -> String {
+    let connection_options = PgConnectOptions::from_str(database_url().expose_secret())
+        .expect("Failed to parse PostgreSQL connection string");
+    let mut connection = PgConnection::connect_with(&connection_options)
+        .await
+        .expect("Failed to connect to Postgres");
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, db_name).as_str())
+        .await
+        .expect("Failed to create database.");
+
+    let mut db_url = Url::parse(database_url().expose_secret()).expect("DATABASE_URL must be a URL");
+    db_url.set_path(db_name);
+    db_url.to_string()
+}
+
 impl Drop for TestApp {
     fn drop(&mut self) {
         if !self.clean_up_called {
@@ -206,8 +827,8 @@ impl Drop for TestApp {
     }
 }
 
-async fn delete_database(db_name: &str) {
-    let connection_options = PgConnectOptions::from_str(DATABASE_URL.expose_secret())
+pub async fn delete_database(db_name: &str) {
+    let connection_options = PgConnectOptions::from_str(database_url().expose_secret())
         .expect("Failed to parse PostgreSQL connection string");
     let mut connection = PgConnection::connect_with(&connection_options)
         .await
@@ -230,7 +851,7 @@ async fn delete_database(db_name: &str) {
         .expect("Failed to terminate database connections.");
 
     connection
-        .execute(format!(r#"DROP DATABASE "{}";"#, db_name).as_str())
+        .execute(format!(r#"DROP DATABASE IF EXISTS "{}";"#, db_name).as_str())
         .await
         .expect("Failed to drop the database.");
 }
\ No newline at end of file
@@ -0,0 +1,58 @@
+use auth_service::utils::constants::JWT_COOKIE_NAME;
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde_json::json;
+use crate::helpers::{get_random_email, TestApp, TEST_JWT_KEY_ID};
+
+async fn signup_and_login(app: &TestApp) -> String {
+    let email = get_random_email();
+    let response = app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+    token
+}
+
+#[tokio::test]
+async fn should_serve_the_key_that_signs_issued_tokens() {
+    let mut app = TestApp::builder().with_rs256().spawn().await;
+    let token = signup_and_login(&app).await;
+
+    let response = app.get_jwks().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let jwks = response.json::<JwkSet>().await.expect("Could not deserialize JWKS");
+
+    let header = decode_header(&token).expect("Invalid token header");
+    assert_eq!(header.alg, Algorithm::RS256);
+    assert_eq!(header.kid.as_deref(), Some(TEST_JWT_KEY_ID));
+    let jwk = jwks.find(TEST_JWT_KEY_ID).expect("No key matches the token's kid");
+
+    // What a downstream service does with the document
+    let decoding_key = DecodingKey::from_jwk(jwk).expect("Unusable JWK");
+    decode::<serde_json::Value>(&token, &decoding_key, &Validation::new(Algorithm::RS256))
+        .expect("Token does not verify against the published key");
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_404_when_tokens_are_signed_with_hs256() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_jwks().await;
+
+    assert_eq!(response.status().as_u16(), 404);
+    app.clean_up().await;
+}
@@ -0,0 +1,69 @@
+use std::time::Duration;
+use auth_service::ErrorResponse;
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use crate::helpers::{get_random_email, TestApp};
+
+const MAX_CONCURRENT_REQUESTS: usize = 2;
+
+async fn signup_with_2fa(app: &TestApp) -> String {
+    let email = get_random_email();
+    let response = app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+    email
+}
+
+// Waits until the email server has seen `count` sends, i.e. that many logins are in flight
+async fn wait_for_email_sends(app: &TestApp, count: usize) {
+    for _ in 0..100 {
+        let received = app.email_server.received_requests().await.unwrap_or_default();
+        if received.len() >= count {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    panic!("Logins never reached the email server");
+}
+
+#[tokio::test]
+async fn should_shed_requests_over_concurrency_limit() {
+    let mut app = TestApp::builder().with_max_concurrent_requests(MAX_CONCURRENT_REQUESTS).spawn().await;
+    let emails = [signup_with_2fa(&app).await, signup_with_2fa(&app).await];
+
+    // 2FA logins hang on the email server until the client's timeout, holding their permits
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(10)))
+        .mount(&app.email_server)
+        .await;
+
+    let slow_app = &app;
+    let login = |email: &str| {
+        let body = json!({ "email": email, "password": "password123" });
+        async move { slow_app.post_login(&body).await }
+    };
+    let (_, _, (shed, livez)) = tokio::join!(login(&emails[0]), login(&emails[1]), async {
+        wait_for_email_sends(&app, MAX_CONCURRENT_REQUESTS).await;
+        tokio::join!(app.get_version(), app.get_livez())
+    });
+
+    assert_eq!(shed.status().as_u16(), 503);
+    assert!(shed.headers().contains_key("retry-after"));
+    let error_response = shed.json::<ErrorResponse>().await.expect("Could not deserialize response body");
+    assert_eq!(error_response.code, "overloaded");
+
+    // Health probes are exempt so a busy instance doesn't look dead
+    assert_eq!(livez.status().as_u16(), 200);
+
+    // Permits are released once the slow requests finish
+    let response = app.get_version().await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.clean_up().await;
+}
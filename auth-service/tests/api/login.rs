@@ -1,25 +1,58 @@
 use crate::helpers::{TestApp, get_random_email};
 use auth_service::{
     domain::{
+        data_stores::{delivery_email_hash, TwoFACode},
         email::Email,
+        locale::Locale,
+        password::Password,
+        user::User,
+    },
+    routes::LoginResponse,  // Import from routes module
+    utils::{
+        constants::{test, JWT_COOKIE_NAME, LOGIN_ATTEMPT_ID_HEADER, TWO_FA_REQUIRED_HEADER},
+        login_delay::LoginDelay,
     },
-    routes::TwoFactorAuthResponse,  // Import from routes module
-    utils::constants::JWT_COOKIE_NAME,
     ErrorResponse,
 };
+use secrecy::{ExposeSecret, Secret};
 use serde_json::json;
+use wiremock::{
+    matchers::{body_partial_json, method, path},
+    Mock, ResponseTemplate,
+};
 
 #[tokio::test]
 async fn should_return_422_if_malformed_credentials() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     let response = app.post_login(&json!({})).await;
     assert_eq!(response.status().as_u16(), 422);
+
+    let response = app.post_login(&json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "remember": true
+    })).await;
+    assert_eq!(response.status().as_u16(), 422);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_422_if_body_is_missing() {
+    let mut app = TestApp::new().await;
+
+    // Sent without a body or Content-Type, as a bare form submission would be
+    let response = app.login().await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+    assert_eq!(error_response.error, "Request body must be a JSON object");
+    assert_eq!(error_response.code, "missing_body");
     app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_400_if_invalid_input() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     
     let response = app.post_login(&json!({
         "email": "notanemail",
@@ -33,12 +66,36 @@ async fn should_return_400_if_invalid_input() {
         .await
         .expect("Failed to parse error response");
     assert_eq!(error_response.error, "Invalid credentials");
+    assert_eq!(error_response.code, "invalid_credentials");
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_email_or_password_empty() {
+    let mut app = TestApp::new().await;
+
+    let test_cases = [
+        (json!({"email": "", "password": "password123"}), "email must not be empty"),
+        (json!({"email": get_random_email(), "password": ""}), "password must not be empty"),
+    ];
+
+    for (body, message) in test_cases {
+        let response = app.post_login(&body).await;
+        assert_eq!(response.status().as_u16(), 400, "Failed for input: {:?}", body);
+
+        let error_response = response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Failed to parse error response");
+        assert_eq!(error_response.error, message);
+        assert_eq!(error_response.code, "empty_field");
+    }
     app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_401_if_incorrect_credentials() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     let email = get_random_email();
     
     // First, create a user
@@ -62,12 +119,13 @@ async fn should_return_401_if_incorrect_credentials() {
         .await
         .expect("Failed to parse error response");
     assert_eq!(error_response.error, "Incorrect credentials");
+    assert_eq!(error_response.code, "incorrect_credentials");
     app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     let random_email = get_random_email();
     
     // First, create a user
@@ -93,13 +151,62 @@ async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
         .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
         .expect("No auth cookie found");
     assert!(!auth_cookie.value().is_empty());
+    assert!(response.headers().get(TWO_FA_REQUIRED_HEADER).is_none());
+    assert!(response.headers().get(LOGIN_ATTEMPT_ID_HEADER).is_none());
+    app.clean_up().await;
+}
+
+// Checks the raw header rather than the parsed cookie, so an attribute dropped or rewritten by a
+// layer between the handler and the wire fails here
+#[tokio::test]
+async fn should_set_auth_cookie_attributes_on_the_wire() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let response = app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let set_cookie = response
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .map(|value| value.to_str().expect("Set-Cookie should be ASCII"))
+        .find(|value| value.starts_with(&format!("{}=", JWT_COOKIE_NAME)))
+        .expect("No auth Set-Cookie header found");
+    let attributes: Vec<String> = set_cookie
+        .split(';')
+        .skip(1)
+        .map(|attribute| attribute.trim().to_ascii_lowercase())
+        .collect();
+
+    assert!(attributes.contains(&"httponly".to_owned()), "Missing HttpOnly: {}", set_cookie);
+    assert!(attributes.contains(&"path=/".to_owned()), "Missing Path=/: {}", set_cookie);
+    assert!(attributes.contains(&"samesite=lax".to_owned()), "Missing SameSite=Lax: {}", set_cookie);
+    // Not marked Secure so the cookie works over plain HTTP
+    assert!(!attributes.contains(&"secure".to_owned()), "Unexpected Secure: {}", set_cookie);
+    // Host-only: browsers ignore the empty `Domain=` the cookie is built with
+    assert!(
+        attributes.iter().all(|attribute| !attribute.starts_with("domain=") || attribute == "domain="),
+        "Unexpected Domain: {}",
+        set_cookie
+    );
     app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
     // Create a new test app instance
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
+    app.mock_email_delivery(200).await;
     
     // Generate a random email for the test
     let email = get_random_email();
@@ -120,42 +227,393 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
 
     // Assert we get a 206 status code
     assert_eq!(login_response.status().as_u16(), 206);
+    assert_eq!(login_response.headers().get(TWO_FA_REQUIRED_HEADER).unwrap(), "true");
+    let login_attempt_id_header = login_response
+        .headers()
+        .get(LOGIN_ATTEMPT_ID_HEADER)
+        .expect("No login attempt ID header found")
+        .to_str()
+        .unwrap()
+        .to_owned();
 
     // Parse and verify the response body
-    let response_body = login_response
-        .json::<TwoFactorAuthResponse>()
-        .await
-        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+    let raw_body = login_response.text().await.expect("Failed to read response body");
+    let response_body = serde_json::from_str::<LoginResponse>(&raw_body)
+        .expect("Could not deserialize response body to LoginResponse");
     
     // Verify the message
     assert_eq!(response_body.message, "2FA required");
+    let response_body = response_body.data.expect("2FA response should include data");
+    let two_fa_code = app.sent_2fa_code(&email).await;
     
     // Verify that a login attempt ID was returned and not empty
     assert!(!response_body.login_attempt_id.is_empty());
-    assert!(!response_body.two_fa_code.is_empty());
+    assert_eq!(response_body.login_attempt_id, login_attempt_id_header);
+    // The code only goes out by email, so knowing the password isn't enough to finish 2FA
+    assert!(!raw_body.contains(&two_fa_code));
 
     // Get access to the 2FA code store
     let two_fa_store = app.two_fa_code_store.read().await;
 
     // Verify that a code was stored for this email
-    let email_obj = Email::parse(email).expect("Failed to parse email");
+    let email_obj = Email::parse(Secret::new(email)).expect("Failed to parse email");
     let stored_code = two_fa_store.get_code(&email_obj).await;
     
     // Assert that we can retrieve the code and that the login attempt ID matches
     match stored_code {
-        Ok((stored_login_attempt_id, _)) => {
+        Ok((stored_login_attempt_id, stored_code_hash)) => {
             assert_eq!(
-                stored_login_attempt_id.as_ref(),
+                stored_login_attempt_id.as_ref().expose_secret(),
                 &response_body.login_attempt_id,
                 "Stored login attempt ID doesn't match the one sent to the client"
             );
+            // Only a hash of the code is stored
+            let sent_code = TwoFACode::parse(Secret::new(two_fa_code.clone()))
+                .expect("Sent 2FA code should be valid");
             assert_eq!(
-                stored_two_fa_code.as_ref(),
-                &response_body.two_fa_code,
-                "Stored 2FA code doesn't match the one sent to the client"
+                stored_code_hash,
+                sent_code.hash(&stored_login_attempt_id, &app.config.two_fa_code_pepper),
+                "Stored 2FA code hash doesn't match the code sent to the client"
             );
+            assert!(!stored_code_hash.as_ref().expose_secret().contains(&two_fa_code));
         },
         Err(e) => panic!("Failed to retrieve stored 2FA code: {:?}", e),
     }
+    drop(two_fa_store);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_503_if_2fa_email_cannot_be_sent() {
+    let mut app = TestApp::new().await;
+    app.mock_email_delivery(500).await;
+    let email = get_random_email();
+
+    let signup_response = app.post_signup(&json!({
+        "email": email.clone(),
+        "password": "validpassword123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "validpassword123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 503);
+    assert!(response.cookies().all(|cookie| cookie.name() != JWT_COOKIE_NAME));
+
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Failed to parse error response");
+    assert_eq!(error_response.error, "Could not send verification email, try again");
+    assert_eq!(error_response.code, "email_delivery_failed");
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_send_2fa_email_in_the_users_language() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let mut user = User::new(
+        Email::parse(Secret::new(email.clone())).unwrap(),
+        Password::parse(Secret::new("validpassword123".to_owned())).unwrap(),
+        true,
+    );
+    user.locale = Some(Locale::Es);
+    app.user_store.add_user(user).await.expect("Failed to add user");
+
+    // Any other email gets wiremock's default 404, which fails the login
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(json!({
+            "Subject": "Tu código de verificación",
+            "Headers": [{ "Name": "Content-Language", "Value": "es" }]
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "validpassword123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 206);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_send_2fa_email_in_the_request_language_when_user_has_none() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let user = User::new(
+        Email::parse(Secret::new(email.clone())).unwrap(),
+        Password::parse(Secret::new("validpassword123".to_owned())).unwrap(),
+        true,
+    );
+    app.user_store.add_user(user).await.expect("Failed to add user");
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(json!({
+            "Subject": "Tu código de verificación",
+            "Headers": [{ "Name": "Content-Language", "Value": "es" }]
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_login_with_language(&json!({
+        "email": email,
+        "password": "validpassword123"
+    }), "es-ES,en;q=0.5").await;
+    assert_eq!(response.status().as_u16(), 206);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_link_to_the_app_in_2fa_email_when_public_url_is_set() {
+    let mut app = TestApp::builder().with_public_app_url("https://auth.example.com/app").spawn().await;
+    let email = get_random_email();
+
+    let user = User::new(
+        Email::parse(Secret::new(email.clone())).unwrap(),
+        Password::parse(Secret::new("validpassword123".to_owned())).unwrap(),
+        true,
+    );
+    app.user_store.add_user(user).await.expect("Failed to add user");
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "validpassword123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = requests[0].body_json().unwrap();
+    let code = app.sent_2fa_code(&email).await;
+    assert_eq!(
+        body["TextBody"],
+        format!("Your verification code is: {}\n\nEnter it at https://auth.example.com/app/", code)
+    );
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_record_2fa_delivery_receipt_when_enabled() {
+    let mut app = TestApp::builder().with_two_fa_delivery_log().spawn().await;
+    app.mock_email_delivery(200).await;
+    let email = get_random_email();
+
+    let signup_response = app.post_signup(&json!({
+        "email": email.clone(),
+        "password": "validpassword123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let response = app.post_login(&json!({
+        "email": email.clone(),
+        "password": "validpassword123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let rows: Vec<(String, bool)> =
+        sqlx::query_as("SELECT email_hash, delivered FROM two_fa_deliveries")
+            .fetch_all(app.db_pool.as_ref().expect("Delivery log app has a database"))
+            .await
+            .expect("Failed to query delivery log");
+    let email = Email::parse(Secret::new(email)).expect("Failed to parse email");
+    assert_eq!(rows, vec![(delivery_email_hash(&email), true)]);
     app.clean_up().await;
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn should_localize_error_message_from_accept_language() {
+    let mut app = TestApp::new().await;
+    let invalid_body = json!({
+        "email": "notanemail",
+        "password": "password123"
+    });
+
+    let test_cases = [
+        ("en-US,en;q=0.9", "Invalid credentials"),
+        ("es-ES,es;q=0.9", "Credenciales inválidas"),
+        // Unsupported languages fall back to English
+        ("fr-FR", "Invalid credentials"),
+    ];
+
+    for (accept_language, expected_message) in test_cases {
+        let response = app.post_login_with_language(&invalid_body, accept_language).await;
+        assert_eq!(response.status().as_u16(), 400);
+
+        let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+        assert_eq!(error_response.error, expected_message, "Failed for {}", accept_language);
+        assert_eq!(error_response.code, "invalid_credentials");
+    }
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_grow_login_delay_across_consecutive_failures() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let email_obj = Email::parse(Secret::new(email.clone())).unwrap();
+    let login_delay = LoginDelay::new(test::LOGIN_DELAY_BASE, test::LOGIN_DELAY_MAX);
+
+    app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+
+    let mut previous_delay = login_delay.delay_for(0);
+    for expected_failures in 1..=3 {
+        let response = app.post_login(&json!({
+            "email": email,
+            "password": "wrongpassword"
+        })).await;
+        assert_eq!(response.status().as_u16(), 401);
+
+        let failures = app.login_failure_store.failure_count(&email_obj).await.unwrap();
+        assert_eq!(failures, expected_failures);
+        let delay = login_delay.delay_for(failures);
+        assert!(delay > previous_delay, "Delay did not grow after {} failures", failures);
+        previous_delay = delay;
+    }
+
+    // A successful login clears the delay
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.login_failure_store.failure_count(&email_obj).await.unwrap(), 0);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_track_login_failures_for_unknown_emails() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let email = Email::parse(Secret::new(email)).unwrap();
+    assert_eq!(app.login_failure_store.failure_count(&email).await.unwrap(), 1);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_200_if_2fa_code_sent_inline() {
+    let mut app = TestApp::new().await;
+    app.mock_email_delivery(200).await;
+    let email = get_random_email();
+
+    let signup_response = app.post_signup(&json!({
+        "email": email.clone(),
+        "password": "password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    // Without a code the login starts the usual challenge
+    let response = app.post_login(&json!({
+        "email": email.clone(),
+        "password": "password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let two_fa_code = app.sent_2fa_code(&email).await;
+
+    // The emailed code completes the login in one call, without the attempt ID
+    let response = app.post_login(&json!({
+        "email": email.clone(),
+        "password": "password123",
+        "2FACode": two_fa_code
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.cookies().any(|cookie| cookie.name() == JWT_COOKIE_NAME && !cookie.value().is_empty()));
+
+    // The code is consumed, so replaying it fails
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "password123",
+        "2FACode": two_fa_code
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_count_wrong_inline_2fa_code_as_failed_attempt() {
+    let mut app = TestApp::new().await;
+    app.mock_email_delivery(200).await;
+    let email = get_random_email();
+
+    let signup_response = app.post_signup(&json!({
+        "email": email.clone(),
+        "password": "password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let response = app.post_login(&json!({
+        "email": email.clone(),
+        "password": "password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let two_fa_code = app.sent_2fa_code(&email).await;
+
+    let wrong_code = if two_fa_code == "000000" { "111111" } else { "000000" };
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "password123",
+        "2FACode": wrong_code
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response.cookies().all(|cookie| cookie.name() != JWT_COOKIE_NAME));
+
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Failed to parse error response");
+    assert_eq!(error_response.details["attempts_remaining"], test::TWO_FA_MAX_ATTEMPTS - 1);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_ignore_inline_2fa_code_if_2fa_disabled() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let signup_response = app.post_signup(&json!({
+        "email": email.clone(),
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "password123",
+        "2FACode": "123456"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.clean_up().await;
+}
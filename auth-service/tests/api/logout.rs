@@ -1,23 +1,27 @@
 use auth_service::{utils::constants::JWT_COOKIE_NAME, ErrorResponse};
 use crate::helpers::{TestApp, get_random_email};
+use futures_util::future::join_all;
 use reqwest::Url;
+use secrecy::Secret;
 use serde_json::json;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 
 #[tokio::test]
 async fn should_return_400_if_jwt_cookie_missing() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     let response = app.logout().await;
     assert_eq!(response.status().as_u16(), 400);
     
     let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
     assert_eq!(error_response.error, "Missing token");
+    assert_eq!(error_response.code, "missing_token");
     app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_401_if_invalid_token() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     
     // Add invalid cookie
     app.cookie_jar.add_cookie_str(
@@ -33,12 +37,13 @@ async fn should_return_401_if_invalid_token() {
     
     let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
     assert_eq!(error_response.error, "Invalid token");
+    assert_eq!(error_response.code, "invalid_token");
     app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_200_if_valid_jwt_cookie() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     let email = get_random_email();
     
     // First, create a user
@@ -70,11 +75,10 @@ async fn should_return_200_if_valid_jwt_cookie() {
     let is_banned = app.banned_token_store
         .read()
         .await
-        .contains_token(&token)
+        .contains_token(&Secret::new(token))
         .await
         .unwrap();
     assert!(is_banned, "Token should be in banned token store");
-    app.clean_up().await;
 
     // Second logout should fail with 400 Missing Token
     let second_logout = app.logout().await;
@@ -82,5 +86,109 @@ async fn should_return_200_if_valid_jwt_cookie() {
     
     let error_response: ErrorResponse = second_logout.json().await.expect("Failed to parse error response");
     assert_eq!(error_response.error, "Missing token");
+    assert_eq!(error_response.code, "missing_token");
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_200_and_ban_token_if_valid_bearer_token() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let signup_response = app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let login_response = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    })).await;
+    assert_eq!(login_response.status().as_u16(), 200);
+
+    let token = login_response.cookies()
+        .find(|c| c.name() == JWT_COOKIE_NAME)
+        .expect("No JWT cookie found")
+        .value()
+        .to_owned();
+
+    let logout_response = app.logout_with_bearer(&token).await;
+    assert_eq!(logout_response.status().as_u16(), 200);
+    // Nothing to clear when the request carried no cookie
+    assert!(logout_response.cookies().all(|c| c.name() != JWT_COOKIE_NAME));
+
+    // The token is rejected from now on, however it's presented
+    let verify_response = app.post_verify_token(&json!({ "token": token })).await;
+    assert_eq!(verify_response.status().as_u16(), 401);
+
+    let second_logout = app.logout_with_bearer(&token).await;
+    assert_eq!(second_logout.status().as_u16(), 401);
+
+    let error_response: ErrorResponse = second_logout.json().await.expect("Failed to parse error response");
+    assert_eq!(error_response.error, "Invalid token");
+    assert_eq!(error_response.code, "invalid_token");
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_never_verify_token_once_logout_has_returned() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let signup_response = app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let login_response = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    })).await;
+    assert_eq!(login_response.status().as_u16(), 200);
+
+    let token = login_response.cookies()
+        .find(|c| c.name() == JWT_COOKIE_NAME)
+        .expect("No JWT cookie found")
+        .value()
+        .to_owned();
+
+    let logged_out = AtomicBool::new(false);
+    let verified_before_logout = AtomicUsize::new(0);
+    let verify_body = json!({ "token": token });
+
+    // Verifications before and during the logout may go either way, and keep the token in the
+    // verified-token cache. Any that starts after the logout returned must see it banned. Eight
+    // callers making at most 12 requests each stay under the verify_token rate limit.
+    let verify_until_banned = || async {
+        let mut verified_after_logout = 0;
+        for _ in 0..12 {
+            let after_logout = logged_out.load(Ordering::SeqCst);
+            let response = app.post_verify_token(&verify_body).await;
+            if after_logout {
+                assert_eq!(response.status().as_u16(), 401, "Token verified after logout returned");
+                verified_after_logout += 1;
+            } else {
+                verified_before_logout.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        verified_after_logout
+    };
+    let logout = async {
+        // Let the verifications fill the cache first
+        while verified_before_logout.load(Ordering::SeqCst) < 8 {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+        let response = app.logout().await;
+        assert_eq!(response.status().as_u16(), 200);
+        logged_out.store(true, Ordering::SeqCst);
+    };
+
+    let verifications = join_all((0..8).map(|_| verify_until_banned()));
+    let (verified_after_logout, ()) = tokio::join!(verifications, logout);
+    assert!(verified_after_logout.iter().sum::<usize>() > 0, "No verification ran after logout");
     app.clean_up().await;
-}
\ No newline at end of file
+}
@@ -1,7 +1,19 @@
+mod account;
+mod admin;
+mod change_password;
+mod cors;
+mod events;
+mod health;
 mod helpers;
+mod jwks;
+mod load_shedding;
 mod login;
 mod logout;
+mod metrics;
+mod migrate;
 mod root;
 mod signup;
+mod sliding_sessions;
 mod verify_2fa;
-mod verify_token;
\ No newline at end of file
+mod verify_token;
+mod version;
\ No newline at end of file
@@ -0,0 +1,56 @@
+use serde_json::json;
+use crate::helpers::{get_random_email, TestApp};
+
+async fn signup_with_2fa(app: &TestApp) -> String {
+    let email = get_random_email();
+    let response = app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+    email
+}
+
+// The value of the sample with exactly this name and labels, e.g. `email_send_total{result="failure"}`
+async fn metric(app: &TestApp, sample: &str) -> u64 {
+    let response = app.get_metrics().await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+        .text()
+        .await
+        .expect("Failed to read metrics")
+        .lines()
+        .find_map(|line| line.strip_prefix(sample)?.strip_prefix(' ')?.parse().ok())
+        .unwrap_or_else(|| panic!("No {} sample found", sample))
+}
+
+#[tokio::test]
+async fn should_count_failed_2fa_email_sends() {
+    let mut app = TestApp::new().await;
+    app.mock_email_delivery(500).await;
+    let email = signup_with_2fa(&app).await;
+    assert_eq!(metric(&app, r#"email_send_total{result="failure"}"#).await, 0);
+
+    let response = app.post_login(&json!({ "email": email, "password": "password123" })).await;
+    assert_eq!(response.status().as_u16(), 503);
+
+    assert_eq!(metric(&app, r#"email_send_total{result="failure"}"#).await, 1);
+    assert_eq!(metric(&app, r#"email_send_total{result="success"}"#).await, 0);
+    assert_eq!(metric(&app, "email_send_duration_seconds_count").await, 1);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_count_successful_2fa_email_sends() {
+    let mut app = TestApp::new().await;
+    app.mock_email_delivery(200).await;
+    let email = signup_with_2fa(&app).await;
+
+    let response = app.post_login(&json!({ "email": email, "password": "password123" })).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    assert_eq!(metric(&app, r#"email_send_total{result="success"}"#).await, 1);
+    assert_eq!(metric(&app, r#"email_send_total{result="failure"}"#).await, 0);
+    app.clean_up().await;
+}
@@ -0,0 +1,29 @@
+use crate::helpers::{create_database, delete_database};
+use sqlx::{Connection, PgConnection};
+use std::process::Command;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn migrate_subcommand_applies_migrations_and_exits() {
+    let db_name = Uuid::new_v4().to_string();
+    let db_url = create_database(&db_name).await;
+
+    let status = Command::new(env!("CARGO_BIN_EXE_auth-service"))
+        .arg("migrate")
+        .env("DATABASE_URL", &db_url)
+        .status()
+        .expect("Failed to run auth-service binary");
+    assert!(status.success());
+
+    let mut connection = PgConnection::connect(&db_url)
+        .await
+        .expect("Failed to connect to migrated database");
+    let (users_table,): (Option<String>,) = sqlx::query_as("SELECT to_regclass('users')::text")
+        .fetch_one(&mut connection)
+        .await
+        .expect("Failed to query migrated database");
+    assert_eq!(users_table.as_deref(), Some("users"));
+    drop(connection);
+
+    delete_database(&db_name).await;
+}
@@ -1,9 +1,114 @@
-use crate::helpers::TestApp;
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::{
+    utils::constants::{API_CACHE_CONTROL, STATIC_ASSETS_CACHE_CONTROL},
+    ErrorResponse,
+};
+use serde_json::json;
 
 #[tokio::test]
 async fn root_returns_auth_ui() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     let response = app.get_root().await;
     assert_eq!(response.status().as_u16(), 200);
     assert_eq!(response.headers().get("content-type").unwrap(), "text/html");
-}
\ No newline at end of file
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn head_root_returns_200_without_body() {
+    let mut app = TestApp::new().await;
+    let response = app.head_root().await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.bytes().await.unwrap().is_empty());
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn static_assets_include_cache_control() {
+    let mut app = TestApp::new().await;
+    let response = app.get_asset("app.js").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("cache-control").unwrap(),
+        STATIC_ASSETS_CACHE_CONTROL
+    );
+    assert!(response.headers().contains_key("last-modified"));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn api_responses_are_not_cacheable_unlike_static_assets() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let body = json!({ "email": email, "password": "password123", "requires2FA": false });
+    assert_eq!(app.post_signup(&body).await.status().as_u16(), 201);
+
+    let response = app.post_login(&json!({ "email": email, "password": "password123" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers().get("cache-control").unwrap(), API_CACHE_CONTROL);
+    assert_eq!(response.headers().get("pragma").unwrap(), "no-cache");
+
+    // Errors may describe the credentials too
+    let response = app.post_login(&json!({ "email": email, "password": "wrongpassword" })).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.headers().get("cache-control").unwrap(), API_CACHE_CONTROL);
+
+    let response = app.get_asset("app.js").await;
+    assert_eq!(response.headers().get("cache-control").unwrap(), STATIC_ASSETS_CACHE_CONTROL);
+    assert!(response.headers().get("pragma").is_none());
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn unknown_api_path_returns_json_404() {
+    let mut app = TestApp::new().await;
+
+    for accept in ["application/json", "*/*"] {
+        let response = app.get_with_accept("api/does-not-exist", accept).await;
+        assert_eq!(response.status().as_u16(), 404, "Failed for Accept: {}", accept);
+        assert_eq!(response.headers().get("content-type").unwrap(), "application/json");
+        assert_eq!(response.headers().get("cache-control").unwrap(), "no-store");
+
+        let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+        assert_eq!(error_response.error, "Not found");
+        assert_eq!(error_response.code, "not_found");
+    }
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn browser_navigation_to_unknown_path_returns_auth_ui() {
+    let mut app = TestApp::new().await;
+    let response = app.get_with_accept("account/settings", "text/html,*/*;q=0.8").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers().get("content-type").unwrap(), "text/html");
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn static_assets_are_compressed_when_accepted() {
+    let mut app = TestApp::new().await;
+
+    for encoding in ["gzip", "br"] {
+        let response = app.get_with_accept_encoding("app.js", "*/*", encoding).await;
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.headers().get("content-encoding").unwrap(), encoding);
+        assert_eq!(response.headers().get("vary").unwrap(), "accept-encoding");
+    }
+
+    let response = app.get_asset("app.js").await;
+    assert!(response.headers().get("content-encoding").is_none());
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn small_error_bodies_are_not_compressed() {
+    let mut app = TestApp::new().await;
+    let response = app.get_with_accept_encoding("api/does-not-exist", "application/json", "gzip").await;
+    assert_eq!(response.status().as_u16(), 404);
+    assert!(response.headers().get("content-encoding").is_none());
+
+    let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+    assert_eq!(error_response.code, "not_found");
+    app.clean_up().await;
+}
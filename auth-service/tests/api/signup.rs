@@ -1,10 +1,19 @@
 use crate::helpers::{get_random_email, TestApp};
 use serde_json::json;
-use auth_service::ErrorResponse;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use auth_service::{
+    routes::{signup::CREATED_USER_LOCATION, LoginResponse, SignupResponse, UserProfile},
+    utils::constants::{JWT_COOKIE_NAME, TWO_FA_REQUIRED_HEADER},
+    ErrorResponse,
+};
 
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     let random_email = get_random_email();
 
     // TODO: add more malformed input test cases
@@ -13,6 +22,12 @@ async fn should_return_422_if_malformed_input() {
             "password": "password123",
             "requires2FA": true
         }),
+        json!({
+            "email": random_email,
+            "password": "password123",
+            "requires2FA": true,
+            "nickname": "extra"
+        }),
         // Add more test cases here
     ];
 
@@ -29,13 +44,67 @@ async fn should_return_422_if_malformed_input() {
 }
 
 #[tokio::test]
-async fn should_return_201_if_valid_input() {
-    // Arrange
-    let app = TestApp::new().await;
+async fn should_return_422_with_field_name_if_unknown_field() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_signup(&json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "passwrod": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    let body = response.text().await.expect("Failed to read response body");
+    assert!(body.contains("unknown field `passwrod`"), "Unexpected body: {}", body);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_415_if_body_is_not_json() {
+    let mut app = TestApp::new().await;
     let body = json!({
         "email": get_random_email(),
         "password": "password123",
         "requires2FA": false
+    })
+    .to_string();
+
+    // Login and 2FA verification share the same JSON extractor
+    for path in ["/signup", "/login", "/verify_2fa"] {
+        let response = app.post_raw(path, "text/plain", &body).await;
+        assert_eq!(response.status().as_u16(), 415, "Failed for {}", path);
+
+        let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+        assert_eq!(error_response.error, "Content-Type must be application/json");
+        assert_eq!(error_response.code, "unsupported_media_type");
+    }
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_422_if_body_is_missing() {
+    let mut app = TestApp::new().await;
+
+    // Sent without a body or Content-Type, as a bare form submission would be
+    let response = app.signup().await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+    assert_eq!(error_response.error, "Request body must be a JSON object");
+    assert_eq!(error_response.code, "missing_body");
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_201_if_valid_input() {
+    // Arrange
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let body = json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": true
     });
 
     // Act
@@ -43,17 +112,129 @@ async fn should_return_201_if_valid_input() {
 
     // Assert
     assert_eq!(response.status().as_u16(), 201);
+    assert_eq!(response.headers()["location"], CREATED_USER_LOCATION);
+
+    let json_response: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(json_response["message"], "User created successfully!");
+    assert!(json_response["data"].get("password").is_none());
+
+    let created_user: UserProfile =
+        serde_json::from_value(json_response["data"].clone()).expect("Failed to parse created user");
+    assert_eq!(created_user, UserProfile { email, requires_2fa: true, display_name: None, locale: None });
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_location_of_created_user() {
+    let mut app = TestApp::builder().with_auto_login_on_signup().spawn().await;
+    let email = get_random_email();
+
+    let response = app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let location = response.headers()["location"].to_str().unwrap().to_owned();
+    assert_eq!(location, CREATED_USER_LOCATION);
+
+    // The auto-login session can read the created user there
+    let response = app.http_client
+        .get(format!("{}{}", app.address, location))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    let json_response: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(json_response["data"]["email"], email);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_set_auth_cookie_by_default() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_signup(&json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+    assert!(response.cookies().all(|cookie| cookie.name() != JWT_COOKIE_NAME));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_201_with_auth_cookie_if_auto_login_enabled() {
+    let mut app = TestApp::builder().with_auto_login_on_signup().spawn().await;
+    let email = get_random_email();
+
+    let response = app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+    assert!(!token.is_empty());
+
+    let created_user = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Failed to parse signup response")
+        .data
+        .expect("Signup response should include the created user");
+    assert_eq!(created_user.email, email);
+
+    // The session is usable straight away
+    let response = app.post_verify_token(&json!({ "token": token })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_206_if_auto_login_enabled_and_2fa_required() {
+    let mut app = TestApp::builder().with_auto_login_on_signup().spawn().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_signup(&json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(response.status().as_u16(), 206);
+    assert_eq!(response.headers()[TWO_FA_REQUIRED_HEADER], "true");
+    assert!(response.cookies().all(|cookie| cookie.name() != JWT_COOKIE_NAME));
+
+    let body = response
+        .json::<LoginResponse>()
+        .await
+        .expect("Failed to parse 2FA response");
+    assert_eq!(body.message, "2FA required");
+    assert!(body.data.is_some());
     app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_400_if_invalid_input() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     
     let test_cases = vec![
-        (json!({"email": "", "password": "password123", "requires2FA": false}), "empty email"),
         (json!({"email": "notanemail", "password": "password123", "requires2FA": false}), "invalid email"),
         (json!({"email": "user@example.com", "password": "short", "requires2FA": false}), "short password"),
+        (json!({"email": "user@example.com", "password": "a".repeat(257), "requires2FA": false}), "overlong password"),
     ];
 
     for (invalid_body, error_case) in test_cases {
@@ -68,13 +249,35 @@ async fn should_return_400_if_invalid_input() {
 
         let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
         assert_eq!(error_response.error, "Invalid credentials");
+        assert_eq!(error_response.code, "invalid_credentials");
+    }
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_naming_the_empty_field() {
+    let mut app = TestApp::new().await;
+
+    let test_cases = [
+        (json!({"email": "", "password": "password123", "requires2FA": false}), "email"),
+        (json!({"email": "user@example.com", "password": "", "requires2FA": false}), "password"),
+        (json!({"email": "  ", "password": "password123", "requires2FA": false}), "email"),
+    ];
+
+    for (body, field) in test_cases {
+        let response = app.post_signup(&body).await;
+        assert_eq!(response.status().as_u16(), 400, "Failed for input: {:?}", body);
+
+        let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+        assert_eq!(error_response.error, format!("{} must not be empty", field));
+        assert_eq!(error_response.code, "empty_field");
     }
     app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_409_if_email_already_exists() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     let email = get_random_email();
     
     let body = json!({
@@ -93,5 +296,225 @@ async fn should_return_409_if_email_already_exists() {
 
     let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
     assert_eq!(error_response.error, "User already exists");
+    assert_eq!(error_response.code, "user_already_exists");
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_429_if_rate_limit_exceeded() {
+    let mut app = TestApp::new().await;
+
+    // Signups within the burst all succeed
+    for _ in 0..app.config.signup_rate_limit_burst.get() {
+        let response = app.post_signup_from_ip(&json!({
+            "email": get_random_email(),
+            "password": "password123",
+            "requires2FA": false
+        }), "10.0.0.1").await;
+        assert_eq!(response.status().as_u16(), 201);
+    }
+
+    // One request over the burst from the same IP is rejected
+    let response = app.post_signup_from_ip(&json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false
+    }), "10.0.0.1").await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+    assert_eq!(error_response.error, "Too many requests");
+    assert_eq!(error_response.code, "too_many_requests");
+
+    // A different IP has its own quota
+    let response = app.post_signup_from_ip(&json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false
+    }), "10.0.0.2").await;
+    assert_eq!(response.status().as_u16(), 201);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_report_remaining_quota_in_rate_limit_headers() {
+    let mut app = TestApp::new().await;
+    let limit = app.config.signup_rate_limit_burst.get();
+
+    let mut previous_remaining = limit;
+    for _ in 0..limit {
+        let response = app.post_signup_from_ip(&json!({
+            "email": get_random_email(),
+            "password": "password123",
+            "requires2FA": false
+        }), "10.0.0.3").await;
+        assert_eq!(response.status().as_u16(), 201);
+        assert_eq!(response.headers()["x-ratelimit-limit"], limit.to_string().as_str());
+
+        let remaining: u32 = response.headers()["x-ratelimit-remaining"].to_str().unwrap().parse().unwrap();
+        assert_eq!(remaining, previous_remaining - 1);
+        previous_remaining = remaining;
+    }
+
+    // The 429 carries the headers too, with a reset the client can wait for
+    let response = app.post_signup_from_ip(&json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false
+    }), "10.0.0.3").await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(response.headers()["x-ratelimit-remaining"], "0");
+    let reset: u64 = response.headers()["x-ratelimit-reset"].to_str().unwrap().parse().unwrap();
+    assert!(reset > 0 && reset <= 60, "Unexpected reset: {}", reset);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_set_display_name_at_signup() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_signup(&json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false,
+        "displayName": "  Ada Lovelace  "
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let created_user = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Failed to parse signup response")
+        .data
+        .expect("Signup response should include the created user");
+    assert_eq!(created_user.display_name.as_deref(), Some("Ada Lovelace"));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_display_name_invalid() {
+    let mut app = TestApp::new().await;
+
+    for display_name in ["".to_owned(), "a".repeat(65), "Ada\nLovelace".to_owned()] {
+        let response = app.post_signup(&json!({
+            "email": get_random_email(),
+            "password": "password123",
+            "requires2FA": false,
+            "displayName": display_name
+        })).await;
+        assert_eq!(response.status().as_u16(), 400, "Failed for display name: {:?}", display_name);
+
+        let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+        assert_eq!(error_response.code, "invalid_display_name");
+    }
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_apply_default_requires_2fa_when_omitted() {
+    for (mut app, expected) in [
+        (TestApp::new().await, false),
+        (TestApp::builder().with_default_requires_2fa().spawn().await, true),
+    ] {
+        let response = app.post_signup(&json!({
+            "email": get_random_email(),
+            "password": "password123"
+        })).await;
+        assert_eq!(response.status().as_u16(), 201);
+
+        let created_user = response
+            .json::<SignupResponse>()
+            .await
+            .expect("Failed to parse signup response")
+            .data
+            .expect("Signup response should include the created user");
+        assert_eq!(created_user.requires_2fa, expected);
+        app.clean_up().await;
+    }
+}
+
+#[tokio::test]
+async fn should_respect_explicit_requires_2fa_over_default() {
+    let mut app = TestApp::builder().with_default_requires_2fa().spawn().await;
+
+    let response = app.post_signup(&json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let created_user = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Failed to parse signup response")
+        .data
+        .expect("Signup response should include the created user");
+    assert!(!created_user.requires_2fa);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_keep_plus_tagged_emails_distinct_by_default() {
+    let mut app = TestApp::new().await;
+    let user = Uuid::new_v4();
+
+    for email in [format!("{}+tag@gmail.com", user), format!("{}@gmail.com", user)] {
+        let response = app.post_signup(&json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false
+        })).await;
+        assert_eq!(response.status().as_u16(), 201);
+    }
+
+    let response = app.post_login(&json!({
+        "email": format!("{}+tag@gmail.com", user),
+        "password": "password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Another tag is another account, which doesn't exist
+    let response = app.post_login(&json!({
+        "email": format!("{}+other@gmail.com", user),
+        "password": "password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_dedupe_plus_tagged_emails_when_normalization_enabled() {
+    let mut app = TestApp::builder().with_normalize_plus_addressing().spawn().await;
+    let user = Uuid::new_v4();
+
+    let response = app.post_signup(&json!({
+        "email": format!("{}+tag@gmail.com", user),
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let created_user = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Failed to parse signup response")
+        .data
+        .expect("Signup response should include the created user");
+    assert_eq!(created_user.email, format!("{}@gmail.com", user));
+
+    let response = app.post_signup(&json!({
+        "email": format!("{}@gmail.com", user),
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    // Any tag logs in to the same account
+    let response = app.post_login(&json!({
+        "email": format!("{}+other@gmail.com", user),
+        "password": "password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
     app.clean_up().await;
-}
\ No newline at end of file
+}
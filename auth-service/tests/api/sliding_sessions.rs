@@ -0,0 +1,102 @@
+use std::time::Duration;
+use auth_service::utils::{
+    auth::{decode_token, Claims, JwtSettings, TOKEN_TTL_SECONDS},
+    constants::JWT_COOKIE_NAME,
+    sliding_session::SlidingSessions,
+};
+use secrecy::Secret;
+use serde_json::json;
+use crate::helpers::{get_random_email, TestApp};
+
+// A threshold as long as the token lifetime makes every authenticated request count as "near
+// expiry", so tests only have to wait for the clock to tick past the token's issue second
+const REFRESH_THRESHOLD: Duration = Duration::from_secs(TOKEN_TTL_SECONDS as u64);
+
+async fn signup_and_login(app: &TestApp) -> Claims {
+    let email = get_random_email();
+    let response = app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    auth_cookie_claims(app, &response).expect("No auth cookie found")
+}
+
+fn auth_cookie_claims(app: &TestApp, response: &reqwest::Response) -> Option<Claims> {
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .map(|cookie| decode_token(&JwtSettings::from_config(&app.config), &Secret::new(cookie.value().to_owned())).expect("Invalid auth cookie"))
+}
+
+#[tokio::test]
+async fn should_extend_session_on_activity_near_expiry() {
+    let mut app = TestApp::builder()
+        .with_sliding_sessions(SlidingSessions::new(REFRESH_THRESHOLD, Duration::from_secs(60 * 60)))
+        .spawn()
+        .await;
+    let login_claims = signup_and_login(&app).await;
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let response = app.get_me().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let refreshed = auth_cookie_claims(&app, &response).expect("Session was not refreshed");
+    assert!(refreshed.exp > login_claims.exp);
+    assert_ne!(refreshed.jti, login_claims.jti);
+    assert_eq!(refreshed.sub, login_claims.sub);
+    assert_eq!(refreshed.auth_time, login_claims.auth_time);
+
+    // The client now carries the refreshed cookie
+    let response = app.get_me().await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_extend_session_past_absolute_max() {
+    // Leaves room for exactly one second of sliding beyond the first token
+    let absolute_max = Duration::from_secs(TOKEN_TTL_SECONDS as u64 + 1);
+    let mut app = TestApp::builder()
+        .with_sliding_sessions(SlidingSessions::new(REFRESH_THRESHOLD, absolute_max))
+        .spawn()
+        .await;
+    let login_claims = signup_and_login(&app).await;
+    let cap = login_claims.auth_time.unwrap() + absolute_max.as_secs() as usize;
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let response = app.get_me().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let refreshed = auth_cookie_claims(&app, &response).expect("Session was not refreshed");
+    assert_eq!(refreshed.exp, cap);
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let response = app.get_me().await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(auth_cookie_claims(&app, &response).is_none());
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_refresh_session_on_logout() {
+    let mut app = TestApp::builder()
+        .with_sliding_sessions(SlidingSessions::new(REFRESH_THRESHOLD, Duration::from_secs(60 * 60)))
+        .spawn()
+        .await;
+    signup_and_login(&app).await;
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.get_me().await;
+    assert_eq!(response.status().as_u16(), 400);
+    app.clean_up().await;
+}
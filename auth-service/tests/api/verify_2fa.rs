@@ -1,25 +1,39 @@
 use crate::helpers::{TestApp, get_random_email};
+use auth_service::domain::data_stores::TwoFACodeStoreError;
 use auth_service::{
-    domain::{
-        email::Email,
-        data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore},
-    },
-    routes::TwoFactorAuthResponse,
-    utils::constants::JWT_COOKIE_NAME,
+    domain::email::Email,
+    routes::LoginResponse,
+    utils::constants::{DEVICE_TRUST_COOKIE_NAME, JWT_COOKIE_NAME, TWO_FA_REQUIRED_HEADER},
     ErrorResponse,
 };
+use secrecy::Secret;
 use serde_json::json;
 
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     let response = app.post_verify_2fa(&json!({})).await;
     assert_eq!(response.status().as_u16(), 422);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_422_if_body_is_missing() {
+    let mut app = TestApp::new().await;
+
+    // Sent without a body or Content-Type, as a bare form submission would be
+    let response = app.verify_2fa().await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+    assert_eq!(error_response.error, "Request body must be a JSON object");
+    assert_eq!(error_response.code, "missing_body");
+    app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_400_if_invalid_input() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     
     let response = app.post_verify_2fa(&json!({
         "email": "notanemail",
@@ -34,12 +48,13 @@ async fn should_return_400_if_invalid_input() {
         .await
         .expect("Failed to parse error response");
     assert_eq!(error_response.error, "Invalid credentials");
+    assert_eq!(error_response.code, "invalid_credentials");
     app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_401_if_incorrect_credentials() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     let email = get_random_email();
     
     // First create a user with 2FA enabled
@@ -64,12 +79,14 @@ async fn should_return_401_if_incorrect_credentials() {
         .await
         .expect("Failed to parse error response");
     assert_eq!(error_response.error, "Incorrect credentials");
+    assert_eq!(error_response.code, "incorrect_credentials");
     app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_200_if_correct_code() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
+    app.mock_email_delivery(200).await;
     let email = get_random_email();
     
     // First create a user with 2FA enabled
@@ -87,23 +104,18 @@ async fn should_return_200_if_correct_code() {
     })).await;
 
     let login_body = login_response
-        .json::<TwoFactorAuthResponse>()
-        .await
-        .expect("Failed to parse login response");
-
-    // Get the stored 2FA code
-    let email_obj = Email::parse(email.clone()).expect("Failed to parse email");
-    let two_fa_store = app.two_fa_code_store.read().await;
-    let (_, stored_code) = two_fa_store
-        .get_code(&email_obj)
+        .json::<LoginResponse>()
         .await
-        .expect("Failed to get stored 2FA code");
+        .expect("Failed to parse login response")
+        .data
+        .expect("2FA response should include data");
+    let two_fa_code = app.sent_2fa_code(&email).await;
 
     // Verify the 2FA code
     let response = app.post_verify_2fa(&json!({
         "email": email,
         "loginAttemptId": login_body.login_attempt_id,
-        "2FACode": stored_code.as_ref()
+        "2FACode": two_fa_code
     })).await;
 
     assert_eq!(response.status().as_u16(), 200);
@@ -114,12 +126,16 @@ async fn should_return_200_if_correct_code() {
         .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
         .expect("No auth cookie found");
     assert!(!auth_cookie.value().is_empty());
+
+    let json_response: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(json_response["message"], "2FA verification successful");
     app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_401_if_same_code_twice() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
+    app.mock_email_delivery(200).await;
     let email = get_random_email();
     
     // First create a user with 2FA enabled
@@ -137,23 +153,18 @@ async fn should_return_401_if_same_code_twice() {
     })).await;
 
     let login_body = login_response
-        .json::<TwoFactorAuthResponse>()
-        .await
-        .expect("Failed to parse login response");
-
-    // Get the stored 2FA code
-    let email_obj = Email::parse(email.clone()).expect("Failed to parse email");
-    let two_fa_store = app.two_fa_code_store.read().await;
-    let (_, stored_code) = two_fa_store
-        .get_code(&email_obj)
+        .json::<LoginResponse>()
         .await
-        .expect("Failed to get stored 2FA code");
+        .expect("Failed to parse login response")
+        .data
+        .expect("2FA response should include data");
+    let two_fa_code = app.sent_2fa_code(&email).await;
 
     // First verification should succeed
     let verify_body = json!({
         "email": email,
         "loginAttemptId": login_body.login_attempt_id,
-        "2FACode": stored_code.as_ref()
+        "2FACode": two_fa_code
     });
     
     let response1 = app.post_verify_2fa(&verify_body).await;
@@ -163,4 +174,565 @@ async fn should_return_401_if_same_code_twice() {
     let response2 = app.post_verify_2fa(&verify_body).await;
     assert_eq!(response2.status().as_u16(), 401);
     app.clean_up().await;
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn should_return_401_if_code_submitted_for_another_email() {
+    let mut app = TestApp::new().await;
+    app.mock_email_delivery(200).await;
+    let email_a = get_random_email();
+    let email_b = get_random_email();
+
+    for email in [&email_a, &email_b] {
+        let signup_response = app.post_signup(&json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": true
+        })).await;
+        assert_eq!(signup_response.status().as_u16(), 201);
+    }
+
+    // Only user A starts a login, so only A has a valid attempt ID and code
+    let login_response = app.post_login(&json!({
+        "email": email_a.clone(),
+        "password": "password123"
+    })).await;
+    assert_eq!(login_response.status().as_u16(), 206);
+
+    let login_body = login_response
+        .json::<LoginResponse>()
+        .await
+        .expect("Failed to parse login response")
+        .data
+        .expect("2FA response should include data");
+    let code_a = &app.sent_2fa_code(&email_a).await;
+
+    // User A's attempt ID and code submitted with user B's email
+    let response = app.post_verify_2fa(&json!({
+        "email": email_b.clone(),
+        "loginAttemptId": login_body.login_attempt_id,
+        "2FACode": code_a
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Failed to parse error response");
+    assert_eq!(error_response.error, "Incorrect credentials");
+    assert_eq!(error_response.code, "incorrect_credentials");
+
+    // Once B has its own pending login, A's attempt ID and code still don't unlock it
+    let login_response = app.post_login(&json!({
+        "email": email_b.clone(),
+        "password": "password123"
+    })).await;
+    assert_eq!(login_response.status().as_u16(), 206);
+
+    let response = app.post_verify_2fa(&json!({
+        "email": email_b,
+        "loginAttemptId": login_body.login_attempt_id,
+        "2FACode": code_a
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response.cookies().all(|cookie| cookie.name() != JWT_COOKIE_NAME));
+
+    // A's own verification is unaffected by the rejected attempts
+    let response = app.post_verify_2fa(&json!({
+        "email": email_a,
+        "loginAttemptId": login_body.login_attempt_id,
+        "2FACode": code_a
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_accept_only_one_of_concurrent_identical_submissions() {
+    let mut app = TestApp::new().await;
+    app.mock_email_delivery(200).await;
+    let email = get_random_email();
+
+    let signup_response = app.post_signup(&json!({
+        "email": email.clone(),
+        "password": "password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let login_body = app.post_login(&json!({
+        "email": email.clone(),
+        "password": "password123"
+    }))
+    .await
+    .json::<LoginResponse>()
+    .await
+    .expect("Failed to parse login response")
+    .data
+    .expect("2FA response should include data");
+    let two_fa_code = app.sent_2fa_code(&email).await;
+
+    let verify_body = json!({
+        "email": email,
+        "loginAttemptId": login_body.login_attempt_id,
+        "2FACode": two_fa_code
+    });
+    let (first, second) = tokio::join!(
+        app.post_verify_2fa(&verify_body),
+        app.post_verify_2fa(&verify_body),
+    );
+
+    let mut statuses = [first.status().as_u16(), second.status().as_u16()];
+    statuses.sort();
+    assert_eq!(statuses, [200, 401]);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_invalidate_code_after_incorrect_guess() {
+    let mut app = TestApp::builder().with_two_fa_max_attempts(1).spawn().await;
+    app.mock_email_delivery(200).await;
+    let email = get_random_email();
+
+    let signup_response = app.post_signup(&json!({
+        "email": email.clone(),
+        "password": "password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let login_body = app.post_login(&json!({
+        "email": email.clone(),
+        "password": "password123"
+    }))
+    .await
+    .json::<LoginResponse>()
+    .await
+    .expect("Failed to parse login response")
+    .data
+    .expect("2FA response should include data");
+    let two_fa_code = app.sent_2fa_code(&email).await;
+
+    let wrong_code = if two_fa_code == "000000" { "111111" } else { "000000" };
+    let response = app.post_verify_2fa(&json!({
+        "email": email.clone(),
+        "loginAttemptId": login_body.login_attempt_id,
+        "2FACode": wrong_code
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // With a single attempt allowed, one wrong guess burns the code
+    let response = app.post_verify_2fa(&json!({
+        "email": email,
+        "loginAttemptId": login_body.login_attempt_id,
+        "2FACode": two_fa_code
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_report_attempts_remaining_until_code_is_invalidated() {
+    let mut app = TestApp::builder().with_two_fa_max_attempts(3).spawn().await;
+    app.mock_email_delivery(200).await;
+    let email = get_random_email();
+
+    let signup_response = app.post_signup(&json!({
+        "email": email.clone(),
+        "password": "password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let login_body = app.post_login(&json!({
+        "email": email.clone(),
+        "password": "password123"
+    }))
+    .await
+    .json::<LoginResponse>()
+    .await
+    .expect("Failed to parse login response")
+    .data
+    .expect("2FA response should include data");
+    let two_fa_code = app.sent_2fa_code(&email).await;
+
+    let wrong_code = if two_fa_code == "000000" { "111111" } else { "000000" };
+    for (attempts_remaining, invalidated) in [(2, false), (1, false), (0, true)] {
+        let response = app.post_verify_2fa(&json!({
+            "email": email.clone(),
+            "loginAttemptId": login_body.login_attempt_id,
+            "2FACode": wrong_code
+        })).await;
+        assert_eq!(response.status().as_u16(), 401);
+
+        let error_response = response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Failed to parse error response");
+        assert_eq!(error_response.code, "incorrect_credentials");
+        assert_eq!(error_response.details["attempts_remaining"], attempts_remaining);
+        assert_eq!(error_response.details["invalidated"], invalidated);
+    }
+
+    // The right code no longer works once the attempts are used up
+    let response = app.post_verify_2fa(&json!({
+        "email": email,
+        "loginAttemptId": login_body.login_attempt_id,
+        "2FACode": two_fa_code
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_accept_correct_code_after_incorrect_guess() {
+    let mut app = TestApp::builder().with_two_fa_max_attempts(3).spawn().await;
+    app.mock_email_delivery(200).await;
+    let email = get_random_email();
+
+    let signup_response = app.post_signup(&json!({
+        "email": email.clone(),
+        "password": "password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let login_body = app.post_login(&json!({
+        "email": email.clone(),
+        "password": "password123"
+    }))
+    .await
+    .json::<LoginResponse>()
+    .await
+    .expect("Failed to parse login response")
+    .data
+    .expect("2FA response should include data");
+    let two_fa_code = app.sent_2fa_code(&email).await;
+
+    let wrong_code = if two_fa_code == "000000" { "111111" } else { "000000" };
+    let response = app.post_verify_2fa(&json!({
+        "email": email.clone(),
+        "loginAttemptId": login_body.login_attempt_id,
+        "2FACode": wrong_code
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_verify_2fa(&json!({
+        "email": email,
+        "loginAttemptId": login_body.login_attempt_id,
+        "2FACode": two_fa_code
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_share_2fa_codes_between_redis_backed_apps() {
+    let (mut app_a, mut app_b) = tokio::join!(
+        TestApp::builder().with_redis().spawn(),
+        TestApp::builder().with_redis().spawn(),
+    );
+    let email = get_random_email();
+    let email_obj = Email::parse(Secret::new(email.clone())).expect("Failed to parse email");
+
+    for app in [&app_a, &app_b] {
+        app.mock_email_delivery(200).await;
+        let signup_response = app.post_signup(&json!({
+            "email": email.clone(),
+            "password": "password123",
+            "requires2FA": true
+        })).await;
+        assert_eq!(signup_response.status().as_u16(), 201);
+    }
+
+    let login_response = app_a.post_login(&json!({
+        "email": email.clone(),
+        "password": "password123"
+    })).await;
+    assert_eq!(login_response.status().as_u16(), 206);
+
+    // Same email, same Redis, but app B never started a login
+    assert!(app_a.two_fa_code_store.read().await.get_code(&email_obj).await.is_ok());
+    let result = app_b.two_fa_code_store.read().await.get_code(&email_obj).await;
+    assert_eq!(result.err(), Some(TwoFACodeStoreError::LoginAttemptIdNotFound));
+
+    app_a.clean_up().await;
+    app_b.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_store_only_a_hash_of_the_2fa_code_in_redis() {
+    let mut app = TestApp::builder().with_redis().spawn().await;
+    app.mock_email_delivery(200).await;
+    let email = get_random_email();
+
+    let signup_response = app.post_signup(&json!({
+        "email": email.clone(),
+        "password": "password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let login_body = app.post_login(&json!({
+        "email": email.clone(),
+        "password": "password123"
+    }))
+    .await
+    .json::<LoginResponse>()
+    .await
+    .expect("Failed to parse login response")
+    .data
+    .expect("2FA response should include data");
+    let two_fa_code = app.sent_2fa_code(&email).await;
+
+    let stored = app
+        .get_redis_value(&format!("two_fa_code:{}", email))
+        .expect("No 2FA code stored in Redis");
+    assert!(stored.contains(&login_body.login_attempt_id));
+    assert!(!stored.contains(&two_fa_code));
+
+    let response = app.post_verify_2fa(&json!({
+        "email": email,
+        "loginAttemptId": login_body.login_attempt_id,
+        "2FACode": two_fa_code
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_delete_redis_2fa_codes_on_clean_up() {
+    let mut app = TestApp::builder().with_redis().spawn().await;
+    app.mock_email_delivery(200).await;
+    let email = get_random_email();
+    let email_obj = Email::parse(Secret::new(email.clone())).expect("Failed to parse email");
+
+    let signup_response = app.post_signup(&json!({
+        "email": email.clone(),
+        "password": "password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let login_response = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    })).await;
+    assert_eq!(login_response.status().as_u16(), 206);
+    assert!(app.two_fa_code_store.read().await.get_code(&email_obj).await.is_ok());
+
+    app.clean_up().await;
+
+    let result = app.two_fa_code_store.read().await.get_code(&email_obj).await;
+    assert_eq!(result.err(), Some(TwoFACodeStoreError::LoginAttemptIdNotFound));
+}
+
+// Signs up a 2FA user if needed, logs in and completes 2FA, returning the verify_2fa response
+async fn complete_2fa_login(app: &TestApp, email: &str, remember_device: bool) -> reqwest::Response {
+    app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": true
+    })).await;
+
+    let login_response = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    })).await;
+    assert_eq!(login_response.status().as_u16(), 206);
+    let login_body = login_response
+        .json::<LoginResponse>()
+        .await
+        .expect("Failed to parse login response")
+        .data
+        .expect("2FA response should include data");
+    let two_fa_code = app.sent_2fa_code(email).await;
+
+    let response = app.post_verify_2fa(&json!({
+        "email": email,
+        "loginAttemptId": login_body.login_attempt_id,
+        "2FACode": two_fa_code,
+        "rememberDevice": remember_device
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+}
+
+#[tokio::test]
+async fn should_skip_2fa_on_remembered_device() {
+    let mut app = TestApp::new().await;
+    app.mock_email_delivery(200).await;
+    let email = get_random_email();
+
+    let response = complete_2fa_login(&app, &email, true).await;
+    let trust_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == DEVICE_TRUST_COOKIE_NAME)
+        .expect("No device trust cookie found");
+    assert!(trust_cookie.http_only());
+    assert!(trust_cookie.max_age().is_some());
+
+    // The client's cookie jar now holds the trust cookie, so the next login skips the challenge
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers().get(TWO_FA_REQUIRED_HEADER).is_none());
+    assert!(response.cookies().any(|cookie| cookie.name() == JWT_COOKIE_NAME));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_require_2fa_when_device_not_remembered() {
+    let mut app = TestApp::new().await;
+    app.mock_email_delivery(200).await;
+    let email = get_random_email();
+
+    let response = complete_2fa_login(&app, &email, false).await;
+    assert!(response.cookies().all(|cookie| cookie.name() != DEVICE_TRUST_COOKIE_NAME));
+
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 206);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_skip_2fa_for_another_user_on_remembered_device() {
+    let mut app = TestApp::new().await;
+    app.mock_email_delivery(200).await;
+
+    complete_2fa_login(&app, &get_random_email(), true).await;
+
+    // The trust cookie is bound to the user who completed 2FA
+    let other_email = get_random_email();
+    app.post_signup(&json!({
+        "email": other_email,
+        "password": "password123",
+        "requires2FA": true
+    })).await;
+    let response = app.post_login(&json!({
+        "email": other_email,
+        "password": "password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 206);
+    app.clean_up().await;
+}
+
+// Logs in as a 2FA user, returning the login attempt ID and the code that was emailed
+async fn start_2fa_login(app: &TestApp, email: &str) -> (String, String) {
+    let login_body = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    }))
+    .await
+    .json::<LoginResponse>()
+    .await
+    .expect("Failed to parse login response")
+    .data
+    .expect("2FA response should include data");
+    let two_fa_code = app.sent_2fa_code(email).await;
+    (login_body.login_attempt_id, two_fa_code)
+}
+
+#[tokio::test]
+async fn should_accept_code_with_spaces_or_hyphens() {
+    let mut app = TestApp::new().await;
+    app.mock_email_delivery(200).await;
+    let email = get_random_email();
+    let signup_response = app.post_signup(&json!({
+        "email": email.clone(),
+        "password": "password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let formats: [fn(&str) -> String; 3] = [
+        |code| format!("{} {}", &code[..3], &code[3..]),
+        |code| format!("{}-{}", &code[..3], &code[3..]),
+        |code| format!(" {} ", code),
+    ];
+    for format in formats {
+        let (login_attempt_id, code) = start_2fa_login(&app, &email).await;
+        let submitted = format(&code);
+        let response = app.post_verify_2fa(&json!({
+            "email": email.clone(),
+            "loginAttemptId": login_attempt_id,
+            "2FACode": submitted
+        })).await;
+        assert_eq!(response.status().as_u16(), 200, "{:?} was rejected", submitted);
+    }
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_accept_snake_case_field_names() {
+    let mut app = TestApp::new().await;
+    app.mock_email_delivery(200).await;
+    let email = get_random_email();
+    let signup_response = app.post_signup(&json!({
+        "email": email.clone(),
+        "password": "password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let field_names = [
+        ("login_attempt_id", "two_fa_code"),
+        ("loginAttemptId", "two_fa_code"),
+        ("login_attempt_id", "2FACode"),
+    ];
+    for (login_attempt_id_field, code_field) in field_names {
+        let (login_attempt_id, code) = start_2fa_login(&app, &email).await;
+        let mut body = json!({ "email": email.clone() });
+        body[login_attempt_id_field] = json!(login_attempt_id);
+        body[code_field] = json!(code);
+
+        let response = app.post_verify_2fa(&body).await;
+        assert_eq!(response.status().as_u16(), 200, "Failed for {}", body);
+    }
+
+    // Inline codes on login take the alias too
+    let (_, code) = start_2fa_login(&app, &email).await;
+    let response = app.post_login(&json!({
+        "email": email.clone(),
+        "password": "password123",
+        "two_fa_code": code
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_wrong_code_with_spaces_or_hyphens() {
+    let mut app = TestApp::new().await;
+    app.mock_email_delivery(200).await;
+    let email = get_random_email();
+    let signup_response = app.post_signup(&json!({
+        "email": email.clone(),
+        "password": "password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let (login_attempt_id, code) = start_2fa_login(&app, &email).await;
+    let wrong_code = if code == "000000" { "111-111" } else { "000 000" };
+    let response = app.post_verify_2fa(&json!({
+        "email": email.clone(),
+        "loginAttemptId": login_attempt_id,
+        "2FACode": wrong_code
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Stripping separators doesn't make a code of the wrong length acceptable
+    let response = app.post_verify_2fa(&json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": format!("{} 7", code)
+    })).await;
+    assert_eq!(response.status().as_u16(), 400);
+    app.clean_up().await;
+}
@@ -1,10 +1,16 @@
 use crate::helpers::{get_random_email, TestApp};
-use auth_service::{utils::constants::JWT_COOKIE_NAME, ErrorResponse};
+use auth_service::{
+    routes::TokenVerification,
+    utils::{auth::TokenRejection, constants::{JWT_COOKIE_NAME, MAX_VERIFY_TOKEN_BATCH_SIZE}},
+    ApiResponse,
+    ErrorResponse,
+};
+use secrecy::Secret;
 use serde_json::json;
 
 #[tokio::test]
 async fn should_return_200_valid_token() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     
     // First sign up a user
     let email = get_random_email();
@@ -43,19 +49,20 @@ async fn should_return_200_valid_token() {
 
 #[tokio::test]
 async fn should_return_401_if_invalid_token() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     let response = app.post_verify_token(&json!({
         "token": "invalid_token"
     })).await;
     
     assert_eq!(401, response.status().as_u16());
+    app.clean_up().await;
 }
 
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     let response = app.post_verify_token(&json!({
-        "not_token": "wrong_field"
+        "token": 42
     })).await;
     
     assert_eq!(422, response.status().as_u16());
@@ -64,7 +71,7 @@ async fn should_return_422_if_malformed_input() {
 
 #[tokio::test]
 async fn should_return_401_if_banned_token() {
-    let app = TestApp::new().await;
+    let mut app = TestApp::new().await;
     let email = get_random_email();
     
     // First sign up a user
@@ -93,7 +100,7 @@ async fn should_return_401_if_banned_token() {
     app.banned_token_store
         .write()
         .await
-        .store_token(token.clone())
+        .store_token(Secret::new(token.clone()))
         .await
         .expect("Failed to store token");
     
@@ -106,5 +113,177 @@ async fn should_return_401_if_banned_token() {
     
     let error_response: ErrorResponse = response.json().await.unwrap();
     assert_eq!("Invalid token", error_response.error);
+    assert_eq!(error_response.code, "invalid_token");
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_200_if_valid_jwt_cookie_and_no_body() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let signup_body = json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let signup_response = app.post_signup(&signup_body).await;
+    assert_eq!(201, signup_response.status().as_u16(), "Signup failed");
+
+    // Logging in stores the JWT cookie in the client's cookie jar
+    let login_body = json!({
+        "email": email,
+        "password": "password123"
+    });
+    let login_response = app.post_login(&login_body).await;
+    assert_eq!(200, login_response.status().as_u16(), "Login failed");
+
+    let response = app.verify_token().await;
+    assert_eq!(200, response.status().as_u16());
+
+    let json_response: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(json_response["message"], "Token is valid");
+    app.clean_up().await;
+}
+
+// Clients that always send JSON mean "use my cookie" with an empty body, `{}` or an empty token
+#[tokio::test]
+async fn should_fall_back_to_jwt_cookie_if_body_has_no_token() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let signup_response = app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(201, signup_response.status().as_u16(), "Signup failed");
+    let login_response = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    })).await;
+    assert_eq!(200, login_response.status().as_u16(), "Login failed");
+
+    for body in ["", "{}", r#"{"token": ""}"#, r#"{"token": null}"#] {
+        let response = app.post_raw("/verify_token", "application/json", body).await;
+        assert_eq!(200, response.status().as_u16(), "Body {:?} did not fall back to the cookie", body);
+    }
     app.clean_up().await;
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn should_return_400_if_no_token_in_body_or_cookie() {
+    let mut app = TestApp::new().await;
+
+    let response = app.verify_token().await;
+    assert_eq!(400, response.status().as_u16());
+
+    let response = app.post_verify_token(&json!({})).await;
+    assert_eq!(400, response.status().as_u16());
+
+    let error_response: ErrorResponse = response.json().await.unwrap();
+    assert_eq!("Missing token", error_response.error);
+    assert_eq!(error_response.code, "missing_token");
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_415_if_body_is_not_json() {
+    let mut app = TestApp::new().await;
+
+    // An explicit non-JSON Content-Type is an error rather than a request for the cookie fallback
+    let response = app.post_raw("/verify_token", "text/plain", r#"{"token": "abc"}"#).await;
+    assert_eq!(response.status().as_u16(), 415);
+
+    let error_response: ErrorResponse = response.json().await.expect("Failed to parse error response");
+    assert_eq!(error_response.error, "Content-Type must be application/json");
+    assert_eq!(error_response.code, "unsupported_media_type");
+    app.clean_up().await;
+}
+
+async fn login_token(app: &TestApp, email: &str) -> String {
+    let response = app.post_login(&json!({
+        "email": email,
+        "password": "password123"
+    })).await;
+    assert_eq!(200, response.status().as_u16(), "Login failed");
+
+    let token = response.cookies()
+        .find(|c| c.name() == JWT_COOKIE_NAME)
+        .expect("No JWT cookie found")
+        .value()
+        .to_string();
+    token
+}
+
+#[tokio::test]
+async fn should_return_per_token_results_for_batch() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    app.post_signup(&json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })).await;
+
+    let valid_token = login_token(&app, &email).await;
+    let banned_token = login_token(&app, &email).await;
+    app.banned_token_store
+        .write()
+        .await
+        .store_token(Secret::new(banned_token.clone()))
+        .await
+        .expect("Failed to store token");
+
+    let response = app.post_verify_token_batch(&json!({
+        "tokens": [valid_token, "invalid_token", banned_token]
+    })).await;
+    assert_eq!(200, response.status().as_u16());
+
+    let results = response
+        .json::<ApiResponse<Vec<TokenVerification>>>()
+        .await
+        .expect("Failed to parse batch response")
+        .data
+        .expect("Batch response should include results");
+    assert_eq!(results, vec![
+        TokenVerification { valid: true, sub: Some(email), reason: None },
+        TokenVerification { valid: false, sub: None, reason: Some(TokenRejection::Invalid) },
+        TokenVerification { valid: false, sub: None, reason: Some(TokenRejection::Banned) },
+    ]);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_413_if_batch_too_large() {
+    let mut app = TestApp::new().await;
+    let tokens = vec!["token"; MAX_VERIFY_TOKEN_BATCH_SIZE + 1];
+
+    let response = app.post_verify_token_batch(&json!({ "tokens": tokens })).await;
+    assert_eq!(413, response.status().as_u16());
+
+    let error_response: ErrorResponse = response.json().await.unwrap();
+    assert_eq!("batch_too_large", error_response.code);
+    app.clean_up().await;
+}
+
+fn rate_limit_remaining(response: &reqwest::Response) -> u32 {
+    response.headers()["x-ratelimit-remaining"].to_str().unwrap().parse().unwrap()
+}
+
+#[tokio::test]
+async fn should_charge_batch_tokens_to_the_verify_token_quota() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_verify_token(&json!({ "token": "token" })).await;
+    let before = rate_limit_remaining(&response);
+
+    let response = app.post_verify_token_batch(&json!({ "tokens": ["a", "b", "c"] })).await;
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(rate_limit_remaining(&response), before - 3);
+
+    // Single checks draw from the same quota
+    let response = app.post_verify_token(&json!({ "token": "token" })).await;
+    assert_eq!(rate_limit_remaining(&response), before - 4);
+    app.clean_up().await;
+}
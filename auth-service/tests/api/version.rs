@@ -0,0 +1,19 @@
+use crate::helpers::TestApp;
+use auth_service::routes::VersionResponse;
+
+#[tokio::test]
+async fn version_returns_build_info() {
+    let mut app = TestApp::new().await;
+    let response = app.get_version().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response
+        .json::<VersionResponse>()
+        .await
+        .expect("Failed to parse version response");
+    assert_eq!(body.name, env!("CARGO_PKG_NAME"));
+    assert_eq!(body.version, env!("CARGO_PKG_VERSION"));
+    assert!(!body.git_sha.is_empty());
+    assert!(!body.build_time.is_empty());
+    app.clean_up().await;
+}